@@ -0,0 +1,319 @@
+use crate::providers::Email;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use std::fmt;
+
+/// When an event starts, as its DTSTART gives it
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventStart {
+    Utc(DateTime<Utc>),
+    /// Wall-clock time in the named zone, or floating when there's none
+    Local(NaiveDateTime, Option<String>),
+    AllDay(NaiveDate),
+}
+
+impl fmt::Display for EventStart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Utc(start) => write!(f, "{} UTC", start.format("%Y-%m-%d %H:%M")),
+            Self::Local(start, Some(zone)) => {
+                write!(f, "{} {}", start.format("%Y-%m-%d %H:%M"), zone)
+            }
+            Self::Local(start, None) => write!(f, "{}", start.format("%Y-%m-%d %H:%M")),
+            Self::AllDay(date) => write!(f, "{} (all day)", date.format("%Y-%m-%d")),
+        }
+    }
+}
+
+/// Furthest any time zone is ahead of UTC
+const MAX_UTC_OFFSET_HOURS: i64 = 14;
+
+impl EventStart {
+    /// Whether the event is still to come. Zoned and floating times count
+    /// until they have passed in every time zone; all-day events last the
+    /// whole UTC day.
+    pub fn is_after(&self, now: DateTime<Utc>) -> bool {
+        match self {
+            Self::Utc(start) => *start > now,
+            Self::Local(start, _) => {
+                *start + Duration::hours(MAX_UTC_OFFSET_HOURS) > now.naive_utc()
+            }
+            Self::AllDay(date) => *date >= now.date_naive(),
+        }
+    }
+}
+
+/// The first VEVENT of an invite
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Event {
+    /// The calendar's METHOD, uppercased: REQUEST for invitations, REPLY for
+    /// answers to them, CANCEL, ...
+    pub method: Option<String>,
+    pub summary: Option<String>,
+    pub start: Option<EventStart>,
+}
+
+impl Event {
+    /// "Standup at 2025-01-10 15:00 UTC", leaving out whatever is missing
+    pub fn describe(&self) -> String {
+        match (&self.summary, &self.start) {
+            (Some(summary), Some(start)) => format!("{} at {}", summary, start),
+            (Some(summary), None) => summary.clone(),
+            (None, Some(start)) => start.to_string(),
+            (None, None) => "(no details)".to_string(),
+        }
+    }
+}
+
+/// Invites are recognisable from their MIME type, .ics attachment, or iCalendar body
+pub fn is_invite(email: &Email) -> bool {
+    let calendar_type = email
+        .content_type
+        .as_deref()
+        .is_some_and(|content_type| content_type.eq_ignore_ascii_case("text/calendar"));
+    let ics_attachment = email
+        .attachment_names
+        .iter()
+        .any(|name| name.to_lowercase().ends_with(".ics"));
+    calendar_type
+        || ics_attachment
+        || email.calendar.is_some()
+        || email.body.trim_start().starts_with("BEGIN:VCALENDAR")
+}
+
+/// The event of an invite, from its calendar part or else its body
+pub fn invite_event(email: &Email) -> Option<Event> {
+    if !is_invite(email) {
+        return None;
+    }
+    [email.calendar.as_deref(), Some(email.body.as_str())]
+        .into_iter()
+        .flatten()
+        .find_map(parse_event)
+}
+
+/// The event of an invitation still waiting for an answer: a METHOD:REQUEST
+/// in a real text/calendar part, for an event that hasn't started. Replies,
+/// cancellations, past events, and iCalendar text in an ordinary body are None.
+pub fn pending_invite(email: &Email, now: DateTime<Utc>) -> Option<Event> {
+    let calendar_body = email
+        .content_type
+        .as_deref()
+        .is_some_and(|content_type| content_type.eq_ignore_ascii_case("text/calendar"))
+        .then_some(email.body.as_str());
+    let event = email
+        .calendar
+        .as_deref()
+        .or(calendar_body)
+        .and_then(parse_event)?;
+    let upcoming = event
+        .start
+        .as_ref()
+        .is_some_and(|start| start.is_after(now));
+    (event.method.as_deref() == Some("REQUEST") && upcoming).then_some(event)
+}
+
+/// The VCALENDAR block of a raw message's text/calendar part, when that part
+/// isn't base64. iCalendar text anywhere else, such as quoted in a reply,
+/// doesn't count.
+pub fn extract_calendar(raw: &str) -> Option<String> {
+    let raw = raw.replace("\r\n", "\n");
+    let lines = raw.lines().collect::<Vec<_>>();
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| is_calendar_content_type(line))
+        .find_map(|(index, _)| {
+            let part_start = index
+                + lines[index..]
+                    .iter()
+                    .position(|line| line.trim().is_empty())?;
+            // Undo quoted-printable soft line breaks
+            let part = lines[part_start..]
+                .join("\n")
+                .replace("=\n", "")
+                .replace("=3D", "=");
+            let part = part.trim_start();
+            if !part.starts_with("BEGIN:VCALENDAR") {
+                return None;
+            }
+            let end = part.find("END:VCALENDAR")? + "END:VCALENDAR".len();
+            Some(part[..end].to_string())
+        })
+}
+
+fn is_calendar_content_type(line: &str) -> bool {
+    line.to_ascii_lowercase()
+        .strip_prefix("content-type:")
+        .is_some_and(|value| value.trim_start().starts_with("text/calendar"))
+}
+
+/// METHOD, and SUMMARY and DTSTART of the first VEVENT; None without one
+pub fn parse_event(ical: &str) -> Option<Event> {
+    let mut method = None;
+    let mut event: Option<Event> = None;
+    for line in unfold(ical) {
+        let Some((name, params, value)) = property(&line) else {
+            continue;
+        };
+        match (name.as_str(), event.as_mut()) {
+            ("METHOD", None) => method = Some(value.to_ascii_uppercase()),
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => {
+                event = Some(Event::default());
+            }
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => break,
+            ("SUMMARY", Some(event)) => event.summary = Some(unescape(value)),
+            ("DTSTART", Some(event)) => event.start = parse_start(params, value),
+            _ => {}
+        }
+    }
+    event.map(|event| Event { method, ..event })
+}
+
+/// Lines continue onto the next when it starts with a space or tab (RFC 5545 3.1)
+fn unfold(ical: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ical.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continued), Some(last)) => last.push_str(continued),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// "DTSTART;TZID=Europe/Paris:20250110T150000" → ("DTSTART", "TZID=Europe/Paris", "20250110T150000")
+fn property(line: &str) -> Option<(String, &str, &str)> {
+    let (head, value) = line.split_once(':')?;
+    let (name, params) = head.split_once(';').unwrap_or((head, ""));
+    Some((name.trim().to_ascii_uppercase(), params, value.trim()))
+}
+
+fn parse_start(params: &str, value: &str) -> Option<EventStart> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        let start = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some(EventStart::Utc(start.and_utc()));
+    }
+    if let Ok(start) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        let zone = params
+            .split(';')
+            .find_map(|param| param.strip_prefix("TZID="))
+            .map(|zone| zone.trim_matches('"').to_string());
+        return Some(EventStart::Local(start, zone));
+    }
+    NaiveDate::parse_from_str(value, "%Y%m%d")
+        .ok()
+        .map(EventStart::AllDay)
+}
+
+/// TEXT values escape commas, semicolons, backslashes, and newlines
+fn unescape(value: &str) -> String {
+    value
+        .replace("\\n", " ")
+        .replace("\\N", " ")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVITE: &str = "BEGIN:VCALENDAR\r\n\
+VERSION:2.0\r\n\
+METHOD:REQUEST\r\n\
+BEGIN:VTIMEZONE\r\n\
+TZID:Europe/Paris\r\n\
+END:VTIMEZONE\r\n\
+BEGIN:VEVENT\r\n\
+DTSTART;TZID=Europe/Paris:20250110T150000\r\n\
+DTEND;TZID=Europe/Paris:20250110T153000\r\n\
+SUMMARY:Quarterly review\\, budget\r\n \x20and hiring\r\n\
+ORGANIZER;CN=Alice:mailto:alice@example.com\r\n\
+END:VEVENT\r\n\
+END:VCALENDAR\r\n";
+
+    #[test]
+    fn test_parse_vevent() {
+        let event = parse_event(INVITE).unwrap();
+        assert_eq!(
+            event.summary.as_deref(),
+            Some("Quarterly review, budget and hiring")
+        );
+        assert_eq!(
+            event.describe(),
+            "Quarterly review, budget and hiring at 2025-01-10 15:00 Europe/Paris"
+        );
+
+        let utc = parse_event("BEGIN:VEVENT\nDTSTART:20250110T140000Z\nEND:VEVENT").unwrap();
+        assert_eq!(utc.describe(), "2025-01-10 14:00 UTC");
+        let all_day =
+            parse_event("BEGIN:VEVENT\nSUMMARY:Offsite\nDTSTART;VALUE=DATE:20250301\nEND:VEVENT");
+        assert_eq!(
+            all_day.unwrap().describe(),
+            "Offsite at 2025-03-01 (all day)"
+        );
+        assert_eq!(parse_event("BEGIN:VCALENDAR\nEND:VCALENDAR"), None);
+    }
+
+    #[test]
+    fn test_invite_event_from_raw_message() {
+        let raw = format!(
+            "Content-Type: text/calendar; method=REQUEST\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\r\n{}",
+            INVITE.replace("DTEND;", "DTEND=\r\n;")
+        );
+        let email = Email {
+            calendar: extract_calendar(&raw),
+            body: "Alice invited you to Quarterly review".to_string(),
+            ..Default::default()
+        };
+        assert!(is_invite(&email));
+        let event = invite_event(&email).unwrap();
+        assert!(
+            matches!(event.start, Some(EventStart::Local(_, Some(ref zone))) if zone == "Europe/Paris")
+        );
+        assert_eq!(invite_event(&Email::default()), None);
+    }
+
+    #[test]
+    fn test_calendar_quoted_in_body_is_not_extracted() {
+        let raw = format!(
+            "Content-Type: text/plain\r\n\r\nSee the invite below:\r\n\r\n{}",
+            INVITE
+        );
+        assert_eq!(extract_calendar(&raw), None);
+    }
+
+    #[test]
+    fn test_only_upcoming_requests_are_pending() {
+        let before = DateTime::parse_from_rfc3339("2025-01-09T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let after = before + Duration::days(2);
+        let invite = |calendar: &str| Email {
+            calendar: Some(calendar.to_string()),
+            ..Default::default()
+        };
+
+        let request = pending_invite(&invite(INVITE), before).unwrap();
+        assert_eq!(request.method.as_deref(), Some("REQUEST"));
+        assert_eq!(pending_invite(&invite(INVITE), after), None);
+        for method in ["REPLY", "CANCEL"] {
+            let answer = INVITE.replace("METHOD:REQUEST", &format!("METHOD:{}", method));
+            assert_eq!(pending_invite(&invite(&answer), before), None);
+        }
+        // iCalendar text in an ordinary body, or only an .ics name, isn't enough
+        let in_body = Email {
+            body: INVITE.to_string(),
+            attachment_names: vec!["invite.ics".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(pending_invite(&in_body, before), None);
+        let calendar_part = Email {
+            content_type: Some("text/calendar".to_string()),
+            ..in_body
+        };
+        assert!(pending_invite(&calendar_part, before).is_some());
+    }
+}
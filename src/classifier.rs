@@ -1,41 +1,43 @@
+use crate::calendar;
+use crate::config::{self, BodyPreview, Config, LlmConfig};
+use crate::contacts::Contacts;
+use crate::headers;
+use crate::heuristic;
+use crate::labels::canonical_label;
+use crate::llm;
 use crate::profile::Profile;
+use crate::prompt::PromptTemplate;
 use crate::providers::Email;
+use crate::sender_stats::SenderStats;
 use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::warn;
+use whatlang::Lang;
 
-const CLASSIFICATION_PROMPT: &str = r#"You are an email classifier. Analyze this email and assign appropriate labels.
-
-<profile>
-__PROFILE__
-</profile>
-
-<email>
-From: __FROM__
-To: __TO__
-Subject: __SUBJECT__
-Body: __BODY__
-</email>
-
-Classify this email:
-- is_spam: true if malicious/scam/phishing/horoscope/astrology/psychic spam, false for legitimate newsletters
-- theme: 1-5 labels describing what email is about. Examples: "Receipts" (payment confirmations AFTER charge), "Bills" (upcoming payments, auto-renewal notices, subscription charges - archive if auto-pay), "Finance", "Health", "Shopping", "Travel", "Work", "Personal", "Social", "Security", "Gaming", "Shipping", "Updates", "Account", "Home" (smart home alerts, leak sensors, thermostat, security cameras)
-- action: 0+ labels for what to do. Options:
-  - "Newsletters" - regular subscription content you signed up for
-  - "Promotional" - ads, sales, marketing, webinar invites from companies (auto-delete)
-  - "Survey" - feedback requests, satisfaction surveys, NPS scores (auto-archive)
-  - "Needs-Reply" - expects a response from you (questions, requests, invitations). Archive unless reply needed today/tomorrow
-  - "Important" - requires your attention today
-  - "Urgent" - time-sensitive, needs immediate attention (security alerts are always Urgent)
-  - "Awaiting-Reply" - you sent something and are waiting for response, no action needed now (auto-archive)
-  - "Group-Thread" - group thread/discussion where you're CC'd (auto-archive)
-  - "Other" - doesn't fit other categories (auto-archive)
-- archive: true if email doesn't need to stay in inbox (Newsletters, Survey, Awaiting-Reply, Group-Thread, Other, Updates, Needs-Reply without urgency, Bills without Needs-Reply, receipts under $500, account notifications without action required). NEVER archive Security emails
-- delete: true if is_spam OR Promotional OR expired calendar invites (date in the past) OR matches auto-delete rules in profile (including language rules). CHECK THE TO FIELD - if email is TO a work address listed in Auto-Delete Rules, set delete=true. NEVER delete Personal emails, Needs-Reply emails, or emails from personal contacts. "Personal" means from someone you know, NOT spam with your name in it
-
-Respond with JSON only:
-{{"is_spam": false, "theme": ["Finance"], "action": ["Important"], "archive": false, "delete": false, "confidence": 0.8}}"#;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Subject-only classifications are trusted this much less than full ones
+const SUBJECT_ONLY_CONFIDENCE_FACTOR: f32 = 0.8;
+/// Subject-only classifications the model is less sure of than this (before
+/// the discount) are never deleted
+const SUBJECT_ONLY_DELETE_CONFIDENCE: f32 = 0.9;
+const SUBJECT_ONLY_BODY: &str =
+    "(not provided - classify from sender and subject only, and be cautious with delete)";
+const EMPTY_BODY: &str =
+    "(empty - no readable text; classify from sender and subject only, and be cautious with delete)";
+/// Calendar invites are classified without the LLM, with this confidence
+const CALENDAR_INVITE_CONFIDENCE: f32 = 0.7;
+/// Classification timeout unless `llm.timeout_secs` is set
+const CLASSIFY_TIMEOUT: Duration = Duration::from_secs(120);
+/// Timeout for translating a foreign body, unless `llm.timeout_secs` is set
+const TRANSLATE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How much of a malformed response is quoted back when asking for a repair
+const REPAIR_QUOTE_CHARS: usize = 4000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Classification {
     pub is_spam: bool,
     #[serde(default)]
@@ -49,80 +51,415 @@ pub struct Classification {
     #[serde(default)]
     pub action: Vec<String>,
     pub confidence: f32,
+    /// One-line justification from the model, shown in spot checks
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Made by the offline heuristics because the LLM failed. Such emails get
+    /// their labels but no Classified stamp or prediction, so the next scan
+    /// asks the LLM again.
+    #[serde(skip)]
+    pub heuristic: bool,
 }
 
 impl Classification {
-    /// Combined labels for backward compatibility
+    /// JSON schema of a classification, for schema-constrained responses
+    pub fn schema() -> serde_json::Value {
+        serde_json::to_value(schemars::schema_for!(Classification))
+            .expect("a derived schema always serializes")
+    }
+
+    /// Whether the actions call for attention (Important or Urgent)
+    pub fn is_important(&self) -> bool {
+        is_important_label_set(&self.action)
+    }
+
+    /// Combined theme and action labels, without case-insensitive repeats
     pub fn labels(&self) -> Vec<String> {
-        self.theme
-            .iter()
-            .chain(self.action.iter())
-            .cloned()
-            .collect()
+        dedup_labels(self.theme.iter().chain(self.action.iter()).cloned())
     }
 }
 
-/// Response event from `claude --output-format json`
-#[derive(Debug, Deserialize)]
-struct ClaudeEvent {
-    #[serde(rename = "type")]
-    event_type: String,
-    #[serde(default)]
-    result: Option<String>,
+/// Whether `labels` include Important or Urgent
+pub fn is_important_label_set(labels: &[String]) -> bool {
+    labels
+        .iter()
+        .any(|label| label == "Important" || label == "Urgent")
+}
+
+/// Answers a freeform classification prompt with the model's text
+#[async_trait]
+pub trait ClassifyBackend: Send + Sync {
+    async fn respond(&self, prompt: &str) -> Result<String>;
+}
+
+/// The claude CLI with the configured classification model and timeout
+struct ClaudeClassifyBackend {
+    model: String,
+    timeout: Duration,
+}
+
+#[async_trait]
+impl ClassifyBackend for ClaudeClassifyBackend {
+    async fn respond(&self, prompt: &str) -> Result<String> {
+        llm::run(prompt, &self.model, self.timeout)
+            .await
+            .context("Classification failed")
+    }
 }
 
 pub struct Classifier<'a> {
     profile: &'a Profile,
+    config: &'a Config,
+    max_body_chars: usize,
+    subject_only: bool,
+    contacts: Option<&'a Contacts>,
+    sender_stats: Option<&'a SenderStats>,
+    /// Custom prompt from prompt.tmpl; the built-in one when unset
+    template: Option<&'a PromptTemplate>,
+    llm: LlmConfig,
+    /// Set once the CLI turns out not to support structured output, so the
+    /// rest of the run goes straight to prompt-and-extract
+    structured_unavailable: AtomicBool,
+    backend: Box<dyn ClassifyBackend + 'a>,
 }
 
 impl<'a> Classifier<'a> {
-    pub fn new(profile: &'a Profile) -> Self {
-        Self { profile }
+    pub fn new(profile: &'a Profile, config: &'a Config) -> Self {
+        let llm = config.llm();
+        let backend = ClaudeClassifyBackend {
+            model: llm.classify_model().to_string(),
+            timeout: llm.timeout(CLASSIFY_TIMEOUT),
+        };
+        Self {
+            profile,
+            config,
+            max_body_chars: config::body_preview_chars(BodyPreview::Classify),
+            subject_only: false,
+            contacts: None,
+            sender_stats: None,
+            template: None,
+            llm,
+            structured_unavailable: AtomicBool::new(false),
+            backend: Box::new(backend),
+        }
+    }
+
+    /// Quote at most `chars` of each body, instead of this run's limit
+    #[cfg(test)]
+    pub fn max_body_chars(mut self, chars: usize) -> Self {
+        self.max_body_chars = chars;
+        self
+    }
+
+    /// Answer freeform prompts from `backend` instead of the claude CLI
+    #[cfg(test)]
+    pub fn backend(mut self, backend: impl ClassifyBackend + 'a) -> Self {
+        self.backend = Box::new(backend);
+        self
+    }
+
+    /// Classify from sender and subject alone: far fewer tokens per email, at
+    /// the cost of lower confidence and no deletes unless the model is sure
+    pub fn subject_only(mut self, subject_only: bool) -> Self {
+        self.subject_only = subject_only;
+        self
+    }
+
+    /// Tell the model whether each sender is in the user's contacts
+    pub fn known_contacts(mut self, contacts: &'a Contacts) -> Self {
+        self.contacts = Some(contacts);
+        self
+    }
+
+    /// Tell the model how each sender's earlier emails were labeled
+    pub fn sender_stats(mut self, sender_stats: &'a SenderStats) -> Self {
+        self.sender_stats = Some(sender_stats);
+        self
+    }
+
+    /// Classify with a custom prompt (prompt.tmpl) instead of the built-in one
+    pub fn prompt_template(mut self, template: &'a PromptTemplate) -> Self {
+        self.template = Some(template);
+        self
     }
 
     pub async fn classify(&self, email: &Email) -> Result<Classification> {
-        let prompt = self.build_prompt(email);
-        let output = claude_safe::call(&prompt, "opus", "json")
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to call claude: {}", e))?;
-        let result_text = parse_result_text(&output)?;
-        let json_str = extract_json(&result_text)?;
-        let mut classification: Classification =
-            serde_json::from_str(&json_str).context("Failed to parse classification response")?;
+        if let Some(event) = calendar::pending_invite(email, Utc::now()) {
+            return Ok(calendar_invite_classification(&event));
+        }
+
+        let prompt = self.prompt_for(email).await;
+        let result = match self.classify_structured(&prompt).await {
+            Some(result) => result,
+            None => self.classify_freeform(&prompt).await,
+        };
+        let mut classification = match result {
+            Ok(classification) => classification,
+            Err(error) => {
+                warn!(
+                    email = %email.id,
+                    "LLM classification failed ({:#}); using offline heuristics",
+                    error
+                );
+                self.classify_heuristic(email)
+            }
+        };
         normalize_classification(&mut classification);
+        if self.config.list_id_newsletters() {
+            apply_list_id_bias(email, &mut classification);
+        }
+        if self.subject_only || has_empty_body(email) {
+            apply_subject_only_caution(&mut classification);
+        }
         Ok(classification)
     }
 
-    fn build_prompt(&self, email: &Email) -> String {
-        let body_preview: String = email.body.chars().take(1000).collect();
+    /// Keyword, sender, and profile-rule classification that needs no LLM,
+    /// at low confidence and without archiving or deleting anything
+    pub fn classify_heuristic(&self, email: &Email) -> Classification {
+        heuristic::classify(self.profile, email)
+    }
+
+    /// Schema-constrained classification, when enabled and supported. None
+    /// sends the prompt freeform instead; any other failure is returned
+    /// as is, so one email never costs two calls.
+    async fn classify_structured(&self, prompt: &str) -> Option<Result<Classification>> {
+        if !self.llm.structured_output() || self.structured_unavailable.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let result = llm::structured(
+            prompt,
+            self.llm.classify_model(),
+            &Classification::schema(),
+            self.llm.timeout(CLASSIFY_TIMEOUT),
+        )
+        .await
+        .and_then(|value| {
+            serde_json::from_value(value).context("Structured output doesn't match the schema")
+        });
+        match result {
+            Err(error) if llm::SchemaUnsupported::is(&error) => {
+                warn!("{:#}; using JSON extraction", error);
+                self.structured_unavailable.store(true, Ordering::Relaxed);
+                None
+            }
+            result => Some(result),
+        }
+    }
+
+    /// Prompt-and-extract, with one repair request when the answer isn't a
+    /// valid classification
+    async fn classify_freeform(&self, prompt: &str) -> Result<Classification> {
+        let response = self.backend.respond(prompt).await?;
+        let error = match parse_classification(&response) {
+            Ok(classification) => return Ok(classification),
+            Err(error) => error,
+        };
+
+        warn!(
+            "malformed classification ({:#}); asking for a repair",
+            error
+        );
+        llm::record_repair();
+        let repaired = self.backend.respond(&repair_prompt(&response)).await?;
+        parse_classification(&repaired).context("Repaired classification is still malformed")
+    }
+
+    async fn prompt_for(&self, email: &Email) -> String {
+        if self.subject_only {
+            let language = detect_subject_language(email);
+            return self.build_prompt(email, SUBJECT_ONLY_BODY, language);
+        }
+        if has_empty_body(email) {
+            let language = detect_subject_language(email);
+            return self.build_prompt(email, EMPTY_BODY, language);
+        }
+
+        let language = detect_language(email);
+        let body_preview = self.body_preview(email, language).await;
+        self.build_prompt(email, &body_preview, language)
+    }
+
+    async fn body_preview(&self, email: &Email, language: Option<Lang>) -> String {
+        let body_preview: String = email.body.chars().take(self.max_body_chars).collect();
+        let is_foreign = language.is_some_and(|lang| lang != Lang::Eng);
+        if !is_foreign || !self.config.translate_non_english() {
+            return body_preview;
+        }
+
+        match self.translate_to_english(&body_preview).await {
+            Ok(translated) => translated,
+            Err(error) => {
+                warn!("translation failed: {}", error);
+                body_preview
+            }
+        }
+    }
+
+    /// Translated with the classification model, within the configured timeout
+    async fn translate_to_english(&self, text: &str) -> Result<String> {
+        let prompt = format!(
+            "Translate this email text to English. Output only the translation.\n\n{}",
+            text
+        );
+        llm::run(
+            &prompt,
+            self.llm.classify_model(),
+            self.llm.timeout(TRANSLATE_TIMEOUT),
+        )
+        .await
+        .context("Translation failed")
+    }
 
-        CLASSIFICATION_PROMPT
-            .replace("__PROFILE__", self.profile.content())
-            .replace("__FROM__", &email.from)
-            .replace("__TO__", &email.to)
-            .replace("__SUBJECT__", &email.subject)
-            .replace("__BODY__", &body_preview)
+    /// "yes"/"no" from the contacts list; "unknown" when there is none to go by
+    fn known_contact_description(&self, email: &Email) -> &'static str {
+        match self.contacts.filter(|contacts| !contacts.is_empty()) {
+            Some(contacts) if contacts.is_known(&email.from) => "yes",
+            Some(_) => "no",
+            None => "unknown",
+        }
+    }
+
+    fn build_prompt(&self, email: &Email, body_preview: &str, language: Option<Lang>) -> String {
+        let sender_history = self
+            .sender_stats
+            .and_then(|stats| stats.hint(&email.from))
+            .unwrap_or_else(|| "none".to_string());
+        let header_signals = if self.config.header_signals() {
+            headers::summary(email).unwrap_or_else(|| "unavailable".to_string())
+        } else {
+            "not checked".to_string()
+        };
+        let values = [
+            ("profile", self.profile.content()),
+            ("from", &email.from),
+            ("to", &email.to),
+            ("subject", &email.subject),
+            ("list_id", email.list_id.as_deref().unwrap_or("none")),
+            ("known_contact", self.known_contact_description(email)),
+            ("sender_history", &sender_history),
+            ("header_signals", &header_signals),
+            ("folder", folder_description(email)),
+            ("language", language_name(language)),
+            ("label_language", self.config.label_language()),
+            ("body", body_preview),
+        ];
+        match self.template {
+            Some(template) => template.render(&values),
+            None => PromptTemplate::default().render(&values),
+        }
     }
 }
 
-fn capitalize_first(s: &str) -> String {
-    let mut chars = s.chars();
-    match chars.next() {
-        None => String::new(),
-        Some(c) => c.to_uppercase().chain(chars).collect(),
+/// Where the email currently sits, from the provider's real labels
+fn folder_description(email: &Email) -> &'static str {
+    let has = |name: &str| email.labels.iter().any(|label| label == name);
+    if has("INBOX") {
+        "Inbox"
+    } else if has("SPAM") {
+        "Spam"
+    } else if has("TRASH") {
+        "Trash"
+    } else {
+        "Archived (not in the inbox)"
     }
 }
 
-fn parse_result_text(output: &str) -> Result<String> {
-    let events: Vec<ClaudeEvent> =
-        serde_json::from_str(output).context("Failed to parse claude response events")?;
+/// Pure-HTML or image-only mail can strip down to nothing
+fn has_empty_body(email: &Email) -> bool {
+    email.body.trim().is_empty()
+}
+
+/// Invitations expect an answer and stay in the inbox until given one
+fn calendar_invite_classification(event: &calendar::Event) -> Classification {
+    let reason = format!(
+        "Calendar invite: {} (classified without the LLM)",
+        event.describe()
+    );
+    Classification {
+        is_spam: false,
+        archive: false,
+        delete: false,
+        theme: vec!["Calendar".to_string()],
+        action: vec!["Needs-Reply".to_string()],
+        confidence: CALENDAR_INVITE_CONFIDENCE,
+        reason: Some(reason),
+        heuristic: false,
+    }
+}
 
-    events
+/// Detect the email's language from its body, falling back to the subject.
+fn detect_language(email: &Email) -> Option<Lang> {
+    let text = if email.body.trim().is_empty() {
+        &email.subject
+    } else {
+        &email.body
+    };
+
+    whatlang::detect(text)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang())
+}
+
+fn detect_subject_language(email: &Email) -> Option<Lang> {
+    whatlang::detect(&email.subject)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang())
+}
+
+fn language_name(language: Option<Lang>) -> &'static str {
+    language.map(|lang| lang.eng_name()).unwrap_or("Unknown")
+}
+
+/// Mailing-list mail is a newsletter regardless of how the model read the body.
+/// It's archived unless the model also found something to act on.
+fn apply_list_id_bias(email: &Email, classification: &mut Classification) {
+    if email.list_id.is_none() || classification.is_spam {
+        return;
+    }
+
+    if !classification
+        .action
         .iter()
-        .rev()
-        .find(|e| e.event_type == "result")
-        .and_then(|e| e.result.clone())
-        .context("No result found in claude response")
+        .any(|action| action == "Newsletters")
+    {
+        classification.action.push("Newsletters".to_string());
+    }
+    let needs_attention = classification
+        .action
+        .iter()
+        .any(|action| matches!(action.as_str(), "Needs-Reply" | "Important" | "Urgent"));
+    if !needs_attention {
+        classification.archive = true;
+    }
+}
+
+/// Without the body the model is guessing more: keep the email unless the
+/// model itself was sure enough to delete, then discount its confidence
+fn apply_subject_only_caution(classification: &mut Classification) {
+    if classification.confidence < SUBJECT_ONLY_DELETE_CONFIDENCE {
+        classification.delete = false;
+    }
+    classification.confidence *= SUBJECT_ONLY_CONFIDENCE_FACTOR;
+}
+
+fn parse_classification(text: &str) -> Result<Classification> {
+    let json_str = extract_json(text)?;
+    serde_json::from_str(&json_str).context("Failed to parse classification response")
+}
+
+/// Quote the malformed answer back with the schema it must follow
+fn repair_prompt(malformed: &str) -> String {
+    let quoted: String = malformed.chars().take(REPAIR_QUOTE_CHARS).collect();
+    let schema = serde_json::to_string_pretty(&Classification::schema()).unwrap_or_default();
+    format!(
+        "This email classification answer is not valid JSON in the expected shape:\n\n\
+         <answer>\n{}\n</answer>\n\n\
+         Return ONLY valid JSON matching this schema, with the same classification \
+         and no other text:\n{}",
+        quoted, schema
+    )
 }
 
 fn normalize_classification(classification: &mut Classification) {
@@ -131,70 +468,101 @@ fn normalize_classification(classification: &mut Classification) {
 }
 
 fn normalize_labels(labels: Vec<String>) -> Vec<String> {
+    dedup_labels(
+        labels
+            .into_iter()
+            .filter(|label| !label.eq_ignore_ascii_case("Classified"))
+            .map(|label| canonical_label(&label))
+            .filter(|label| !label.is_empty()),
+    )
+}
+
+/// Keep the first spelling of each label, comparing case-insensitively
+fn dedup_labels(labels: impl Iterator<Item = String>) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
     labels
-        .into_iter()
-        .filter(|label| !label.eq_ignore_ascii_case("Classified"))
-        .map(|label| capitalize_first(&label))
+        .filter(|label| seen.insert(label.to_lowercase()))
         .collect()
 }
 
+/// Find the classification object in a model response. Models sometimes
+/// wrap it in prose that has braces of its own, so a fenced ```json block
+/// wins, then the first balanced `{...}` that parses as a Classification.
 fn extract_json(text: &str) -> Result<String> {
     let text = text.trim();
+    let fenced = extract_fenced_json(text);
 
-    if let Some(json) = extract_leading_json_object(text) {
-        return Ok(json);
-    }
-    if let Some(json) = extract_fenced_json(text) {
-        return Ok(json);
-    }
-    if let Some(json) = extract_braced_json(text) {
-        return Ok(json);
-    }
-    anyhow::bail!("Could not find JSON in response: {}", text)
-}
-
-fn extract_leading_json_object(text: &str) -> Option<String> {
-    let end = json_object_end(text)?;
-    Some(text[..end].to_string())
+    fenced
+        .into_iter()
+        .chain(json_object_candidates(text))
+        .find(|candidate| serde_json::from_str::<Classification>(candidate).is_ok())
+        .map(str::to_string)
+        .with_context(|| format!("Could not find JSON in response: {}", text))
 }
 
-fn extract_fenced_json(text: &str) -> Option<String> {
+fn extract_fenced_json(text: &str) -> Option<&str> {
     let start = text.find("```json")?;
     let fenced = &text[start + 7..];
     let end = fenced.find("```")?;
-    Some(fenced[..end].trim().to_string())
+    Some(fenced[..end].trim())
 }
 
-fn extract_braced_json(text: &str) -> Option<String> {
-    let start = text.find('{')?;
-    let end = text.rfind('}')?;
-    (end > start).then(|| text[start..=end].to_string())
+/// Every balanced `{...}` in the text, outermost first, in order of position
+fn json_object_candidates(text: &str) -> impl Iterator<Item = &str> {
+    text.char_indices()
+        .filter(|(_, ch)| *ch == '{')
+        .filter_map(move |(start, _)| {
+            json_object_end(&text[start..]).map(|end| &text[start..start + end])
+        })
 }
 
+/// Byte length of the object opening `text`, skipping braces inside strings
 fn json_object_end(text: &str) -> Option<usize> {
-    if !text.starts_with('{') {
-        return None;
-    }
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
 
-    let mut depth = 0;
-    text.char_indices().find_map(|(index, ch)| {
-        depth = next_json_depth(depth, ch)?;
-        (depth == 0).then_some(index + ch.len_utf8())
-    })
-}
+    for (index, ch) in text.char_indices() {
+        if in_string {
+            match ch {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
 
-fn next_json_depth(depth: usize, ch: char) -> Option<usize> {
-    match ch {
-        '{' => Some(depth + 1),
-        '}' => depth.checked_sub(1),
-        _ => Some(depth),
+        match ch {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(index + 1);
+                }
+            }
+            _ => {}
+        }
     }
+    None
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn make_email(subject: &str, body: &str) -> Email {
+        Email {
+            id: "test123".to_string(),
+            from: "sender@example.com".to_string(),
+            to: "me@example.com".to_string(),
+            subject: subject.to_string(),
+            body: body.to_string(),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_extract_json_direct() {
         let json =
@@ -202,6 +570,80 @@ mod tests {
         assert_eq!(extract_json(json).unwrap(), json);
     }
 
+    #[test]
+    fn test_detected_language_reaches_prompt() {
+        let profile = Profile::from_content("# Email Classification Profile");
+        let config = Config::default();
+        let classifier = Classifier::new(&profile, &config);
+        let email = make_email(
+            "Confirmación de su pedido",
+            "Hola, gracias por su compra. Su pedido ha sido enviado y llegará en los próximos días. Puede seguir el envío desde su cuenta.",
+        );
+
+        let language = detect_language(&email);
+        let prompt = classifier.build_prompt(&email, &email.body, language);
+
+        assert_eq!(language, Some(Lang::Spa));
+        assert!(prompt.contains("Language: Spanish"));
+        assert!(prompt.contains("Write theme labels in English"));
+    }
+
+    #[test]
+    fn test_extract_json_skips_braces_in_prose() {
+        let text = r#"Here's the result: {"is_spam": false, "theme": ["Work"], "action": [], "confidence": 0.8, "reason": "Mentions {project} plans"}. Note that {nested} placeholders were ignored."#;
+        let json = extract_json(text).unwrap();
+        assert!(json.starts_with(r#"{"is_spam": false"#));
+        assert!(json.ends_with(r#"plans"}"#));
+
+        let text = r#"Format is {"theme": [...]} as asked. {"is_spam": true, "confidence": 0.95}"#;
+        assert_eq!(
+            extract_json(text).unwrap(),
+            r#"{"is_spam": true, "confidence": 0.95}"#
+        );
+    }
+
+    #[test]
+    fn test_extract_json_prefers_fenced_block() {
+        let text = "Example: {\"is_spam\": true, \"confidence\": 0.1}\n```json\n{\"is_spam\": false, \"confidence\": 0.9}\n```";
+        assert_eq!(
+            extract_json(text).unwrap(),
+            r#"{"is_spam": false, "confidence": 0.9}"#
+        );
+        assert!(extract_json("No JSON {here} at all").is_err());
+    }
+
+    #[test]
+    fn test_labels_are_deduplicated_case_insensitively() {
+        let mut classification = Classification {
+            is_spam: false,
+            archive: false,
+            delete: false,
+            theme: vec!["finance".into(), "Finance".into(), "Shopping".into()],
+            action: vec!["FINANCE".into(), "Important".into()],
+            confidence: 0.9,
+            reason: None,
+            heuristic: false,
+        };
+        assert_eq!(
+            classification.labels(),
+            vec!["finance", "Shopping", "Important"]
+        );
+
+        normalize_classification(&mut classification);
+        assert_eq!(classification.theme, vec!["Finance", "Shopping"]);
+        assert_eq!(classification.action, vec!["FINANCE", "Important"]);
+    }
+
+    #[test]
+    fn test_schema_requires_core_fields() {
+        let schema = Classification::schema();
+        let required = schema["required"].as_array().unwrap();
+        for field in ["is_spam", "confidence"] {
+            assert!(required.iter().any(|name| name == field), "{}", field);
+        }
+        assert!(schema["properties"]["theme"].is_object());
+    }
+
     #[test]
     fn test_extract_json_with_whitespace() {
         let text = r#"
@@ -209,4 +651,306 @@ mod tests {
         "#;
         assert!(extract_json(text).unwrap().contains("is_spam"));
     }
+
+    #[tokio::test]
+    async fn test_max_body_chars_override_changes_prompt_body() {
+        let profile = Profile::from_content("# Email Classification Profile");
+        let config = Config::default();
+        let email = make_email("Report", &"word ".repeat(400));
+        let default_body = Classifier::new(&profile, &config)
+            .body_preview(&email, None)
+            .await;
+        let classifier = Classifier::new(&profile, &config).max_body_chars(50);
+        let override_body = classifier.body_preview(&email, None).await;
+
+        assert_eq!(
+            default_body.chars().count(),
+            BodyPreview::Classify.default_chars()
+        );
+        assert_eq!(override_body.chars().count(), 50);
+        assert!(classifier
+            .build_prompt(&email, &override_body, None)
+            .contains(&format!("{}\n", override_body)));
+    }
+
+    #[test]
+    fn test_list_id_email_treated_as_newsletter() {
+        let mut email = make_email("Weekly digest", "This week in Rust...");
+        email.list_id = Some("<this-week.rust-lang.org>".to_string());
+        let mut classification = Classification {
+            is_spam: false,
+            archive: false,
+            delete: false,
+            theme: vec!["Updates".to_string()],
+            action: vec![],
+            confidence: 0.7,
+            reason: None,
+            heuristic: false,
+        };
+
+        apply_list_id_bias(&email, &mut classification);
+
+        assert_eq!(classification.action, vec!["Newsletters"]);
+        assert!(classification.archive);
+
+        let profile = Profile::from_content("# Email Classification Profile");
+        let config = Config::default();
+        let prompt = Classifier::new(&profile, &config).build_prompt(&email, &email.body, None);
+        assert!(prompt.contains("Mailing list: <this-week.rust-lang.org>"));
+    }
+
+    #[test]
+    fn test_prompt_reports_folder() {
+        let profile = Profile::from_content("# Email Classification Profile");
+        let config = Config::default();
+        let classifier = Classifier::new(&profile, &config);
+        let mut email = make_email("Receipt", "Thanks for your order");
+
+        let prompt = classifier.build_prompt(&email, &email.body, None);
+        assert!(prompt.contains("Folder: Archived (not in the inbox)"));
+
+        email.labels = vec!["INBOX".to_string(), "UNREAD".to_string()];
+        let prompt = classifier.build_prompt(&email, &email.body, None);
+        assert!(prompt.contains("Folder: Inbox"));
+    }
+
+    #[test]
+    fn test_custom_template_replaces_builtin_prompt() {
+        let profile = Profile::from_content("Work: acme.example");
+        let config = Config::default();
+        let email = make_email("Standup moved", "Now at 10");
+        let template =
+            PromptTemplate::new("{profile}\n{from}: {subject}\n{body}\nLabels: Work, Home");
+        let prompt = Classifier::new(&profile, &config)
+            .prompt_template(&template)
+            .build_prompt(&email, &email.body, None);
+        assert_eq!(
+            prompt,
+            "Work: acme.example\nsender@example.com: Standup moved\nNow at 10\nLabels: Work, Home"
+        );
+
+        let builtin = Classifier::new(&profile, &config).build_prompt(&email, &email.body, None);
+        assert!(builtin.contains("Subject: Standup moved"));
+        assert!(builtin.contains(r#"{"is_spam": false"#));
+    }
+
+    #[test]
+    fn test_prompt_reports_known_contact() {
+        let profile = Profile::from_content("# Email Classification Profile");
+        let config = Config::default();
+        let email = make_email("Dinner Friday?", "Are you free?");
+        let prompt = Classifier::new(&profile, &config).build_prompt(&email, &email.body, None);
+        assert!(prompt.contains("Known contact: unknown"));
+
+        let contacts = Contacts::from_entries(["Sam <sender@example.com>".to_string()]);
+        let classifier = Classifier::new(&profile, &config).known_contacts(&contacts);
+        let prompt = classifier.build_prompt(&email, &email.body, None);
+        assert!(prompt.contains("Known contact: yes"));
+
+        let stranger = Email {
+            from: "deals@shop.example".to_string(),
+            ..email
+        };
+        let prompt = classifier.build_prompt(&stranger, &stranger.body, None);
+        assert!(prompt.contains("Known contact: no"));
+    }
+
+    #[test]
+    fn test_prompt_includes_sender_history() {
+        let profile = Profile::from_content("# Email Classification Profile");
+        let config = Config::default();
+        let email = make_email("This week in Rust", "Issue 500");
+        let prompt = Classifier::new(&profile, &config).build_prompt(&email, &email.body, None);
+        assert!(prompt.contains("Sender history: none"));
+
+        let mut stats = SenderStats::default();
+        stats.record(&email.from, false, &["Newsletters".to_string()]);
+        let prompt = Classifier::new(&profile, &config)
+            .sender_stats(&stats)
+            .build_prompt(&email, &email.body, None);
+        assert!(prompt.contains("Sender history: labeled Newsletters once (1 email; never spam)"));
+    }
+
+    #[test]
+    fn test_header_signals_are_opt_in() {
+        let profile = Profile::from_content("# Email Classification Profile");
+        let email = Email {
+            headers: [("Reply-To".to_string(), "claims@prize.example".to_string())].into(),
+            ..make_email("You won", "Claim your prize")
+        };
+
+        let config = Config::default();
+        let prompt = Classifier::new(&profile, &config).build_prompt(&email, &email.body, None);
+        assert!(prompt.contains("Header signals: not checked"));
+
+        let config = Config {
+            header_signals: Some(true),
+            ..Config::default()
+        };
+        let prompt = Classifier::new(&profile, &config).build_prompt(&email, &email.body, None);
+        assert!(
+            prompt.contains("Header signals: Reply-To domain prize.example differs from From\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_empty_body_falls_back_to_sender_and_subject() {
+        let profile = Profile::from_content("# Email Classification Profile");
+        let config = Config::default();
+        let classifier = Classifier::new(&profile, &config);
+        let email = make_email("Spring sale: 40% off everything", " \n\t ");
+
+        let prompt = classifier.prompt_for(&email).await;
+
+        assert!(prompt.contains("Subject: Spring sale: 40% off everything"));
+        assert!(prompt.contains("From: sender@example.com"));
+        assert!(prompt.contains(&format!("Body: {}", EMPTY_BODY)));
+    }
+
+    /// Answers with canned responses in order, recording the prompts
+    #[derive(Default)]
+    struct ScriptedBackend {
+        responses: std::sync::Mutex<Vec<&'static str>>,
+        prompts: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl ScriptedBackend {
+        fn new(mut responses: Vec<&'static str>) -> Self {
+            responses.reverse();
+            Self {
+                responses: std::sync::Mutex::new(responses),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ClassifyBackend for &ScriptedBackend {
+        async fn respond(&self, prompt: &str) -> Result<String> {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            self.responses
+                .lock()
+                .unwrap()
+                .pop()
+                .map(str::to_string)
+                .context("no more responses")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_malformed_json_is_repaired_once() {
+        let profile = Profile::from_content("# Email Classification Profile");
+        let config = Config::default();
+        let email = make_email("Your invoice", "Invoice #42 is due on Friday.");
+
+        let backend = ScriptedBackend::new(vec![
+            r#"Sure! {"is_spam": false, "theme": ["Finance"], "confidence": 0.9,}"#,
+            r#"{"is_spam": false, "theme": ["Finance"], "action": ["Important"], "confidence": 0.9}"#,
+        ]);
+        let classifier = Classifier::new(&profile, &config).backend(&backend);
+        let classification = classifier.classify(&email).await.unwrap();
+        assert_eq!(classification.theme, vec!["Finance"]);
+        assert_eq!(classification.action, vec!["Important"]);
+        let prompts = backend.prompts.lock().unwrap().clone();
+        assert_eq!(prompts.len(), 2);
+        assert!(prompts[1].contains("Return ONLY valid JSON"));
+        assert!(prompts[1].contains(r#""confidence": 0.9,}"#));
+
+        // A second bad answer falls back to the offline heuristics
+        let backend = ScriptedBackend::new(vec!["not json", "still not json"]);
+        let classifier = Classifier::new(&profile, &config).backend(&backend);
+        let classification = classifier.classify(&email).await.unwrap();
+        assert_eq!(backend.prompts.lock().unwrap().len(), 2);
+        let heuristic = classifier.classify_heuristic(&email);
+        assert_eq!(classification.confidence, heuristic.confidence);
+        assert!(!classification.archive && !classification.delete);
+    }
+
+    #[tokio::test]
+    async fn test_calendar_invite_skips_llm() {
+        let profile = Profile::from_content("# Email Classification Profile");
+        let config = Config::default();
+        let invite = |method: &str, start: &str| {
+            Email {
+            calendar: Some(format!(
+                "BEGIN:VCALENDAR\nMETHOD:{}\nBEGIN:VEVENT\nSUMMARY:Planning\nDTSTART:{}\nEND:VEVENT\nEND:VCALENDAR",
+                method, start
+            )),
+            ..make_email("Invitation: Planning", "Alice invited you")
+        }
+        };
+
+        // No responses are scripted, so a result from the backend is impossible
+        let backend = ScriptedBackend::new(vec![]);
+        let classifier = Classifier::new(&profile, &config).backend(&backend);
+        let planning = classifier
+            .classify(&invite("REQUEST", "20990110T140000Z"))
+            .await
+            .unwrap();
+        assert_eq!(planning.theme, vec!["Calendar"]);
+        assert_eq!(planning.action, vec!["Needs-Reply"]);
+        assert!(!planning.delete);
+        assert_eq!(
+            planning.reason.as_deref(),
+            Some("Calendar invite: Planning at 2099-01-10 14:00 UTC (classified without the LLM)")
+        );
+        assert!(backend.prompts.lock().unwrap().is_empty());
+
+        let reply = invite("REPLY", "20990110T140000Z");
+        let past = invite("REQUEST", "20200110T140000Z");
+        let quoted = make_email(
+            "Re: Planning",
+            "BEGIN:VCALENDAR\nMETHOD:REQUEST\nBEGIN:VEVENT\nDTSTART:20990110T140000Z\nEND:VEVENT\nEND:VCALENDAR",
+        );
+        for email in [reply, past, quoted] {
+            let classification = classifier.classify(&email).await.unwrap();
+            assert!(classification.heuristic, "{:?}", email.subject);
+        }
+        assert_eq!(backend.prompts.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_subject_only_omits_body() {
+        let profile = Profile::from_content("# Email Classification Profile");
+        let config = Config::default();
+        let classifier = Classifier::new(&profile, &config).subject_only(true);
+        let email = make_email("Your invoice is ready", "SECRET-BODY-MARKER amount due");
+
+        let prompt = classifier.prompt_for(&email).await;
+
+        assert!(prompt.contains("Subject: Your invoice is ready"));
+        assert!(prompt.contains("From: sender@example.com"));
+        assert!(!prompt.contains("SECRET-BODY-MARKER"));
+
+        let mut classification = Classification {
+            is_spam: false,
+            archive: true,
+            delete: true,
+            theme: vec!["Shopping".to_string()],
+            action: vec!["Promotional".to_string()],
+            confidence: 0.85,
+            reason: None,
+            heuristic: false,
+        };
+        apply_subject_only_caution(&mut classification);
+        assert!(!classification.delete);
+        assert!(classification.confidence < 0.85);
+    }
+
+    #[test]
+    fn test_subject_only_still_deletes_when_model_is_sure() {
+        let mut classification = Classification {
+            is_spam: true,
+            archive: false,
+            delete: true,
+            theme: vec![],
+            action: vec![],
+            confidence: 0.97,
+            reason: None,
+            heuristic: false,
+        };
+        apply_subject_only_caution(&mut classification);
+        assert!(classification.delete);
+        assert!((classification.confidence - 0.97 * SUBJECT_ONLY_CONFIDENCE_FACTOR).abs() < 1e-6);
+    }
 }
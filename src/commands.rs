@@ -1,63 +1,538 @@
+mod dedupe;
+mod digest;
+mod export;
 mod support;
 
-use crate::classifier::{Classification, Classifier};
-use crate::config::Config;
-use crate::labels::LabelManager;
-use crate::learning::{is_system_label, Correction, LearningEngine};
-use crate::predictions::PredictionStore;
-use crate::profile::Profile;
+pub use digest::SummaryFormat;
+pub use export::ExportFormat;
+
+use crate::calendar;
+use crate::classifier::{is_important_label_set, Classification, Classifier};
+use crate::config::{self, Config, DeleteMode, LlmConfig};
+use crate::contacts::Contacts;
+use crate::corrections::CorrectionLog;
+use crate::filters::{
+    filter_for_rule, merge_gmail_import, rules_from_gmail_filters, FilterSpec, FilterStore,
+};
+use crate::labels::{
+    fetch_label_counts, LabelColor, LabelCountCache, LabelManager, LABEL_PALETTE, LABEL_SEPARATOR,
+};
+use crate::learning::{
+    compact_profile, is_system_label, replay_corrections, verify_predictions, Accuracy,
+    ClaudeBackend, Correction, Drift, LearningBackend, LearningEngine, ReplayBackend,
+    CORRECTION_BATCH_SIZE,
+};
+use crate::llm::{self, LlmStats};
+use crate::notify::Notifier;
+use crate::output::{self, note, Formatter, Renderable, Rows};
+use crate::policy::{normalize_sender, IgnoredSenders, PolicyTable, ProtectedSenders};
+use crate::predictions::{
+    group_reply_threads, CalibrationBucket, NeedsReplySort, PredictionStats, PredictionStore,
+    ReplyThread, CONFIDENCE_BUCKETS,
+};
+use crate::profile::diff_lines;
+use crate::profile::{self, Profile};
+use crate::prompt::PromptTemplate;
+use crate::providers::aliased::{Aliased, LabelAliasMap};
 use crate::providers::gmail::GmailProvider;
+use crate::providers::imap::ImapProvider;
+use crate::providers::jmap::JmapProvider;
+#[cfg(any(debug_assertions, feature = "mock"))]
+use crate::providers::mock::MockProvider;
 use crate::providers::outlook::OutlookProvider;
 use crate::providers::outlook_web::OutlookWebProvider;
-use crate::providers::{Email, EmailProvider};
-use crate::rules;
-use anyhow::Result;
+use crate::providers::traced::Traced;
+use crate::providers::{
+    env_credential, mime, sender_address, Email, EmailProvider, Label, ProviderError, ProviderOp,
+    SortOrder,
+};
+use crate::quarantine::{Quarantine, QUARANTINE_LABEL};
+use crate::rules::{self, RuleFile};
+use crate::sender_stats::SenderStats;
+use crate::spam_feedback::{SpamDecision, SpamFeedback, SpamFeedbackLog};
+use crate::usage::UsageLog;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{Hash, Hasher};
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::time::Duration;
 use support::{
-    build_status_indicators, learn_from_manual_action, print_action_preview,
-    print_needs_reply_entry, run_summary_prompt, summary_prompt,
+    ask, build_status_indicators, confirm, draft_reply_prompt, format_llm_stats,
+    learn_from_manual_action, open_in_editor, print_action_preview, report_llm_usage,
+    run_claude_prompt, summary_prompt, NeedsReplyRow,
 };
+use tokio::time::Instant;
+use tracing::{error, info, warn};
 
-const CORRECTION_BATCH_SIZE: usize = 25;
+/// Consecutive failures after which `watch` stops backing off further (8x interval)
+const WATCH_MAX_BACKOFF_DOUBLINGS: u32 = 3;
+/// Provider actions in flight at once during `bulk`
+const BULK_CONCURRENCY: usize = 8;
+/// Matching emails listed before the `bulk` confirmation prompt
+const BULK_PREVIEW_COUNT: usize = 10;
+/// Emails fetched at once when checking the needs-reply list
+const NEEDS_REPLY_CONCURRENCY: usize = 8;
+/// Misclassifications before `spam-review` calls out a sender
+const SPAM_REVIEW_REPEAT_THRESHOLD: usize = 2;
+/// Width in characters of the longest `profile stats` histogram bar
+const HISTOGRAM_WIDTH: usize = 40;
+/// Attempts per correction batch before giving up on it
+const CORRECTION_BATCH_ATTEMPTS: u32 = 3;
+/// First retry delay for a failed batch; doubles on each further attempt
+const CORRECTION_BATCH_BACKOFF: Duration = Duration::from_secs(5);
 const INBOX_CLASSIFICATION_QUERY: &str = "-label:Classified";
-const ARCHIVED_CLASSIFICATION_QUERY: &str = "-label:Classified -in:spam -in:trash";
+const ARCHIVED_CLASSIFICATION_QUERY: &str =
+    "-label:Classified -label:Quarantine -in:spam -in:trash";
+
+/// Retry and pacing for correction batches sent to the LLM
+struct BatchPolicy {
+    attempts: u32,
+    backoff: Duration,
+    delay_between: Duration,
+}
+
+impl BatchPolicy {
+    fn from_config(cfg: &Config) -> Self {
+        Self {
+            attempts: CORRECTION_BATCH_ATTEMPTS,
+            backoff: CORRECTION_BATCH_BACKOFF,
+            delay_between: cfg.correction_batch_delay(),
+        }
+    }
+}
+
+/// What a batched correction run got through
+#[derive(Default)]
+struct BatchProgress {
+    applied_ids: Vec<String>,
+    /// 1-based numbers of batches that weren't applied
+    remaining_batches: Vec<usize>,
+    total_batches: usize,
+    error: Option<anyhow::Error>,
+}
+
+impl BatchProgress {
+    fn report_remaining(&self) {
+        if self.remaining_batches.is_empty() {
+            return;
+        }
+        let batches = self
+            .remaining_batches
+            .iter()
+            .map(usize::to_string)
+            .collect::<Vec<_>>();
+        warn!(
+            "batch(es) {} of {} not applied; progress saved, re-run to retry them",
+            batches.join(", "),
+            self.total_batches
+        );
+    }
+}
+
+/// Something that folds a batch of corrections into the profile
+#[async_trait]
+trait CorrectionBackend: Send {
+    async fn apply_batch(&mut self, batch: &[Correction]) -> Result<()>;
+}
+
+#[async_trait]
+impl<P: EmailProvider + ?Sized> CorrectionBackend for LearningEngine<'_, P> {
+    async fn apply_batch(&mut self, batch: &[Correction]) -> Result<()> {
+        self.apply_corrections(batch).await
+    }
+}
 
+#[derive(Default)]
 struct CorrectionPass {
     deleted_ids: Vec<String>,
     corrected_ids: Vec<String>,
     had_corrections: bool,
 }
 
+#[derive(Clone)]
+pub struct ScanOptions {
+    pub max: u32,
+    pub archived: bool,
+    pub dry_run: bool,
+    /// Abort on the first per-email error instead of warning and continuing
+    pub fail_fast: bool,
+    pub oldest_first: bool,
+    /// Fraction of emails (0.0-1.0) to print in full for auditing
+    pub spot_check: Option<f64>,
+    /// Classify from sender and subject only, without sending bodies
+    pub subject_only: bool,
+    /// Classify and print every email of a thread separately
+    pub no_collapse: bool,
+    /// Extra provider search terms, e.g. `from:amazon is:unread`
+    pub query: Option<String>,
+    /// Print the model's reason for each email and keep it in predictions
+    pub explain: bool,
+    /// Stop classifying once this run has made this many LLM calls
+    pub max_llm_calls: Option<u64>,
+    /// Only classify unread emails
+    pub unread_only: bool,
+    /// Hold deletes back until the end of the run and confirm them together
+    pub confirm_deletes: bool,
+}
+
+impl ScanOptions {
+    fn order(&self) -> SortOrder {
+        if self.oldest_first {
+            SortOrder::OldestFirst
+        } else {
+            SortOrder::NewestFirst
+        }
+    }
+}
+
+/// Deterministic sampler for `scan --spot-check`: an email is selected when
+/// the hash of (seed, id) falls below the sampling rate
+struct SpotCheck {
+    rate: f64,
+    seed: u64,
+}
+
+impl SpotCheck {
+    fn new(rate: f64) -> Self {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or_default();
+        Self { rate, seed }
+    }
+
+    fn selects(&self, email_id: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        self.seed.hash(&mut hasher);
+        email_id.hash(&mut hasher);
+        (hasher.finish() as f64 / u64::MAX as f64) < self.rate
+    }
+}
+
+/// `--max-llm-calls` for one scan, learning included. Calls are counted
+/// from the scan's start, so earlier `watch` iterations don't use it up.
+struct LlmBudget {
+    limit: u64,
+    calls_before: u64,
+}
+
+impl LlmBudget {
+    fn start(limit: u64) -> Self {
+        Self {
+            limit,
+            calls_before: llm::run_calls(),
+        }
+    }
+
+    fn spent(&self) -> u64 {
+        llm::run_calls().saturating_sub(self.calls_before)
+    }
+
+    fn exhausted(&self) -> bool {
+        self.spent() >= self.limit
+    }
+}
+
+/// Local stores updated during a scan, plus config-driven scan behaviour
+struct ScanState {
+    predictions: PredictionStore,
+    label_manager: LabelManager,
+    delta: ScanDelta,
+    /// Per-label archive/delete overrides from config
+    policies: PolicyTable,
+    protected_senders: ProtectedSenders,
+    /// Senders skipped before classification
+    ignored_senders: IgnoredSenders,
+    /// Keep starred/important emails out of auto-archive and delete
+    protect_curated: bool,
+    notifier: Option<Notifier>,
+    spot_check: Option<SpotCheck>,
+    llm_budget: Option<LlmBudget>,
+    delete_mode: DeleteMode,
+    quarantine: Quarantine,
+    latencies: ClassifyLatencies,
+    /// Updated with each stored prediction
+    sender_stats: SenderStats,
+    /// Mirror Important/Urgent onto the provider's own important flag
+    sync_importance: bool,
+    /// Emails classified for deletion under --confirm-deletes, awaiting the
+    /// end-of-run confirmation
+    deferred_deletes: Vec<(Email, Classification)>,
+    /// One record per classified thread, in the `--output` format
+    results: Rows<ScanRow>,
+}
+
+impl ScanState {
+    fn new(
+        cfg: &Config,
+        predictions: PredictionStore,
+        label_manager: LabelManager,
+        sender_stats: SenderStats,
+    ) -> Result<Self> {
+        Ok(Self {
+            predictions,
+            label_manager,
+            delta: ScanDelta::default(),
+            policies: PolicyTable::from_config(cfg),
+            protected_senders: ProtectedSenders::from_config(cfg),
+            ignored_senders: IgnoredSenders::from_config(cfg)?,
+            protect_curated: cfg.protect_curated_emails(),
+            notifier: cfg.notifications.clone().map(Notifier::new),
+            spot_check: None,
+            llm_budget: None,
+            delete_mode: cfg.delete_mode(),
+            quarantine: Quarantine::load()?,
+            latencies: ClassifyLatencies::default(),
+            sender_stats,
+            sync_importance: cfg.sync_importance(),
+            deferred_deletes: Vec::new(),
+            results: Rows::new(output::formatter()),
+        })
+    }
+}
+
+/// Net changes a scan makes to the local stores
+#[derive(Debug, Default)]
+struct ScanDelta {
+    new_predictions: usize,
+    removed_predictions: usize,
+    new_labels: BTreeSet<String>,
+}
+
+impl ScanDelta {
+    fn record(&mut self, classification: &Classification, label_manager: &LabelManager) {
+        if classification.delete {
+            return;
+        }
+
+        self.new_predictions += 1;
+        let labels = classification.labels();
+        self.new_labels.extend(
+            label_manager
+                .unknown_labels(&labels)
+                .into_iter()
+                .map(str::to_string),
+        );
+    }
+
+    fn summary(&self) -> String {
+        let mut summary = format!(
+            "Prediction store: +{} new, -{} removed",
+            self.new_predictions, self.removed_predictions
+        );
+        if self.new_labels.is_empty() {
+            summary.push_str("\nNew LLM labels: none");
+        } else {
+            let labels = self.new_labels.iter().cloned().collect::<Vec<_>>();
+            summary.push_str(&format!("\nNew LLM labels: {}", labels.join(", ")));
+        }
+        summary
+    }
+}
+
+/// How long each classify call of a scan took, for tuning model and batch size
+#[derive(Debug, Default)]
+struct ClassifyLatencies {
+    durations: Vec<Duration>,
+}
+
+impl ClassifyLatencies {
+    fn record(&mut self, duration: Duration) {
+        self.durations.push(duration);
+    }
+
+    /// "min 0.8s, median 1.4s, max 3.2s over 25 emails", or None before any call
+    fn summary(&self) -> Option<String> {
+        let mut sorted = self.durations.clone();
+        sorted.sort();
+        let (min, max) = (sorted.first()?, sorted.last()?);
+        let median = sorted[sorted.len() / 2];
+        Some(format!(
+            "min {:.1}s, median {:.1}s, max {:.1}s over {} emails",
+            min.as_secs_f64(),
+            median.as_secs_f64(),
+            max.as_secs_f64(),
+            sorted.len()
+        ))
+    }
+}
+
 async fn create_provider(name: &str) -> Result<Box<dyn EmailProvider>> {
+    let aliases = LabelAliasMap::new(&Config::load()?.label_aliases(name));
     match name {
-        "gmail" => Ok(Box::new(GmailProvider::new().await?)),
-        "outlook" => Ok(Box::new(OutlookProvider::new().await?)),
-        "outlook-web" => Ok(Box::new(OutlookWebProvider::new()?)),
-        _ => anyhow::bail!(
-            "Unknown provider: {}. Use 'gmail', 'outlook', or 'outlook-web'",
-            name
-        ),
+        "gmail" => Ok(wrap("gmail", GmailProvider::new().await?, aliases)),
+        "outlook" => Ok(wrap("outlook", OutlookProvider::new().await?, aliases)),
+        "outlook-web" => Ok(wrap("outlook-web", OutlookWebProvider::new()?, aliases)),
+        "imap" => Ok(wrap("imap", ImapProvider::new().await?, aliases)),
+        "jmap" => Ok(wrap("jmap", JmapProvider::new().await?, aliases)),
+        #[cfg(any(debug_assertions, feature = "mock"))]
+        "mock" => Ok(wrap("mock", MockProvider::from_fixture()?, aliases)),
+        _ => anyhow::bail!("Unknown provider: {}. Use {}", name, PROVIDER_CHOICES),
+    }
+}
+
+/// Provider names for error messages; `mock` only exists in debug builds
+/// and with the `mock` feature
+#[cfg(any(debug_assertions, feature = "mock"))]
+const PROVIDER_CHOICES: &str = "'gmail', 'outlook', 'outlook-web', 'imap', 'jmap', or 'mock'";
+#[cfg(not(any(debug_assertions, feature = "mock")))]
+const PROVIDER_CHOICES: &str = "'gmail', 'outlook', 'outlook-web', 'imap', or 'jmap'";
+
+/// Trace every call, and translate label names when the provider has aliases
+fn wrap(
+    name: &'static str,
+    provider: impl EmailProvider + 'static,
+    aliases: LabelAliasMap,
+) -> Box<dyn EmailProvider> {
+    let provider = Traced::new(name, provider);
+    if aliases.is_empty() {
+        Box::new(provider)
+    } else {
+        Box::new(Aliased::new(provider, aliases))
+    }
+}
+
+/// Providers with saved tokens or config, in the order `--all-providers` runs them.
+/// Token validity is only checked once a command uses the provider.
+pub fn configured_providers(cfg: &Config) -> Vec<&'static str> {
+    let mut providers = Vec::new();
+    if gmail::config::load_tokens().is_ok() {
+        providers.push("gmail");
+    }
+    if outlook::config::load_tokens().is_ok() {
+        providers.push("outlook");
+    }
+    if outlook_web::config::load_config().is_ok() {
+        providers.push("outlook-web");
+    }
+    if cfg.imap.is_some() {
+        providers.push("imap");
+    }
+    if cfg.jmap.is_some() || env_credential("JMAP_TOKEN").is_some() {
+        providers.push("jmap");
+    }
+    providers
+}
+
+/// Add an address or domain to `protected_senders`
+pub async fn protect(sender: &str) -> Result<()> {
+    let sender = normalize_sender(sender);
+    if sender.is_empty() {
+        anyhow::bail!("Give an email address or a domain");
+    }
+
+    let mut cfg = Config::load()?;
+    let protected = cfg.protected_senders.get_or_insert_with(Vec::new);
+    if protected
+        .iter()
+        .any(|entry| normalize_sender(entry) == sender)
+    {
+        println!("Already protected: {}", sender);
+        return Ok(());
     }
+    protected.push(sender.clone());
+    cfg.save()?;
+    println!("Protected: {} (never auto-archived or deleted)", sender);
+    Ok(())
+}
+
+pub async fn unprotect(sender: &str) -> Result<()> {
+    let sender = normalize_sender(sender);
+    let mut cfg = Config::load()?;
+    let protected = cfg.protected_senders.get_or_insert_with(Vec::new);
+    let before = protected.len();
+    protected.retain(|entry| normalize_sender(entry) != sender);
+    if protected.len() == before {
+        println!("Not protected: {}", sender);
+        return Ok(());
+    }
+    cfg.save()?;
+    println!("No longer protected: {}", sender);
+    Ok(())
+}
+
+pub async fn ignore(sender: &str) -> Result<()> {
+    let sender = normalize_sender(sender);
+    if sender.is_empty() {
+        anyhow::bail!("Give an email address, a domain, or a /regex/");
+    }
+
+    let mut cfg = Config::load()?;
+    let ignored = cfg.ignored_senders.get_or_insert_with(Vec::new);
+    if ignored
+        .iter()
+        .any(|entry| normalize_sender(entry) == sender)
+    {
+        println!("Already ignored: {}", sender);
+        return Ok(());
+    }
+    ignored.push(sender.clone());
+    // Reject a bad pattern now rather than at the next scan
+    IgnoredSenders::from_config(&cfg)?;
+    cfg.save()?;
+    println!("Ignored: {} (skipped by scan, never classified)", sender);
+    Ok(())
+}
+
+pub async fn unignore(sender: &str) -> Result<()> {
+    let sender = normalize_sender(sender);
+    let mut cfg = Config::load()?;
+    let ignored = cfg.ignored_senders.get_or_insert_with(Vec::new);
+    let before = ignored.len();
+    ignored.retain(|entry| normalize_sender(entry) != sender);
+    if ignored.len() == before {
+        println!("Not ignored: {}", sender);
+        return Ok(());
+    }
+    cfg.save()?;
+    println!("No longer ignored: {}", sender);
+    Ok(())
 }
 
-pub async fn config(provider: Option<String>) -> Result<()> {
+pub async fn config(provider: Option<String>, prediction_max_age_days: Option<u32>) -> Result<()> {
     let mut cfg = Config::load()?;
 
+    if provider.is_none() && prediction_max_age_days.is_none() {
+        println!("Current settings:");
+        println!("  provider: {}", cfg.default_provider());
+        println!(
+            "  prediction_max_age_days: {}",
+            cfg.prediction_max_age_days()
+        );
+        return Ok(());
+    }
+
     if let Some(provider) = provider {
         validate_provider_name(&provider)?;
         cfg.provider = Some(provider.clone());
-        cfg.save()?;
         println!("Default provider set to: {}", provider);
-        return Ok(());
+    }
+    if let Some(days) = prediction_max_age_days {
+        cfg.prediction_max_age_days = Some(days);
+        println!("Prediction max age set to: {} days", days);
     }
 
-    println!("Current settings:");
-    println!("  provider: {}", cfg.default_provider());
-    Ok(())
+    cfg.save()
 }
 
-pub async fn login(provider_name: &str) -> Result<()> {
+pub async fn login(provider_name: &str, device: bool) -> Result<()> {
     match provider_name {
+        // Google restricts the device-code grant to a few scopes, none of them Gmail's
+        "gmail" if device => anyhow::bail!(
+            "Google doesn't allow Gmail access through device-code login. \
+             Run `login` on a machine with a browser and copy the saved tokens to this one."
+        ),
         "gmail" => login_gmail().await,
+        "outlook" if device => {
+            OutlookProvider::device_login().await?;
+            println!("Outlook login successful! Tokens saved.");
+            Ok(())
+        }
         "outlook" => login_outlook().await,
         "outlook-web" => {
             println!("outlook-web uses browser automation - no login required.");
@@ -66,68 +541,499 @@ pub async fn login(provider_name: &str) -> Result<()> {
             println!("Then open Outlook Web and log in manually.");
             Ok(())
         }
+        "imap" => {
+            println!("imap uses the credentials in config.json - no login required.");
+            println!("Add an \"imap\" section with host, user, and password:");
+            println!("  {}", config::config_dir().join("config.json").display());
+            Ok(())
+        }
+        "jmap" => {
+            println!("jmap uses an API token - no login required.");
+            println!("Create one in Fastmail under Settings > Privacy & Security > API tokens,");
+            println!(
+                "then set EMAIL_ASSISTANT_JMAP_TOKEN or add a \"jmap\" section with a token to:"
+            );
+            println!("  {}", config::config_dir().join("config.json").display());
+            Ok(())
+        }
         _ => anyhow::bail!(
-            "Unknown provider: {}. Use 'gmail', 'outlook', or 'outlook-web'",
+            "Unknown provider: {}. Use 'gmail', 'outlook', 'outlook-web', 'imap', or 'jmap'",
             provider_name
         ),
     }
 }
 
-pub async fn scan(max: u32, dry_run: bool, provider_name: &str, archived: bool) -> Result<()> {
-    let _config = Config::load()?;
+#[tracing::instrument(name = "scan", skip_all, fields(provider = provider_name))]
+pub async fn scan(options: ScanOptions, provider_name: &str) -> Result<()> {
+    if options.query.is_some() {
+        ensure_query_supported(provider_name)?;
+    }
+    let dry_run = options.dry_run;
+    let llm_budget = options.max_llm_calls.map(LlmBudget::start);
+    let cfg = Config::load()?;
     let provider = create_provider(provider_name).await?;
     let mut profile = Profile::load()?;
     let mut predictions = PredictionStore::load()?;
-    let _label_manager = LabelManager::load()?;
+    predictions.keep_reasons(options.explain);
+    let label_manager = LabelManager::load()?;
+    let mut sender_stats = SenderStats::load()?;
+    let pruned = prune_stale_predictions(&mut predictions, &cfg, dry_run);
 
-    let correction_pass =
-        run_scan_correction_pass(provider.as_ref(), &mut profile, &predictions, dry_run).await?;
+    let correction_pass = if learns_from_provider(provider_name) {
+        run_scan_correction_pass(
+            provider.as_ref(),
+            &mut profile,
+            &predictions,
+            &mut sender_stats,
+            &cfg,
+            dry_run,
+            options.fail_fast,
+        )
+        .await?
+    } else {
+        CorrectionPass::default()
+    };
     persist_profile(&profile, correction_pass.had_corrections, dry_run)?;
-    remove_predictions(
+    // Pruning went first, so nothing it dropped is counted again here
+    let removed = remove_predictions(
         &mut predictions,
         &correction_pass.deleted_ids,
         &correction_pass.corrected_ids,
         dry_run,
     );
 
-    let classifier = Classifier::new(&profile);
+    let mut state = ScanState::new(&cfg, predictions, label_manager, sender_stats)?;
+    state.delta.removed_predictions = pruned + removed;
+    state.spot_check = options.spot_check.map(SpotCheck::new);
+    state.llm_budget = llm_budget;
+    purge_quarantine(
+        provider.as_ref(),
+        &mut state.quarantine,
+        cfg.quarantine_grace(),
+        dry_run,
+    )
+    .await?;
+    let contacts = Contacts::load()?;
+    // The scan records into its own stats as it goes; the classifier keeps
+    // the history as it was when the run started
+    let sender_history = state.sender_stats.clone();
+    let template = PromptTemplate::load()?;
+    let classifier = Classifier::new(&profile, &cfg)
+        .subject_only(options.subject_only)
+        .known_contacts(&contacts)
+        .sender_stats(&sender_history)
+        .prompt_template(&template);
     let user_rules = rules::load_rules().unwrap_or_default();
-    let emails = load_scan_emails(provider.as_ref(), max, archived).await?;
+    let emails = load_scan_emails(provider.as_ref(), &options).await?;
+    info!(emails = emails.len(), "loaded emails to scan");
 
-    for email in emails {
-        process_scan_email(
+    let mut threads = group_threads(emails, !options.no_collapse).into_iter();
+    while let Some(thread) = threads.next() {
+        let size = 1 + thread.earlier.len();
+        let progress = process_scan_thread(
             provider.as_ref(),
             &classifier,
             &user_rules,
-            &mut predictions,
-            email,
-            dry_run,
+            &mut state,
+            thread,
+            &options,
         )
         .await?;
+        if progress.is_break() {
+            // Unclassified emails stay unmarked, so the next scan picks them up
+            let remaining: usize = size
+                + threads
+                    .map(|thread| 1 + thread.earlier.len())
+                    .sum::<usize>();
+            note!(
+                "\nStopped after {} LLM calls (--max-llm-calls); {} emails left for the next run",
+                state.llm_budget.as_ref().map_or(0, LlmBudget::spent),
+                remaining
+            );
+            break;
+        }
     }
 
-    save_predictions(&predictions, dry_run)?;
-    Ok(())
+    state.results.flush();
+    confirm_deferred_deletes(provider.as_ref(), &mut state, &options).await?;
+    info!(llm_calls = llm::run_calls(), "scan finished");
+    if let Some(latency) = state.latencies.summary() {
+        info!("classification latency: {}", latency);
+    }
+    save_scan_state(&state, dry_run)?;
+    report_llm_usage(&cfg, dry_run)
 }
 
-pub async fn labels_list(provider_name: &str) -> Result<()> {
+/// Classify one email and print the result, changing nothing
+pub async fn classify(id: &str, explain: bool, provider_name: &str) -> Result<()> {
+    let cfg = Config::load()?;
     let provider = create_provider(provider_name).await?;
-    let label_manager = LabelManager::load()?;
-    let provider_labels = provider.list_labels().await?;
+    let profile = Profile::load()?;
+    let state = ScanState::new(
+        &cfg,
+        PredictionStore::load()?,
+        LabelManager::load()?,
+        SenderStats::load()?,
+    )?;
+    let email = provider.get_message(id).await?;
+
+    let contacts = Contacts::load()?;
+    let template = PromptTemplate::load()?;
+    let classifier = Classifier::new(&profile, &cfg)
+        .known_contacts(&contacts)
+        .sender_stats(&state.sender_stats)
+        .prompt_template(&template);
+    let user_rules = rules::load_rules().unwrap_or_default();
+    let llm_classification = classifier.classify(&email).await?;
+    let classification = finalize_classification(&state, &user_rules, &email, &llm_classification);
+
+    println!("{}", ScanRow::new(&email, &classification, 0).plain());
+    if explain {
+        print_reason(&classification);
+    }
+    report_llm_usage(&cfg, false)
+}
+
+/// Classify one email again from scratch, replacing its stored prediction
+/// and removing labels the new classification no longer predicts
+pub async fn reclassify(id: &str, dry_run: bool, provider_name: &str) -> Result<()> {
+    let cfg = Config::load()?;
+    let provider = create_provider(provider_name).await?;
+    let profile = Profile::load()?;
+    let mut state = ScanState::new(
+        &cfg,
+        PredictionStore::load()?,
+        LabelManager::load()?,
+        SenderStats::load()?,
+    )?;
+    let email = provider.get_message(id).await?;
+    let old_labels = match state.predictions.get(id) {
+        Some(prediction) => prediction.all_labels(),
+        None => {
+            println!("No stored prediction for {}; classifying it fresh.", id);
+            Vec::new()
+        }
+    };
+
+    let contacts = Contacts::load()?;
+    let template = PromptTemplate::load()?;
+    let llm_classification = Classifier::new(&profile, &cfg)
+        .known_contacts(&contacts)
+        .sender_stats(&state.sender_stats)
+        .prompt_template(&template)
+        .classify(&email)
+        .await?;
+    let user_rules = rules::load_rules().unwrap_or_default();
+    let classification = finalize_classification(&state, &user_rules, &email, &llm_classification);
+    let stale = stale_labels(&old_labels, &classification.labels());
+
+    println!("{}", ScanRow::new(&email, &classification, 0).plain());
+    if dry_run {
+        ScanPlan::new(&email, &classification, state.delete_mode, &stale).print();
+        return report_llm_usage(&cfg, dry_run);
+    }
+
+    state.predictions.remove(id);
+    let removed = apply_label_diff(provider.as_ref(), &email, &[], &stale, true).await?;
+    if !removed.is_empty() {
+        println!("  Removed labels: {:?}", removed);
+    }
+    apply_scan_actions(provider.as_ref(), &mut state, &email, &classification, true).await?;
+    if state.sync_importance
+        && is_important_label_set(&old_labels)
+        && !classification.delete
+        && !classification.is_important()
+    {
+        sync_importance(provider.as_ref(), &email, false, true).await?;
+    }
+    if !classification.delete {
+        state
+            .label_manager
+            .record_llm_labels(&classification.labels());
+    }
+
+    save_scan_state(&state, dry_run)?;
+    report_llm_usage(&cfg, dry_run)
+}
+
+/// Labels of the old prediction that the new one dropped, ignoring case
+fn stale_labels(old: &[String], new: &[String]) -> Vec<String> {
+    old.iter()
+        .filter(|label| !new.iter().any(|kept| kept.eq_ignore_ascii_case(label)))
+        .cloned()
+        .collect()
+}
+
+/// List emails matching `query` with their current labels. With `classify`,
+/// each also shows the classification a scan would give it; nothing is
+/// applied or stored either way.
+pub async fn search(
+    query: &str,
+    max: u32,
+    archived: bool,
+    classify: bool,
+    provider_name: &str,
+) -> Result<()> {
+    ensure_query_supported(provider_name)?;
+    let provider = create_provider(provider_name).await?;
+    let (label, list_op) = if archived {
+        ("", ProviderOp::ListByLabel)
+    } else {
+        ("INBOX", ProviderOp::List)
+    };
+    provider.capabilities().require(list_op)?;
+    let emails = provider
+        .list_messages(max, label, Some(query), SortOrder::NewestFirst)
+        .await?;
+    if emails.is_empty() {
+        println!("No emails match.");
+        return Ok(());
+    }
+
+    let mut rows = Rows::new(output::formatter());
+    if !classify {
+        for email in &emails {
+            rows.push(SearchRow::new(email, None));
+        }
+        rows.flush();
+        return Ok(());
+    }
 
-    println!("Provider labels:");
-    for label in &provider_labels {
-        println!("  {} ({})", label.name, label.id);
+    let cfg = Config::load()?;
+    let profile = Profile::load()?;
+    let state = ScanState::new(
+        &cfg,
+        PredictionStore::load()?,
+        LabelManager::load()?,
+        SenderStats::load()?,
+    )?;
+    let contacts = Contacts::load()?;
+    let template = PromptTemplate::load()?;
+    let classifier = Classifier::new(&profile, &cfg)
+        .known_contacts(&contacts)
+        .sender_stats(&state.sender_stats)
+        .prompt_template(&template);
+    let user_rules = rules::load_rules().unwrap_or_default();
+    for email in &emails {
+        let classification = match classifier.classify(email).await {
+            Ok(llm_classification) => Some(finalize_classification(
+                &state,
+                &user_rules,
+                email,
+                &llm_classification,
+            )),
+            Err(error) => {
+                warn!(id = email.id, "couldn't classify: {:#}", error);
+                None
+            }
+        };
+        rows.push(SearchRow::new(email, classification.as_ref()));
     }
+    rows.flush();
+    report_llm_usage(&cfg, false)
+}
+
+/// Run scan (which learns from corrections first) every `interval` until
+/// Ctrl-C. A failed iteration is logged and the next one is delayed further.
+pub async fn watch(interval: Duration, options: ScanOptions, provider_name: &str) -> Result<()> {
+    let shutdown = tokio::signal::ctrl_c();
+    tokio::pin!(shutdown);
+    let mut failures = 0;
+
+    loop {
+        println!(
+            "[{}] Scanning...",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+        );
+        // Dropping a scan midway would leave emails stamped Classified
+        // without saved predictions, so Ctrl-C lets the current one finish
+        let current = scan(options.clone(), provider_name);
+        tokio::pin!(current);
+        let mut stopping = false;
+        let result = tokio::select! {
+            result = &mut current => result,
+            _ = &mut shutdown => {
+                println!("\nStopping once the current scan has saved its results (Ctrl-C again to abort it)...");
+                stopping = true;
+                tokio::select! {
+                    result = &mut current => result,
+                    _ = tokio::signal::ctrl_c() => {
+                        println!("\nAborted; the current scan was stopped.");
+                        return Ok(());
+                    }
+                }
+            }
+        };
+        match result {
+            Ok(()) => failures = 0,
+            Err(error) => {
+                failures += 1;
+                error!(failures, "scan failed: {:#}", error);
+            }
+        }
+        if stopping {
+            println!("Stopped.");
+            return Ok(());
+        }
 
-    println!("\nLLM-created labels:");
-    for label in label_manager.llm_labels() {
-        println!("  {}", label);
+        let delay = watch_delay(interval, failures);
+        println!("Next scan in {}s (Ctrl-C to stop)", delay.as_secs());
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = &mut shutdown => {
+                println!("\nStopped.");
+                return Ok(());
+            }
+        }
     }
+}
+
+/// The interval, doubled per consecutive failure up to WATCH_MAX_BACKOFF
+fn watch_delay(interval: Duration, failures: u32) -> Duration {
+    let factor = 1u32 << failures.min(WATCH_MAX_BACKOFF_DOUBLINGS);
+    interval * factor
+}
+
+/// With `counts`, every label's email count comes from the provider (reused
+/// for a few minutes from `label_counts.json`) and is recorded for tracked
+/// labels; a dry run saves neither
+pub async fn labels_list(counts: bool, dry_run: bool, provider_name: &str) -> Result<()> {
+    let provider = create_provider(provider_name).await?;
+    let mut label_manager = LabelManager::load()?;
+    let provider_labels = provider.list_labels().await?;
+
+    let counts = if counts {
+        provider.capabilities().require(ProviderOp::LabelCount)?;
+        let counts = label_counts(
+            provider.as_ref(),
+            &provider_labels,
+            &label_manager,
+            provider_name,
+            dry_run,
+        )
+        .await?;
+        label_manager.set_email_counts(&counts);
+        if !dry_run {
+            label_manager.save()?;
+        }
+        Some(counts)
+    } else {
+        None
+    };
 
+    let provider_rows = provider_labels
+        .into_iter()
+        .map(|label| LabelRow {
+            emails: counts
+                .as_ref()
+                .and_then(|counts| counts.get(&label.name).copied()),
+            name: label.name,
+            source: "provider",
+            id: Some(label.id),
+        })
+        .collect::<Vec<_>>();
+    let llm_rows = label_manager
+        .llm_labels()
+        .into_iter()
+        .map(|label| LabelRow {
+            name: label.to_string(),
+            source: "llm",
+            id: None,
+            emails: Some(label_manager.rolled_up_count(label)),
+        })
+        .collect::<Vec<_>>();
+
+    let formatter = output::formatter();
+    if formatter.is_plain() {
+        println!("Provider labels:");
+        formatter.print(&provider_rows);
+        println!("\nLLM-created labels:");
+        formatter.print(&llm_rows);
+    } else {
+        formatter.print(&[provider_rows, llm_rows].concat());
+    }
     Ok(())
 }
 
+/// Counts for the provider's labels and the tracked ones, from the cache when
+/// it's fresh, otherwise fetched and cached
+async fn label_counts(
+    provider: &dyn EmailProvider,
+    provider_labels: &[Label],
+    label_manager: &LabelManager,
+    provider_name: &str,
+    dry_run: bool,
+) -> Result<BTreeMap<String, u32>> {
+    let now = chrono::Utc::now();
+    let cache = LabelCountCache::load()?;
+    if let Some(counts) = cache.fresh(provider_name, now) {
+        return Ok(counts.clone());
+    }
+
+    let mut labels = provider_labels
+        .iter()
+        .map(|label| label.name.clone())
+        .collect::<Vec<_>>();
+    for label in label_manager.label_names() {
+        if !labels.iter().any(|existing| existing == label) {
+            labels.push(label.to_string());
+        }
+    }
+    let counts = fetch_label_counts(provider, &labels).await;
+    if !dry_run {
+        LabelCountCache::new(provider_name, counts.clone(), now).save()?;
+    }
+    Ok(counts)
+}
+
+/// A provider label, or one the classifier created with its email count
+#[derive(Debug, Clone, serde::Serialize)]
+struct LabelRow {
+    name: String,
+    /// "provider" or "llm"
+    source: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    emails: Option<u32>,
+}
+
+impl Renderable for LabelRow {
+    const COLUMNS: &'static [&'static str] = &["NAME", "SOURCE", "ID", "EMAILS"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.source.to_string(),
+            self.id.clone().unwrap_or_else(|| "-".to_string()),
+            self.emails
+                .map_or("-".to_string(), |count| count.to_string()),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        match (&self.id, self.emails) {
+            (Some(id), None) => format!("  {} ({})", self.name, id),
+            (Some(id), Some(count)) => format!("  {} ({}, {} emails)", self.name, id, count),
+            (None, count) => {
+                // Nested labels are indented under their parent, with their own name
+                let depth = self.name.matches(LABEL_SEPARATOR).count();
+                let name = self
+                    .name
+                    .rsplit(LABEL_SEPARATOR)
+                    .next()
+                    .unwrap_or(&self.name);
+                format!(
+                    "  {}{} ({})",
+                    "  ".repeat(depth),
+                    name,
+                    count.unwrap_or_default()
+                )
+            }
+        }
+    }
+}
+
 pub async fn labels_cleanup(dry_run: bool, provider_name: &str) -> Result<()> {
     let provider = create_provider(provider_name).await?;
     let mut label_manager = LabelManager::load()?;
@@ -148,8 +1054,131 @@ pub async fn labels_cleanup(dry_run: bool, provider_name: &str) -> Result<()> {
     Ok(())
 }
 
-pub async fn spam(id: &str, dry_run: bool, provider_name: &str) -> Result<()> {
+pub async fn labels_merge(
+    from: &str,
+    into: &str,
+    dry_run: bool,
+    provider_name: &str,
+) -> Result<()> {
+    if from == into {
+        anyhow::bail!("Cannot merge a label into itself");
+    }
+    let provider = create_provider(provider_name).await?;
+
+    if dry_run {
+        let emails = provider
+            .list_messages(500, from, None, SortOrder::default())
+            .await?;
+        println!(
+            "Would move {} emails from \"{}\" to \"{}\" and remove \"{}\" rules from the profile",
+            emails.len(),
+            from,
+            into,
+            from
+        );
+        return Ok(());
+    }
+
+    let mut label_manager = LabelManager::load()?;
+    let mut profile = Profile::load()?;
+    let mut predictions = PredictionStore::load()?;
+    let moved = label_manager
+        .merge(&provider, &mut profile, from, into)
+        .await?;
+    let renamed = predictions.rename_label(from, into);
+
+    label_manager.save()?;
+    profile.save()?;
+    predictions.save()?;
+    println!(
+        "Moved {} emails from \"{}\" to \"{}\" ({} predictions updated)",
+        moved, from, into, renamed
+    );
+    Ok(())
+}
+
+/// Color `labels` (every tracked label when empty) on the provider
+pub async fn labels_recolor(
+    labels: &[String],
+    color: Option<&str>,
+    dry_run: bool,
+    provider_name: &str,
+) -> Result<()> {
+    let color = color
+        .map(|name| {
+            LabelColor::named(name).with_context(|| {
+                let names = LABEL_PALETTE.iter().map(|c| c.name).collect::<Vec<_>>();
+                format!(
+                    "Unknown color \"{}\" (choose from {})",
+                    name,
+                    names.join(", ")
+                )
+            })
+        })
+        .transpose()?;
+    let mut label_manager = LabelManager::load()?;
+    let labels = if labels.is_empty() {
+        label_manager
+            .label_names()
+            .into_iter()
+            .map(str::to_string)
+            .collect()
+    } else {
+        labels.to_vec()
+    };
+    if labels.is_empty() {
+        println!("No labels to recolor.");
+        return Ok(());
+    }
+
+    if dry_run {
+        for label in &labels {
+            let color = color.unwrap_or_else(|| label_manager.color_of(label));
+            println!("Would color \"{}\" {}", label, color.name);
+        }
+        return Ok(());
+    }
+
+    let provider = create_provider(provider_name).await?;
+    provider.capabilities().require(ProviderOp::LabelColor)?;
+    let recolored = label_manager.recolor(&provider, &labels, color).await?;
+    label_manager.save()?;
+    for (label, color) in recolored {
+        println!("✓ \"{}\" is now {}", label, color.name);
+    }
+    Ok(())
+}
+
+pub async fn labels_normalize(provider_name: &str) -> Result<()> {
+    let provider = create_provider(provider_name).await?;
+    let label_manager = LabelManager::load()?;
+    let provider_labels = provider
+        .list_labels()
+        .await?
+        .into_iter()
+        .map(|label| label.name)
+        .filter(|name| !is_system_label(name))
+        .collect::<Vec<_>>();
+
+    let suggestions = label_manager.suggest_merges(&provider_labels);
+    if suggestions.is_empty() {
+        println!("No duplicate labels found.");
+        return Ok(());
+    }
+
+    println!("Possible duplicates:");
+    for suggestion in &suggestions {
+        println!(
+            "  email-assistant labels merge \"{}\" \"{}\"",
+            suggestion.from, suggestion.into
+        );
+    }
+    Ok(())
+}
+
+pub async fn spam(id: &str, dry_run: bool, provider_name: &str) -> Result<()> {
     let provider = create_provider(provider_name).await?;
+    provider.capabilities().require(ProviderOp::Spam)?;
     let mut profile = Profile::load()?;
     let predictions = PredictionStore::load()?;
     let email = provider.get_message(id).await?;
@@ -161,6 +1190,7 @@ pub async fn spam(id: &str, dry_run: bool, provider_name: &str) -> Result<()> {
 
     provider.mark_spam(id).await?;
     println!("Marked as spam: \"{}\"", email.subject);
+    record_spam_feedback(&email, SpamDecision::Spam, &predictions)?;
     learn_from_manual_action(
         provider.as_ref(),
         &mut profile,
@@ -168,12 +1198,14 @@ pub async fn spam(id: &str, dry_run: bool, provider_name: &str) -> Result<()> {
         id,
         "spam",
         &email,
+        &Config::load()?.llm(),
     )
     .await
 }
 
 pub async fn unspam(id: &str, dry_run: bool, provider_name: &str) -> Result<()> {
     let provider = create_provider(provider_name).await?;
+    provider.capabilities().require(ProviderOp::Spam)?;
     let mut profile = Profile::load()?;
     let predictions = PredictionStore::load()?;
     let email = provider.get_message(id).await?;
@@ -185,6 +1217,7 @@ pub async fn unspam(id: &str, dry_run: bool, provider_name: &str) -> Result<()>
 
     provider.unspam(id).await?;
     println!("Removed from spam: \"{}\"", email.subject);
+    record_spam_feedback(&email, SpamDecision::NotSpam, &predictions)?;
     learn_from_manual_action(
         provider.as_ref(),
         &mut profile,
@@ -192,10 +1225,63 @@ pub async fn unspam(id: &str, dry_run: bool, provider_name: &str) -> Result<()>
         id,
         "unspam",
         &email,
+        &Config::load()?.llm(),
     )
     .await
 }
 
+fn record_spam_feedback(
+    email: &Email,
+    decision: SpamDecision,
+    predictions: &PredictionStore,
+) -> Result<()> {
+    let predicted_spam = predictions
+        .get(&email.id)
+        .map(|prediction| prediction.is_spam);
+    let mut log = SpamFeedbackLog::load()?;
+    log.record(SpamFeedback::new(email, decision, predicted_spam));
+    log.save()
+}
+
+pub async fn spam_review(limit: usize) -> Result<()> {
+    let log = SpamFeedbackLog::load()?;
+    let mut recent = log.recent(limit).peekable();
+    if recent.peek().is_none() {
+        println!("No spam decisions recorded yet.");
+        return Ok(());
+    }
+
+    println!("Recent spam decisions:");
+    for entry in recent {
+        let decision = match entry.decision {
+            SpamDecision::Spam => "spam",
+            SpamDecision::NotSpam => "not spam",
+        };
+        let marker = if entry.is_misclassification() {
+            " (classifier disagreed)"
+        } else {
+            ""
+        };
+        println!(
+            "  {} | {:<8} | {} | {}{}",
+            entry.date.format("%Y-%m-%d"),
+            decision,
+            entry.sender,
+            entry.subject.chars().take(50).collect::<String>(),
+            marker
+        );
+    }
+
+    let repeat = log.repeat_misclassified(SPAM_REVIEW_REPEAT_THRESHOLD);
+    if !repeat.is_empty() {
+        println!("\nSenders the classifier keeps getting wrong:");
+        for (sender, count) in repeat {
+            println!("  {} ({} times)", sender, count);
+        }
+    }
+    Ok(())
+}
+
 pub async fn archive(id: &str, dry_run: bool, provider_name: &str) -> Result<()> {
     let provider = create_provider(provider_name).await?;
     let email = provider.get_message(id).await?;
@@ -247,467 +1333,3742 @@ pub async fn label(id: &str, label: &str, dry_run: bool, provider_name: &str) ->
         id,
         &action,
         &email,
+        &Config::load()?.llm(),
     )
     .await
 }
 
-pub async fn learn(dry_run: bool, provider_name: &str) -> Result<()> {
+pub async fn unlabel(id: &str, label: &str, dry_run: bool, provider_name: &str) -> Result<()> {
     let provider = create_provider(provider_name).await?;
     let mut profile = Profile::load()?;
-    let mut predictions = PredictionStore::load()?;
-    let correction_pass =
-        run_learning_pass(provider.as_ref(), &mut profile, &predictions, dry_run).await?;
+    let predictions = PredictionStore::load()?;
+    let email = provider.get_message(id).await?;
 
-    if correction_pass.had_corrections && !dry_run {
-        profile.save()?;
-        println!("Profile updated.");
+    if dry_run {
+        println!("Would remove label '{}' from: \"{}\"", label, email.subject);
+        println!("  From: {}", email.from);
+        return Ok(());
     }
 
-    cleanup_deleted_predictions(&mut predictions, &correction_pass.deleted_ids, dry_run)?;
-    Ok(())
+    provider.remove_label(id, label).await?;
+    println!("Removed label '{}' from: \"{}\"", label, email.subject);
+
+    let action = format!("remove-label:{}", label);
+    learn_from_manual_action(
+        provider.as_ref(),
+        &mut profile,
+        &predictions,
+        id,
+        &action,
+        &email,
+        &Config::load()?.llm(),
+    )
+    .await
 }
 
-pub async fn profile() -> Result<()> {
-    let profile = Profile::load()?;
-    println!("{}", profile.content());
-    Ok(())
+/// What `bulk` does to every matching email
+#[derive(Debug, Clone)]
+pub enum BulkOperation {
+    Archive,
+    Label(String),
+    Delete,
 }
 
-pub async fn needs_reply(provider_name: &str) -> Result<()> {
-    let provider = create_provider(provider_name).await?;
-    let predictions = PredictionStore::load()?;
+impl BulkOperation {
+    fn op(&self) -> ProviderOp {
+        match self {
+            Self::Archive => ProviderOp::Archive,
+            Self::Label(_) => ProviderOp::Label,
+            Self::Delete => ProviderOp::Trash,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Archive => "archive".to_string(),
+            Self::Label(label) => format!("label as {}", label),
+            Self::Delete => "move to trash".to_string(),
+        }
+    }
 
-    println!("Emails that need a reply:\n");
-    let mut found = false;
+    async fn apply(&self, provider: &dyn EmailProvider, id: &str) -> Result<()> {
+        match self {
+            Self::Archive => provider.archive(id).await,
+            Self::Label(label) => provider.add_label(id, label).await,
+            Self::Delete => provider.trash(id).await,
+        }
+    }
+}
 
-    for prediction in predictions
-        .all_predictions()
-        .filter(|prediction| prediction.needs_reply())
-    {
-        let Ok(email) = provider.get_message(&prediction.email_id).await else {
-            continue;
-        };
+/// Outcome of a bulk operation
+#[derive(Debug, Default)]
+struct BulkReport {
+    succeeded: usize,
+    failed: Vec<(String, anyhow::Error)>,
+}
 
-        print_needs_reply_entry(prediction, &email);
-        found = true;
+/// Apply `operation` to every inbox email matching `query`, after confirmation
+pub async fn bulk(
+    operation: BulkOperation,
+    query: &str,
+    max: u32,
+    yes: bool,
+    dry_run: bool,
+    provider_name: &str,
+) -> Result<()> {
+    ensure_query_supported(provider_name)?;
+    let provider = create_provider(provider_name).await?;
+    provider.capabilities().require(operation.op())?;
+    let emails = provider
+        .list_messages(max, "INBOX", Some(query), SortOrder::NewestFirst)
+        .await?;
+    if emails.is_empty() {
+        println!("No emails match.");
+        return Ok(());
     }
 
-    if !found {
-        println!("No emails need a reply.");
+    let action = operation.describe();
+    if dry_run {
+        for email in &emails {
+            print_action_preview(&action, email);
+        }
+        println!("\nWould {} {} email(s)", action, emails.len());
+        return Ok(());
+    }
+
+    for email in emails.iter().take(BULK_PREVIEW_COUNT) {
+        println!("  {} | {}", email.from, email.subject);
+    }
+    if emails.len() > BULK_PREVIEW_COUNT {
+        println!("  ... and {} more", emails.len() - BULK_PREVIEW_COUNT);
+    }
+    let question = format!("{} {} email(s)?", capitalize(&action), emails.len());
+    if !yes && !confirm(&question)? {
+        println!("Cancelled.");
+        return Ok(());
     }
 
+    let ids = emails
+        .iter()
+        .map(|email| email.id.as_str())
+        .collect::<Vec<_>>();
+    let report = apply_bulk(provider.as_ref(), &operation, &ids).await;
+    for (id, error) in &report.failed {
+        warn!(id, "failed: {:#}", error);
+    }
+    println!(
+        "Done: {} succeeded, {} failed",
+        report.succeeded,
+        report.failed.len()
+    );
+    if !report.failed.is_empty() {
+        anyhow::bail!("{} of {} email(s) failed", report.failed.len(), ids.len());
+    }
     Ok(())
 }
 
-pub async fn summary(provider_name: &str) -> Result<()> {
+/// Archive every inbox email but the newest of each set of duplicate deliveries
+pub async fn dedupe(max: u32, dry_run: bool, provider_name: &str) -> Result<()> {
     let provider = create_provider(provider_name).await?;
+    provider.capabilities().require(ProviderOp::Archive)?;
     let emails = provider
-        .list_messages(100, "INBOX", Some(INBOX_CLASSIFICATION_QUERY))
+        .list_messages(max, "INBOX", None, SortOrder::NewestFirst)
         .await?;
+    let groups = dedupe::duplicate_groups(&emails);
+    if groups.is_empty() {
+        println!("No duplicates among {} inbox email(s).", emails.len());
+        return Ok(());
+    }
 
-    if emails.is_empty() {
-        println!("No unclassified emails in inbox.");
+    let verb = if dry_run { "Would archive" } else { "Archive" };
+    let mut ids = Vec::new();
+    for group in &groups {
+        let (newest, copies) = group.split_first().expect("groups have several emails");
+        println!(
+            "{} | {} ({} copies)",
+            newest.from,
+            newest.subject,
+            group.len()
+        );
+        println!("  Keep {}", newest.id);
+        let archivable = dedupe::archivable_copies(group);
+        for copy in &archivable {
+            println!("  {} {}", verb, copy.id);
+            ids.push(copy.id.as_str());
+        }
+        let shared = copies.len() - archivable.len();
+        if shared > 0 {
+            println!("  Skip {} copy(ies) sharing an ID with another", shared);
+        }
+    }
+    if dry_run {
+        println!(
+            "\nWould archive {} duplicate(s) in {} group(s)",
+            ids.len(),
+            groups.len()
+        );
         return Ok(());
     }
 
-    println!("Analyzing {} emails...\n", emails.len());
-    let prompt = summary_prompt(&emails);
-    let response = run_summary_prompt(&prompt).await?;
-    println!("{}", response.trim());
+    let report = apply_bulk(provider.as_ref(), &BulkOperation::Archive, &ids).await;
+    for (id, error) in &report.failed {
+        warn!(id, "failed: {:#}", error);
+    }
+    println!(
+        "\nArchived {} duplicate(s) in {} group(s), {} failed",
+        report.succeeded,
+        groups.len(),
+        report.failed.len()
+    );
+    if !report.failed.is_empty() {
+        anyhow::bail!("{} of {} email(s) failed", report.failed.len(), ids.len());
+    }
     Ok(())
 }
 
-fn validate_provider_name(provider: &str) -> Result<()> {
-    match provider {
-        "gmail" | "outlook" | "outlook-web" => Ok(()),
-        _ => anyhow::bail!(
-            "Invalid provider: {}. Use 'gmail', 'outlook', or 'outlook-web'",
-            provider
-        ),
+async fn apply_bulk(
+    provider: &dyn EmailProvider,
+    operation: &BulkOperation,
+    ids: &[&str],
+) -> BulkReport {
+    let results = stream::iter(ids)
+        .map(|id| async move { (*id, operation.apply(provider, id).await) })
+        .buffer_unordered(BULK_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut report = BulkReport::default();
+    for (id, result) in results {
+        match result {
+            Ok(()) => report.succeeded += 1,
+            Err(error) => report.failed.push((id.to_string(), error)),
+        }
     }
+    report
 }
 
-async fn login_gmail() -> Result<()> {
-    let cfg = gmail::config::load_config()?;
-    let client_id = cfg.client_id();
-    let client_secret = cfg.client_secret();
-    gmail::auth::login(client_id, client_secret).await?;
-    println!("Gmail login successful! Tokens saved.");
-    Ok(())
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
 }
 
-async fn login_outlook() -> Result<()> {
-    let cfg = outlook::config::load_config()?;
-    let client_id = cfg.client_id();
-    outlook::auth::login(client_id).await?;
-    println!("Outlook login successful! Tokens saved.");
+/// Other providers only understand `-label:`, so they would silently ignore
+/// the rest of a search and match everything
+fn ensure_query_supported(provider_name: &str) -> Result<()> {
+    if !matches!(provider_name, "gmail" | "outlook") {
+        anyhow::bail!("--query is only supported with the gmail and outlook providers");
+    }
     Ok(())
 }
 
-async fn run_scan_correction_pass(
-    provider: &dyn EmailProvider,
-    profile: &mut Profile,
-    predictions: &PredictionStore,
-    dry_run: bool,
-) -> Result<CorrectionPass> {
-    let mut learning = LearningEngine::new(provider, profile, predictions);
-    let result = learning.detect_corrections().await?;
-    let corrected_ids = result
-        .corrections
-        .iter()
-        .map(|correction| correction.email_id.clone())
-        .collect::<Vec<_>>();
-    let had_corrections = !result.corrections.is_empty();
+#[tracing::instrument(name = "learn", skip_all, fields(provider = provider_name))]
+pub async fn learn(dry_run: bool, provider_name: &str) -> Result<()> {
+    if !learns_from_provider(provider_name) {
+        println!(
+            "The mock mailbox starts over on every run, so there are no corrections to learn."
+        );
+        return Ok(());
+    }
+    let cfg = Config::load()?;
+    let provider = create_provider(provider_name).await?;
+    let mut profile = Profile::load()?;
+    let mut predictions = PredictionStore::load()?;
+    let mut sender_stats = SenderStats::load()?;
+    let pruned = prune_stale_predictions(&mut predictions, &cfg, dry_run);
+    let correction_pass = run_learning_pass(
+        provider.as_ref(),
+        &mut profile,
+        &predictions,
+        &mut sender_stats,
+        &BatchPolicy::from_config(&cfg),
+        &cfg.llm(),
+        dry_run,
+    )
+    .await?;
 
-    if had_corrections {
-        print_scan_corrections(&result.corrections);
-        if dry_run {
-            println!("  [dry-run] Would update profile with corrections");
-        } else {
-            apply_corrections_in_batches(&mut learning, &result.corrections, true).await?;
-        }
+    if correction_pass.had_corrections && !dry_run {
+        profile.save()?;
+        println!("Profile updated.");
     }
 
-    Ok(CorrectionPass {
-        deleted_ids: result.deleted_ids,
-        corrected_ids,
-        had_corrections,
-    })
+    let cleaned = cleanup_deleted_predictions(&mut predictions, &correction_pass.deleted_ids);
+    if (pruned > 0 || cleaned) && !dry_run {
+        predictions.save()?;
+    }
+    report_llm_usage(&cfg, dry_run)
 }
 
-async fn run_learning_pass(
-    provider: &dyn EmailProvider,
-    profile: &mut Profile,
-    predictions: &PredictionStore,
-    dry_run: bool,
-) -> Result<CorrectionPass> {
-    let mut learning = LearningEngine::new(provider, profile, predictions);
-    let result = learning.detect_corrections().await?;
-    let had_corrections = !result.corrections.is_empty();
+pub async fn verify(fix: bool, dry_run: bool, provider_name: &str) -> Result<()> {
+    let provider = create_provider(provider_name).await?;
+    let mut predictions = PredictionStore::load()?;
+    let verification = verify_predictions(provider.as_ref(), &predictions).await;
+    let drifts = verification.drifts;
 
-    if !had_corrections {
-        println!("No corrections found.");
-        return Ok(CorrectionPass {
-            deleted_ids: result.deleted_ids,
-            corrected_ids: Vec::new(),
-            had_corrections: false,
-        });
+    if drifts.is_empty() {
+        println!("All stored predictions match the provider.");
+    } else {
+        println!("{} inconsistent prediction(s):\n", drifts.len());
+        for drift in &drifts {
+            print_drift(drift);
+        }
+    }
+    print_accuracy(&verification.accuracy);
+    if !verification.failed.is_empty() {
+        println!(
+            "\nCouldn't check {} prediction(s); they are kept as they are:",
+            verification.failed.len()
+        );
+        for (id, error) in &verification.failed {
+            println!("  {}: {}", id, error);
+        }
     }
 
-    print_learning_corrections(&result.corrections);
-    if dry_run {
-        println!("\n[dry-run] Would update profile with these corrections");
-    } else {
-        apply_corrections_in_batches(&mut learning, &result.corrections, false).await?;
+    if drifts.is_empty() {
+        return Ok(());
+    }
+    if !fix {
+        println!("\nRun with --fix to re-stamp missing labels and prune missing emails.");
+        return Ok(());
     }
 
-    Ok(CorrectionPass {
-        deleted_ids: result.deleted_ids,
-        corrected_ids: Vec::new(),
-        had_corrections: true,
-    })
+    println!();
+    let pruned = reconcile_drifts(provider.as_ref(), &mut predictions, &drifts, dry_run).await;
+    if pruned > 0 && !dry_run {
+        predictions.save()?;
+    }
+    Ok(())
 }
 
-async fn apply_corrections_in_batches<P: EmailProvider + ?Sized>(
-    learning: &mut LearningEngine<'_, P>,
-    corrections: &[Correction],
-    continue_on_error: bool,
-) -> Result<()> {
-    let chunks = corrections
-        .chunks(CORRECTION_BATCH_SIZE)
-        .collect::<Vec<_>>();
-
-    for (index, chunk) in chunks.iter().enumerate() {
-        print_correction_batch_status(index, chunks.len());
-
-        if continue_on_error {
-            if let Err(error) = learning.apply_corrections(chunk).await {
-                eprintln!("  Warning: profile update failed: {}", error);
-                eprintln!("  Continuing with classification...");
-            }
-            continue;
-        }
+pub async fn profile() -> Result<()> {
+    let profile = Profile::load()?;
+    println!("{}", profile.content());
+    Ok(())
+}
 
-        learning.apply_corrections(chunk).await?;
+pub async fn profile_edit() -> Result<()> {
+    let path = config::profile_path();
+    if !path.exists() {
+        Profile::load()?.save()?;
     }
 
+    open_in_editor(&path).await?;
+    warn_missing_profile_sections(&Profile::load()?);
     Ok(())
 }
 
-fn print_correction_batch_status(index: usize, total_batches: usize) {
-    if total_batches > 1 {
-        println!(
-            "Updating profile (batch {}/{})...",
-            index + 1,
-            total_batches
-        );
-    } else {
-        println!("Updating profile...");
+/// Print the classification prompt template in use
+pub async fn prompt_show() -> Result<()> {
+    let path = config::prompt_template_path();
+    if !path.exists() {
+        println!("# Built-in prompt; `prompt edit` saves a copy to customize");
     }
+    println!("{}", PromptTemplate::load()?.text());
+    Ok(())
 }
 
-fn print_scan_corrections(corrections: &[Correction]) {
-    println!("Found {} corrections:", corrections.len());
-    for correction in corrections {
-        println!(
-            "  - {} | {} (predicted: {:?}, actual: {:?})",
-            correction.email_id,
-            correction.subject.chars().take(40).collect::<String>(),
-            correction.predicted_labels,
-            correction.actual_labels
-        );
+/// Open prompt.tmpl in $EDITOR, starting from the built-in prompt
+pub async fn prompt_edit() -> Result<()> {
+    let path = config::prompt_template_path();
+    if !path.exists() {
+        PromptTemplate::default().save()?;
     }
-}
 
-fn print_learning_corrections(corrections: &[Correction]) {
-    println!("Found {} corrections:", corrections.len());
-    for correction in corrections {
-        println!(
-            "  - {} (predicted: {:?}, actual: {:?})",
-            correction.email_id, correction.predicted_labels, correction.actual_labels
-        );
-    }
+    open_in_editor(&path).await?;
+    warn_prompt_placeholders(&PromptTemplate::load()?);
+    Ok(())
 }
 
-fn persist_profile(profile: &Profile, had_corrections: bool, dry_run: bool) -> Result<()> {
-    if had_corrections && !dry_run {
-        profile.save()?;
+/// Delete prompt.tmpl so classification goes back to the built-in prompt
+pub async fn prompt_reset(dry_run: bool) -> Result<()> {
+    let path = config::prompt_template_path();
+    if !path.exists() {
+        println!("Already using the built-in prompt.");
+        return Ok(());
     }
+    if dry_run {
+        println!("Would remove {}", path.display());
+        return Ok(());
+    }
+    std::fs::remove_file(&path)?;
+    println!("Removed {}; using the built-in prompt.", path.display());
     Ok(())
 }
 
-fn remove_predictions(
-    predictions: &mut PredictionStore,
-    deleted_ids: &[String],
-    corrected_ids: &[String],
-    dry_run: bool,
-) {
+pub async fn profile_reset(skip_confirmation: bool, dry_run: bool) -> Result<()> {
     if dry_run {
-        return;
+        println!(
+            "Would reset profile to defaults: {}",
+            config::profile_path().display()
+        );
+        return Ok(());
     }
 
-    for id in deleted_ids {
-        predictions.remove(id);
-    }
-    for id in corrected_ids {
-        predictions.remove(id);
+    if !skip_confirmation && !confirm("Reset profile to defaults? Learned rules will be lost.")? {
+        println!("Profile unchanged.");
+        return Ok(());
     }
-}
-
-async fn load_scan_emails(
-    provider: &dyn EmailProvider,
-    max: u32,
-    archived: bool,
-) -> Result<Vec<Email>> {
-    let label = if archived { "" } else { "INBOX" };
-    let query = if archived {
-        ARCHIVED_CLASSIFICATION_QUERY
-    } else {
-        INBOX_CLASSIFICATION_QUERY
-    };
 
-    provider.list_messages(max, label, Some(query)).await
+    let mut profile = Profile::load()?;
+    profile.reset();
+    profile.save()?;
+    println!("Profile reset to defaults.");
+    Ok(())
 }
 
-async fn process_scan_email(
-    provider: &dyn EmailProvider,
-    classifier: &Classifier<'_>,
-    user_rules: &[rules::Rule],
-    predictions: &mut PredictionStore,
-    email: Email,
-    dry_run: bool,
-) -> Result<()> {
-    let mut classification = classifier.classify(&email).await?;
-    rules::apply_rules(&email, &mut classification, user_rules);
-    protect_personal_and_reply_emails(&mut classification);
+/// Have the model merge redundant and contradictory profile lines, show the
+/// diff, and save the result once confirmed. Saving backs up the old profile.
+pub async fn profile_compact(skip_confirmation: bool, dry_run: bool) -> Result<()> {
+    let mut profile = Profile::load()?;
+    let backend = ClaudeBackend::new(&Config::load()?.llm());
+    println!("Asking the model to compact the profile...");
+    let Some(compacted) = compact_profile(&profile, &backend).await? else {
+        println!("Nothing to compact.");
+        return Ok(());
+    };
+    let diff = diff_lines(profile.content(), &compacted);
+    if diff.is_empty() {
+        println!("Nothing to compact.");
+        return Ok(());
+    }
 
-    print_scan_result(&email, &classification);
+    println!("\n--- current profile");
+    println!("+++ compacted profile");
+    for line in diff {
+        println!("{}", line);
+    }
+    println!(
+        "\n{} lines → {} lines",
+        profile.content().lines().count(),
+        compacted.lines().count()
+    );
     if dry_run {
-        print_scan_dry_run(&classification);
+        println!("[dry-run] Would save the compacted profile");
+        return Ok(());
+    }
+    if !skip_confirmation && !confirm("Save the compacted profile?")? {
+        println!("Profile unchanged.");
         return Ok(());
     }
 
-    apply_scan_actions(provider, predictions, &email, &classification).await
+    profile.update(compacted);
+    profile.save()?;
+    println!("Profile compacted. `profile restore` lists the backup of the previous one.");
+    Ok(())
 }
 
-fn protect_personal_and_reply_emails(classification: &mut Classification) {
-    if !classification.delete {
-        return;
+/// Replace the profile with one rebuilt from the default by replaying every
+/// logged correction, after showing the diff. `offline` replays without the
+/// model, so only the corrections themselves are recorded.
+pub async fn profile_rebuild(offline: bool, skip_confirmation: bool, dry_run: bool) -> Result<()> {
+    let log = CorrectionLog::load()?;
+    if log.is_empty() {
+        println!("No logged corrections to rebuild the profile from.");
+        return Ok(());
     }
 
-    let is_personal = classification
-        .theme
-        .iter()
-        .any(|theme| theme.eq_ignore_ascii_case("Personal"));
-    let needs_reply = classification
-        .action
-        .iter()
-        .any(|action| action.eq_ignore_ascii_case("Needs-Reply"));
+    println!(
+        "Rebuilding the profile from {} logged corrections...",
+        log.entries().len()
+    );
+    let rebuilt = replay_log(&log, offline).await?;
 
-    if is_personal || needs_reply {
-        classification.delete = false;
+    let mut profile = Profile::load()?;
+    let diff = diff_lines(profile.content(), rebuilt.content());
+    if diff.is_empty() {
+        println!("The rebuilt profile matches the current one.");
+        return Ok(());
+    }
+    print_profile_diff(&diff, "rebuilt");
+    if dry_run {
+        println!("[dry-run] Would save the rebuilt profile");
+        return Ok(());
+    }
+    if !skip_confirmation && !confirm("Replace the profile with the rebuilt one?")? {
+        println!("Profile unchanged.");
+        return Ok(());
     }
+
+    profile.update(rebuilt.content().to_string());
+    profile.save()?;
+    println!("Profile rebuilt. `profile restore` lists the backup of the previous one.");
+    Ok(())
 }
 
-fn print_scan_result(email: &Email, classification: &Classification) {
-    let is_important = classification
-        .action
-        .iter()
-        .any(|action| action == "Important" || action == "Urgent");
-    let status = build_status_indicators(&email.labels, is_important);
-    let action_suffix = action_suffix(classification);
-    let labels = classification.labels();
+/// Histogram of stored prediction confidence, and how many fall below `threshold`
+pub async fn profile_stats(threshold: f32) -> Result<()> {
+    let predictions = PredictionStore::load()?;
+    let histogram = predictions.confidence_histogram();
+    let total: usize = histogram.iter().sum();
+    if total == 0 {
+        println!("No predictions stored yet.");
+        return Ok(());
+    }
 
+    println!("Confidence of {} stored predictions:", total);
+    let widest = histogram.iter().copied().max().unwrap_or(0);
+    for (bucket, &count) in histogram.iter().enumerate() {
+        let bar_len = (count * HISTOGRAM_WIDTH).div_ceil(widest);
+        println!(
+            "  {:.1}-{:.1} | {:<width$} {}",
+            bucket as f32 / CONFIDENCE_BUCKETS as f32,
+            (bucket + 1) as f32 / CONFIDENCE_BUCKETS as f32,
+            "#".repeat(bar_len),
+            count,
+            width = HISTOGRAM_WIDTH
+        );
+    }
+    let below = predictions.count_below(threshold);
     println!(
-        "{} | {} | {:?}{}",
-        status,
-        email.subject.chars().take(60).collect::<String>(),
-        labels,
-        action_suffix
+        "Below {:.2}: {} ({:.0}%)",
+        threshold,
+        below,
+        below as f32 * 100.0 / total as f32
     );
+    Ok(())
 }
 
-fn action_suffix(classification: &Classification) -> &'static str {
-    if classification.delete {
-        " → DELETE"
-    } else if classification.archive {
-        " → archive"
-    } else {
-        ""
-    }
-}
+/// List profile backups, or restore one by its number in that list or its file name
+pub async fn profile_restore(backup: Option<&str>, dry_run: bool) -> Result<()> {
+    let backups = profile::list_backups()?;
+    let Some(backup) = backup else {
+        if backups.is_empty() {
+            println!("No profile backups yet.");
+        }
+        for (index, path) in backups.iter().enumerate() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            println!("  {:>2}. {}", index + 1, name);
+        }
+        return Ok(());
+    };
 
-fn print_scan_dry_run(classification: &Classification) {
-    println!(
-        "  [dry-run] Would apply labels: {:?}",
-        classification.labels()
-    );
-    if classification.delete {
-        println!("  [dry-run] Would DELETE");
-        return;
+    let chosen = match backup.parse::<usize>() {
+        Ok(number) => number.checked_sub(1).and_then(|index| backups.get(index)),
+        Err(_) => backups
+            .iter()
+            .find(|path| path.file_name() == Some(backup.as_ref())),
     }
-    if classification.archive {
-        println!("  [dry-run] Would archive");
+    .with_context(|| {
+        format!(
+            "No profile backup \"{}\" (run `profile restore` to list them)",
+            backup
+        )
+    })?;
+
+    if dry_run {
+        println!("Would restore profile from {}", chosen.display());
+        return Ok(());
     }
+    let mut profile = Profile::load()?;
+    profile.update(std::fs::read_to_string(chosen)?);
+    // Saving backs up the profile being replaced, so a restore can be undone
+    profile.save()?;
+    println!("Profile restored from {}", chosen.display());
+    Ok(())
 }
 
-async fn apply_scan_actions(
-    provider: &dyn EmailProvider,
-    predictions: &mut PredictionStore,
-    email: &Email,
-    classification: &Classification,
+/// With `fast`, list stored predictions as-is; otherwise fetch each email to
+/// drop deleted ones and show its unread state
+pub async fn needs_reply(
+    sort: NeedsReplySort,
+    fast: bool,
+    label: Option<&str>,
+    provider_name: &str,
 ) -> Result<()> {
-    if classification.delete {
-        trash_email(provider, email).await;
-        return Ok(());
+    let predictions = PredictionStore::load()?;
+    let mut pending = predictions.needs_reply(sort);
+    if let Some(label) = label {
+        pending.retain(|prediction| prediction.has_theme_within(label));
     }
 
-    let labels = classification.labels();
-    add_predicted_labels(provider, email, &labels).await;
-    store_classification_prediction(provider, predictions, email, classification).await?;
-    archive_if_needed(provider, email, classification).await;
-    Ok(())
-}
+    // Sorted by date, a thread's first listed message is its latest, so
+    // threads come out by most recent activity
+    let threads = group_reply_threads(pending);
 
-async fn trash_email(provider: &dyn EmailProvider, email: &Email) {
-    if let Err(error) = provider.trash(&email.id).await {
-        eprintln!("  Warning: couldn't delete: {}", error);
+    let formatter = output::formatter();
+    if formatter.is_plain() {
+        println!("Emails that need a reply:\n");
     }
-}
+    let entries = if fast {
+        threads.into_iter().map(|thread| (thread, None)).collect()
+    } else {
+        let provider = create_provider(provider_name).await?;
+        live_needs_reply(provider.as_ref(), threads)
+            .await
+            .into_iter()
+            .map(|(thread, email)| (thread, Some(email)))
+            .collect::<Vec<_>>()
+    };
 
-async fn add_predicted_labels(provider: &dyn EmailProvider, email: &Email, labels: &[String]) {
-    for label in labels {
-        if let Err(error) = provider.add_label(&email.id, label).await {
-            eprintln!("  Warning: couldn't apply label '{}': {}", label, error);
-        }
+    let rows = entries
+        .iter()
+        .map(|(thread, email)| NeedsReplyRow::new(thread, email.as_ref()))
+        .collect::<Vec<_>>();
+    formatter.print(&rows);
+    if rows.is_empty() && formatter.is_plain() {
+        println!("No emails need a reply.");
     }
+
+    Ok(())
 }
 
-async fn store_classification_prediction(
+/// Fetch each thread's latest email concurrently, keeping order and dropping
+/// threads whose latest email the provider no longer has
+async fn live_needs_reply<'p>(
     provider: &dyn EmailProvider,
-    predictions: &mut PredictionStore,
-    email: &Email,
-    classification: &Classification,
+    threads: Vec<ReplyThread<'p>>,
+) -> Vec<(ReplyThread<'p>, Email)> {
+    stream::iter(threads)
+        .map(|thread| async move {
+            let email = provider.get_message(&thread.latest().email_id).await;
+            email.ok().map(|email| (thread, email))
+        })
+        .buffered(NEEDS_REPLY_CONCURRENCY)
+        .filter_map(|entry| async move { entry })
+        .collect()
+        .await
+}
+
+pub async fn needs_reply_done(
+    id: Option<&str>,
+    remove_label: bool,
+    dry_run: bool,
+    provider_name: &str,
 ) -> Result<()> {
-    match provider.add_label(&email.id, "Classified").await {
-        Ok(_) => {
-            let pre_existing = email
-                .labels
-                .iter()
-                .filter(|label| !is_system_label(label))
-                .cloned()
-                .collect();
-            predictions.store(
-                &email.id,
-                &email.from,
-                &email.subject,
-                classification,
-                pre_existing,
-            )?;
-        }
-        Err(error) => {
-            eprintln!("  Warning: couldn't apply Classified label: {}", error);
+    let mut predictions = PredictionStore::load()?;
+    let ids: Vec<String> = match id {
+        Some(id) => predictions.needs_reply_thread(id),
+        None => predictions
+            .needs_reply(NeedsReplySort::default())
+            .iter()
+            .map(|prediction| prediction.email_id.clone())
+            .collect(),
+    };
+
+    if dry_run {
+        for id in &ids {
+            println!("Would mark {} as replied", id);
         }
+        return Ok(());
     }
 
-    Ok(())
-}
+    let provider = if remove_label {
+        Some(create_provider(provider_name).await?)
+    } else {
+        None
+    };
 
-async fn archive_if_needed(
-    provider: &dyn EmailProvider,
-    email: &Email,
-    classification: &Classification,
-) {
-    if !classification.archive {
-        return;
+    let mut dismissed = 0;
+    for id in &ids {
+        let mut label_kept = true;
+        if let Some(provider) = &provider {
+            match provider.remove_label(id, "Needs-Reply").await {
+                Ok(()) => label_kept = false,
+                Err(error) => warn!(id, "couldn't remove label: {}", error),
+            }
+        }
+        if predictions.dismiss_needs_reply(id, label_kept) {
+            dismissed += 1;
+        } else {
+            println!("{} is not on the needs-reply list", id);
+        }
     }
 
-    if let Err(error) = provider.archive(&email.id).await {
-        eprintln!("  Warning: couldn't archive: {}", error);
+    if dismissed > 0 {
+        predictions.save()?;
     }
+    println!("✓ Marked {} email(s) as replied", dismissed);
+    Ok(())
 }
 
-fn save_predictions(predictions: &PredictionStore, dry_run: bool) -> Result<()> {
-    if dry_run {
-        println!("\n[dry-run] Would save predictions");
+pub async fn summary(
+    max: u32,
+    format: SummaryFormat,
+    out: Option<&Path>,
+    provider_name: &str,
+) -> Result<()> {
+    let provider = create_provider(provider_name).await?;
+    let emails = provider
+        .list_messages(
+            max,
+            "INBOX",
+            Some(INBOX_CLASSIFICATION_QUERY),
+            SortOrder::NewestFirst,
+        )
+        .await?;
+
+    if emails.is_empty() {
+        println!("No unclassified emails in inbox.");
         return Ok(());
     }
 
-    predictions.save()
+    println!("Analyzing {} emails...\n", emails.len());
+    let cfg = Config::load()?;
+    let prompt = summary_prompt(&emails);
+    let response = run_claude_prompt(&prompt, &cfg.llm()).await?;
+    let title = format!("Inbox summary {}", chrono::Local::now().format("%Y-%m-%d"));
+    let rendered = digest::render(&response, format, &title);
+    match out {
+        Some(path) => {
+            std::fs::write(path, rendered)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!("Wrote summary to {}", path.display());
+        }
+        None => print!("{}", rendered),
+    }
+    report_llm_usage(&cfg, false)
 }
 
-fn print_label_cleanup_result(labels: &[String], dry_run: bool) {
-    if dry_run {
-        println!("Would remove {} labels:", labels.len());
-    } else {
-        println!("Removed {} labels:", labels.len());
-    }
+/// Daily LLM usage recorded by scan, learn, and summary over the last `days`
+pub async fn usage(days: u32) -> Result<()> {
+    let log = UsageLog::load()?;
+    let since = (chrono::Utc::now() - chrono::Duration::days(i64::from(days.saturating_sub(1))))
+        .format("%Y-%m-%d")
+        .to_string();
 
-    for label in labels {
-        println!("  - {}", label);
+    let mut total = LlmStats::default();
+    for (date, stats) in log.since(&since) {
+        println!("{}  {}", date, format_llm_stats(stats));
+        total.add(stats);
     }
+    if total.calls == 0 {
+        println!("No LLM usage recorded in the last {} days.", days);
+        return Ok(());
+    }
+    println!("\nLast {} days: {}", days, format_llm_stats(&total));
+    Ok(())
 }
 
-fn cleanup_deleted_predictions(
-    predictions: &mut PredictionStore,
-    deleted_ids: &[String],
+pub async fn send(
+    to: &str,
+    subject: &str,
+    body: &str,
     dry_run: bool,
+    provider_name: &str,
 ) -> Result<()> {
-    if deleted_ids.is_empty() {
+    if dry_run {
+        let message = mime::Message {
+            to,
+            subject,
+            body,
+            ..Default::default()
+        };
+        println!(
+            "Would send:\n\n{}",
+            message.to_rfc822().replace("\r\n", "\n")
+        );
         return Ok(());
     }
 
-    println!(
-        "Cleaned up {} deleted emails from predictions.",
-        deleted_ids.len()
-    );
+    let provider = create_provider(provider_name).await?;
+    provider.capabilities().require(ProviderOp::Send)?;
+    provider.send(to, subject, body).await?;
+    println!("✓ Sent to {}", to);
+    Ok(())
+}
+
+pub async fn draft_reply(id: &str, dry_run: bool, provider_name: &str) -> Result<()> {
+    let provider = create_provider(provider_name).await?;
+    // Checked before the LLM call the draft would otherwise waste
+    if !dry_run {
+        provider.capabilities().require(ProviderOp::Draft)?;
+    }
+    let email = provider.get_message(id).await?;
+    let profile = Profile::load()?;
+
+    println!("Drafting reply to: \"{}\"", email.subject);
+    println!("  From: {}\n", email.from);
+    let draft = run_claude_prompt(
+        &draft_reply_prompt(&profile, &email),
+        &Config::load()?.llm(),
+    )
+    .await?;
+    let draft = draft.trim();
 
     if dry_run {
+        println!("{}", draft);
         return Ok(());
     }
 
-    for id in deleted_ids {
-        predictions.remove(id);
+    let draft_id = provider.create_draft(id, draft).await?;
+    println!("{}\n", draft);
+    println!("✓ Draft saved ({})", draft_id);
+    Ok(())
+}
+
+pub async fn capabilities(provider_name: &str) -> Result<()> {
+    let provider = create_provider(provider_name).await?;
+    println!("Capabilities of {}:", provider_name);
+    for (name, supported) in provider.capabilities().entries() {
+        let mark = if supported { "✓" } else { "✗" };
+        println!("  {} {}", mark, name);
+    }
+    Ok(())
+}
+
+/// List quarantined emails with the time each has left before deletion
+pub async fn quarantine_review() -> Result<()> {
+    let cfg = Config::load()?;
+    let quarantine = Quarantine::load()?;
+    if quarantine.is_empty() {
+        println!("Nothing in quarantine.");
+        return Ok(());
+    }
+
+    let now = chrono::Utc::now();
+    for entry in quarantine.entries() {
+        let remaining = entry.delete_after(cfg.quarantine_grace()) - now;
+        let due = if remaining > chrono::Duration::zero() {
+            format!(
+                "deleted in {}d {}h",
+                remaining.num_days(),
+                remaining.num_hours() % 24
+            )
+        } else {
+            "deleted on the next scan".to_string()
+        };
+        println!("{} | {} | {}", entry.email_id, entry.from, entry.subject);
+        println!(
+            "  quarantined {}, {}",
+            entry.quarantined_at.format("%Y-%m-%d"),
+            due
+        );
+        if let Some(reason) = &entry.reason {
+            println!("  Reason: {}", reason);
+        }
+    }
+    println!("\n{} quarantined emails", quarantine.len());
+    Ok(())
+}
+
+/// Take an email out of quarantine and back to the inbox, marked Classified
+/// so the next scan doesn't quarantine it again
+pub async fn quarantine_rescue(id: &str, dry_run: bool, provider_name: &str) -> Result<()> {
+    let mut quarantine = Quarantine::load()?;
+    let Some(entry) = quarantine.remove(id) else {
+        anyhow::bail!("{} is not in quarantine (see `quarantine review`)", id);
+    };
+    if dry_run {
+        println!("[dry-run] Would move back to the inbox: {}", entry.subject);
+        return Ok(());
+    }
+
+    let provider = create_provider(provider_name).await?;
+    provider.remove_label(id, QUARANTINE_LABEL).await?;
+    provider.add_label(id, "Classified").await?;
+    // Moves the email back to the inbox on every provider
+    provider.unspam(id).await?;
+    quarantine.save()?;
+    println!("Rescued: {}", entry.subject);
+    Ok(())
+}
+
+/// Delete a quarantined email now instead of waiting out the grace period
+pub async fn quarantine_confirm(id: &str, dry_run: bool, provider_name: &str) -> Result<()> {
+    let mut quarantine = Quarantine::load()?;
+    let Some(entry) = quarantine.remove(id) else {
+        anyhow::bail!("{} is not in quarantine (see `quarantine review`)", id);
+    };
+    if dry_run {
+        println!("[dry-run] Would delete: {}", entry.subject);
+        return Ok(());
+    }
+
+    let provider = create_provider(provider_name).await?;
+    provider.trash(id).await?;
+    quarantine.save()?;
+    println!("Deleted: {}", entry.subject);
+    Ok(())
+}
+
+/// Delete every quarantined email whose grace period is over
+pub async fn quarantine_purge(dry_run: bool, provider_name: &str) -> Result<()> {
+    let cfg = Config::load()?;
+    let provider = create_provider(provider_name).await?;
+    let mut quarantine = Quarantine::load()?;
+    let deleted = purge_quarantine(
+        provider.as_ref(),
+        &mut quarantine,
+        cfg.quarantine_grace(),
+        dry_run,
+    )
+    .await?;
+    if dry_run {
+        return Ok(());
+    }
+    quarantine.save()?;
+    if deleted == 0 {
+        println!("No quarantined emails are past their grace period.");
+    }
+    Ok(())
+}
+
+pub async fn contacts_list() -> Result<()> {
+    let contacts = Contacts::load()?;
+    if contacts.is_empty() {
+        println!("No contacts. Run `contacts sync` or list addresses in contacts.json.");
+        return Ok(());
+    }
+    for address in contacts.addresses() {
+        println!("  {}", address);
+    }
+    println!("{} contacts", contacts.len());
+    Ok(())
+}
+
+pub async fn contacts_sync(dry_run: bool, provider_name: &str) -> Result<()> {
+    let provider = create_provider(provider_name).await?;
+    let mut contacts = Contacts::load()?;
+    let added = sync_contacts(provider.as_ref(), &mut contacts).await?;
+    if dry_run {
+        println!("[dry-run] Would add {} new contacts", added);
+        return Ok(());
+    }
+    contacts.save()?;
+    println!("Added {} new contacts ({} total)", added, contacts.len());
+    Ok(())
+}
+
+/// Merge the provider's contacts into the local list, returning how many were new
+async fn sync_contacts(provider: &dyn EmailProvider, contacts: &mut Contacts) -> Result<usize> {
+    provider.capabilities().require(ProviderOp::Contacts)?;
+    Ok(contacts.merge(provider.list_contacts().await?))
+}
+
+/// `json` is the older spelling of `--output json`
+pub async fn stats(json: bool, rollup: bool) -> Result<()> {
+    let stats = PredictionStore::load()?.stats(rollup);
+
+    match output::formatter() {
+        _ if json => println!("{}", serde_json::to_string(&stats)?),
+        Formatter::Json => println!("{}", serde_json::to_string(&stats)?),
+        Formatter::Table => Formatter::Table.print(&stats_rows(&stats)),
+        Formatter::Plain => print_stats(&stats),
+    }
+    Ok(())
+}
+
+/// Compare each confidence bucket with how often its predictions were
+/// corrected. Read-only: the corrections aren't learned from.
+pub async fn calibrate(provider_name: &str) -> Result<()> {
+    let provider = create_provider(provider_name).await?;
+    let mut profile = Profile::load()?;
+    let predictions = PredictionStore::load()?;
+    if predictions.all_predictions().next().is_none() {
+        println!("No predictions stored yet.");
+        return Ok(());
+    }
+
+    let result = LearningEngine::new(provider.as_ref(), &mut profile, &predictions)
+        .detect_corrections()
+        .await?;
+    let buckets =
+        predictions.calibration(&correction_ids(&result.corrections), &result.deleted_ids);
+    let rows = calibration_rows(&buckets);
+    let formatter = output::formatter();
+    if formatter.is_plain() {
+        let checked: usize = buckets.iter().map(|bucket| bucket.predictions).sum();
+        println!(
+            "{} corrected of {} predictions still on the provider:",
+            result.corrections.len(),
+            checked
+        );
+    }
+    formatter.print(&rows);
+    Ok(())
+}
+
+pub async fn export(format: ExportFormat, out: Option<&Path>, corrections: bool) -> Result<()> {
+    let writer: Box<dyn std::io::Write> = match out {
+        Some(path) => Box::new(std::io::BufWriter::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?,
+        )),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    let (count, kind) = if corrections {
+        let profile = Profile::load()?;
+        (
+            export::write_corrections(&profile, format, writer)?,
+            "corrections",
+        )
+    } else {
+        let predictions = PredictionStore::load()?;
+        (
+            export::write_predictions(&predictions, format, writer)?,
+            "predictions",
+        )
+    };
+
+    if let Some(path) = out {
+        println!("Exported {} {} to {}", count, kind, path.display());
+    }
+    Ok(())
+}
+
+pub async fn simulate(dataset: Option<&Path>, offline: bool) -> Result<()> {
+    let log = match dataset {
+        Some(path) => CorrectionLog::load_from(path)?,
+        None => CorrectionLog::load()?,
+    };
+    if log.is_empty() {
+        println!("No corrections to replay.");
+        return Ok(());
+    }
+
+    println!("Replaying {} corrections...", log.entries().len());
+    let simulated = replay_log(&log, offline).await?;
+
+    println!("\n{}", simulated.content());
+    let diff = diff_lines(Profile::load()?.content(), simulated.content());
+    print_profile_diff(&diff, "simulated");
+    if diff.is_empty() {
+        println!("(identical)");
+    }
+    Ok(())
+}
+
+/// Replay a correction log onto the default profile, for `simulate` and
+/// `profile rebuild`. `offline` records the corrections without the model.
+async fn replay_log(log: &CorrectionLog, offline: bool) -> Result<Profile> {
+    let backend: Box<dyn LearningBackend> = if offline {
+        Box::new(ReplayBackend::default())
+    } else {
+        Box::new(ClaudeBackend::new(&Config::load()?.llm()))
+    };
+    replay_corrections(log.entries(), backend.as_ref()).await
+}
+
+/// Print a diff of the current profile against a replayed one
+fn print_profile_diff(diff: &[String], replayed: &str) {
+    println!("\n--- current profile");
+    println!("+++ {} profile", replayed);
+    for line in diff {
+        println!("{}", line);
+    }
+}
+
+pub async fn rules_list() -> Result<()> {
+    let paths = rules::rule_file_paths()?;
+    if paths.is_empty() {
+        println!("No rule files. Add JSON files to {}", rules_dirs_display());
+        return Ok(());
+    }
+    for path in paths {
+        println!("{}", path.display());
+        for rule in rules::load_rule_file(&path)?.rules {
+            let status = if rule.enabled { "enabled" } else { "disabled" };
+            println!("  [{}] {}", status, rule.name);
+            if !rule.description.is_empty() {
+                println!("      {}", rule.description);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn rules_validate() -> Result<()> {
+    let paths = rules::rule_file_paths()?;
+    if paths.is_empty() {
+        println!("No rule files in {}", rules_dirs_display());
+        return Ok(());
+    }
+    let mut invalid = 0;
+    let mut checked = 0;
+    for path in &paths {
+        let rule_file = match rules::load_rule_file(path) {
+            Ok(rule_file) => rule_file,
+            Err(error) => {
+                println!("✗ {:#}", error);
+                invalid += 1;
+                continue;
+            }
+        };
+        for rule in &rule_file.rules {
+            checked += 1;
+            let problems = rule.problems();
+            if !problems.is_empty() {
+                invalid += 1;
+                println!("✗ {}: rule \"{}\"", path.display(), rule.name);
+                for problem in problems {
+                    println!("    {}", problem);
+                }
+            }
+        }
+    }
+    if invalid > 0 {
+        anyhow::bail!(
+            "{} problem(s) found in {} rule file(s)",
+            invalid,
+            paths.len()
+        );
+    }
+    println!("✓ {} rules in {} file(s) are valid", checked, paths.len());
+    Ok(())
+}
+
+/// Translate the account's Gmail filters into rules/gmail-import.json,
+/// merged into an earlier import so edits to its rules survive
+pub async fn rules_import_gmail(dry_run: bool, provider_name: &str) -> Result<()> {
+    if provider_name != "gmail" {
+        anyhow::bail!("rules import-gmail needs the Gmail provider");
+    }
+
+    let provider = GmailProvider::new().await?;
+    let filters = provider.list_filters().await?;
+    let import = rules_from_gmail_filters(&filters);
+    let path = config::config_dir().join("rules").join("gmail-import.json");
+
+    for (_, rule) in &import.rules {
+        println!("  {}", rule.name);
+    }
+    if !import.skipped.is_empty() {
+        println!("Not translated:");
+        for skipped in &import.skipped {
+            println!("  {}", skipped);
+        }
+    }
+    println!(
+        "{} rule(s) from {} Gmail filter(s), {} not translated",
+        import.rules.len(),
+        filters.len(),
+        import.skipped.len()
+    );
+    if import.rules.is_empty() {
+        return Ok(());
+    }
+    if dry_run {
+        println!("Would write {}", path.display());
+        return Ok(());
+    }
+
+    // The filters already exist on Gmail, so sync-filters tracks them
+    // instead of creating duplicates, but never deletes them
+    let mut store = FilterStore::load()?;
+    for (filter_id, rule) in &import.rules {
+        if let Some(spec) = filter_for_rule(rule) {
+            store.adopt(spec.key(), filter_id.clone());
+        }
+    }
+
+    let earlier = if path.exists() {
+        rules::load_rule_file(&path)?.rules
+    } else {
+        Vec::new()
+    };
+    let rule_file = RuleFile {
+        rules: merge_gmail_import(
+            earlier,
+            import.rules.into_iter().map(|(_, rule)| rule).collect(),
+        ),
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(&rule_file)?)?;
+    store.save()?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+fn rules_dirs_display() -> String {
+    rules::rules_dirs()
+        .iter()
+        .map(|dir| dir.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+pub async fn sync_filters(dry_run: bool, provider_name: &str) -> Result<()> {
+    if provider_name != "gmail" {
+        anyhow::bail!("sync-filters is only supported for Gmail");
+    }
+    if !Config::load()?.sync_gmail_filters() {
+        anyhow::bail!(
+            "Gmail filter sync is disabled. Set \"sync_gmail_filters\": true in config.json"
+        );
+    }
+
+    let mut desired: Vec<FilterSpec> = Vec::new();
+    for spec in rules::load_rules()?.iter().filter_map(filter_for_rule) {
+        if !desired.iter().any(|existing| existing.key() == spec.key()) {
+            desired.push(spec);
+        }
+    }
+
+    let mut store = FilterStore::load()?;
+    let to_create = desired
+        .iter()
+        .filter(|spec| !store.contains(&spec.key()))
+        .collect::<Vec<_>>();
+    let stale_keys = store.stale_keys(&desired);
+    if to_create.is_empty() && stale_keys.is_empty() {
+        println!("Gmail filters are up to date.");
+        return Ok(());
+    }
+
+    if dry_run {
+        for spec in &to_create {
+            println!("Would create filter: {}", spec.describe());
+        }
+        println!("Would remove {} stale filters", stale_keys.len());
+        return Ok(());
+    }
+
+    let provider = GmailProvider::new().await?;
+    for spec in to_create {
+        let filter_id = provider.create_filter(spec).await?;
+        println!("Created filter: {}", spec.describe());
+        store.insert(spec.key(), filter_id);
+        store.save()?;
+    }
+    for key in stale_keys {
+        let Some(filter_id) = store.remove(&key) else {
+            continue;
+        };
+        provider.delete_filter(&filter_id).await?;
+        println!("Removed filter: {}", filter_id);
+        store.save()?;
+    }
+
+    Ok(())
+}
+
+fn validate_provider_name(provider: &str) -> Result<()> {
+    match provider {
+        "gmail" | "outlook" | "outlook-web" | "imap" | "jmap" => Ok(()),
+        #[cfg(any(debug_assertions, feature = "mock"))]
+        "mock" => Ok(()),
+        _ => anyhow::bail!("Invalid provider: {}. Use {}", provider, PROVIDER_CHOICES),
+    }
+}
+
+async fn login_gmail() -> Result<()> {
+    let (client_id, client_secret) = GmailProvider::client_credentials()?;
+    gmail::auth::login(&client_id, &client_secret).await?;
+    println!("Gmail login successful! Tokens saved.");
+    Ok(())
+}
+
+async fn login_outlook() -> Result<()> {
+    let client_id = OutlookProvider::client_id()?;
+    outlook::auth::login(&client_id).await?;
+    println!("Outlook login successful! Tokens saved.");
+    Ok(())
+}
+
+/// The mock mailbox starts over on every run while its predictions persist,
+/// so every earlier label would look removed by the user. Learning from it
+/// would feed those fake corrections into the shared profile and logs.
+fn learns_from_provider(provider_name: &str) -> bool {
+    provider_name != "mock"
+}
+
+#[tracing::instrument(skip_all)]
+async fn run_scan_correction_pass(
+    provider: &dyn EmailProvider,
+    profile: &mut Profile,
+    predictions: &PredictionStore,
+    sender_stats: &mut SenderStats,
+    cfg: &Config,
+    dry_run: bool,
+    fail_fast: bool,
+) -> Result<CorrectionPass> {
+    let mut learning = LearningEngine::new(provider, profile, predictions).with_llm(&cfg.llm());
+    let result = learning.detect_corrections().await?;
+    info!(
+        corrections = result.corrections.len(),
+        deleted = result.deleted_ids.len(),
+        "detected corrections"
+    );
+    if result.corrections.is_empty() {
+        return Ok(CorrectionPass {
+            deleted_ids: result.deleted_ids,
+            corrected_ids: Vec::new(),
+            had_corrections: false,
+        });
+    }
+
+    print_scan_corrections(&result.corrections);
+    if dry_run {
+        note!("  [dry-run] Would update profile with corrections");
+        return Ok(CorrectionPass {
+            deleted_ids: result.deleted_ids,
+            corrected_ids: correction_ids(&result.corrections),
+            had_corrections: true,
+        });
+    }
+
+    let progress = apply_corrections_in_batches(
+        &mut learning,
+        &result.corrections,
+        &BatchPolicy::from_config(cfg),
+        !fail_fast,
+    )
+    .await;
+    progress.report_remaining();
+    log_applied_corrections(&result.corrections, &progress.applied_ids);
+    record_sender_corrections(sender_stats, &result.corrections, &progress.applied_ids);
+    let had_corrections = !progress.applied_ids.is_empty();
+    if let Some(error) = progress.error {
+        persist_profile(profile, had_corrections, dry_run)?;
+        return Err(error);
+    }
+
+    // Predictions of unapplied corrections are kept so the next run re-detects them
+    Ok(CorrectionPass {
+        deleted_ids: result.deleted_ids,
+        corrected_ids: progress.applied_ids,
+        had_corrections,
+    })
+}
+
+/// Keep a history of applied corrections for `simulate`; failing to write it
+/// shouldn't fail the pass
+fn log_applied_corrections(corrections: &[Correction], applied_ids: &[String]) {
+    let applied = corrections
+        .iter()
+        .filter(|correction| applied_ids.contains(&correction.email_id))
+        .collect::<Vec<_>>();
+    let date = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    if let Err(error) = CorrectionLog::append(&date, &applied) {
+        warn!("couldn't write corrections log: {}", error);
+    }
+}
+
+/// Move sender stats to the labels the user chose; like the log, a failed
+/// write only warns
+fn record_sender_corrections(
+    stats: &mut SenderStats,
+    corrections: &[Correction],
+    applied_ids: &[String],
+) {
+    for correction in corrections
+        .iter()
+        .filter(|correction| applied_ids.contains(&correction.email_id))
+    {
+        stats.record_correction(correction);
+    }
+    if let Err(error) = stats.save() {
+        warn!("couldn't update sender stats: {}", error);
+    }
+}
+
+fn correction_ids(corrections: &[Correction]) -> Vec<String> {
+    corrections
+        .iter()
+        .map(|correction| correction.email_id.clone())
+        .collect()
+}
+
+#[tracing::instrument(skip_all)]
+async fn run_learning_pass(
+    provider: &dyn EmailProvider,
+    profile: &mut Profile,
+    predictions: &PredictionStore,
+    sender_stats: &mut SenderStats,
+    policy: &BatchPolicy,
+    llm: &LlmConfig,
+    dry_run: bool,
+) -> Result<CorrectionPass> {
+    let mut learning = LearningEngine::new(provider, profile, predictions).with_llm(llm);
+    let result = learning.detect_corrections().await?;
+    info!(
+        corrections = result.corrections.len(),
+        deleted = result.deleted_ids.len(),
+        "detected corrections"
+    );
+    let had_corrections = !result.corrections.is_empty();
+
+    if !had_corrections {
+        println!("No corrections found.");
+        return Ok(CorrectionPass {
+            deleted_ids: result.deleted_ids,
+            corrected_ids: Vec::new(),
+            had_corrections: false,
+        });
+    }
+
+    print_learning_corrections(&result.corrections);
+    if dry_run {
+        println!("\n[dry-run] Would update profile with these corrections");
+        return Ok(CorrectionPass {
+            deleted_ids: result.deleted_ids,
+            corrected_ids: Vec::new(),
+            had_corrections: true,
+        });
+    }
+
+    let progress =
+        apply_corrections_in_batches(&mut learning, &result.corrections, policy, false).await;
+    progress.report_remaining();
+    log_applied_corrections(&result.corrections, &progress.applied_ids);
+    record_sender_corrections(sender_stats, &result.corrections, &progress.applied_ids);
+    let had_corrections = !progress.applied_ids.is_empty();
+    if let Some(error) = progress.error {
+        persist_profile(profile, had_corrections, dry_run)?;
+        return Err(error);
+    }
+
+    Ok(CorrectionPass {
+        deleted_ids: result.deleted_ids,
+        corrected_ids: Vec::new(),
+        had_corrections,
+    })
+}
+
+/// Apply corrections batch by batch. With `continue_on_error`, a batch that
+/// still fails after its retries is skipped; otherwise the run stops there.
+async fn apply_corrections_in_batches(
+    backend: &mut impl CorrectionBackend,
+    corrections: &[Correction],
+    policy: &BatchPolicy,
+    continue_on_error: bool,
+) -> BatchProgress {
+    let chunks = corrections
+        .chunks(CORRECTION_BATCH_SIZE)
+        .collect::<Vec<_>>();
+    let mut progress = BatchProgress {
+        total_batches: chunks.len(),
+        ..Default::default()
+    };
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        if index > 0 && !policy.delay_between.is_zero() {
+            tokio::time::sleep(policy.delay_between).await;
+        }
+        print_correction_batch_status(index, chunks.len());
+
+        match apply_batch_with_retry(backend, chunk, policy).await {
+            Ok(()) => progress.applied_ids.extend(correction_ids(chunk)),
+            Err(error) if continue_on_error => {
+                warn!(
+                    "profile update failed, continuing with classification: {}",
+                    error
+                );
+                progress.remaining_batches.push(index + 1);
+            }
+            Err(error) => {
+                progress.remaining_batches.extend(index + 1..=chunks.len());
+                progress.error = Some(error);
+                break;
+            }
+        }
+    }
+
+    progress
+}
+
+async fn apply_batch_with_retry(
+    backend: &mut impl CorrectionBackend,
+    batch: &[Correction],
+    policy: &BatchPolicy,
+) -> Result<()> {
+    let mut delay = policy.backoff;
+    let mut attempt = 1;
+    loop {
+        match backend.apply_batch(batch).await {
+            Ok(()) => return Ok(()),
+            Err(error) if attempt < policy.attempts => {
+                warn!("batch failed ({}), retrying in {}s", error, delay.as_secs());
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+fn print_correction_batch_status(index: usize, total_batches: usize) {
+    if total_batches > 1 {
+        note!(
+            "Updating profile (batch {}/{})...",
+            index + 1,
+            total_batches
+        );
+    } else {
+        note!("Updating profile...");
+    }
+}
+
+fn print_scan_corrections(corrections: &[Correction]) {
+    note!("Found {} corrections:", corrections.len());
+    for correction in corrections {
+        note!(
+            "  - {} | {} (predicted: {:?}, actual: {:?})",
+            correction.email_id,
+            correction.subject.chars().take(40).collect::<String>(),
+            correction.predicted_labels,
+            correction.actual_labels
+        );
+    }
+}
+
+fn print_learning_corrections(corrections: &[Correction]) {
+    println!("Found {} corrections:", corrections.len());
+    for correction in corrections {
+        println!(
+            "  - {} (predicted: {:?}, actual: {:?})",
+            correction.email_id, correction.predicted_labels, correction.actual_labels
+        );
+    }
+}
+
+fn persist_profile(profile: &Profile, had_corrections: bool, dry_run: bool) -> Result<()> {
+    if had_corrections && !dry_run {
+        profile.save()?;
+    }
+    Ok(())
+}
+
+/// Drop the predictions of deleted and corrected emails. Returns how many
+/// distinct stored predictions that removes (or, in a dry run, would).
+fn remove_predictions(
+    predictions: &mut PredictionStore,
+    deleted_ids: &[String],
+    corrected_ids: &[String],
+    dry_run: bool,
+) -> usize {
+    let ids = deleted_ids
+        .iter()
+        .chain(corrected_ids)
+        .filter(|id| predictions.get(id).is_some())
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    if !dry_run {
+        for id in &ids {
+            predictions.remove(id);
+        }
+    }
+    ids.len()
+}
+
+async fn load_scan_emails(
+    provider: &dyn EmailProvider,
+    options: &ScanOptions,
+) -> Result<Vec<Email>> {
+    let label = if options.archived { "" } else { "INBOX" };
+    let list_op = if options.archived {
+        ProviderOp::ListByLabel
+    } else {
+        ProviderOp::List
+    };
+    provider.capabilities().require(list_op)?;
+    let base = if options.archived {
+        ARCHIVED_CLASSIFICATION_QUERY
+    } else {
+        INBOX_CLASSIFICATION_QUERY
+    };
+    let mut query = match &options.query {
+        Some(extra) => format!("{} {}", base, extra),
+        None => base.to_string(),
+    };
+    if options.unread_only {
+        query.push_str(" is:unread");
+    }
+
+    provider
+        .list_messages(options.max, label, Some(&query), options.order())
+        .await
+}
+
+/// Emails of one conversation in a scan batch. Only the latest is sent to
+/// the classifier; the earlier ones get the same labels and actions.
+struct ScanThread {
+    latest: Email,
+    earlier: Vec<Email>,
+}
+
+/// Group emails by `thread_id`, keeping threads in the order they were listed.
+/// Without `collapse`, or without a thread ID, every email is its own thread.
+fn group_threads(emails: Vec<Email>, collapse: bool) -> Vec<ScanThread> {
+    let mut groups: Vec<Vec<Email>> = Vec::new();
+    for email in emails {
+        let existing = email
+            .thread_id
+            .as_ref()
+            .filter(|_| collapse)
+            .and_then(|id| {
+                groups
+                    .iter()
+                    .position(|group| group[0].thread_id.as_ref() == Some(id))
+            });
+        match existing {
+            Some(index) => groups[index].push(email),
+            None => groups.push(vec![email]),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|mut group| {
+            group.sort_by_key(|email| Reverse(email.date));
+            let latest = group.remove(0);
+            ScanThread {
+                latest,
+                earlier: group,
+            }
+        })
+        .collect()
+}
+
+/// Classify a thread and apply the result to each of its emails. Breaks,
+/// leaving the thread untouched, once the scan's LLM budget is spent.
+#[tracing::instrument(skip_all, fields(id = %thread.latest.id, earlier = thread.earlier.len()))]
+async fn process_scan_thread(
+    provider: &dyn EmailProvider,
+    classifier: &Classifier<'_>,
+    user_rules: &[rules::Rule],
+    state: &mut ScanState,
+    thread: ScanThread,
+    options: &ScanOptions,
+) -> Result<ControlFlow<()>> {
+    let email = &thread.latest;
+    if state.ignored_senders.matches(&email.from) {
+        let sender = sender_address(&email.from);
+        if output::formatter().is_plain() {
+            note!(
+                "  Skipped (ignored sender {}): {}",
+                sender,
+                email.subject.chars().take(60).collect::<String>()
+            );
+        } else {
+            info!(sender, "skipped ignored sender");
+        }
+        return Ok(ControlFlow::Continue(()));
+    }
+    if state.llm_budget.as_ref().is_some_and(LlmBudget::exhausted) {
+        return Ok(ControlFlow::Break(()));
+    }
+    let started = Instant::now();
+    let llm_classification = match classifier.classify(email).await {
+        Ok(classification) => classification,
+        Err(error) => {
+            let context = format!("couldn't classify \"{}\"", email.subject);
+            report_scan_error(error, &context, options.fail_fast)?;
+            return Ok(ControlFlow::Continue(()));
+        }
+    };
+    let elapsed = started.elapsed();
+    state.latencies.record(elapsed);
+    let classification = finalize_classification(state, user_rules, email, &llm_classification);
+    info!(
+        elapsed_ms = elapsed.as_millis() as u64,
+        theme = ?classification.theme,
+        action = ?classification.action,
+        confidence = classification.confidence,
+        archive = classification.archive,
+        delete = classification.delete,
+        "classified"
+    );
+
+    let mut row = ScanRow::new(email, &classification, thread.earlier.len());
+    if options.explain {
+        row.reason = Some(
+            classification
+                .reason
+                .clone()
+                .unwrap_or_else(|| "(none given)".to_string()),
+        );
+    }
+    state.results.push(row);
+    if state
+        .spot_check
+        .as_ref()
+        .is_some_and(|spot_check| spot_check.selects(&email.id))
+    {
+        print_spot_check(email, &classification);
+    }
+    if options.dry_run {
+        ScanPlan::new(email, &classification, state.delete_mode, &[]).print();
+    }
+
+    apply_thread_classification(provider, state, email, &classification, options).await?;
+    for earlier in &thread.earlier {
+        // Rules and curation protection still apply to each message on its own
+        let classification =
+            finalize_classification(state, user_rules, earlier, &llm_classification);
+        if options.dry_run {
+            note!("  [dry-run] Earlier message {} in the thread:", earlier.id);
+            ScanPlan::new(earlier, &classification, state.delete_mode, &[]).print();
+        }
+        apply_thread_classification(provider, state, earlier, &classification, options).await?;
+    }
+
+    if !options.dry_run && !classification.delete {
+        notify_routed_targets(state.notifier.as_ref(), email, &classification).await;
+    }
+    Ok(ControlFlow::Continue(()))
+}
+
+fn finalize_classification(
+    state: &ScanState,
+    user_rules: &[rules::Rule],
+    email: &Email,
+    llm_classification: &Classification,
+) -> Classification {
+    // Label policies override the model, rules override policies, and the
+    // safety protections override everything
+    let mut classification = llm_classification.clone();
+    state.policies.apply(&mut classification);
+    rules::apply_rules(email, &mut classification, user_rules);
+    protect_personal_and_reply_emails(&mut classification);
+    if state.protect_curated {
+        protect_curated_emails(
+            email,
+            &mut classification,
+            flagged_important_by_us(state, email),
+        );
+    }
+    state
+        .protected_senders
+        .apply(&email.from, &mut classification);
+    classification
+}
+
+async fn apply_thread_classification(
+    provider: &dyn EmailProvider,
+    state: &mut ScanState,
+    email: &Email,
+    classification: &Classification,
+    options: &ScanOptions,
+) -> Result<()> {
+    if options.confirm_deletes && classification.delete && !options.dry_run {
+        state
+            .deferred_deletes
+            .push((email.clone(), classification.clone()));
+        return Ok(());
+    }
+    state.delta.record(classification, &state.label_manager);
+    if options.dry_run {
+        return Ok(());
+    }
+
+    apply_scan_actions(provider, state, email, classification, options.fail_fast).await?;
+    if !classification.delete {
+        state
+            .label_manager
+            .record_llm_labels(&classification.labels());
+    }
+    Ok(())
+}
+
+/// Ask once about every delete --confirm-deletes held back: delete them all,
+/// decide one by one, or keep them all. Kept emails are labeled and stored
+/// like any other classified email.
+async fn confirm_deferred_deletes(
+    provider: &dyn EmailProvider,
+    state: &mut ScanState,
+    options: &ScanOptions,
+) -> Result<()> {
+    let deferred = std::mem::take(&mut state.deferred_deletes);
+    if deferred.is_empty() {
+        return Ok(());
+    }
+
+    note!("\n{} emails classified for deletion:", deferred.len());
+    for (index, (email, classification)) in deferred.iter().enumerate() {
+        note!(
+            "  {:>2}. {} | {}",
+            index + 1,
+            email.from,
+            email.subject.chars().take(60).collect::<String>()
+        );
+        if let Some(reason) = &classification.reason {
+            note!("      {}", reason);
+        }
+    }
+    let answer = ask("Delete them? [y]es / [n]o / [s]elect one by one")?;
+
+    let outcome = apply_deferred_deletes(provider, state, deferred, options, |email| match answer
+        .as_str()
+    {
+        "y" | "yes" => Ok(true),
+        "s" | "select" => confirm(&format!("  Delete \"{}\"?", email.subject)),
+        _ => Ok(false),
+    })
+    .await?;
+    if outcome.failed > 0 {
+        note!(
+            "Deleted {}, kept {}, {} failed",
+            outcome.deleted,
+            outcome.kept,
+            outcome.failed
+        );
+    } else {
+        note!("Deleted {}, kept {}", outcome.deleted, outcome.kept);
+    }
+    Ok(())
+}
+
+/// How the held-back deletes of a --confirm-deletes scan ended
+#[derive(Debug, Default, PartialEq)]
+struct DeferredOutcome {
+    deleted: usize,
+    kept: usize,
+    failed: usize,
+}
+
+/// Delete each held-back email `decide` approves, and label and store the
+/// rest like any other classified email
+async fn apply_deferred_deletes(
+    provider: &dyn EmailProvider,
+    state: &mut ScanState,
+    deferred: Vec<(Email, Classification)>,
+    options: &ScanOptions,
+    mut decide: impl FnMut(&Email) -> Result<bool>,
+) -> Result<DeferredOutcome> {
+    let mut outcome = DeferredOutcome::default();
+    for (email, classification) in deferred {
+        if !decide(&email)? {
+            let keep = Classification {
+                delete: false,
+                ..classification
+            };
+            apply_thread_classification(provider, state, &email, &keep, options).await?;
+            outcome.kept += 1;
+            continue;
+        }
+
+        state.delta.record(&classification, &state.label_manager);
+        match delete_email(provider, state, &email, &classification).await {
+            Ok(()) => outcome.deleted += 1,
+            Err(error) => {
+                report_scan_error(error, "couldn't delete", options.fail_fast)?;
+                outcome.failed += 1;
+            }
+        }
+    }
+    Ok(outcome)
+}
+
+async fn notify_routed_targets(
+    notifier: Option<&Notifier>,
+    email: &Email,
+    classification: &Classification,
+) {
+    let Some(notifier) = notifier else {
+        return;
+    };
+    if let Err(error) = notifier.notify(email, &classification.labels()).await {
+        warn!(email = %email.id, "{}", error);
+    }
+}
+
+fn protect_personal_and_reply_emails(classification: &mut Classification) {
+    if !classification.delete {
+        return;
+    }
+
+    let is_personal = classification
+        .theme
+        .iter()
+        .any(|theme| theme.eq_ignore_ascii_case("Personal"));
+    let needs_reply = classification
+        .action
+        .iter()
+        .any(|action| action.eq_ignore_ascii_case("Needs-Reply"));
+
+    if is_personal || needs_reply {
+        classification.delete = false;
+    }
+}
+
+/// Whether the email's IMPORTANT flag is one `sync_importance` set: its
+/// stored prediction was Important or Urgent
+fn flagged_important_by_us(state: &ScanState, email: &Email) -> bool {
+    state.sync_importance
+        && state
+            .predictions
+            .get(&email.id)
+            .is_some_and(|prediction| is_important_label_set(&prediction.all_labels()))
+}
+
+/// Starred or Gmail-important mail was curated by the user, so leave it in
+/// place. An IMPORTANT flag the tool set itself (`important_set_by_us`) doesn't count.
+fn protect_curated_emails(
+    email: &Email,
+    classification: &mut Classification,
+    important_set_by_us: bool,
+) {
+    let is_curated = email
+        .labels
+        .iter()
+        .any(|label| label == "STARRED" || (label == "IMPORTANT" && !important_set_by_us));
+
+    if is_curated {
+        classification.archive = false;
+        classification.delete = false;
+    }
+}
+
+/// How scan reports one classified email (the latest of its thread)
+#[derive(Debug, serde::Serialize)]
+struct ScanRow {
+    id: String,
+    from: String,
+    subject: String,
+    labels: Vec<String>,
+    /// "archive" or "delete", when the email leaves the inbox
+    disposition: Option<&'static str>,
+    confidence: f32,
+    earlier_in_thread: usize,
+    /// Summary and start of a calendar invite
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event: Option<String>,
+    /// The model's reason, under --explain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    /// Unread/location/priority markers for plain output
+    #[serde(skip)]
+    status: String,
+}
+
+impl ScanRow {
+    fn new(email: &Email, classification: &Classification, earlier_in_thread: usize) -> Self {
+        let is_important = classification.is_important();
+        let disposition = if classification.delete {
+            Some("delete")
+        } else if classification.archive {
+            Some("archive")
+        } else {
+            None
+        };
+        Self {
+            id: email.id.clone(),
+            from: email.from.clone(),
+            subject: email.subject.clone(),
+            labels: classification.labels(),
+            disposition,
+            confidence: classification.confidence,
+            earlier_in_thread,
+            event: calendar::invite_event(email).map(|event| event.describe()),
+            reason: None,
+            status: build_status_indicators(&email.labels, is_important),
+        }
+    }
+}
+
+impl Renderable for ScanRow {
+    const COLUMNS: &'static [&'static str] = &[
+        "ID",
+        "SUBJECT",
+        "LABELS",
+        "ACTION",
+        "CONFIDENCE",
+        "THREAD",
+        "EVENT",
+    ];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.subject.chars().take(60).collect(),
+            self.labels.join(", "),
+            self.disposition.unwrap_or("-").to_string(),
+            format!("{:.2}", self.confidence),
+            format!("+{}", self.earlier_in_thread),
+            self.event.clone().unwrap_or_else(|| "-".to_string()),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        let action_suffix = match self.disposition {
+            Some("delete") => " → DELETE",
+            Some("archive") => " → archive",
+            _ => "",
+        };
+        let thread_suffix = if self.earlier_in_thread > 0 {
+            format!(" (+{} earlier in thread)", self.earlier_in_thread)
+        } else {
+            String::new()
+        };
+        let mut line = format!(
+            "{} | {} | {:?}{}{}",
+            self.status,
+            self.subject.chars().take(60).collect::<String>(),
+            self.labels,
+            action_suffix,
+            thread_suffix
+        );
+        if let Some(event) = &self.event {
+            line.push_str(&format!("\n  📅 {}", event));
+        }
+        if let Some(reason) = &self.reason {
+            line.push_str(&format!("\n  Reason: {}", reason));
+        }
+        line
+    }
+}
+
+/// One email `search` lists, with the classification it would get under
+/// --classify
+#[derive(Debug, serde::Serialize)]
+struct SearchRow {
+    id: String,
+    from: String,
+    subject: String,
+    /// Labels the email has now
+    labels: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggested_labels: Option<Vec<String>>,
+    /// "archive" or "delete", when the suggestion takes it out of the inbox
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disposition: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<f32>,
+}
+
+impl SearchRow {
+    fn new(email: &Email, classification: Option<&Classification>) -> Self {
+        let disposition = classification.and_then(|classification| {
+            if classification.delete {
+                Some("delete")
+            } else if classification.archive {
+                Some("archive")
+            } else {
+                None
+            }
+        });
+        Self {
+            id: email.id.clone(),
+            from: email.from.clone(),
+            subject: email.subject.clone(),
+            labels: email.labels.clone(),
+            suggested_labels: classification.map(Classification::labels),
+            disposition,
+            confidence: classification.map(|classification| classification.confidence),
+        }
+    }
+}
+
+impl Renderable for SearchRow {
+    const COLUMNS: &'static [&'static str] = &[
+        "ID",
+        "FROM",
+        "SUBJECT",
+        "LABELS",
+        "SUGGESTED",
+        "ACTION",
+        "CONFIDENCE",
+    ];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.from.clone(),
+            self.subject.chars().take(60).collect(),
+            self.labels.join(", "),
+            self.suggested_labels
+                .as_ref()
+                .map_or_else(|| "-".to_string(), |labels| labels.join(", ")),
+            self.disposition.unwrap_or("-").to_string(),
+            self.confidence.map_or_else(
+                || "-".to_string(),
+                |confidence| format!("{:.2}", confidence),
+            ),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        let mut line = format!(
+            "{} | {} | {} | {:?}",
+            self.id,
+            self.from,
+            self.subject.chars().take(60).collect::<String>(),
+            self.labels
+        );
+        if let Some(suggested) = &self.suggested_labels {
+            line.push_str(&format!(
+                "\n  Suggested: {:?} (confidence {:.2})",
+                suggested,
+                self.confidence.unwrap_or_default()
+            ));
+            if let Some(disposition) = self.disposition {
+                line.push_str(&format!(" → {}", disposition));
+            }
+        }
+        line
+    }
+}
+
+fn print_reason(classification: &Classification) {
+    println!(
+        "  Reason: {}",
+        classification.reason.as_deref().unwrap_or("(none given)")
+    );
+}
+
+fn print_spot_check(email: &Email, classification: &Classification) {
+    let body_preview: String = email.body.chars().take(300).collect();
+    note!("  ┌ spot check");
+    note!("  │ From: {}", email.from);
+    note!("  │ Subject: {}", email.subject);
+    note!(
+        "  │ Body: {}",
+        body_preview
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    note!(
+        "  │ Labels: {:?} (confidence {:.2})",
+        classification.labels(),
+        classification.confidence
+    );
+    note!(
+        "  └ Reason: {}",
+        classification.reason.as_deref().unwrap_or("(none given)")
+    );
+}
+
+/// What scan (or reclassify) would do to one email, printed under --dry-run
+/// instead of doing it
+#[derive(Debug, PartialEq)]
+struct ScanPlan {
+    add_labels: Vec<String>,
+    remove_labels: Vec<String>,
+    disposition: Option<&'static str>,
+    store_prediction: bool,
+}
+
+impl ScanPlan {
+    /// `stale` are labels of an earlier prediction to take off (reclassify)
+    fn new(
+        email: &Email,
+        classification: &Classification,
+        delete_mode: DeleteMode,
+        stale: &[String],
+    ) -> Self {
+        let remove_labels = LabelDiff::new(&email.labels, &[], stale).remove;
+        if classification.delete {
+            let (add_labels, disposition) = match delete_mode {
+                DeleteMode::Trash => (Vec::new(), "DELETE"),
+                DeleteMode::Quarantine => (vec![QUARANTINE_LABEL.to_string()], "quarantine"),
+            };
+            return Self {
+                add_labels: LabelDiff::new(&email.labels, &add_labels, &[]).add,
+                remove_labels,
+                disposition: Some(disposition),
+                store_prediction: false,
+            };
+        }
+
+        // Heuristic fallbacks stay unstamped so the next scan asks the LLM
+        let mut desired = classification.labels();
+        if !classification.heuristic {
+            desired.push("Classified".to_string());
+        }
+        Self {
+            add_labels: LabelDiff::new(&email.labels, &desired, &[]).add,
+            remove_labels,
+            disposition: classification.archive.then_some("archive"),
+            store_prediction: !classification.heuristic,
+        }
+    }
+
+    fn print(&self) {
+        if self.add_labels.is_empty() {
+            note!("  [dry-run] No labels to add");
+        } else {
+            note!("  [dry-run] Would add labels: {:?}", self.add_labels);
+        }
+        if !self.remove_labels.is_empty() {
+            note!("  [dry-run] Would remove labels: {:?}", self.remove_labels);
+        }
+        match self.disposition {
+            Some(disposition) => note!("  [dry-run] Would {}", disposition),
+            None => note!("  [dry-run] Would leave it in place"),
+        }
+        if self.store_prediction {
+            note!("  [dry-run] Would store the prediction");
+        }
+    }
+}
+
+async fn apply_scan_actions(
+    provider: &dyn EmailProvider,
+    state: &mut ScanState,
+    email: &Email,
+    classification: &Classification,
+    fail_fast: bool,
+) -> Result<()> {
+    if classification.delete {
+        let context = match state.delete_mode {
+            DeleteMode::Trash => "couldn't delete",
+            DeleteMode::Quarantine => "couldn't quarantine",
+        };
+        return delete_email(provider, state, email, classification)
+            .await
+            .or_else(|error| report_scan_error(error, context, fail_fast));
+    }
+
+    let labels = classification.labels();
+    apply_label_diff(provider, email, &labels, &[], fail_fast).await?;
+    if state.sync_importance && classification.is_important() {
+        sync_importance(provider, email, true, fail_fast).await?;
+    }
+    // A heuristic fallback is only a stopgap: without the Classified stamp
+    // and a prediction, the next scan classifies it with the LLM and its
+    // labels are never learned from as corrections
+    if !classification.heuristic {
+        store_classification_prediction(
+            provider,
+            &mut state.predictions,
+            &mut state.sender_stats,
+            email,
+            classification,
+            fail_fast,
+        )
+        .await?;
+    }
+    archive_if_needed(provider, email, classification, fail_fast).await
+}
+
+/// Set or clear the provider's important flag (`sync_importance`). Providers
+/// without one are skipped quietly.
+async fn sync_importance(
+    provider: &dyn EmailProvider,
+    email: &Email,
+    important: bool,
+    fail_fast: bool,
+) -> Result<()> {
+    if !provider.capabilities().supports(ProviderOp::Importance) {
+        return Ok(());
+    }
+    let result = if important {
+        provider.set_important(&email.id).await
+    } else {
+        provider.clear_important(&email.id).await
+    };
+    result.or_else(|error| report_scan_error(error, "couldn't sync importance", fail_fast))
+}
+
+/// Warn about a per-email failure, or propagate it when running with --fail-fast.
+fn report_scan_error(error: anyhow::Error, context: &str, fail_fast: bool) -> Result<()> {
+    if fail_fast {
+        return Err(error.context(context.to_string()));
+    }
+
+    warn!("{}: {}", context, error);
+    Ok(())
+}
+
+/// Trash or quarantine the email, as the delete mode says
+async fn delete_email(
+    provider: &dyn EmailProvider,
+    state: &mut ScanState,
+    email: &Email,
+    classification: &Classification,
+) -> Result<()> {
+    match state.delete_mode {
+        DeleteMode::Trash => provider.trash(&email.id).await,
+        DeleteMode::Quarantine => {
+            quarantine_email(provider, &mut state.quarantine, email, classification).await
+        }
+    }
+}
+
+/// Label the email Quarantine and archive it, recording it for review. It's
+/// only recorded once both succeed, so a failed attempt is retried next scan.
+async fn quarantine_email(
+    provider: &dyn EmailProvider,
+    quarantine: &mut Quarantine,
+    email: &Email,
+    classification: &Classification,
+) -> Result<()> {
+    provider.add_label(&email.id, QUARANTINE_LABEL).await?;
+    provider.archive(&email.id).await?;
+    quarantine.add(email, classification.reason.clone(), chrono::Utc::now());
+    Ok(())
+}
+
+/// Trash quarantined emails whose grace period is over. Emails the user
+/// rescued in their mail client (Quarantine label removed, or moved back to
+/// the inbox) and emails the provider no longer has are only dropped from
+/// the quarantine.
+async fn purge_quarantine(
+    provider: &dyn EmailProvider,
+    quarantine: &mut Quarantine,
+    grace: chrono::Duration,
+    dry_run: bool,
+) -> Result<usize> {
+    let expired = quarantine.expired(grace, chrono::Utc::now());
+    if dry_run {
+        if !expired.is_empty() {
+            note!(
+                "[dry-run] Would delete {} quarantined emails past their grace period",
+                expired.len()
+            );
+        }
+        return Ok(expired.len());
+    }
+
+    let mut deleted = 0;
+    for id in &expired {
+        let still_quarantined = match provider.get_message(id).await {
+            Ok(email) => {
+                email.labels.iter().any(|label| label == QUARANTINE_LABEL)
+                    && !email.labels.iter().any(|label| label == "INBOX")
+            }
+            Err(error) if ProviderError::is_not_found(&error) => false,
+            // Try again on the next run rather than forget the email
+            Err(error) => {
+                warn!(id, "couldn't check quarantined email: {:#}", error);
+                continue;
+            }
+        };
+        if !still_quarantined {
+            quarantine.remove(id);
+            continue;
+        }
+        if let Err(error) = provider.trash(id).await {
+            let gone = matches!(
+                provider.get_message(id).await,
+                Err(error) if ProviderError::is_not_found(&error)
+            );
+            if !gone {
+                warn!(id, "couldn't delete quarantined email: {}", error);
+                continue;
+            }
+        } else {
+            deleted += 1;
+        }
+        quarantine.remove(id);
+    }
+    if deleted > 0 {
+        note!(
+            "Deleted {} quarantined emails past their grace period",
+            deleted
+        );
+    }
+    Ok(deleted)
+}
+
+/// Label changes that take an email from the labels it has to the ones wanted
+#[derive(Debug, Default, PartialEq)]
+struct LabelDiff {
+    add: Vec<String>,
+    remove: Vec<String>,
+}
+
+impl LabelDiff {
+    /// Add the `desired` labels the email lacks; remove the `unwanted` ones it
+    /// has. Labels it has that are in neither list are left alone.
+    fn new(current: &[String], desired: &[String], unwanted: &[String]) -> Self {
+        let has = |label: &String| current.iter().any(|l| l.eq_ignore_ascii_case(label));
+        Self {
+            add: desired
+                .iter()
+                .filter(|label| !has(label))
+                .cloned()
+                .collect(),
+            remove: unwanted
+                .iter()
+                .filter(|label| has(label))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// Apply only the label changes the email actually needs, going by the labels
+/// it was fetched with. Returns the labels that were removed.
+async fn apply_label_diff(
+    provider: &dyn EmailProvider,
+    email: &Email,
+    desired: &[String],
+    unwanted: &[String],
+    fail_fast: bool,
+) -> Result<Vec<String>> {
+    let diff = LabelDiff::new(&email.labels, desired, unwanted);
+    for label in &diff.add {
+        if let Err(error) = provider.add_label(&email.id, label).await {
+            let context = format!("couldn't apply label '{}'", label);
+            report_scan_error(error, &context, fail_fast)?;
+        }
+    }
+    let mut removed = Vec::new();
+    for label in diff.remove {
+        match provider.remove_label(&email.id, &label).await {
+            Ok(()) => removed.push(label),
+            Err(error) => {
+                let context = format!("couldn't remove label '{}'", label);
+                report_scan_error(error, &context, fail_fast)?;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+async fn store_classification_prediction(
+    provider: &dyn EmailProvider,
+    predictions: &mut PredictionStore,
+    sender_stats: &mut SenderStats,
+    email: &Email,
+    classification: &Classification,
+    fail_fast: bool,
+) -> Result<()> {
+    // Rescans (--archived, reclassify) see emails that are already stamped
+    let already_stamped = email
+        .labels
+        .iter()
+        .any(|label| label.eq_ignore_ascii_case("Classified"));
+    let stamped = if already_stamped {
+        Ok(())
+    } else {
+        provider.add_label(&email.id, "Classified").await
+    };
+    match stamped {
+        Ok(_) => {
+            let pre_existing = email
+                .labels
+                .iter()
+                .filter(|label| !is_system_label(label))
+                .cloned()
+                .collect();
+            predictions.store(email, classification, pre_existing)?;
+            sender_stats.record(
+                &email.from,
+                classification.is_spam,
+                &classification.labels(),
+            );
+        }
+        Err(error) => {
+            report_scan_error(error, "couldn't apply Classified label", fail_fast)?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn archive_if_needed(
+    provider: &dyn EmailProvider,
+    email: &Email,
+    classification: &Classification,
+    fail_fast: bool,
+) -> Result<()> {
+    if !classification.archive {
+        return Ok(());
+    }
+
+    provider
+        .archive(&email.id)
+        .await
+        .or_else(|error| report_scan_error(error, "couldn't archive", fail_fast))
+}
+
+fn save_scan_state(state: &ScanState, dry_run: bool) -> Result<()> {
+    if dry_run {
+        note!("\n[dry-run] Would save predictions, labels, sender stats, and quarantine");
+        for line in state.delta.summary().lines() {
+            note!("  {}", line);
+        }
+        return Ok(());
+    }
+
+    state.predictions.save()?;
+    state.label_manager.save()?;
+    state.sender_stats.save()?;
+    state.quarantine.save()
+}
+
+fn warn_missing_profile_sections(profile: &Profile) {
+    let missing = profile.missing_sections();
+    if missing.is_empty() {
+        return;
+    }
+
+    warn!(
+        "profile is missing expected sections: {}",
+        missing.join(", ")
+    );
+}
+
+fn warn_prompt_placeholders(template: &PromptTemplate) {
+    let missing = template.missing_placeholders();
+    if !missing.is_empty() {
+        warn!(
+            "prompt template never uses: {}",
+            missing
+                .iter()
+                .map(|name| format!("{{{}}}", name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    let unknown = template.unknown_placeholders();
+    if !unknown.is_empty() {
+        warn!(
+            "prompt template has unknown placeholders (left as written): {}",
+            unknown
+                .iter()
+                .map(|name| format!("{{{}}}", name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+}
+
+/// One figure of `stats`, for table output
+#[derive(Debug, serde::Serialize)]
+struct StatsRow {
+    metric: String,
+    value: String,
+}
+
+impl Renderable for StatsRow {
+    const COLUMNS: &'static [&'static str] = &["METRIC", "VALUE"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![self.metric.clone(), self.value.clone()]
+    }
+
+    fn plain(&self) -> String {
+        format!("{}: {}", self.metric, self.value)
+    }
+}
+
+fn stats_rows(stats: &PredictionStats) -> Vec<StatsRow> {
+    let date = |date: Option<chrono::DateTime<chrono::Utc>>| {
+        date.map_or("-".to_string(), |date| date.format("%Y-%m-%d").to_string())
+    };
+    let mut rows = vec![
+        ("predictions", stats.total.to_string()),
+        ("oldest", date(stats.oldest)),
+        ("newest", date(stats.newest)),
+        ("oldest received", date(stats.oldest_received)),
+        ("newest received", date(stats.newest_received)),
+        ("spam", stats.spam.to_string()),
+        ("needs reply", stats.needs_reply.to_string()),
+        (
+            "average confidence",
+            format!("{:.2}", stats.average_confidence),
+        ),
+    ]
+    .into_iter()
+    .map(|(metric, value)| StatsRow {
+        metric: metric.to_string(),
+        value,
+    })
+    .collect::<Vec<_>>();
+    for (kind, counts) in [("theme", &stats.themes), ("action", &stats.actions)] {
+        rows.extend(counts.iter().map(|(label, count)| StatsRow {
+            metric: format!("{} {}", kind, label),
+            value: count.to_string(),
+        }));
+    }
+    rows
+}
+
+/// One confidence bucket of `calibrate`
+#[derive(Debug, serde::Serialize)]
+struct CalibrationRow {
+    /// "0.9-1.0"
+    confidence: String,
+    predictions: usize,
+    corrected: usize,
+    average_confidence: f32,
+    /// Share of the bucket's predictions left uncorrected
+    accuracy: f32,
+}
+
+impl Renderable for CalibrationRow {
+    const COLUMNS: &'static [&'static str] = &[
+        "CONFIDENCE",
+        "PREDICTIONS",
+        "CORRECTED",
+        "AVG CONFIDENCE",
+        "ACCURACY",
+    ];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.confidence.clone(),
+            self.predictions.to_string(),
+            self.corrected.to_string(),
+            format!("{:.2}", self.average_confidence),
+            format!("{:.0}%", self.accuracy * 100.0),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        format!(
+            "  {}: {} predictions, {} corrected → {:.0}% right (average confidence {:.2})",
+            self.confidence,
+            self.predictions,
+            self.corrected,
+            self.accuracy * 100.0,
+            self.average_confidence
+        )
+    }
+}
+
+/// Rows for the buckets that have predictions
+fn calibration_rows(buckets: &[CalibrationBucket]) -> Vec<CalibrationRow> {
+    buckets
+        .iter()
+        .enumerate()
+        .filter_map(|(bucket, counts)| {
+            Some(CalibrationRow {
+                confidence: format!(
+                    "{:.1}-{:.1}",
+                    bucket as f32 / CONFIDENCE_BUCKETS as f32,
+                    (bucket + 1) as f32 / CONFIDENCE_BUCKETS as f32
+                ),
+                predictions: counts.predictions,
+                corrected: counts.corrected,
+                average_confidence: counts.average_confidence()?,
+                accuracy: counts.accuracy()?,
+            })
+        })
+        .collect()
+}
+
+fn print_stats(stats: &PredictionStats) {
+    if stats.total == 0 {
+        println!("No predictions stored yet.");
+        return;
+    }
+
+    println!("Predictions: {}", stats.total);
+    if let (Some(oldest), Some(newest)) = (stats.oldest, stats.newest) {
+        println!(
+            "Date range: {} to {}",
+            oldest.format("%Y-%m-%d"),
+            newest.format("%Y-%m-%d")
+        );
+    }
+    if let (Some(oldest), Some(newest)) = (stats.oldest_received, stats.newest_received) {
+        println!(
+            "Received: {} to {}",
+            oldest.format("%Y-%m-%d"),
+            newest.format("%Y-%m-%d")
+        );
+    }
+    println!("Spam: {}", stats.spam);
+    println!("Needs reply: {}", stats.needs_reply);
+    println!("Average confidence: {:.2}", stats.average_confidence);
+
+    print_label_counts("Themes", &stats.themes);
+    print_label_counts("Actions", &stats.actions);
+}
+
+fn print_label_counts(title: &str, counts: &[(String, usize)]) {
+    println!("\n{}:", title);
+    for (label, count) in counts {
+        println!("  {:>5}  {}", count, label);
+    }
+}
+
+fn print_drift(drift: &Drift) {
+    println!("{} | {}", drift.email_id, drift.subject);
+    if drift.email_gone {
+        println!("  email no longer exists");
+    }
+    if !drift.missing_labels.is_empty() {
+        println!("  missing predicted labels: {:?}", drift.missing_labels);
+    }
+    if !drift.extra_labels.is_empty() {
+        println!("  extra user labels: {:?}", drift.extra_labels);
+    }
+}
+
+/// Accuracy summary: how many predictions the user left alone, then per label
+/// how often it was kept, removed, or added by hand
+fn print_accuracy(accuracy: &Accuracy) {
+    let Some(percent) = accuracy.unchanged_percent() else {
+        return;
+    };
+    println!(
+        "\nAccuracy: {}/{} predictions unchanged ({:.0}%)",
+        accuracy.unchanged, accuracy.checked, percent
+    );
+    if accuracy.gone > 0 {
+        println!("  {} emails no longer exist", accuracy.gone);
+    }
+
+    let mut labels = accuracy.by_label.iter().collect::<Vec<_>>();
+    labels.sort_by_key(|(label, counts)| (Reverse(counts.kept + counts.removed), label.as_str()));
+    println!(
+        "\n{:<24} {:>6} {:>8} {:>6} {:>7}",
+        "Label", "Kept", "Removed", "Added", "Kept %"
+    );
+    for (label, counts) in labels {
+        let predicted = counts.kept + counts.removed;
+        let kept_percent = if predicted == 0 {
+            "-".to_string()
+        } else {
+            format!("{:.0}%", counts.kept as f64 * 100.0 / predicted as f64)
+        };
+        println!(
+            "{:<24} {:>6} {:>8} {:>6} {:>7}",
+            label, counts.kept, counts.removed, counts.added, kept_percent
+        );
+    }
+}
+
+/// Prune predictions for emails that are gone and re-apply missing labels.
+/// Extra user labels are left for `learn` to pick up. Returns the prune count.
+async fn reconcile_drifts(
+    provider: &dyn EmailProvider,
+    predictions: &mut PredictionStore,
+    drifts: &[Drift],
+    dry_run: bool,
+) -> usize {
+    let mut pruned = 0;
+    for drift in drifts {
+        if drift.email_gone {
+            println!("Pruning prediction for {}", drift.email_id);
+            if !dry_run {
+                predictions.remove(&drift.email_id);
+            }
+            pruned += 1;
+            continue;
+        }
+
+        for label in &drift.missing_labels {
+            println!("Re-stamping '{}' on {}", label, drift.email_id);
+            if dry_run {
+                continue;
+            }
+            if let Err(error) = provider.add_label(&drift.email_id, label).await {
+                warn!(id = %drift.email_id, "couldn't apply label '{}': {}", label, error);
+            }
+        }
+    }
+    pruned
+}
+
+fn print_label_cleanup_result(labels: &[String], dry_run: bool) {
+    if dry_run {
+        println!("Would remove {} labels:", labels.len());
+    } else {
+        println!("Removed {} labels:", labels.len());
+    }
+
+    for label in labels {
+        println!("  - {}", label);
+    }
+}
+
+fn cleanup_deleted_predictions(predictions: &mut PredictionStore, deleted_ids: &[String]) -> bool {
+    if deleted_ids.is_empty() {
+        return false;
+    }
+
+    println!(
+        "Cleaned up {} deleted emails from predictions.",
+        deleted_ids.len()
+    );
+
+    for id in deleted_ids {
+        predictions.remove(id);
+    }
+    true
+}
+
+/// Drop predictions past the configured max age so correction detection
+/// doesn't refetch emails from months ago.
+fn prune_stale_predictions(
+    predictions: &mut PredictionStore,
+    cfg: &Config,
+    dry_run: bool,
+) -> usize {
+    let pruned = predictions.prune(cfg.prediction_max_age());
+    if pruned == 0 {
+        return 0;
+    }
+
+    let verb = if dry_run { "Would prune" } else { "Pruned" };
+    println!(
+        "{} {} predictions older than {} days.",
+        verb,
+        pruned,
+        cfg.prediction_max_age_days()
+    );
+    pruned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::ClassifyBackend;
+    use crate::providers::mock::MockProvider;
+    use chrono::{TimeZone, Utc};
+
+    fn make_classification(theme: &[&str], delete: bool) -> Classification {
+        Classification {
+            is_spam: false,
+            archive: false,
+            delete,
+            theme: theme.iter().map(|label| label.to_string()).collect(),
+            action: vec![],
+            confidence: 0.9,
+            reason: None,
+            heuristic: false,
+        }
+    }
+
+    #[test]
+    fn test_search_row_shows_suggestion_only_when_classified() {
+        let email = Email {
+            id: "42".to_string(),
+            from: "shop@example.com".to_string(),
+            subject: "Spring sale".to_string(),
+            labels: vec!["INBOX".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            SearchRow::new(&email, None).plain(),
+            "42 | shop@example.com | Spring sale | [\"INBOX\"]"
+        );
+
+        let classification = Classification {
+            archive: true,
+            ..make_classification(&["Promotional"], false)
+        };
+        let row = SearchRow::new(&email, Some(&classification));
+        assert_eq!(
+            row.plain(),
+            "42 | shop@example.com | Spring sale | [\"INBOX\"]\n  \
+             Suggested: [\"Promotional\"] (confidence 0.90) → archive"
+        );
+    }
+
+    #[test]
+    fn test_stale_labels_ignores_case() {
+        let old = ["Finance", "Important", "Shopping"].map(String::from);
+        let new = ["finance", "Needs-Reply"].map(String::from);
+        assert_eq!(stale_labels(&old, &new), vec!["Important", "Shopping"]);
+    }
+
+    #[tokio::test]
+    async fn test_sync_contacts_merges_provider_addresses() {
+        let mut provider = MockProvider::default();
+        provider.contacts = vec![
+            "Alice <alice@example.com>".to_string(),
+            "bob@example.org".to_string(),
+        ];
+        let mut contacts = Contacts::from_entries(["bob@example.org".to_string()]);
+
+        let added = sync_contacts(&provider, &mut contacts).await.unwrap();
+
+        assert_eq!(added, 1);
+        assert!(contacts.is_known("Alice Smith <ALICE@example.com>"));
+    }
+
+    #[tokio::test]
+    async fn test_apply_bulk_reports_successes_and_failures() {
+        let provider = MockProvider::default();
+        let report = apply_bulk(
+            &provider,
+            &BulkOperation::Label("Receipts".to_string()),
+            &["1", "2", "3"],
+        )
+        .await;
+        assert_eq!(report.succeeded, 3);
+        assert!(report.failed.is_empty());
+        let mut calls = provider.calls();
+        calls.sort();
+        assert_eq!(
+            calls,
+            vec![
+                "add_label 1 Receipts",
+                "add_label 2 Receipts",
+                "add_label 3 Receipts"
+            ]
+        );
+
+        let failing = MockProvider::default().failing_on("archive");
+        let report = apply_bulk(&failing, &BulkOperation::Archive, &["1", "2"]).await;
+        assert_eq!(report.succeeded, 0);
+        assert_eq!(report.failed.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_live_needs_reply_keeps_order_and_drops_deleted() {
+        let email = |id: &str| Email {
+            id: id.to_string(),
+            subject: format!("Question {}", id),
+            ..Default::default()
+        };
+        let mut predictions = PredictionStore::default();
+        let mut needs_reply = make_classification(&["Work"], false);
+        needs_reply.action = vec!["Needs-Reply".to_string()];
+        for id in ["a", "b", "c"] {
+            predictions.store(&email(id), &needs_reply, vec![]).unwrap();
+        }
+        let provider = MockProvider::with_emails([email("a"), email("c")]);
+
+        let pending = predictions.needs_reply(NeedsReplySort::Date);
+        let expected = pending
+            .iter()
+            .map(|prediction| prediction.email_id.clone())
+            .filter(|id| id != "b")
+            .collect::<Vec<_>>();
+        let live = live_needs_reply(&provider, group_reply_threads(pending)).await;
+
+        let ids = live
+            .iter()
+            .map(|(thread, email)| {
+                assert_eq!(thread.latest().email_id, email.id);
+                email.id.clone()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_watch_backs_off_after_failures() {
+        let interval = Duration::from_secs(300);
+        assert_eq!(watch_delay(interval, 0), interval);
+        assert_eq!(watch_delay(interval, 1), Duration::from_secs(600));
+        assert_eq!(watch_delay(interval, 3), Duration::from_secs(2400));
+        assert_eq!(watch_delay(interval, 10), Duration::from_secs(2400));
+    }
+
+    #[test]
+    fn test_spot_check_samples_roughly_rate_with_fixed_seed() {
+        let spot_check = SpotCheck {
+            rate: 0.2,
+            seed: 42,
+        };
+        let ids = (0..1000)
+            .map(|index| format!("msg{}", index))
+            .collect::<Vec<_>>();
+
+        let selected = ids.iter().filter(|id| spot_check.selects(id)).count();
+        let again = ids.iter().filter(|id| spot_check.selects(id)).count();
+
+        assert!((150..=250).contains(&selected), "selected {}", selected);
+        assert_eq!(selected, again);
+    }
+
+    #[test]
+    fn test_label_diff_only_touches_what_changed() {
+        let labels = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        let current = labels(&["INBOX", "finance", "Travel", "Family"]);
+        let diff = LabelDiff::new(
+            &current,
+            &labels(&["Finance", "Receipts"]),
+            &labels(&["Travel", "Shopping"]),
+        );
+        assert_eq!(diff.add, labels(&["Receipts"]));
+        assert_eq!(diff.remove, labels(&["Travel"]));
+    }
+
+    #[tokio::test]
+    async fn test_scan_skips_labels_the_email_already_has() {
+        let email = Email {
+            id: "1".to_string(),
+            labels: vec!["INBOX".to_string(), "Newsletters".to_string()],
+            ..Default::default()
+        };
+        let provider = MockProvider::with_emails([email.clone()]);
+        let mut state = empty_scan_state();
+        let classification = make_classification(&["Newsletters", "Tech"], false);
+
+        apply_scan_actions(&provider, &mut state, &email, &classification, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            provider.calls(),
+            vec!["add_label 1 Tech", "add_label 1 Classified"]
+        );
+    }
+
+    #[test]
+    fn test_classify_latency_summary() {
+        let mut latencies = ClassifyLatencies::default();
+        assert_eq!(latencies.summary(), None);
+        for millis in [3200, 800, 1400] {
+            latencies.record(Duration::from_millis(millis));
+        }
+        assert_eq!(
+            latencies.summary().unwrap(),
+            "min 0.8s, median 1.4s, max 3.2s over 3 emails"
+        );
+    }
+
+    #[test]
+    fn test_removed_predictions_are_counted_once() {
+        let email = |id: &str| Email {
+            id: id.to_string(),
+            ..Default::default()
+        };
+        let classification = make_classification(&["Work"], false);
+        let mut predictions = PredictionStore::default();
+        for id in ["a", "b", "c"] {
+            predictions
+                .store(&email(id), &classification, vec![])
+                .unwrap();
+        }
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        let deleted = ids(&["a", "gone"]);
+        let corrected = ids(&["a", "b", "b"]);
+
+        assert_eq!(
+            remove_predictions(&mut predictions, &deleted, &corrected, true),
+            2
+        );
+        assert!(predictions.get("a").is_some());
+
+        assert_eq!(
+            remove_predictions(&mut predictions, &deleted, &corrected, false),
+            2
+        );
+        assert!(predictions.get("a").is_none() && predictions.get("b").is_none());
+        assert!(predictions.get("c").is_some());
+    }
+
+    #[test]
+    fn test_dry_run_delta_summary() {
+        let mut label_manager = LabelManager::default();
+        label_manager.record_llm_labels(&["Finance".to_string()]);
+        let mut delta = ScanDelta {
+            removed_predictions: 2,
+            ..Default::default()
+        };
+
+        delta.record(
+            &make_classification(&["Finance", "Travel"], false),
+            &label_manager,
+        );
+        delta.record(
+            &make_classification(&["Travel", "Health"], false),
+            &label_manager,
+        );
+        delta.record(&make_classification(&["Shopping"], true), &label_manager);
+
+        assert_eq!(
+            delta.summary(),
+            "Prediction store: +2 new, -2 removed\nNew LLM labels: Health, Travel"
+        );
+    }
+
+    #[test]
+    fn test_thread_collapses_to_latest_with_count() {
+        let email = |id: &str, thread: Option<&str>, day: u32| Email {
+            id: id.to_string(),
+            thread_id: thread.map(str::to_string),
+            date: Some(Utc.with_ymd_and_hms(2025, 1, day, 9, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        let emails = vec![
+            email("a1", Some("a"), 1),
+            email("b1", Some("b"), 2),
+            email("a3", Some("a"), 3),
+            email("solo", None, 4),
+            email("a2", Some("a"), 2),
+        ];
+
+        let threads = group_threads(emails.clone(), true);
+        let summary = threads
+            .iter()
+            .map(|thread| (thread.latest.id.as_str(), thread.earlier.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![("a3", 2), ("b1", 0), ("solo", 0)]);
+        let earlier = threads[0]
+            .earlier
+            .iter()
+            .map(|email| email.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(earlier, vec!["a2", "a1"]);
+
+        assert_eq!(group_threads(emails, false).len(), 5);
+    }
+
+    #[test]
+    fn test_policy_precedence_between_model_rules_and_protection() {
+        let config = Config {
+            label_policies: Some(
+                [
+                    (
+                        "Promotional".to_string(),
+                        config::LabelPolicy {
+                            archive: Some(false),
+                            delete: Some(false),
+                        },
+                    ),
+                    (
+                        "Social".to_string(),
+                        config::LabelPolicy {
+                            archive: None,
+                            delete: Some(true),
+                        },
+                    ),
+                ]
+                .into(),
+            ),
+            ..Default::default()
+        };
+        let state = ScanState {
+            policies: PolicyTable::from_config(&config),
+            ..empty_scan_state()
+        };
+        let archive_deals = rules::Rule {
+            name: "Deals".to_string(),
+            description: String::new(),
+            enabled: true,
+            condition: rules::Condition {
+                field: "from".to_string(),
+                contains: "deals@".to_string(),
+                and: None,
+            },
+            action: "archive".to_string(),
+            label: None,
+            gmail_filter: None,
+        };
+        let email = |id: &str, from: &str, labels: &[&str]| Email {
+            id: id.to_string(),
+            from: from.to_string(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            ..Default::default()
+        };
+        let promotional = make_classification(&["Promotional"], true);
+        let social = make_classification(&["Social"], false);
+        let rules = [archive_deals];
+
+        // Policies override the model either way
+        let result = finalize_classification(&state, &rules, &email("1", "a@x", &[]), &promotional);
+        assert!(!result.delete && !result.archive);
+        let result = finalize_classification(&state, &rules, &email("1", "a@x", &[]), &social);
+        assert!(result.delete);
+
+        // A matching rule overrides the policy
+        let result =
+            finalize_classification(&state, &rules, &email("2", "deals@x", &[]), &promotional);
+        assert!(result.archive && !result.delete);
+
+        // Curated mail stays put whatever the policy says
+        let result =
+            finalize_classification(&state, &rules, &email("3", "a@x", &["STARRED"]), &social);
+        assert!(!result.delete && !result.archive);
+    }
+
+    /// Scan state with nothing loaded from disk and no config
+    fn empty_scan_state() -> ScanState {
+        ScanState {
+            predictions: PredictionStore::default(),
+            label_manager: LabelManager::default(),
+            delta: ScanDelta::default(),
+            policies: PolicyTable::default(),
+            protected_senders: ProtectedSenders::default(),
+            ignored_senders: IgnoredSenders::default(),
+            protect_curated: true,
+            notifier: None,
+            spot_check: None,
+            llm_budget: None,
+            delete_mode: DeleteMode::Trash,
+            quarantine: Quarantine::default(),
+            latencies: ClassifyLatencies::default(),
+            sender_stats: SenderStats::default(),
+            sync_importance: false,
+            deferred_deletes: Vec::new(),
+            results: Rows::new(Formatter::Plain),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unread_only_scan_skips_read_emails() {
+        let email = |id: &str, labels: &[&str]| Email {
+            id: id.to_string(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            ..Default::default()
+        };
+        let provider = MockProvider::with_emails([
+            email("read", &["INBOX"]),
+            email("unread", &["INBOX", "UNREAD"]),
+            email("done", &["INBOX", "UNREAD", "Classified"]),
+        ]);
+        let mut options = ScanOptions {
+            max: 10,
+            archived: false,
+            dry_run: true,
+            fail_fast: true,
+            oldest_first: false,
+            spot_check: None,
+            subject_only: false,
+            no_collapse: false,
+            query: None,
+            explain: false,
+            max_llm_calls: None,
+            unread_only: false,
+            confirm_deletes: false,
+        };
+        assert_eq!(
+            load_scan_emails(&provider, &options).await.unwrap().len(),
+            2
+        );
+
+        options.unread_only = true;
+        let emails = load_scan_emails(&provider, &options).await.unwrap();
+        let ids = emails
+            .iter()
+            .map(|email| email.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["unread"]);
+    }
+
+    #[tokio::test]
+    async fn test_confirm_deletes_holds_deletes_back() {
+        let email = Email {
+            id: "1".to_string(),
+            ..Default::default()
+        };
+        let provider = MockProvider::with_emails([email.clone()]);
+        let options = ScanOptions {
+            max: 10,
+            archived: false,
+            dry_run: false,
+            fail_fast: true,
+            oldest_first: false,
+            spot_check: None,
+            subject_only: false,
+            no_collapse: false,
+            query: None,
+            explain: false,
+            max_llm_calls: None,
+            unread_only: false,
+            confirm_deletes: true,
+        };
+        let mut state = empty_scan_state();
+
+        let delete = make_classification(&["Promotional"], true);
+        apply_thread_classification(&provider, &mut state, &email, &delete, &options)
+            .await
+            .unwrap();
+        assert!(provider.calls().is_empty());
+        assert_eq!(state.deferred_deletes.len(), 1);
+
+        // Everything else is applied right away
+        let keep = make_classification(&["Finance"], false);
+        apply_thread_classification(&provider, &mut state, &email, &keep, &options)
+            .await
+            .unwrap();
+        assert!(provider
+            .calls()
+            .contains(&"add_label 1 Finance".to_string()));
+        assert_eq!(state.deferred_deletes.len(), 1);
+
+        // Once confirmed, only deletes the provider carried out count
+        let failing = MockProvider::with_emails([email.clone()]).failing_on("trash");
+        let options = ScanOptions {
+            fail_fast: false,
+            ..options
+        };
+        let deferred = std::mem::take(&mut state.deferred_deletes);
+        let outcome =
+            apply_deferred_deletes(&failing, &mut state, deferred, &options, |_| Ok(true))
+                .await
+                .unwrap();
+        assert_eq!(
+            outcome,
+            DeferredOutcome {
+                deleted: 0,
+                kept: 0,
+                failed: 1
+            }
+        );
+        assert_eq!(failing.calls(), vec!["trash 1"]);
+    }
+
+    /// Fails the test if a classification reaches the model
+    struct NoLlm;
+
+    #[async_trait]
+    impl ClassifyBackend for NoLlm {
+        async fn respond(&self, _prompt: &str) -> Result<String> {
+            anyhow::bail!("unexpected LLM call")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spent_llm_budget_stops_before_classifying() {
+        let email = Email {
+            id: "1".to_string(),
+            labels: vec!["INBOX".to_string()],
+            ..Default::default()
+        };
+        let provider = MockProvider::with_emails([email.clone()]);
+        let profile = Profile::from_content("# Email Classification Profile");
+        let cfg = Config::default();
+        let classifier = Classifier::new(&profile, &cfg).backend(NoLlm);
+        let options = ScanOptions {
+            max: 10,
+            archived: false,
+            dry_run: false,
+            fail_fast: true,
+            oldest_first: false,
+            spot_check: None,
+            subject_only: false,
+            no_collapse: false,
+            query: None,
+            explain: false,
+            max_llm_calls: Some(0),
+            unread_only: false,
+            confirm_deletes: false,
+        };
+        let mut state = empty_scan_state();
+        state.llm_budget = options.max_llm_calls.map(LlmBudget::start);
+        let thread = ScanThread {
+            latest: email,
+            earlier: Vec::new(),
+        };
+
+        let progress =
+            process_scan_thread(&provider, &classifier, &[], &mut state, thread, &options)
+                .await
+                .unwrap();
+        assert!(progress.is_break());
+        assert!(provider.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_important_emails_flagged_when_syncing_importance() {
+        let email = Email {
+            id: "1".to_string(),
+            ..Default::default()
+        };
+        let important = Classification {
+            action: vec!["Urgent".to_string()],
+            ..make_classification(&["Finance"], false)
+        };
+        let provider = MockProvider::with_emails([email.clone()]);
+
+        let mut state = empty_scan_state();
+        apply_scan_actions(&provider, &mut state, &email, &important, true)
+            .await
+            .unwrap();
+        assert!(!provider.calls().contains(&"set_important 1".to_string()));
+
+        let mut state = ScanState {
+            sync_importance: true,
+            ..empty_scan_state()
+        };
+        let routine = make_classification(&["Finance"], false);
+        apply_scan_actions(&provider, &mut state, &email, &routine, true)
+            .await
+            .unwrap();
+        assert!(!provider.calls().contains(&"set_important 1".to_string()));
+        apply_scan_actions(&provider, &mut state, &email, &important, true)
+            .await
+            .unwrap();
+        assert!(provider.calls().contains(&"set_important 1".to_string()));
+        let flagged = provider.get_message("1").await.unwrap();
+        assert!(flagged.labels.contains(&"IMPORTANT".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_quarantine_mode_holds_deletes_back() {
+        let email = Email {
+            id: "1".to_string(),
+            subject: "Flash sale".to_string(),
+            ..Default::default()
+        };
+        let classification = make_classification(&["Promotional"], true);
+        let provider = MockProvider::with_emails([email.clone()]);
+        let mut state = ScanState {
+            delete_mode: DeleteMode::Quarantine,
+            ..empty_scan_state()
+        };
+
+        apply_scan_actions(&provider, &mut state, &email, &classification, true)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            provider.calls(),
+            vec!["add_label 1 Quarantine", "archive 1"]
+        );
+        assert_eq!(state.quarantine.entries()[0].subject, "Flash sale");
+        assert!(state.predictions.get("1").is_none());
+
+        // Not due yet, then due once the grace period is over
+        let grace = chrono::Duration::days(7);
+        let purged = purge_quarantine(&provider, &mut state.quarantine, grace, false)
+            .await
+            .unwrap();
+        assert_eq!(purged, 0);
+        let purged = purge_quarantine(
+            &provider,
+            &mut state.quarantine,
+            chrono::Duration::zero(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(purged, 1);
+        assert!(state.quarantine.is_empty());
+        assert!(provider.calls().contains(&"trash 1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_rescued_quarantine_emails_are_not_deleted() {
+        let email = |id: &str, labels: &[&str]| Email {
+            id: id.to_string(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            ..Default::default()
+        };
+        let unlabeled = email("1", &[]);
+        let back_in_inbox = email("2", &["INBOX", QUARANTINE_LABEL]);
+        let still_held = email("3", &[QUARANTINE_LABEL]);
+        let provider = MockProvider::with_emails([
+            unlabeled.clone(),
+            back_in_inbox.clone(),
+            still_held.clone(),
+        ]);
+        let mut quarantine = Quarantine::default();
+        let quarantined_at = chrono::Utc::now() - chrono::Duration::days(30);
+        for email in [&unlabeled, &back_in_inbox, &still_held] {
+            quarantine.add(email, None, quarantined_at);
+        }
+
+        let already_deleted = email("4", &[QUARANTINE_LABEL]);
+        quarantine.add(&already_deleted, None, quarantined_at);
+
+        let purged = purge_quarantine(&provider, &mut quarantine, chrono::Duration::days(7), false)
+            .await
+            .unwrap();
+
+        assert_eq!(purged, 1);
+        assert_eq!(provider.calls(), vec!["trash 3"]);
+        assert!(quarantine.is_empty());
+
+        // An email that can't be checked right now stays quarantined for later
+        let provider = MockProvider::with_emails([still_held.clone()]).failing_on("get_message");
+        quarantine.add(&still_held, None, quarantined_at);
+        let purged = purge_quarantine(&provider, &mut quarantine, chrono::Duration::days(7), false)
+            .await
+            .unwrap();
+        assert_eq!(purged, 0);
+        assert!(provider.calls().is_empty());
+        assert!(!quarantine.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_scan_never_writes() {
+        let keep = Email {
+            id: "1".to_string(),
+            labels: vec!["INBOX".to_string(), "Finance".to_string()],
+            ..Default::default()
+        };
+        let junk = Email {
+            id: "2".to_string(),
+            labels: vec!["INBOX".to_string()],
+            ..Default::default()
+        };
+        let provider = MockProvider::with_emails([keep.clone(), junk.clone()]);
+        let mut state = ScanState {
+            delete_mode: DeleteMode::Quarantine,
+            ..empty_scan_state()
+        };
+        state.quarantine.add(&junk, None, chrono::Utc::now());
+        let options = ScanOptions {
+            max: 10,
+            archived: false,
+            dry_run: true,
+            fail_fast: true,
+            oldest_first: false,
+            spot_check: None,
+            subject_only: false,
+            no_collapse: false,
+            query: None,
+            explain: false,
+            max_llm_calls: None,
+            unread_only: false,
+            confirm_deletes: false,
+        };
+        let mut archive = make_classification(&["Finance", "Receipts"], false);
+        archive.archive = true;
+        let delete = make_classification(&["Promotional"], true);
+
+        apply_thread_classification(&provider, &mut state, &keep, &archive, &options)
+            .await
+            .unwrap();
+        apply_thread_classification(&provider, &mut state, &junk, &delete, &options)
+            .await
+            .unwrap();
+        let due = purge_quarantine(
+            &provider,
+            &mut state.quarantine,
+            chrono::Duration::zero(),
+            true,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(due, 1);
+        assert!(provider.calls().is_empty());
+        assert!(state.predictions.get("1").is_none());
+        assert_eq!(state.quarantine.len(), 1);
+        assert_eq!(
+            state.label_manager.unknown_labels(&archive.labels()).len(),
+            2
+        );
+
+        let plan = ScanPlan::new(&keep, &archive, state.delete_mode, &["Travel".to_string()]);
+        assert_eq!(
+            plan,
+            ScanPlan {
+                add_labels: vec!["Receipts".to_string(), "Classified".to_string()],
+                remove_labels: vec![],
+                disposition: Some("archive"),
+                store_prediction: true,
+            }
+        );
+        let plan = ScanPlan::new(&junk, &delete, state.delete_mode, &[]);
+        assert_eq!(plan.add_labels, vec![QUARANTINE_LABEL.to_string()]);
+        assert_eq!(plan.disposition, Some("quarantine"));
+        assert!(!plan.store_prediction);
+    }
+
+    #[tokio::test]
+    async fn test_heuristic_fallback_is_not_stamped_or_stored() {
+        let email = Email {
+            id: "1".to_string(),
+            labels: vec!["INBOX".to_string()],
+            ..Default::default()
+        };
+        let provider = MockProvider::with_emails([email.clone()]);
+        let mut state = empty_scan_state();
+        let mut fallback = make_classification(&["Finance"], false);
+        fallback.heuristic = true;
+
+        let plan = ScanPlan::new(&email, &fallback, state.delete_mode, &[]);
+        assert_eq!(plan.add_labels, vec!["Finance"]);
+        assert!(!plan.store_prediction);
+        apply_scan_actions(&provider, &mut state, &email, &fallback, true)
+            .await
+            .unwrap();
+
+        assert_eq!(provider.calls(), vec!["add_label 1 Finance"]);
+        assert!(state.predictions.get("1").is_none());
+    }
+
+    #[test]
+    fn test_important_flag_set_by_sync_is_not_curation() {
+        let email = Email {
+            id: "1".to_string(),
+            labels: vec!["INBOX".to_string(), "IMPORTANT".to_string()],
+            ..Default::default()
+        };
+        let mut archive = make_classification(&["Newsletters"], false);
+        archive.archive = true;
+        let mut state = ScanState {
+            sync_importance: true,
+            ..empty_scan_state()
+        };
+
+        // Nothing says the tool flagged it, so the user did
+        let kept = finalize_classification(&state, &[], &email, &archive);
+        assert!(!kept.archive);
+
+        let mut important = make_classification(&["Newsletters"], false);
+        important.action = vec!["Important".to_string()];
+        state.predictions.store(&email, &important, vec![]).unwrap();
+        let archived = finalize_classification(&state, &[], &email, &archive);
+        assert!(archived.archive);
+
+        state.sync_importance = false;
+        let kept = finalize_classification(&state, &[], &email, &archive);
+        assert!(!kept.archive);
+    }
+
+    /// Answers every classification prompt the same way
+    struct CannedLlm(&'static str);
+
+    #[async_trait]
+    impl ClassifyBackend for CannedLlm {
+        async fn respond(&self, _prompt: &str) -> Result<String> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_starred_email_never_archived() {
+        let email = Email {
+            id: "1".to_string(),
+            subject: "This week's top stories".to_string(),
+            body: "Here are the stories our readers liked most this week.".to_string(),
+            labels: vec!["INBOX".to_string(), "STARRED".to_string()],
+            ..Default::default()
+        };
+        let profile = Profile::from_content("# Email Classification Profile");
+        let cfg = Config::default();
+        let classifier = Classifier::new(&profile, &cfg).backend(CannedLlm(
+            r#"{"is_spam": false, "theme": ["Newsletters"], "archive": true, "delete": true, "confidence": 0.95}"#,
+        ));
+        let options = ScanOptions {
+            max: 10,
+            archived: false,
+            dry_run: false,
+            fail_fast: true,
+            oldest_first: false,
+            spot_check: None,
+            subject_only: false,
+            no_collapse: false,
+            query: None,
+            explain: false,
+            max_llm_calls: None,
+            unread_only: false,
+            confirm_deletes: false,
+        };
+        let thread = || ScanThread {
+            latest: email.clone(),
+            earlier: Vec::new(),
+        };
+
+        let provider = MockProvider::with_emails([email.clone()]);
+        let mut state = empty_scan_state();
+        let progress =
+            process_scan_thread(&provider, &classifier, &[], &mut state, thread(), &options)
+                .await
+                .unwrap();
+        assert!(progress.is_continue());
+        assert_eq!(
+            provider.calls(),
+            vec!["add_label 1 Newsletters", "add_label 1 Classified"]
+        );
+
+        // With protection turned off in config, the model's delete goes ahead
+        let provider = MockProvider::with_emails([email.clone()]);
+        let mut state = empty_scan_state();
+        state.protect_curated = false;
+        let progress =
+            process_scan_thread(&provider, &classifier, &[], &mut state, thread(), &options)
+                .await
+                .unwrap();
+        assert!(progress.is_continue());
+        assert!(provider.calls().contains(&"trash 1".to_string()));
+    }
+
+    /// Rejects the second batch with a 429 a fixed number of times
+    struct RateLimitedBackend {
+        calls: usize,
+        rejections_left: usize,
+        applied: Vec<usize>,
+    }
+
+    #[async_trait]
+    impl CorrectionBackend for RateLimitedBackend {
+        async fn apply_batch(&mut self, batch: &[Correction]) -> Result<()> {
+            self.calls += 1;
+            let first_id = batch[0].email_id.parse::<usize>()?;
+            let batch_number = first_id / CORRECTION_BATCH_SIZE + 1;
+            if batch_number == 2 && self.rejections_left > 0 {
+                self.rejections_left -= 1;
+                anyhow::bail!("429 Too Many Requests");
+            }
+            self.applied.push(batch_number);
+            Ok(())
+        }
+    }
+
+    fn make_corrections(count: usize) -> Vec<Correction> {
+        (0..count)
+            .map(|index| Correction {
+                email_id: index.to_string(),
+                from: String::new(),
+                subject: String::new(),
+                predicted_labels: vec![],
+                actual_labels: vec![],
+                predicted_spam: false,
+                actual_spam: false,
+            })
+            .collect()
+    }
+
+    fn instant_policy() -> BatchPolicy {
+        BatchPolicy {
+            attempts: 3,
+            backoff: Duration::ZERO,
+            delay_between: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_batch_is_retried() {
+        let corrections = make_corrections(CORRECTION_BATCH_SIZE * 3);
+        let mut backend = RateLimitedBackend {
+            calls: 0,
+            rejections_left: 2,
+            applied: vec![],
+        };
+
+        let progress =
+            apply_corrections_in_batches(&mut backend, &corrections, &instant_policy(), false)
+                .await;
+
+        assert!(progress.error.is_none());
+        assert!(progress.remaining_batches.is_empty());
+        assert_eq!(backend.applied, vec![1, 2, 3]);
+        assert_eq!(backend.calls, 5);
+        assert_eq!(progress.applied_ids.len(), corrections.len());
+    }
+
+    #[tokio::test]
+    async fn test_persistent_rate_limit_reports_remaining_batches() {
+        let corrections = make_corrections(CORRECTION_BATCH_SIZE * 3);
+        let mut backend = RateLimitedBackend {
+            calls: 0,
+            rejections_left: usize::MAX,
+            applied: vec![],
+        };
+
+        let progress =
+            apply_corrections_in_batches(&mut backend, &corrections, &instant_policy(), false)
+                .await;
+
+        assert!(progress.error.is_some());
+        assert_eq!(backend.applied, vec![1]);
+        assert_eq!(progress.remaining_batches, vec![2, 3]);
+        assert_eq!(
+            progress.applied_ids,
+            correction_ids(&corrections[..CORRECTION_BATCH_SIZE])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_aborts_on_first_failure() {
+        let email = Email {
+            id: "1".to_string(),
+            ..Default::default()
+        };
+        let mut classification = make_classification(&["Finance", "Travel"], false);
+        classification.archive = true;
+        let provider = MockProvider::default().failing_on("add_label");
+
+        let mut state = empty_scan_state();
+        let result = apply_scan_actions(&provider, &mut state, &email, &classification, true).await;
+
+        assert!(result.is_err());
+        assert_eq!(provider.calls(), vec!["add_label 1 Finance"]);
+    }
+
+    #[tokio::test]
+    async fn test_resilient_scan_continues_after_failure() {
+        let email = Email {
+            id: "1".to_string(),
+            ..Default::default()
+        };
+        let mut classification = make_classification(&["Finance"], false);
+        classification.archive = true;
+        let provider = MockProvider::default().failing_on("add_label");
+
+        let mut state = empty_scan_state();
+        let result =
+            apply_scan_actions(&provider, &mut state, &email, &classification, false).await;
+
+        assert!(result.is_ok());
+        assert_eq!(
+            provider.calls(),
+            vec!["add_label 1 Finance", "add_label 1 Classified", "archive 1"]
+        );
     }
-    predictions.save()
 }
@@ -0,0 +1,260 @@
+use crate::providers::{sender_address, Email};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+/// Copies of one email arrive within minutes of each other; emails further
+/// than this from a group's newest copy start a group of their own
+const DUPLICATE_WINDOW_SECS: i64 = 10 * 60;
+
+/// What duplicate deliveries of one email have in common
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DuplicateKey {
+    /// Copies of one delivery share its Message-ID, whenever they arrive
+    MessageId(String),
+    /// Without a Message-ID, only identical emails count: same sender
+    /// address, subject, and body, ignoring case in the subject and
+    /// whitespace in both
+    Content {
+        from: String,
+        subject: String,
+        body: String,
+    },
+}
+
+/// The Message-ID when the email has one, else its content. Emails with
+/// neither a Message-ID nor a date never count as duplicates.
+pub fn duplicate_key(email: &Email) -> Option<DuplicateKey> {
+    let message_id = email
+        .message_id
+        .as_deref()
+        .map(|id| id.trim().trim_start_matches('<').trim_end_matches('>'))
+        .filter(|id| !id.is_empty());
+    if let Some(message_id) = message_id {
+        return Some(DuplicateKey::MessageId(message_id.to_string()));
+    }
+
+    email.date?;
+    Some(DuplicateKey::Content {
+        from: sender_address(&email.from),
+        subject: collapse_whitespace(&email.subject).to_lowercase(),
+        body: collapse_whitespace(&email.body),
+    })
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Whether `email` arrived close enough to `newest` to be a copy of it;
+/// a shared Message-ID is enough on its own
+fn within_window(key: &DuplicateKey, newest: &Email, email: &Email) -> bool {
+    match (key, newest.date, email.date) {
+        (DuplicateKey::MessageId(_), _, _) => true,
+        (DuplicateKey::Content { .. }, Some(newest), Some(date)) => {
+            (newest - date).num_seconds() <= DUPLICATE_WINDOW_SECS
+        }
+        (DuplicateKey::Content { .. }, _, _) => false,
+    }
+}
+
+/// Emails sharing a key, newest first, for every key with more than one;
+/// groups come in the order their first email was listed
+pub fn duplicate_groups(emails: &[Email]) -> Vec<Vec<&Email>> {
+    let mut listed = emails.iter().enumerate().collect::<Vec<_>>();
+    listed.sort_by_key(|(_, email)| Reverse(email.date));
+
+    let mut groups: Vec<Vec<(usize, &Email)>> = Vec::new();
+    let mut indexes_by_key: HashMap<DuplicateKey, Vec<usize>> = HashMap::new();
+    for (position, email) in listed {
+        let Some(key) = duplicate_key(email) else {
+            continue;
+        };
+        let matching = indexes_by_key
+            .get(&key)
+            .into_iter()
+            .flatten()
+            .copied()
+            .find(|&index| within_window(&key, groups[index][0].1, email));
+        match matching {
+            Some(index) => groups[index].push((position, email)),
+            None => {
+                indexes_by_key.entry(key).or_default().push(groups.len());
+                groups.push(vec![(position, email)]);
+            }
+        }
+    }
+
+    groups.retain(|group| group.len() > 1);
+    groups.sort_by_key(|group| group.iter().map(|(position, _)| *position).min());
+    groups
+        .into_iter()
+        .map(|group| group.into_iter().map(|(_, email)| email).collect())
+        .collect()
+}
+
+/// The copies in a group (newest first) that can be archived by ID without
+/// touching the kept email. IMAP IDs are Message-IDs, so there every copy
+/// shares the kept email's ID and archiving it could move the kept copy;
+/// such copies are left alone, as are repeats of an ID already archived.
+pub fn archivable_copies<'a>(group: &[&'a Email]) -> Vec<&'a Email> {
+    let Some((newest, copies)) = group.split_first() else {
+        return Vec::new();
+    };
+    let mut seen = HashSet::from([newest.id.as_str()]);
+    copies
+        .iter()
+        .copied()
+        .filter(|email| seen.insert(email.id.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, Utc};
+
+    fn email(
+        id: &str,
+        from: &str,
+        subject: &str,
+        body: &str,
+        date: Option<DateTime<Utc>>,
+    ) -> Email {
+        Email {
+            id: id.to_string(),
+            from: from.to_string(),
+            subject: subject.to_string(),
+            body: body.to_string(),
+            date,
+            ..Default::default()
+        }
+    }
+
+    fn group_ids<'a>(groups: &[Vec<&'a Email>]) -> Vec<Vec<&'a str>> {
+        groups
+            .iter()
+            .map(|group| group.iter().map(|email| email.id.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_identical_copies_grouped_newest_first() {
+        let sent = DateTime::parse_from_rfc3339("2025-03-01T09:59:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let later = |minutes| Some(sent + Duration::minutes(minutes));
+        let body = "Your order #1042 has shipped.";
+        let emails = [
+            email(
+                "1",
+                "Shop <orders@shop.com>",
+                "Order shipped",
+                body,
+                Some(sent),
+            ),
+            // Across a 10-minute boundary from the first copy
+            email(
+                "2",
+                "orders@SHOP.com",
+                "order  shipped ",
+                " Your order #1042  has shipped.",
+                later(2),
+            ),
+            email("3", "orders@shop.com", "Order shipped", body, later(45)),
+            email("4", "news@shop.com", "Order shipped", body, later(1)),
+            email("5", "orders@shop.com", "Order shipped", body, None),
+            email("6", "orders@shop.com", "Order shipped", body, None),
+        ];
+
+        assert_eq!(group_ids(&duplicate_groups(&emails)), vec![vec!["2", "1"]]);
+        assert_eq!(duplicate_key(&emails[4]), None);
+    }
+
+    #[test]
+    fn test_distinct_alerts_with_one_subject_are_kept() {
+        let sent = DateTime::parse_from_rfc3339("2025-03-01T09:01:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let emails = [
+            email(
+                "1",
+                "alerts@bank.com",
+                "Card used",
+                "$12.50 at Cafe",
+                Some(sent),
+            ),
+            email(
+                "2",
+                "alerts@bank.com",
+                "Card used",
+                "$80.00 at Grocer",
+                Some(sent + Duration::minutes(2)),
+            ),
+        ];
+
+        assert!(duplicate_groups(&emails).is_empty());
+    }
+
+    #[test]
+    fn test_message_id_groups_copies_whatever_their_content() {
+        let sent = DateTime::parse_from_rfc3339("2025-03-01T09:01:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let with_id = |id: &str, message_id: &str, body: &str, date| Email {
+            message_id: Some(message_id.to_string()),
+            ..email(id, "alerts@bank.com", "Card used", body, date)
+        };
+        let emails = [
+            with_id("1", "<abc@bank.com>", "$12.50 at Cafe", Some(sent)),
+            with_id(
+                "2",
+                "abc@bank.com",
+                "$12.50 at Cafe (resent)",
+                Some(sent + Duration::hours(2)),
+            ),
+            with_id(
+                "3",
+                "<def@bank.com>",
+                "$12.50 at Cafe",
+                Some(sent + Duration::minutes(1)),
+            ),
+        ];
+
+        assert_eq!(group_ids(&duplicate_groups(&emails)), vec![vec!["2", "1"]]);
+    }
+
+    #[test]
+    fn test_copies_sharing_the_kept_id_are_not_archived() {
+        let sent = DateTime::parse_from_rfc3339("2025-03-01T09:01:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        // IMAP gives every copy of a delivery its Message-ID as the ID
+        let copy = |id: &str, minutes| Email {
+            message_id: Some("<abc@bank.com>".to_string()),
+            ..email(
+                id,
+                "alerts@bank.com",
+                "Card used",
+                "$12.50 at Cafe",
+                Some(sent + Duration::minutes(minutes)),
+            )
+        };
+        let emails = [
+            copy("<abc@bank.com>", 0),
+            copy("<abc@bank.com>", 5),
+            copy("INBOX:7", 3),
+            copy("INBOX:7", 1),
+        ];
+
+        let groups = duplicate_groups(&emails);
+        assert_eq!(groups.len(), 1);
+        let archivable = archivable_copies(&groups[0]);
+        assert_eq!(
+            archivable
+                .iter()
+                .map(|email| email.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["INBOX:7"]
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use pulldown_cmark::{html, Event, Parser};
+
+/// Output format for `summary`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SummaryFormat {
+    #[default]
+    Markdown,
+    /// A standalone HTML page, e.g. for emailing the digest
+    Html,
+}
+
+/// The model's Markdown summary in the requested format
+pub fn render(markdown: &str, format: SummaryFormat, title: &str) -> String {
+    match format {
+        SummaryFormat::Markdown => format!("{}\n", markdown.trim()),
+        SummaryFormat::Html => html_page(markdown, title),
+    }
+}
+
+fn html_page(markdown: &str, title: &str) -> String {
+    // The summary quotes email subjects and senders, so raw HTML in it is
+    // shown as text rather than passed into the page
+    let events = Parser::new(markdown).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        event => event,
+    });
+    let mut body = String::new();
+    html::push_html(&mut body, events);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n{}</body>\n</html>\n",
+        escape_html(title),
+        escape_html(title),
+        body
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_page_wraps_body_and_escapes_title() {
+        let page = render(
+            "## Needs Action\n",
+            SummaryFormat::Html,
+            "Inbox <digest> & more",
+        );
+
+        assert!(page.starts_with("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n"));
+        assert!(page.contains("<title>Inbox &lt;digest&gt; &amp; more</title>"));
+        assert!(page.trim_end().ends_with("</body>\n</html>"));
+        assert_eq!(
+            render("  ## Needs Action\n\n", SummaryFormat::Markdown, "ignored"),
+            "## Needs Action\n"
+        );
+    }
+
+    #[test]
+    fn test_html_page_escapes_raw_html() {
+        let page = render(
+            "- Re: <script>alert(1)</script> from Bob\n\n<div onclick=\"x()\">hi</div>\n",
+            SummaryFormat::Html,
+            "Digest",
+        );
+
+        assert!(!page.contains("<script>"));
+        assert!(!page.contains("<div"));
+        assert!(page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(page.contains("&lt;div onclick=&quot;x()&quot;&gt;hi&lt;/div&gt;"));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::predictions::{Prediction, PredictionStore};
+use crate::profile::Profile;
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+
+/// Output format for `export`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+/// One prediction, flattened for spreadsheets (labels joined with ";")
+#[derive(Debug, Serialize)]
+struct PredictionRow<'a> {
+    email_id: &'a str,
+    from: &'a str,
+    subject: &'a str,
+    theme: String,
+    action: String,
+    is_spam: bool,
+    confidence: f32,
+    timestamp: String,
+}
+
+impl<'a> From<&'a Prediction> for PredictionRow<'a> {
+    fn from(prediction: &'a Prediction) -> Self {
+        Self {
+            email_id: &prediction.email_id,
+            from: &prediction.from,
+            subject: &prediction.subject,
+            theme: prediction.theme.join(";"),
+            action: prediction.action.join(";"),
+            is_spam: prediction.is_spam,
+            confidence: prediction.confidence,
+            timestamp: prediction.timestamp.to_rfc3339(),
+        }
+    }
+}
+
+/// A line from the profile's Learned Corrections section
+#[derive(Debug, Serialize)]
+struct CorrectionRow<'a> {
+    date: &'a str,
+    description: &'a str,
+}
+
+impl<'a> CorrectionRow<'a> {
+    /// Entries are written as "YYYY-MM-DD: description"; anything else is
+    /// kept whole with an empty date
+    fn parse(line: &'a str) -> Self {
+        match line.split_once(": ") {
+            Some((date, description)) if is_iso_date(date) => Self { date, description },
+            _ => Self {
+                date: "",
+                description: line,
+            },
+        }
+    }
+}
+
+fn is_iso_date(text: &str) -> bool {
+    chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d").is_ok()
+}
+
+/// Predictions oldest first, so exports are stable between runs
+pub fn write_predictions(
+    predictions: &PredictionStore,
+    format: ExportFormat,
+    out: impl Write,
+) -> Result<usize> {
+    let mut sorted = predictions.all_predictions().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| {
+        a.timestamp
+            .cmp(&b.timestamp)
+            .then_with(|| a.email_id.cmp(&b.email_id))
+    });
+
+    match format {
+        ExportFormat::Csv => write_csv(sorted.iter().map(|p| PredictionRow::from(*p)), out)?,
+        ExportFormat::Json => serde_json::to_writer_pretty(out, &sorted)?,
+    }
+    Ok(sorted.len())
+}
+
+pub fn write_corrections(
+    profile: &Profile,
+    format: ExportFormat,
+    out: impl Write,
+) -> Result<usize> {
+    let rows = profile
+        .learned_corrections()
+        .into_iter()
+        .map(CorrectionRow::parse)
+        .collect::<Vec<_>>();
+
+    match format {
+        ExportFormat::Csv => write_csv(rows.iter(), out)?,
+        ExportFormat::Json => serde_json::to_writer_pretty(out, &rows)?,
+    }
+    Ok(rows.len())
+}
+
+fn write_csv<T: Serialize>(rows: impl Iterator<Item = T>, out: impl Write) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(out);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::Classification;
+    use crate::providers::Email;
+
+    #[test]
+    fn test_csv_export_quotes_commas() {
+        let mut predictions = PredictionStore::default();
+        let email = Email {
+            id: "msg1".to_string(),
+            from: "Billing <billing@example.com>".to_string(),
+            subject: "Invoice #42, due Friday".to_string(),
+            ..Default::default()
+        };
+        let classification = Classification {
+            is_spam: false,
+            archive: false,
+            delete: false,
+            theme: vec!["Finance".to_string(), "Bills".to_string()],
+            action: vec!["Important".to_string()],
+            confidence: 0.75,
+            reason: None,
+            heuristic: false,
+        };
+        predictions.store(&email, &classification, vec![]).unwrap();
+
+        let mut out = Vec::new();
+        let count = write_predictions(&predictions, ExportFormat::Csv, &mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+
+        assert_eq!(count, 1);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("email_id,from,subject,theme,action,is_spam,confidence,timestamp")
+        );
+        assert!(lines.next().unwrap().starts_with(
+            "msg1,Billing <billing@example.com>,\"Invoice #42, due Friday\",Finance;Bills,Important,false,0.75,"
+        ));
+    }
+}
@@ -1,16 +1,105 @@
+use crate::config::{self, BodyPreview, Config, LlmConfig};
 use crate::learning::LearningEngine;
-use crate::predictions::{Prediction, PredictionStore};
+use crate::llm::{self, LlmStats};
+use crate::output::{note, Renderable};
+use crate::predictions::{PredictionStore, ReplyThread};
 use crate::profile::Profile;
 use crate::providers::{Email, EmailProvider};
+use crate::usage::UsageLog;
 use anyhow::{Context, Result};
-use std::process::Stdio;
+use chrono::{DateTime, Utc};
+use std::io::Write;
+use std::path::Path;
 use std::time::Duration;
-use tokio::io::AsyncWriteExt;
-use tokio::process::Command;
-use tokio::time::timeout;
 
-const CLAUDE_MODEL: &str = "haiku";
-const CLAUDE_BROWSER_TOOLS: &str = "mcp__browsermcp__browser_navigate,mcp__browsermcp__browser_click,mcp__browsermcp__browser_snapshot,mcp__browsermcp__browser_screenshot,mcp__browsermcp__browser_wait,mcp__browsermcp__browser_hover,mcp__browsermcp__browser_type,mcp__browsermcp__browser_select_option,mcp__browsermcp__browser_press_key,mcp__browsermcp__browser_go_back,mcp__browsermcp__browser_go_forward,mcp__browsermcp__browser_get_console_logs";
+/// Default for summary and draft prompts when `llm.timeout_secs` isn't set
+const SUMMARY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Ask a yes/no question on stdin. Anything but "y"/"yes" counts as no.
+pub fn confirm(question: &str) -> Result<bool> {
+    Ok(matches!(
+        ask(&format!("{} [y/N]", question))?.as_str(),
+        "y" | "yes"
+    ))
+}
+
+/// Ask a question on stdin, returning the trimmed, lowercased answer. The
+/// question goes to stderr, so stdout keeps only a command's output.
+pub fn ask(question: &str) -> Result<String> {
+    eprint!("{} ", question);
+    std::io::stderr().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_lowercase())
+}
+
+/// Open a file in $EDITOR (vi when unset) and wait for it to close
+pub async fn open_in_editor(path: &Path) -> Result<()> {
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let status = editor_command(&editor, path)
+        .status()
+        .await
+        .with_context(|| format!("Failed to launch editor '{}'", editor))?;
+    if !status.success() {
+        anyhow::bail!("Editor '{}' exited with {}", editor, status);
+    }
+    Ok(())
+}
+
+/// Run `editor` through the shell, as git does, so a value with arguments
+/// such as `code --wait` works. The path goes in as its own argument, so
+/// spaces in it need no quoting.
+fn editor_command(editor: &str, path: &Path) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("sh");
+    command
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(editor)
+        .arg(path);
+    command
+}
+
+/// Print this run's LLM usage and add it to the daily totals in usage.json
+pub fn report_llm_usage(cfg: &Config, dry_run: bool) -> Result<()> {
+    let stats = llm::take_run_stats();
+    if stats.calls == 0 {
+        return Ok(());
+    }
+
+    note!("\nLLM usage: {}", format_llm_stats(&stats));
+    if cfg.record_usage() && !dry_run {
+        let mut log = UsageLog::load()?;
+        log.record(&chrono::Utc::now().format("%Y-%m-%d").to_string(), &stats);
+        log.save()?;
+    }
+    Ok(())
+}
+
+/// "3 calls, 4200 chars in / 350 out, 1800 tokens in / 120 out, $0.0123, 12.5s"
+pub fn format_llm_stats(stats: &LlmStats) -> String {
+    let mut parts = vec![
+        format!("{} calls", stats.calls),
+        format!(
+            "{} chars in / {} out",
+            stats.input_chars, stats.output_chars
+        ),
+    ];
+    if stats.input_tokens > 0 || stats.output_tokens > 0 {
+        parts.push(format!(
+            "{} tokens in / {} out",
+            stats.input_tokens, stats.output_tokens
+        ));
+    }
+    if stats.cost_usd > 0.0 {
+        parts.push(format!("${:.4}", stats.cost_usd));
+    }
+    if stats.repairs > 0 {
+        parts.push(format!("{} JSON repairs", stats.repairs));
+    }
+    parts.push(format!("{:.1}s", stats.elapsed_ms as f64 / 1000.0));
+    parts.join(", ")
+}
 
 pub fn print_action_preview(action: &str, email: &Email) {
     println!("Would {}: \"{}\"", action, email.subject);
@@ -24,27 +113,93 @@ pub async fn learn_from_manual_action(
     id: &str,
     action: &str,
     email: &Email,
+    llm: &LlmConfig,
 ) -> Result<()> {
-    let learning = LearningEngine::new(provider, profile, predictions);
-    if let Some(update) = learning.learn_from_action(id, action, email).await? {
-        println!("\n📝 Profile updated:");
-        println!("{}", update);
-        profile.save()?;
+    let learning = LearningEngine::new(provider, profile, predictions).with_llm(llm);
+    if let Some(patch) = learning.learn_from_action(id, action, email).await? {
+        let applied = profile.apply_patch(&patch);
+        if !applied.is_empty() {
+            println!("\n📝 Profile updated:");
+            print!("{}", applied);
+            profile.save()?;
+        }
     }
     Ok(())
 }
 
-pub fn print_needs_reply_entry(prediction: &Prediction, email: &Email) {
-    let is_unread = email.labels.iter().any(|label| label == "UNREAD");
-    let marker = if is_unread { "●" } else { " " };
+/// One needs-reply thread, shown by its latest email
+#[derive(Debug, serde::Serialize)]
+pub struct NeedsReplyRow {
+    pub id: String,
+    pub date: DateTime<Utc>,
+    pub from: String,
+    pub subject: String,
+    /// The thread's emails that need a reply
+    pub messages: usize,
+    pub labels: Vec<String>,
+    /// Unknown under --fast, which doesn't ask the provider
+    pub unread: Option<bool>,
+}
+
+impl NeedsReplyRow {
+    /// `email` is the live copy, when it was fetched; without it `unread` is unknown
+    pub fn new(thread: &ReplyThread, email: Option<&Email>) -> Self {
+        let prediction = thread.latest();
+        Self {
+            id: prediction.email_id.clone(),
+            date: prediction.date(),
+            from: prediction.from.clone(),
+            subject: prediction.subject.clone(),
+            messages: thread.messages.len(),
+            labels: prediction.all_labels(),
+            unread: email.map(|email| email.labels.iter().any(|label| label == "UNREAD")),
+        }
+    }
+}
+
+impl Renderable for NeedsReplyRow {
+    const COLUMNS: &'static [&'static str] = &["ID", "DATE", "SUBJECT", "MESSAGES", "LABELS"];
 
-    println!(
-        "{} {} | {} | {:?}",
-        marker,
-        prediction.email_id,
-        prediction.subject.chars().take(50).collect::<String>(),
-        prediction.all_labels()
-    );
+    fn cells(&self) -> Vec<String> {
+        let marker = if self.unread == Some(true) {
+            "● "
+        } else {
+            ""
+        };
+        vec![
+            self.id.clone(),
+            self.date.format("%Y-%m-%d").to_string(),
+            format!(
+                "{}{}",
+                marker,
+                self.subject.chars().take(50).collect::<String>()
+            ),
+            self.messages.to_string(),
+            self.labels.join(", "),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        let marker = if self.unread == Some(true) {
+            "●"
+        } else {
+            " "
+        };
+        let count = if self.messages > 1 {
+            format!(" ({} messages)", self.messages)
+        } else {
+            String::new()
+        };
+        format!(
+            "{} {} | {} | {}{} | {:?}",
+            marker,
+            self.id,
+            self.date.format("%Y-%m-%d"),
+            self.subject.chars().take(50).collect::<String>(),
+            count,
+            self.labels
+        )
+    }
 }
 
 pub fn summary_prompt(emails: &[Email]) -> String {
@@ -76,7 +231,7 @@ fn format_summary_emails(emails: &[Email]) -> String {
     let mut email_text = String::new();
 
     for (index, email) in emails.iter().enumerate() {
-        let body_preview: String = email.body.chars().take(2000).collect();
+        let body_preview = config::body_preview(&email.body, BodyPreview::Summary);
         email_text.push_str(&format!(
             "=== Email {} ===\nFrom: {}\nSubject: {}\nBody:\n{}\n\n",
             index + 1,
@@ -89,41 +244,57 @@ fn format_summary_emails(emails: &[Email]) -> String {
     email_text
 }
 
-pub async fn run_summary_prompt(prompt: &str) -> Result<String> {
-    let prompt_file = std::env::temp_dir().join("email-assistant-summary-prompt.txt");
-    let _ = std::fs::write(&prompt_file, prompt);
-
-    let mut child = Command::new("claude")
-        .args([
-            "-p",
-            "-",
-            "--model",
-            CLAUDE_MODEL,
-            "--disallowedTools",
-            CLAUDE_BROWSER_TOOLS,
-            "--no-session-persistence",
-        ])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .context("Failed to spawn claude CLI")?;
-
-    if let Some(mut stdin) = child.stdin.take() {
-        stdin.write_all(prompt.as_bytes()).await?;
-    }
+pub fn draft_reply_prompt(profile: &Profile, email: &Email) -> String {
+    let body = config::body_preview(&strip_quoted_text(&email.body), BodyPreview::Draft);
 
-    let output = timeout(Duration::from_secs(60), child.wait_with_output())
-        .await
-        .context("Claude CLI timed out after 60s")?
-        .context("Failed to run claude CLI")?;
+    format!(
+        r#"Draft a reply to this email on behalf of the user.
+
+User profile (who they are and how they handle email):
+{}
+
+From: {}
+Subject: {}
+Body:
+{}
+
+Rules:
+- Be concise: a few sentences, no filler
+- Match the tone of the original email
+- If the email asks something you can't know, leave a [placeholder] for the user
+- Output only the reply body - no subject line, no quoted original"#,
+        profile.content(),
+        email.from,
+        email.subject,
+        body
+    )
+}
+
+/// Drop the quoted thread below a reply so prompts only carry the new text
+pub fn strip_quoted_text(body: &str) -> String {
+    let mut kept = Vec::new();
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("claude CLI failed: {}", stderr);
+    for line in body.lines() {
+        let trimmed = line.trim();
+        if is_quote_header(trimmed) {
+            break;
+        }
+        if !trimmed.starts_with('>') {
+            kept.push(line);
+        }
     }
 
-    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    kept.join("\n").trim().to_string()
+}
+
+fn is_quote_header(line: &str) -> bool {
+    (line.starts_with("On ") && line.ends_with("wrote:"))
+        || line.starts_with("-----Original Message-----")
+        || line.starts_with("________________________________")
+}
+
+pub async fn run_claude_prompt(prompt: &str, llm: &LlmConfig) -> Result<String> {
+    llm::run(prompt, llm.summary_model(), llm.timeout(SUMMARY_TIMEOUT)).await
 }
 
 pub fn build_status_indicators(labels: &[String], is_important: bool) -> String {
@@ -143,3 +314,40 @@ pub fn build_status_indicators(labels: &[String], is_important: bool) -> String
 
     format!("[{}{}{}]", unread_marker, location_marker, priority_marker)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_quoted_text() {
+        let body = "Sounds good, see you then.\n\n\
+On Mon, Jan 6, 2025 at 9:00 AM Alice <alice@example.com> wrote:\n\
+> Can we meet Tuesday?\n\
+> Thanks";
+        assert_eq!(strip_quoted_text(body), "Sounds good, see you then.");
+
+        let outlook = "Approved.\n-----Original Message-----\nFrom: Bob";
+        assert_eq!(strip_quoted_text(outlook), "Approved.");
+
+        let inline = "> earlier point\nMy answer";
+        assert_eq!(strip_quoted_text(inline), "My answer");
+    }
+
+    #[tokio::test]
+    async fn test_editor_with_arguments_gets_the_path() {
+        let dir =
+            std::env::temp_dir().join(format!("email-assistant-editor-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("profile draft.md");
+        std::fs::write(&path, "# Profile\n").unwrap();
+
+        let status = editor_command("test -f", &path).status().await.unwrap();
+        assert!(status.success());
+        let missing = dir.join("missing.md");
+        let status = editor_command("test -f", &missing).status().await.unwrap();
+        assert!(!status.success());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,17 +1,355 @@
 use anyhow::Result;
+use chrono::Duration;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+
+const DEFAULT_PREDICTION_MAX_AGE_DAYS: u32 = 30;
+const DEFAULT_LABEL_LANGUAGE: &str = "English";
+const DEFAULT_IMAP_PORT: u16 = 993;
+/// Fastmail's JMAP session resource
+const DEFAULT_JMAP_SESSION_URL: &str = "https://api.fastmail.com/jmap/session";
+const DEFAULT_PROFILE_BACKUPS: usize = 10;
+const DEFAULT_QUARANTINE_DAYS: u32 = 7;
+const DEFAULT_CLASSIFY_MODEL: &str = "opus";
+const DEFAULT_SUMMARY_MODEL: &str = "haiku";
+const DEFAULT_LEARN_MODEL: &str = "haiku";
+/// Gmail allows 250 quota units/user/second; most calls cost 5-10 units
+const DEFAULT_REQUESTS_PER_SECOND: f64 = 10.0;
+
+/// Body preview lengths for LLM prompts, resolved once at startup
+static BODY_PREVIEW_LIMITS: OnceLock<BodyPreviewLimits> = OnceLock::new();
+/// Rules directory given with `--rules-dir`, read after `rules/`
+static EXTRA_RULES_DIR: OnceLock<PathBuf> = OnceLock::new();
+/// Config directory given with `--config-dir`
+static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
+/// Replaces the default config directory, below `--config-dir`
+const CONFIG_DIR_ENV: &str = "EMAIL_ASSISTANT_CONFIG_DIR";
+/// Provider whose predictions are in use; None (before one is chosen) means
+/// the unscoped predictions.json
+static PREDICTION_SCOPE: RwLock<Option<String>> = RwLock::new(None);
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Config {
     pub provider: Option<String>,
+    /// Predictions older than this many days are pruned before scan/learn
+    pub prediction_max_age_days: Option<u32>,
+    /// Language theme labels are written in, regardless of the email's language
+    pub label_language: Option<String>,
+    /// Translate non-English email bodies to English before classifying
+    pub translate_non_english: Option<bool>,
+    /// Summarize Reply-To, Return-Path, and authentication headers in the
+    /// classify prompt (default false; costs a few tokens per email)
+    pub header_signals: Option<bool>,
+    pub imap: Option<ImapConfig>,
+    pub jmap: Option<JmapConfig>,
+    /// Allow `sync-filters` to create Gmail filters from deterministic rules
+    pub sync_gmail_filters: Option<bool>,
+    /// Have scans flag Important/Urgent emails important in the provider too
+    pub sync_importance: Option<bool>,
+    /// Never auto-archive or delete starred/important emails (default true)
+    pub protect_curated_emails: Option<bool>,
+    /// Truncate email bodies to this many characters in every LLM prompt;
+    /// `llm.body_preview_chars` and the per-prompt settings take precedence
+    pub max_body_chars: Option<usize>,
+    /// Treat emails with a List-Id header as archivable newsletters (default true)
+    pub list_id_newsletters: Option<bool>,
+    /// Pause between correction batches sent to the LLM, to stay under rate limits
+    pub correction_batch_delay_ms: Option<u64>,
+    pub notifications: Option<NotificationConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub llm: Option<LlmConfig>,
+    /// Label → archive/delete decision applied after classification, overriding the model
+    pub label_policies: Option<BTreeMap<String, LabelPolicy>>,
+    /// Addresses or domains never auto-archived or deleted, whatever else applies
+    pub protected_senders: Option<Vec<String>>,
+    /// Addresses, domains, or `/regex/` patterns whose mail scan skips unclassified
+    pub ignored_senders: Option<Vec<String>>,
+    /// Keep daily LLM usage totals in usage.json for `usage` (default true)
+    pub record_usage: Option<bool>,
+    /// Copies of profile.md kept in profile-backups/ (default 10)
+    pub profile_backups: Option<usize>,
+    /// What scan does with emails classified for deletion (default trash)
+    pub delete_mode: Option<DeleteMode>,
+    /// Days a quarantined email waits before it's trashed (default 7)
+    pub quarantine_days: Option<u32>,
+    /// Per provider, canonical label → the provider's own name for it
+    pub label_aliases: Option<BTreeMap<String, BTreeMap<String, String>>>,
+}
+
+/// How scan carries out a delete decision
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeleteMode {
+    /// Move the email to the provider's trash right away
+    #[default]
+    Trash,
+    /// Label it Quarantine and archive it, trashing it once the grace period
+    /// is over unless it was rescued
+    Quarantine,
+}
+
+/// Claude models and timeout per kind of prompt
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LlmConfig {
+    pub classify_model: Option<String>,
+    /// Used for `summary` and `draft-reply`
+    pub summary_model: Option<String>,
+    /// Used for profile updates from corrections and manual actions
+    pub learn_model: Option<String>,
+    /// Replaces every operation's own default timeout
+    pub timeout_secs: Option<u64>,
+    /// Ask claude for schema-constrained JSON when classifying (default false)
+    pub structured_output: Option<bool>,
+    /// Body characters included in every prompt that quotes an email
+    pub body_preview_chars: Option<usize>,
+    /// Per-prompt overrides of `body_preview_chars`
+    pub classify_body_chars: Option<usize>,
+    pub summary_body_chars: Option<usize>,
+    pub learn_body_chars: Option<usize>,
+    pub draft_body_chars: Option<usize>,
+}
+
+/// Prompts that quote an email body, each with its own preview length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyPreview {
+    Classify,
+    Summary,
+    /// Learning from a manual action (archive, delete, ...)
+    Learn,
+    Draft,
+}
+
+impl BodyPreview {
+    /// Conservative defaults: longer previews cost more tokens on every call
+    pub fn default_chars(self) -> usize {
+        match self {
+            Self::Classify => 1000,
+            Self::Summary => 2000,
+            Self::Learn => 500,
+            Self::Draft => 3000,
+        }
+    }
+}
+
+/// Preview length of each prompt for this run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BodyPreviewLimits {
+    classify: usize,
+    summary: usize,
+    learn: usize,
+    draft: usize,
+}
+
+impl LlmConfig {
+    pub fn classify_model(&self) -> &str {
+        self.classify_model
+            .as_deref()
+            .unwrap_or(DEFAULT_CLASSIFY_MODEL)
+    }
+
+    pub fn summary_model(&self) -> &str {
+        self.summary_model
+            .as_deref()
+            .unwrap_or(DEFAULT_SUMMARY_MODEL)
+    }
+
+    pub fn learn_model(&self) -> &str {
+        self.learn_model.as_deref().unwrap_or(DEFAULT_LEARN_MODEL)
+    }
+
+    pub fn structured_output(&self) -> bool {
+        self.structured_output.unwrap_or(false)
+    }
+
+    /// The prompt's own setting, else `body_preview_chars`, else `fallback`
+    /// (the top-level `max_body_chars`), else the prompt's default
+    pub fn body_preview_chars(&self, preview: BodyPreview, fallback: Option<usize>) -> usize {
+        let specific = match preview {
+            BodyPreview::Classify => self.classify_body_chars,
+            BodyPreview::Summary => self.summary_body_chars,
+            BodyPreview::Learn => self.learn_body_chars,
+            BodyPreview::Draft => self.draft_body_chars,
+        };
+        specific
+            .or(self.body_preview_chars)
+            .or(fallback)
+            .unwrap_or(preview.default_chars())
+    }
+
+    pub fn timeout(&self, default: std::time::Duration) -> std::time::Duration {
+        self.timeout_secs
+            .map(std::time::Duration::from_secs)
+            .unwrap_or(default)
+    }
+}
+
+/// What scan does with mail carrying a label; unset fields leave the model's choice
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LabelPolicy {
+    pub archive: Option<bool>,
+    pub delete: Option<bool>,
+}
+
+/// Client-side pacing of Gmail/Outlook API requests
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RateLimitConfig {
+    pub requests_per_second: Option<f64>,
+}
+
+/// Where scan notifications go. Targets are webhook URLs (`https://...`)
+/// or log files (`file:/path/to/log`).
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct NotificationConfig {
+    /// Target for notified emails whose labels have no route of their own
+    pub default: Option<String>,
+    /// Label → target, e.g. "Urgent" → a push webhook, "Finance" → a log file
+    #[serde(default)]
+    pub routes: BTreeMap<String, String>,
+}
+
+/// Connection settings for the IMAP provider (TLS only)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImapConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub user: String,
+    pub password: String,
+    pub junk_folder: Option<String>,
+    pub trash_folder: Option<String>,
+    pub archive_folder: Option<String>,
+    pub drafts_folder: Option<String>,
+}
+
+impl ImapConfig {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_IMAP_PORT)
+    }
+
+    pub fn junk_folder(&self) -> &str {
+        self.junk_folder.as_deref().unwrap_or("Junk")
+    }
+
+    pub fn trash_folder(&self) -> &str {
+        self.trash_folder.as_deref().unwrap_or("Trash")
+    }
+
+    pub fn archive_folder(&self) -> &str {
+        self.archive_folder.as_deref().unwrap_or("Archive")
+    }
+
+    pub fn drafts_folder(&self) -> &str {
+        self.drafts_folder.as_deref().unwrap_or("Drafts")
+    }
+}
+
+/// Account settings for the JMAP provider (Fastmail unless `session_url` says otherwise)
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct JmapConfig {
+    pub session_url: Option<String>,
+    /// API token, sent as a bearer token; EMAIL_ASSISTANT_JMAP_TOKEN overrides it
+    pub token: Option<String>,
+}
+
+impl JmapConfig {
+    pub fn session_url(&self) -> &str {
+        self.session_url
+            .as_deref()
+            .unwrap_or(DEFAULT_JMAP_SESSION_URL)
+    }
+}
+
+/// Resolve each prompt's body preview length for this run. `--max-body-chars`
+/// (`cli_limit`) applies to every prompt; otherwise the `llm` settings do.
+pub fn set_body_preview_limits(cli_limit: Option<usize>, cfg: &Config) {
+    let llm = cfg.llm();
+    let limit =
+        |preview| cli_limit.unwrap_or_else(|| llm.body_preview_chars(preview, cfg.max_body_chars));
+    let _ = BODY_PREVIEW_LIMITS.set(BodyPreviewLimits {
+        classify: limit(BodyPreview::Classify),
+        summary: limit(BodyPreview::Summary),
+        learn: limit(BodyPreview::Learn),
+        draft: limit(BodyPreview::Draft),
+    });
+}
+
+/// Body characters the prompt may include this run
+pub fn body_preview_chars(preview: BodyPreview) -> usize {
+    let Some(limits) = BODY_PREVIEW_LIMITS.get() else {
+        return preview.default_chars();
+    };
+    match preview {
+        BodyPreview::Classify => limits.classify,
+        BodyPreview::Summary => limits.summary,
+        BodyPreview::Learn => limits.learn,
+        BodyPreview::Draft => limits.draft,
+    }
+}
+
+/// The start of `body` that fits the prompt's preview length
+pub fn body_preview(body: &str, preview: BodyPreview) -> String {
+    body.chars().take(body_preview_chars(preview)).collect()
+}
+
+/// Select the predictions file for `provider`. Email IDs only mean something
+/// to their own provider, so each keeps its own file named after it, whichever
+/// provider is the default.
+pub fn set_prediction_scope(provider: &str) {
+    *PREDICTION_SCOPE
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(provider.to_string());
+}
+
+/// Stores that are kept per provider
+const SCOPED_STORES: [&str; 2] = ["predictions", "quarantine"];
+
+/// Earlier versions kept the default provider's predictions and quarantine
+/// in unscoped files; rename them after that provider, once
+pub fn migrate_unscoped_stores(default_provider: &str) -> Result<()> {
+    migrate_unscoped_stores_in(&config_dir(), default_provider)
+}
+
+fn migrate_unscoped_stores_in(dir: &Path, default_provider: &str) -> Result<()> {
+    for stem in SCOPED_STORES {
+        let unscoped = dir.join(format!("{}.json", stem));
+        let scoped = dir.join(format!("{}-{}.json", stem, default_provider));
+        if unscoped.exists() && !scoped.exists() {
+            fs::rename(&unscoped, &scoped)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn set_extra_rules_dir(dir: PathBuf) -> Result<()> {
+    if !dir.is_dir() {
+        anyhow::bail!("Rules directory {} does not exist", dir.display());
+    }
+    let _ = EXTRA_RULES_DIR.set(dir);
+    Ok(())
+}
+
+pub fn extra_rules_dir() -> Option<&'static Path> {
+    EXTRA_RULES_DIR.get().map(PathBuf::as_path)
+}
+
+pub fn set_config_dir(dir: PathBuf) {
+    let _ = CONFIG_DIR.set(dir);
 }
 
+/// `--config-dir`, else EMAIL_ASSISTANT_CONFIG_DIR, else the platform's
+/// config directory (~/.config/email-assistant on Linux)
 pub fn config_dir() -> PathBuf {
-    dirs::config_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join("email-assistant")
+    if let Some(dir) = CONFIG_DIR.get() {
+        return dir.clone();
+    }
+    match std::env::var_os(CONFIG_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("email-assistant"),
+    }
 }
 
 fn config_path() -> PathBuf {
@@ -22,14 +360,68 @@ pub fn profile_path() -> PathBuf {
     config_dir().join("profile.md")
 }
 
+/// Custom classification prompt; the built-in one is used when it's missing
+pub fn prompt_template_path() -> PathBuf {
+    config_dir().join("prompt.tmpl")
+}
+
+pub fn profile_backups_dir() -> PathBuf {
+    config_dir().join("profile-backups")
+}
+
 pub fn predictions_path() -> PathBuf {
-    config_dir().join("predictions.json")
+    scoped_path("predictions")
+}
+
+/// Quarantined emails, kept per provider like predictions
+pub fn quarantine_path() -> PathBuf {
+    scoped_path("quarantine")
+}
+
+/// `<stem>-<provider>.json` for the provider in use
+fn scoped_path(stem: &str) -> PathBuf {
+    let scope = PREDICTION_SCOPE
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    match scope.as_deref() {
+        Some(provider) => config_dir().join(format!("{}-{}.json", stem, provider)),
+        None => config_dir().join(format!("{}.json", stem)),
+    }
 }
 
 pub fn labels_path() -> PathBuf {
     config_dir().join("labels.json")
 }
 
+/// Per-label email counts from the provider, cached by `labels --counts`
+pub fn label_counts_path() -> PathBuf {
+    config_dir().join("label_counts.json")
+}
+
+pub fn corrections_log_path() -> PathBuf {
+    config_dir().join("corrections.jsonl")
+}
+
+pub fn spam_feedback_path() -> PathBuf {
+    config_dir().join("spam_feedback.json")
+}
+
+pub fn usage_path() -> PathBuf {
+    config_dir().join("usage.json")
+}
+
+pub fn filters_path() -> PathBuf {
+    config_dir().join("filters.json")
+}
+
+pub fn contacts_path() -> PathBuf {
+    config_dir().join("contacts.json")
+}
+
+pub fn sender_stats_path() -> PathBuf {
+    config_dir().join("sender_stats.json")
+}
+
 impl Config {
     pub fn load() -> Result<Self> {
         let path = config_path();
@@ -54,4 +446,127 @@ impl Config {
     pub fn default_provider(&self) -> &str {
         self.provider.as_deref().unwrap_or("gmail")
     }
+
+    pub fn prediction_max_age_days(&self) -> u32 {
+        self.prediction_max_age_days
+            .unwrap_or(DEFAULT_PREDICTION_MAX_AGE_DAYS)
+    }
+
+    pub fn label_language(&self) -> &str {
+        self.label_language
+            .as_deref()
+            .unwrap_or(DEFAULT_LABEL_LANGUAGE)
+    }
+
+    pub fn translate_non_english(&self) -> bool {
+        self.translate_non_english.unwrap_or(false)
+    }
+
+    pub fn header_signals(&self) -> bool {
+        self.header_signals.unwrap_or(false)
+    }
+
+    pub fn sync_gmail_filters(&self) -> bool {
+        self.sync_gmail_filters.unwrap_or(false)
+    }
+
+    pub fn sync_importance(&self) -> bool {
+        self.sync_importance.unwrap_or(false)
+    }
+
+    pub fn protect_curated_emails(&self) -> bool {
+        self.protect_curated_emails.unwrap_or(true)
+    }
+
+    /// Canonical label → `provider`'s name for it; empty when none are set
+    pub fn label_aliases(&self, provider: &str) -> BTreeMap<String, String> {
+        self.label_aliases
+            .as_ref()
+            .and_then(|aliases| aliases.get(provider))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn correction_batch_delay(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.correction_batch_delay_ms.unwrap_or(0))
+    }
+
+    pub fn llm(&self) -> LlmConfig {
+        self.llm.clone().unwrap_or_default()
+    }
+
+    pub fn requests_per_second(&self) -> f64 {
+        self.rate_limit
+            .as_ref()
+            .and_then(|rate_limit| rate_limit.requests_per_second)
+            .filter(|rate| *rate > 0.0)
+            .unwrap_or(DEFAULT_REQUESTS_PER_SECOND)
+    }
+
+    pub fn record_usage(&self) -> bool {
+        self.record_usage.unwrap_or(true)
+    }
+
+    pub fn profile_backups(&self) -> usize {
+        self.profile_backups.unwrap_or(DEFAULT_PROFILE_BACKUPS)
+    }
+
+    pub fn list_id_newsletters(&self) -> bool {
+        self.list_id_newsletters.unwrap_or(true)
+    }
+
+    pub fn delete_mode(&self) -> DeleteMode {
+        self.delete_mode.unwrap_or_default()
+    }
+
+    pub fn quarantine_grace(&self) -> Duration {
+        Duration::days(i64::from(
+            self.quarantine_days.unwrap_or(DEFAULT_QUARANTINE_DAYS),
+        ))
+    }
+
+    pub fn prediction_max_age(&self) -> Duration {
+        Duration::days(i64::from(self.prediction_max_age_days()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_body_preview_chars_precedence() {
+        let mut llm = LlmConfig::default();
+        assert_eq!(llm.body_preview_chars(BodyPreview::Learn, None), 500);
+        assert_eq!(llm.body_preview_chars(BodyPreview::Learn, Some(800)), 800);
+
+        llm.body_preview_chars = Some(1500);
+        llm.summary_body_chars = Some(4000);
+        assert_eq!(llm.body_preview_chars(BodyPreview::Learn, Some(800)), 1500);
+        assert_eq!(
+            llm.body_preview_chars(BodyPreview::Summary, Some(800)),
+            4000
+        );
+    }
+
+    #[test]
+    fn test_unscoped_stores_move_to_default_provider_once() {
+        let dir =
+            std::env::temp_dir().join(format!("email-assistant-scope-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("predictions.json"), "gmail").unwrap();
+        fs::write(dir.join("quarantine.json"), "old").unwrap();
+        fs::write(dir.join("quarantine-gmail.json"), "current").unwrap();
+
+        migrate_unscoped_stores_in(&dir, "gmail").unwrap();
+        migrate_unscoped_stores_in(&dir, "outlook").unwrap();
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+        assert_eq!(read("predictions-gmail.json").as_deref(), Some("gmail"));
+        assert_eq!(read("predictions.json"), None);
+        assert_eq!(read("predictions-outlook.json"), None);
+        // An existing scoped file is never overwritten
+        assert_eq!(read("quarantine-gmail.json").as_deref(), Some("current"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,99 @@
+use crate::config;
+use crate::providers::sender_address;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+
+/// Addresses of people the user knows, kept in `contacts.json` as a plain
+/// list so it can be written by hand as well as by `contacts sync`
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct Contacts {
+    addresses: BTreeSet<String>,
+}
+
+impl Contacts {
+    pub fn load() -> Result<Self> {
+        let path = config::contacts_path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        let entries: Vec<String> = serde_json::from_str(&content)
+            .with_context(|| format!("{} must be a list of addresses", path.display()))?;
+        Ok(Self::from_entries(entries))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = config::config_dir();
+        if !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(config::contacts_path(), content)?;
+        Ok(())
+    }
+
+    /// Entries may be bare addresses or "Name <address>"
+    pub fn from_entries(entries: impl IntoIterator<Item = String>) -> Self {
+        let mut contacts = Self::default();
+        contacts.merge(entries);
+        contacts
+    }
+
+    /// Add addresses, returning how many weren't already known
+    pub fn merge(&mut self, entries: impl IntoIterator<Item = String>) -> usize {
+        let before = self.addresses.len();
+        self.addresses.extend(
+            entries
+                .into_iter()
+                .map(|entry| sender_address(&entry))
+                .filter(|address| address.contains('@')),
+        );
+        self.addresses.len() - before
+    }
+
+    /// Whether the sender of a From header is a known contact
+    pub fn is_known(&self, from: &str) -> bool {
+        self.addresses.contains(&sender_address(from))
+    }
+
+    pub fn addresses(&self) -> impl Iterator<Item = &str> {
+        self.addresses.iter().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contacts_match_sender_address() {
+        let mut contacts = Contacts::from_entries([
+            "Alice Smith <Alice@Example.com>".to_string(),
+            "not an address".to_string(),
+        ]);
+        assert_eq!(contacts.len(), 1);
+        assert!(contacts.is_known("\"Smith, Alice\" <alice@example.com>"));
+        assert!(!contacts.is_known("noreply@example.com"));
+
+        let added = contacts.merge([
+            "alice@example.com".to_string(),
+            "bob@example.org".to_string(),
+        ]);
+        assert_eq!(added, 1);
+        assert_eq!(
+            contacts.addresses().collect::<Vec<_>>(),
+            vec!["alice@example.com", "bob@example.org"]
+        );
+    }
+}
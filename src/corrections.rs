@@ -0,0 +1,80 @@
+use crate::config;
+use crate::learning::Correction;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// A correction as it was applied to the profile, for `simulate` to replay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggedCorrection {
+    /// Day the correction was learned (YYYY-MM-DD)
+    pub date: String,
+    #[serde(flatten)]
+    pub correction: Correction,
+}
+
+/// Append-only history of applied corrections, one JSON object per line
+#[derive(Debug, Default)]
+pub struct CorrectionLog {
+    entries: Vec<LoggedCorrection>,
+}
+
+impl CorrectionLog {
+    pub fn load() -> Result<Self> {
+        let path = config::corrections_log_path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::load_from(&path)
+    }
+
+    /// Read a log, or a dataset in the same JSON Lines format
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let entries = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).with_context(|| {
+                    format!("{}:{}: invalid correction", path.display(), index + 1)
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { entries })
+    }
+
+    pub fn append(date: &str, corrections: &[&Correction]) -> Result<()> {
+        if corrections.is_empty() {
+            return Ok(());
+        }
+
+        let dir = config::config_dir();
+        if !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(config::corrections_log_path())?;
+        for correction in corrections {
+            let entry = LoggedCorrection {
+                date: date.to_string(),
+                correction: (*correction).clone(),
+            };
+            writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[LoggedCorrection] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -0,0 +1,507 @@
+use crate::config;
+use crate::learning::is_system_label;
+use crate::rules::{Condition, Rule};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+/// A provider-side filter derived from a deterministic user rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterSpec {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub subject: Option<String>,
+    pub add_label_ids: Vec<String>,
+    pub remove_label_ids: Vec<String>,
+}
+
+impl FilterSpec {
+    /// Stable identity used to match tracked filters against current rules
+    pub fn key(&self) -> String {
+        format!(
+            "from={};to={};subject={};add={};remove={}",
+            self.from.as_deref().unwrap_or(""),
+            self.to.as_deref().unwrap_or(""),
+            self.subject.as_deref().unwrap_or(""),
+            self.add_label_ids.join(","),
+            self.remove_label_ids.join(",")
+        )
+    }
+
+    pub fn describe(&self) -> String {
+        let criteria = [
+            ("from", &self.from),
+            ("to", &self.to),
+            ("subject", &self.subject),
+        ]
+        .iter()
+        .filter_map(|(field, value)| value.as_ref().map(|v| format!("{}:{}", field, v)))
+        .collect::<Vec<_>>()
+        .join(" ");
+        let action = if self.add_label_ids.iter().any(|id| id == "TRASH") {
+            "delete"
+        } else {
+            "archive"
+        };
+        format!("{} → {}", criteria, action)
+    }
+}
+
+/// Translate a user rule into a provider filter.
+///
+/// Only enabled rules that don't depend on the LLM's classification are
+/// eligible: a plain from/to/subject match with an archive or delete action.
+pub fn filter_for_rule(rule: &Rule) -> Option<FilterSpec> {
+    if !rule.enabled || rule.condition.and.is_some() || rule.condition.contains.trim().is_empty() {
+        return None;
+    }
+
+    let (add_label_ids, remove_label_ids) = match rule.action.as_str() {
+        "archive" => (vec![], vec!["INBOX".to_string()]),
+        "delete" => (vec!["TRASH".to_string()], vec!["INBOX".to_string()]),
+        _ => return None,
+    };
+
+    let value = Some(rule.condition.contains.trim().to_lowercase());
+    let mut spec = FilterSpec {
+        from: None,
+        to: None,
+        subject: None,
+        add_label_ids,
+        remove_label_ids,
+    };
+    match rule.condition.field.as_str() {
+        "from" => spec.from = value,
+        "to" => spec.to = value,
+        "subject" => spec.subject = value,
+        _ => return None,
+    }
+
+    Some(spec)
+}
+
+/// A filter as Gmail's settings.filters.list returns it, with label IDs
+/// already resolved to names
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GmailFilter {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub criteria: GmailCriteria,
+    #[serde(default)]
+    pub action: GmailAction,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GmailCriteria {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub subject: Option<String>,
+    pub query: Option<String>,
+    pub negated_query: Option<String>,
+    pub has_attachment: Option<bool>,
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GmailAction {
+    #[serde(default)]
+    pub add_label_ids: Vec<String>,
+    #[serde(default)]
+    pub remove_label_ids: Vec<String>,
+    pub forward: Option<String>,
+}
+
+impl GmailCriteria {
+    pub fn describe(&self) -> String {
+        let criteria = [
+            ("from", &self.from),
+            ("to", &self.to),
+            ("subject", &self.subject),
+            ("query", &self.query),
+            ("-query", &self.negated_query),
+        ]
+        .iter()
+        .filter_map(|(field, value)| value.as_ref().map(|v| format!("{}:{}", field, v)))
+        .chain(
+            self.has_attachment
+                .filter(|&has| has)
+                .map(|_| "has:attachment".to_string()),
+        )
+        .chain(self.size.map(|size| format!("size:{}", size)))
+        .collect::<Vec<_>>();
+        if criteria.is_empty() {
+            "(any message)".to_string()
+        } else {
+            criteria.join(" ")
+        }
+    }
+
+    /// The single from/to/subject/attachment test this filter makes
+    fn condition(&self) -> std::result::Result<Condition, String> {
+        let mut fields = [
+            ("from", &self.from),
+            ("to", &self.to),
+            ("subject", &self.subject),
+        ]
+        .into_iter()
+        .filter_map(|(field, value)| value.as_deref().map(|value| (field, value.trim())))
+        .collect::<Vec<_>>();
+        if self.has_attachment == Some(true) {
+            fields.push(("has_attachment", ""));
+        }
+        if self.query.is_some() || self.negated_query.is_some() || self.size.is_some() {
+            return Err("uses a search query or size criterion".to_string());
+        }
+        let [(field, value)] = fields[..] else {
+            return Err(if fields.is_empty() {
+                "has no from/to/subject criterion".to_string()
+            } else {
+                "combines several criteria".to_string()
+            });
+        };
+        if is_search_expression(field, value) {
+            return Err(format!("{} uses search operators", field));
+        }
+        Ok(Condition {
+            field: field.to_string(),
+            contains: value.to_string(),
+            and: None,
+        })
+    }
+}
+
+/// Gmail reads criteria as search syntax; only plain values mean "contains".
+/// Spaces are fine in subjects, where they separate words.
+fn is_search_expression(field: &str, value: &str) -> bool {
+    value.contains(['{', '}', '(', ')', '"', '*'])
+        || value
+            .split_whitespace()
+            .any(|word| word == "OR" || word.starts_with('-'))
+        || (field != "subject" && value.contains(char::is_whitespace))
+}
+
+/// Rules translated from Gmail filters, each with the filter it came from
+#[derive(Debug, Default)]
+pub struct GmailImport {
+    pub rules: Vec<(String, Rule)>,
+    /// "<criteria>: <why>" for filters, or parts of them, that weren't translated
+    pub skipped: Vec<String>,
+}
+
+/// Translate Gmail filters into rules: a trash action becomes "delete",
+/// skipping the inbox "archive", and each user label a "label" rule.
+/// Anything else (forwarding, stars, marking read, query criteria) is reported.
+pub fn rules_from_gmail_filters(filters: &[GmailFilter]) -> GmailImport {
+    let mut import = GmailImport::default();
+    for filter in filters {
+        let criteria = filter.criteria.describe();
+        let condition = match filter.criteria.condition() {
+            Ok(condition) => condition,
+            Err(reason) => {
+                import.skipped.push(format!("{}: {}", criteria, reason));
+                continue;
+            }
+        };
+
+        let action = &filter.action;
+        let adds = |label: &str| action.add_label_ids.iter().any(|id| id == label);
+        let mut actions: Vec<(&str, Option<&str>)> = Vec::new();
+        if adds("TRASH") {
+            actions.push(("delete", None));
+        } else if action.remove_label_ids.iter().any(|id| id == "INBOX") {
+            actions.push(("archive", None));
+        }
+        for label in &action.add_label_ids {
+            if !is_system_label(label) {
+                actions.push(("label", Some(label)));
+            } else if label != "TRASH" {
+                import
+                    .skipped
+                    .push(format!("{}: adds system label {}", criteria, label));
+            }
+        }
+        for label in &action.remove_label_ids {
+            if label != "INBOX" {
+                import
+                    .skipped
+                    .push(format!("{}: removes label {}", criteria, label));
+            }
+        }
+        if let Some(address) = &action.forward {
+            import
+                .skipped
+                .push(format!("{}: forwards to {}", criteria, address));
+        }
+        if actions.is_empty() {
+            continue;
+        }
+
+        for (name, label) in actions {
+            let rule = Rule {
+                name: match label {
+                    Some(label) => format!("Gmail: {} → label {}", criteria, label),
+                    None => format!("Gmail: {} → {}", criteria, name),
+                },
+                description: format!("Imported from Gmail filter {}", filter.id),
+                enabled: true,
+                condition: Condition {
+                    field: condition.field.clone(),
+                    contains: condition.contains.clone(),
+                    and: None,
+                },
+                action: name.to_string(),
+                label: label.map(str::to_string),
+                gmail_filter: Some(filter.id.clone()),
+            };
+            import.rules.push((filter.id.clone(), rule));
+        }
+    }
+    import
+}
+
+/// Fold a fresh import into the rules of an earlier one. Rules are matched
+/// by Gmail filter ID and name, so ones imported before keep their edits
+/// (e.g. `enabled: false`); new ones are added, and ones whose filter is
+/// gone from Gmail are dropped. Rules without a filter ID are kept as they are.
+pub fn merge_gmail_import(mut existing: Vec<Rule>, imported: Vec<Rule>) -> Vec<Rule> {
+    let mut merged = imported
+        .into_iter()
+        .map(|rule| {
+            let earlier = existing.iter().position(|old| {
+                old.name == rule.name
+                    && (old.gmail_filter.is_none() || old.gmail_filter == rule.gmail_filter)
+            });
+            match earlier {
+                Some(index) => Rule {
+                    gmail_filter: rule.gmail_filter,
+                    ..existing.remove(index)
+                },
+                None => rule,
+            }
+        })
+        .collect::<Vec<_>>();
+    merged.extend(
+        existing
+            .into_iter()
+            .filter(|rule| rule.gmail_filter.is_none()),
+    );
+    merged
+}
+
+/// Filters this tool tracks on the provider, keyed by `FilterSpec::key`
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct FilterStore {
+    filters: HashMap<String, String>,
+    /// Keys of the user's own filters adopted by `rules import-gmail`; they
+    /// are never deleted, since they may do more than the rule expresses
+    #[serde(default)]
+    imported: HashSet<String>,
+}
+
+impl FilterStore {
+    pub fn load() -> Result<Self> {
+        let path = config::filters_path();
+        if path.exists() {
+            let content = fs::read_to_string(&path)?;
+            Ok(serde_json::from_str(&content)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = config::config_dir();
+        if !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(config::filters_path(), content)?;
+        Ok(())
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.filters.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: String, filter_id: String) {
+        self.filters.insert(key, filter_id);
+    }
+
+    /// Track a filter the user made, so it isn't created again but is never
+    /// removed as stale
+    pub fn adopt(&mut self, key: String, filter_id: String) {
+        self.imported.insert(key.clone());
+        self.filters.insert(key, filter_id);
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.filters.remove(key)
+    }
+
+    /// Tracked filters this tool created whose source rule no longer exists
+    pub fn stale_keys(&self, desired: &[FilterSpec]) -> Vec<String> {
+        self.filters
+            .keys()
+            .filter(|key| !self.imported.contains(*key))
+            .filter(|key| !desired.iter().any(|spec| &spec.key() == *key))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_rule(field: &str, contains: &str, and: Option<&str>, action: &str) -> Rule {
+        Rule {
+            name: "Test rule".to_string(),
+            description: String::new(),
+            enabled: true,
+            condition: Condition {
+                field: field.to_string(),
+                contains: contains.to_string(),
+                and: and.map(str::to_string),
+            },
+            action: action.to_string(),
+            label: None,
+            gmail_filter: None,
+        }
+    }
+
+    #[test]
+    fn test_archive_rule_becomes_filter() {
+        let spec =
+            filter_for_rule(&make_rule("from", "News@Example.com", None, "archive")).unwrap();
+
+        assert_eq!(spec.from.as_deref(), Some("news@example.com"));
+        assert!(spec.add_label_ids.is_empty());
+        assert_eq!(spec.remove_label_ids, vec!["INBOX"]);
+        assert_eq!(spec.describe(), "from:news@example.com → archive");
+    }
+
+    #[test]
+    fn test_delete_rule_trashes() {
+        let spec = filter_for_rule(&make_rule("to", "work@example.com", None, "delete")).unwrap();
+
+        assert_eq!(spec.to.as_deref(), Some("work@example.com"));
+        assert_eq!(spec.add_label_ids, vec!["TRASH"]);
+    }
+
+    #[test]
+    fn test_classification_dependent_rules_not_eligible() {
+        assert!(filter_for_rule(&make_rule("to", "x.com", Some("archive"), "delete")).is_none());
+        assert!(filter_for_rule(&make_rule("has_attachment", "", None, "archive")).is_none());
+        assert!(filter_for_rule(&make_rule("from", "x.com", None, "label")).is_none());
+    }
+
+    #[test]
+    fn test_stale_keys() {
+        let kept = filter_for_rule(&make_rule("from", "a.com", None, "archive")).unwrap();
+        let dropped = filter_for_rule(&make_rule("from", "b.com", None, "archive")).unwrap();
+        let mut store = FilterStore::default();
+        store.insert(kept.key(), "1".to_string());
+        store.insert(dropped.key(), "2".to_string());
+
+        let desired = [kept];
+        assert_eq!(store.stale_keys(&desired), vec![dropped.key()]);
+
+        // The user's own filters stay even when their rule is disabled
+        let users = filter_for_rule(&make_rule("from", "c.com", None, "archive")).unwrap();
+        store.adopt(users.key(), "3".to_string());
+        assert_eq!(store.stale_keys(&desired), vec![dropped.key()]);
+    }
+
+    #[test]
+    fn test_reimport_keeps_edits_and_drops_deleted_filters() {
+        let imported = |filter: &str, contains: &str| Rule {
+            gmail_filter: Some(filter.to_string()),
+            name: format!("Gmail: from:{} → archive", contains),
+            ..make_rule("from", contains, None, "archive")
+        };
+        let mut disabled = imported("f1", "news@a.com");
+        disabled.enabled = false;
+        let handwritten = make_rule("subject", "invoice", None, "label");
+        let existing = vec![disabled, imported("f2", "gone@b.com"), handwritten];
+
+        let merged = merge_gmail_import(
+            existing,
+            vec![imported("f1", "news@a.com"), imported("f3", "new@c.com")],
+        );
+
+        let summary = merged
+            .iter()
+            .map(|rule| (rule.gmail_filter.as_deref(), rule.enabled))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![(Some("f1"), false), (Some("f3"), true), (None, true)]
+        );
+    }
+
+    #[test]
+    fn test_gmail_filters_to_rules() {
+        let filters: Vec<GmailFilter> = serde_json::from_str(
+            r#"[
+                {"id": "f1", "criteria": {"from": "news@example.com"},
+                 "action": {"addLabelIds": ["Newsletters"], "removeLabelIds": ["INBOX"]}},
+                {"id": "f2", "criteria": {"subject": "Weekly digest"},
+                 "action": {"addLabelIds": ["TRASH"], "removeLabelIds": ["UNREAD"]}},
+                {"id": "f3", "criteria": {"from": "a@x.com OR b@y.com"},
+                 "action": {"removeLabelIds": ["INBOX"]}},
+                {"id": "f4", "criteria": {"from": "boss@x.com", "subject": "urgent"},
+                 "action": {"addLabelIds": ["STARRED"]}},
+                {"id": "f5", "criteria": {"query": "list:dev.example.com"},
+                 "action": {"removeLabelIds": ["INBOX"]}}
+            ]"#,
+        )
+        .unwrap();
+
+        let import = rules_from_gmail_filters(&filters);
+        let rules = import
+            .rules
+            .iter()
+            .map(|(id, rule)| {
+                (
+                    id.as_str(),
+                    rule.condition.field.as_str(),
+                    rule.condition.contains.as_str(),
+                    rule.action.as_str(),
+                    rule.label.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rules,
+            vec![
+                ("f1", "from", "news@example.com", "archive", None),
+                (
+                    "f1",
+                    "from",
+                    "news@example.com",
+                    "label",
+                    Some("Newsletters")
+                ),
+                ("f2", "subject", "Weekly digest", "delete", None),
+            ]
+        );
+        assert!(import
+            .rules
+            .iter()
+            .all(|(_, rule)| rule.problems().is_empty()));
+        assert_eq!(
+            import.skipped,
+            vec![
+                "subject:Weekly digest: removes label UNREAD",
+                "from:a@x.com OR b@y.com: from uses search operators",
+                "from:boss@x.com subject:urgent: combines several criteria",
+                "query:list:dev.example.com: uses a search query or size criterion",
+            ]
+        );
+    }
+}
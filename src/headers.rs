@@ -0,0 +1,184 @@
+use crate::providers::{sender_address, Email};
+use std::collections::HashMap;
+
+/// Headers providers copy into `Email::headers`, for spoofing and phishing signals
+pub const SECURITY_HEADERS: [&str; 5] = [
+    "Reply-To",
+    "Return-Path",
+    "List-Unsubscribe",
+    "Authentication-Results",
+    "Received-SPF",
+];
+
+/// The `SECURITY_HEADERS` that `get` finds, keyed by their names above
+pub fn collect(get: impl Fn(&str) -> Option<String>) -> HashMap<String, String> {
+    SECURITY_HEADERS
+        .iter()
+        .filter_map(|name| {
+            let value = get(name)?.trim().to_string();
+            (!value.is_empty()).then(|| (name.to_string(), value))
+        })
+        .collect()
+}
+
+fn header<'a>(email: &'a Email, name: &str) -> Option<&'a str> {
+    email
+        .headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Second-level labels that country-code TLDs register under, as in
+/// "co.uk", "com.au", or "ne.jp"
+const COUNTRY_SECOND_LEVELS: [&str; 12] = [
+    "ac", "co", "com", "edu", "gob", "gov", "ltd", "ne", "net", "or", "org", "plc",
+];
+
+/// The registrable domain: "mail.bounces.shop.com" → "shop.com" and
+/// "alerts.bank.co.uk" → "bank.co.uk", so a sender's own subdomains match
+/// but two organisations under one country suffix don't
+fn base_domain(address: &str) -> Option<String> {
+    let domain = sender_address(address).rsplit_once('@')?.1.to_string();
+    let labels = domain.split('.').collect::<Vec<_>>();
+    let country_suffix = match labels.as_slice() {
+        [.., second, tld] => tld.len() == 2 && COUNTRY_SECOND_LEVELS.contains(second),
+        _ => false,
+    };
+    let kept = if country_suffix { 3 } else { 2 };
+    Some(labels[labels.len().saturating_sub(kept)..].join("."))
+}
+
+/// Authentication checks (spf, dkim, dmarc) that came back fail or softfail
+fn failed_checks(email: &Email) -> Vec<&'static str> {
+    let results = header(email, "Authentication-Results")
+        .unwrap_or("")
+        .to_lowercase();
+    let mut failed = ["spf", "dkim", "dmarc"]
+        .into_iter()
+        .filter(|check| {
+            results.contains(&format!("{}=fail", check))
+                || results.contains(&format!("{}=softfail", check))
+        })
+        .collect::<Vec<_>>();
+    let received_spf = header(email, "Received-SPF").unwrap_or("").to_lowercase();
+    let spf_failed = received_spf.starts_with("fail") || received_spf.starts_with("softfail");
+    if spf_failed && !failed.contains(&"spf") {
+        failed.insert(0, "spf");
+    }
+    failed
+}
+
+/// Compact notes on the security headers for the classify prompt, e.g.
+/// "Reply-To domain other.net differs from From; DKIM failed". None when
+/// the provider supplied no headers to go by.
+pub fn summary(email: &Email) -> Option<String> {
+    if email.headers.is_empty() {
+        return None;
+    }
+
+    let from_domain = base_domain(&email.from);
+    let mut notes = Vec::new();
+    for name in ["Reply-To", "Return-Path"] {
+        let domain = header(email, name).and_then(base_domain);
+        if let (Some(domain), Some(from_domain)) = (domain, &from_domain) {
+            if &domain != from_domain {
+                notes.push(format!("{} domain {} differs from From", name, domain));
+            }
+        }
+    }
+    let failed = failed_checks(email);
+    if !failed.is_empty() {
+        let failed = failed
+            .iter()
+            .map(|check| check.to_uppercase())
+            .collect::<Vec<_>>();
+        notes.push(format!("{} failed", failed.join("/")));
+    }
+    // Only worth noting for list mail; personal email never has one
+    if email.list_id.is_some() && header(email, "List-Unsubscribe").is_none() {
+        notes.push("List-Id without List-Unsubscribe".to_string());
+    }
+
+    Some(if notes.is_empty() {
+        "nothing unusual".to_string()
+    } else {
+        notes.join("; ")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(from: &str, headers: &[(&str, &str)]) -> Email {
+        Email {
+            from: from.to_string(),
+            headers: collect(|name| {
+                headers
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.to_string())
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_summary_flags_mismatches_and_failures() {
+        let phishing = email(
+            "PayPal <service@paypal.com>",
+            &[
+                ("reply-to", "support@secure-paypa1.net"),
+                ("Return-Path", "<bounce@mail.paypal.com>"),
+                (
+                    "Authentication-Results",
+                    "mx.google.com; spf=softfail smtp.mailfrom=x; dkim=pass; dmarc=fail",
+                ),
+                ("X-Mailer", "ignored"),
+            ],
+        );
+        assert_eq!(phishing.headers.len(), 3);
+        assert_eq!(
+            summary(&phishing).unwrap(),
+            "Reply-To domain secure-paypa1.net differs from From; SPF/DMARC failed"
+        );
+
+        let newsletter = email(
+            "news@shop.com",
+            &[
+                ("Return-Path", "bounces@em.shop.com"),
+                ("List-Unsubscribe", "<mailto:unsubscribe@shop.com>"),
+                ("Received-SPF", "pass (domain of shop.com)"),
+            ],
+        );
+        assert_eq!(summary(&newsletter).unwrap(), "nothing unusual");
+        let unlisted = Email {
+            list_id: Some("<deals.shop.com>".to_string()),
+            ..email("news@shop.com", &[("Received-SPF", "pass")])
+        };
+        assert_eq!(
+            summary(&unlisted).unwrap(),
+            "List-Id without List-Unsubscribe"
+        );
+        assert_eq!(summary(&Email::default()), None);
+    }
+
+    #[test]
+    fn test_country_second_level_domains_compared_whole() {
+        let spoofed = email(
+            "Bank <alerts@bank.co.uk>",
+            &[
+                ("Reply-To", "help@evil.co.uk"),
+                ("Return-Path", "<bounce@mail.bank.co.uk>"),
+            ],
+        );
+        assert_eq!(
+            summary(&spoofed).unwrap(),
+            "Reply-To domain evil.co.uk differs from From"
+        );
+        assert_eq!(base_domain("x@shop.com.au").as_deref(), Some("shop.com.au"));
+        assert_eq!(base_domain("x@mail.shop.de").as_deref(), Some("shop.de"));
+        assert_eq!(base_domain("x@co.uk").as_deref(), Some("co.uk"));
+    }
+}
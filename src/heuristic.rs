@@ -0,0 +1,299 @@
+use crate::classifier::Classification;
+use crate::profile::Profile;
+use crate::providers::Email;
+
+/// Confidence of an offline classification: below anything the model would
+/// normally report, so spot checks and stats show it for what it is
+const HEURISTIC_CONFIDENCE: f32 = 0.4;
+
+/// Built-in subject keywords per theme label, matched on whole words
+const THEME_KEYWORDS: &[(&str, &[&str])] = &[
+    (
+        "Security",
+        &[
+            "password",
+            "security alert",
+            "sign-in",
+            "new sign in",
+            "verification code",
+            "two-factor",
+        ],
+    ),
+    (
+        "Receipts",
+        &["receipt", "payment confirmation", "payment received"],
+    ),
+    (
+        "Bills",
+        &[
+            "invoice",
+            "bill is ready",
+            "payment due",
+            "statement is ready",
+        ],
+    ),
+    (
+        "Shipping",
+        &[
+            "shipped",
+            "out for delivery",
+            "tracking number",
+            "delivered",
+        ],
+    ),
+    (
+        "Travel",
+        &[
+            "flight",
+            "itinerary",
+            "boarding pass",
+            "check-in",
+            "reservation",
+        ],
+    ),
+];
+
+/// Built-in subject keywords per action label
+const ACTION_KEYWORDS: &[(&str, &[&str])] = &[
+    (
+        "Survey",
+        &["survey", "how did we do", "rate your", "feedback"],
+    ),
+    ("Newsletters", &["newsletter", "digest", "weekly roundup"]),
+    ("Promotional", &["sale", "discount", "limited time", "deal"]),
+];
+
+/// Words in profile rules that say nothing about which emails they mean
+const STOPWORDS: &[&str] = &[
+    "a", "about", "all", "an", "and", "any", "are", "as", "by", "email", "emails", "for", "from",
+    "in", "is", "mail", "mails", "message", "messages", "of", "or", "sent", "that", "the", "to",
+    "with",
+];
+
+/// What a profile rule matches on
+#[derive(Debug)]
+struct RuleMatcher {
+    /// Address fragments ("billing@", "amazon.com"): any one in From matches
+    senders: Vec<String>,
+    /// Plain words: all of them must appear in the searched text
+    keywords: Vec<String>,
+}
+
+impl RuleMatcher {
+    fn parse(text: &str) -> Option<Self> {
+        let mut senders = Vec::new();
+        let mut keywords = Vec::new();
+        for token in text.split(|c: char| c.is_whitespace() || c == ',') {
+            let token = token
+                .trim_matches(|c: char| !c.is_alphanumeric() && c != '@')
+                .to_lowercase();
+            if token.contains('@') || token.contains('.') {
+                senders.push(token);
+            } else if token.len() > 1 && !STOPWORDS.contains(&token.as_str()) {
+                keywords.push(token);
+            }
+        }
+        (!senders.is_empty() || !keywords.is_empty()).then_some(Self { senders, keywords })
+    }
+
+    fn matches(&self, from: &str, text: &str) -> bool {
+        if !self.senders.is_empty() {
+            return self.senders.iter().any(|sender| from.contains(sender));
+        }
+        self.keywords
+            .iter()
+            .all(|keyword| contains_words(text, keyword))
+    }
+}
+
+/// Classify from keywords, senders, and the profile's own rules, without the
+/// LLM. Only labels are assigned; nothing is archived or deleted on a guess.
+pub fn classify(profile: &Profile, email: &Email) -> Classification {
+    let from = email.from.to_lowercase();
+    let subject = normalize_words(&email.subject);
+    let subject_and_body = format!("{}{}", subject, normalize_words(&email.body));
+
+    let mut matched = Vec::new();
+    let mut theme = keyword_labels(THEME_KEYWORDS, &subject);
+    let mut action = keyword_labels(ACTION_KEYWORDS, &subject);
+    matched.extend(theme.iter().chain(action.iter()).cloned());
+
+    for (label, matcher) in label_rules(profile) {
+        if matcher.matches(&from, &subject) && !theme.contains(&label) {
+            matched.push(format!("rule for {}", label));
+            theme.push(label);
+        }
+    }
+
+    let is_spam = spam_patterns(profile).any(|matcher| matcher.matches(&from, &subject_and_body));
+    if is_spam {
+        matched.push("spam pattern".to_string());
+        theme.clear();
+        action.clear();
+    }
+
+    let reason = if matched.is_empty() {
+        "Offline heuristics: no keyword or profile rule matched".to_string()
+    } else {
+        format!("Offline heuristics matched {}", matched.join(", "))
+    };
+    Classification {
+        is_spam,
+        archive: false,
+        delete: false,
+        theme,
+        action,
+        confidence: HEURISTIC_CONFIDENCE,
+        reason: Some(reason),
+        heuristic: true,
+    }
+}
+
+fn keyword_labels(table: &[(&str, &[&str])], text: &str) -> Vec<String> {
+    table
+        .iter()
+        .filter(|(_, keywords)| keywords.iter().any(|keyword| contains_words(text, keyword)))
+        .map(|(label, _)| label.to_string())
+        .collect()
+}
+
+/// Rules under `## Label Rules`: "- X is Label" / "- X → Label" bullets, and
+/// bullets under a `### Label` subsection
+fn label_rules(profile: &Profile) -> Vec<(String, RuleMatcher)> {
+    let mut rules = Vec::new();
+    let mut subsection: Option<String> = None;
+    for line in profile.section_lines("## Label Rules") {
+        if let Some(header) = line.strip_prefix("### ") {
+            subsection = Some(header.trim().to_string());
+            continue;
+        }
+        let Some(rule) = line.strip_prefix("- ") else {
+            continue;
+        };
+        let parsed = match &subsection {
+            Some(label) => RuleMatcher::parse(rule).map(|matcher| (label.clone(), matcher)),
+            None => split_rule(rule).and_then(|(pattern, label)| {
+                RuleMatcher::parse(pattern).map(|matcher| (label.to_string(), matcher))
+            }),
+        };
+        rules.extend(parsed);
+    }
+    rules
+}
+
+/// Split "billing@ mail is Finance" into the pattern and a one-word label
+fn split_rule(rule: &str) -> Option<(&str, &str)> {
+    let (pattern, label) = [" → ", " -> ", " is ", " are "]
+        .iter()
+        .find_map(|separator| rule.rsplit_once(separator))?;
+    let label = label.trim().trim_end_matches(['.', '!']);
+    let is_label = label.starts_with(|c: char| c.is_uppercase())
+        && label
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '/');
+    is_label.then_some((pattern, label))
+}
+
+/// Bullets under `## Spam Patterns`, skipping the "(Add patterns…)" placeholder
+fn spam_patterns(profile: &Profile) -> impl Iterator<Item = RuleMatcher> + '_ {
+    profile
+        .section_lines("## Spam Patterns")
+        .filter_map(|line| line.strip_prefix("- "))
+        .filter(|pattern| !pattern.starts_with('('))
+        .filter_map(RuleMatcher::parse)
+}
+
+/// Lowercase words separated and surrounded by single spaces, so phrases can
+/// be found on word boundaries
+fn normalize_words(text: &str) -> String {
+    let mut normalized = String::from(" ");
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        normalized.push_str(&word.to_lowercase());
+        normalized.push(' ');
+    }
+    normalized
+}
+
+/// Whether the phrase appears in normalized text as whole words, also
+/// accepting it with or without a trailing plural "s"
+fn contains_words(normalized: &str, phrase: &str) -> bool {
+    let phrase = normalize_words(phrase);
+    let phrase = phrase.trim();
+    if phrase.is_empty() {
+        return false;
+    }
+    let singular = phrase.strip_suffix('s').filter(|stem| stem.len() > 3);
+    [Some(phrase.to_string()), Some(format!("{}s", phrase))]
+        .into_iter()
+        .chain([singular.map(str::to_string)])
+        .flatten()
+        .any(|candidate| normalized.contains(&format!(" {} ", candidate)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_email(from: &str, subject: &str, body: &str) -> Email {
+        Email {
+            id: "test123".to_string(),
+            from: from.to_string(),
+            subject: subject.to_string(),
+            body: body.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_builtin_keywords_map_to_labels() {
+        let profile = Profile::default();
+        let cases = [
+            ("Your password was changed", vec!["Security"], vec![]),
+            ("Invoice #4821 from Acme", vec!["Bills"], vec![]),
+            ("Your flight to Lisbon", vec!["Travel"], vec![]),
+            ("Quick survey about your stay", vec![], vec!["Survey"]),
+            ("Wholesale pricing update", vec![], vec![]),
+        ];
+
+        for (subject, theme, action) in cases {
+            let classification = classify(&profile, &make_email("x@example.com", subject, ""));
+            assert_eq!(classification.theme, theme, "{}", subject);
+            assert_eq!(classification.action, action, "{}", subject);
+            assert!(!classification.archive && !classification.delete);
+            assert_eq!(classification.confidence, HEURISTIC_CONFIDENCE);
+        }
+    }
+
+    #[test]
+    fn test_profile_rules_drive_labels_and_spam() {
+        let profile = Profile::from_content(
+            "## Spam Patterns\n- (Add patterns as you mark emails as spam)\n- crypto giveaways\n\n\
+             ## Label Rules\n- billing@ and invoice@ mail is Finance\n- flights are Travel\n\
+             ### Gaming\n- anything from steampowered.com\n\n## Learned Corrections\n",
+        );
+
+        let finance = classify(&profile, &make_email("Billing@acme.com", "March", ""));
+        assert_eq!(finance.theme, vec!["Finance"]);
+
+        let gaming = classify(
+            &profile,
+            &make_email("noreply@steampowered.com", "Sale", ""),
+        );
+        assert_eq!(gaming.theme, vec!["Gaming"]);
+        assert_eq!(gaming.action, vec!["Promotional"]);
+
+        let spam = classify(
+            &profile,
+            &make_email(
+                "win@example.net",
+                "You won",
+                "Claim free crypto giveaway now",
+            ),
+        );
+        assert!(spam.is_spam);
+        assert!(spam.theme.is_empty() && !spam.delete);
+    }
+}
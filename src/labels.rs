@@ -1,16 +1,133 @@
 use crate::config;
 use crate::profile::Profile;
-use crate::providers::EmailProvider;
+use crate::providers::{EmailProvider, ProviderError, SortOrder};
 use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
+use tracing::warn;
+
+/// Emails relabeled per provider listing during a merge
+const MERGE_PAGE_SIZE: u32 = 500;
+/// Labels probed at once by `cleanup`
+const CLEANUP_CONCURRENCY: usize = 8;
+/// Labels counted at once by `labels --counts`
+const COUNT_CONCURRENCY: usize = 8;
+/// How long counts fetched from the provider are reused
+const COUNT_CACHE_MINUTES: i64 = 15;
+
+/// Separates a parent label from its child, as Gmail nests "Finance/Receipts"
+pub const LABEL_SEPARATOR: char = '/';
+
+/// Exact spellings of the labels the classifier and learning rely on
+const CANONICAL_LABELS: &[&str] = &[
+    "Newsletters",
+    "Promotional",
+    "Survey",
+    "Needs-Reply",
+    "Important",
+    "Urgent",
+    "Awaiting-Reply",
+    "Group-Thread",
+    "Other",
+    "FYI",
+    "Classified",
+];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LabelInfo {
     pub name: String,
     pub source: LabelSource,
     pub email_count: u32,
+    /// Name of the `LABEL_PALETTE` entry the label is shown in
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+/// A label color, as Gmail's allowed background/text pair and the closest
+/// Outlook category preset
+#[derive(Debug, PartialEq, Eq)]
+pub struct LabelColor {
+    pub name: &'static str,
+    pub background: &'static str,
+    pub text: &'static str,
+    pub outlook_preset: &'static str,
+}
+
+/// Colors new labels are drawn from. Gmail rejects colors outside its own
+/// palette, so every pair here comes from it.
+pub const LABEL_PALETTE: [LabelColor; 8] = [
+    LabelColor {
+        name: "red",
+        background: "#fb4c2f",
+        text: "#ffffff",
+        outlook_preset: "preset0",
+    },
+    LabelColor {
+        name: "orange",
+        background: "#ffad47",
+        text: "#000000",
+        outlook_preset: "preset1",
+    },
+    LabelColor {
+        name: "yellow",
+        background: "#fad165",
+        text: "#000000",
+        outlook_preset: "preset3",
+    },
+    LabelColor {
+        name: "green",
+        background: "#16a766",
+        text: "#ffffff",
+        outlook_preset: "preset4",
+    },
+    LabelColor {
+        name: "teal",
+        background: "#43d692",
+        text: "#000000",
+        outlook_preset: "preset5",
+    },
+    LabelColor {
+        name: "blue",
+        background: "#4a86e8",
+        text: "#ffffff",
+        outlook_preset: "preset7",
+    },
+    LabelColor {
+        name: "purple",
+        background: "#a479e2",
+        text: "#ffffff",
+        outlook_preset: "preset8",
+    },
+    LabelColor {
+        name: "pink",
+        background: "#f691b3",
+        text: "#000000",
+        outlook_preset: "preset9",
+    },
+];
+
+impl LabelColor {
+    pub fn named(name: &str) -> Option<&'static LabelColor> {
+        LABEL_PALETTE
+            .iter()
+            .find(|color| color.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The palette entry for a label, stable across runs and case-insensitive.
+    /// FNV-1a rather than `DefaultHasher`, whose output may change between
+    /// Rust releases.
+    pub fn for_label(label: &str) -> &'static LabelColor {
+        let hash = label
+            .to_lowercase()
+            .bytes()
+            .fold(0xcbf29ce484222325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            });
+        &LABEL_PALETTE[(hash % LABEL_PALETTE.len() as u64) as usize]
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -20,6 +137,13 @@ pub enum LabelSource {
     Llm,
 }
 
+/// Two spellings of what looks like the same label
+#[derive(Debug, PartialEq)]
+pub struct MergeSuggestion {
+    pub from: String,
+    pub into: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct LabelManager {
     labels: HashMap<String, LabelInfo>,
@@ -47,49 +171,621 @@ impl LabelManager {
     }
 
     pub fn llm_labels(&self) -> Vec<&str> {
-        self.labels
+        let mut names = self
+            .labels
             .values()
             .filter(|l| l.source == LabelSource::Llm)
             .map(|l| l.name.as_str())
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    /// Tracked labels nested directly under `parent`
+    pub fn children(&self, parent: &str) -> Vec<&str> {
+        let mut children = self
+            .labels
+            .keys()
+            .filter(|name| parent_label(name) == Some(parent))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        children.sort_unstable();
+        children
+    }
+
+    /// Emails recorded under the label and everything nested below it
+    pub fn rolled_up_count(&self, label: &str) -> u32 {
+        self.labels
+            .values()
+            .filter(|info| is_within(&info.name, label))
+            .map(|info| info.email_count)
+            .sum()
+    }
+
+    /// Labels not yet tracked by the manager
+    pub fn unknown_labels<'a>(&self, labels: &'a [String]) -> Vec<&'a str> {
+        labels
+            .iter()
+            .filter(|label| !self.labels.contains_key(label.as_str()))
+            .map(String::as_str)
             .collect()
     }
 
+    /// Replace recorded email counts with the provider's, for labels the
+    /// manager tracks
+    pub fn set_email_counts(&mut self, counts: &BTreeMap<String, u32>) {
+        for (label, count) in counts {
+            if let Some(info) = self.labels.get_mut(label) {
+                info.email_count = *count;
+            }
+        }
+    }
+
+    /// Track labels applied by the classifier, creating LLM entries as needed.
+    /// The parents of a nested label are tracked too, without emails of their own.
+    pub fn record_llm_labels(&mut self, labels: &[String]) {
+        for label in labels {
+            for path in label_with_ancestors(label) {
+                self.labels
+                    .entry(path.to_string())
+                    .or_insert_with(|| LabelInfo {
+                        name: path.to_string(),
+                        source: LabelSource::Llm,
+                        email_count: 0,
+                        color: Some(LabelColor::for_label(path).name.to_string()),
+                    });
+            }
+            if let Some(info) = self.labels.get_mut(label) {
+                info.email_count += 1;
+            }
+        }
+    }
+
+    /// Names of every tracked label, sorted
+    pub fn label_names(&self) -> Vec<&str> {
+        let mut names = self.labels.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    /// The label's recorded color, falling back to its hashed palette entry
+    pub fn color_of(&self, label: &str) -> &'static LabelColor {
+        self.labels
+            .get(label)
+            .and_then(|info| info.color.as_deref())
+            .and_then(LabelColor::named)
+            .unwrap_or_else(|| LabelColor::for_label(label))
+    }
+
+    /// Record the color a label was given on the provider
+    pub fn set_color(&mut self, label: &str, color: &LabelColor) {
+        let info = self
+            .labels
+            .entry(label.to_string())
+            .or_insert_with(|| LabelInfo {
+                name: label.to_string(),
+                source: LabelSource::Provider,
+                email_count: 0,
+                color: None,
+            });
+        info.color = Some(color.name.to_string());
+    }
+
+    /// Color each label on the provider, in `color` or else its current one,
+    /// recording the result. Returns the color each label got.
+    pub async fn recolor<P: EmailProvider + ?Sized>(
+        &mut self,
+        provider: &P,
+        labels: &[String],
+        color: Option<&'static LabelColor>,
+    ) -> Result<Vec<(String, &'static LabelColor)>> {
+        let mut recolored = Vec::new();
+        for label in labels {
+            let color = color.unwrap_or_else(|| self.color_of(label));
+            provider.set_label_color(label, color).await?;
+            self.set_color(label, color);
+            recolored.push((label.clone(), color));
+        }
+        Ok(recolored)
+    }
+
     pub async fn cleanup<P: EmailProvider>(
         &mut self,
         provider: &P,
         profile: &mut Profile,
     ) -> Result<Vec<String>> {
-        let mut removed = Vec::new();
-
-        // Get LLM labels that need checking
-        let llm_labels: Vec<String> = self
+        // Get LLM labels that need checking. A parent may hold no emails
+        // itself while its children do, so only leaves are probed.
+        let llm_labels: Vec<&str> = self
             .labels
             .values()
             .filter(|l| l.source == LabelSource::Llm)
-            .map(|l| l.name.clone())
+            .map(|l| l.name.as_str())
+            .filter(|name| self.children(name).is_empty())
             .collect();
 
-        for label_name in llm_labels {
-            // Query provider for emails with this label
-            let emails = provider.list_messages(1, &label_name, None).await;
+        // Probe the provider concurrently first, then change self afterwards
+        let mut removed: Vec<String> = stream::iter(llm_labels)
+            .map(|label_name| async move {
+                // Query provider for emails with this label
+                let emails = provider
+                    .list_messages(1, label_name, None, SortOrder::default())
+                    .await;
+                let unused = match emails {
+                    // No emails with this label
+                    Ok(emails) => emails.is_empty(),
+                    // Label doesn't exist in provider
+                    Err(error) if ProviderError::is_not_found(&error) => true,
+                    // Can't tell whether the label is still in use
+                    Err(error) if ProviderError::is_unsupported(&error) => false,
+                    Err(error) => {
+                        warn!(label = label_name, "keeping label: {:#}", error);
+                        false
+                    }
+                };
+                unused.then(|| label_name.to_string())
+            })
+            .buffer_unordered(CLEANUP_CONCURRENCY)
+            .filter_map(|label| async move { label })
+            .collect()
+            .await;
 
-            match emails {
-                Ok(emails) if emails.is_empty() => {
-                    // No emails with this label, remove it
-                    self.labels.remove(&label_name);
-                    profile.remove_label_rules(&label_name);
-                    removed.push(label_name);
-                }
-                Err(_) => {
-                    // Label doesn't exist in provider, remove it
-                    self.labels.remove(&label_name);
-                    profile.remove_label_rules(&label_name);
-                    removed.push(label_name);
+        removed.sort_unstable();
+        for label_name in &removed {
+            self.labels.remove(label_name);
+            profile.remove_label_rules(label_name);
+        }
+        Ok(removed)
+    }
+
+    /// Move every email from `from` to `into`, then drop `from` from tracking
+    /// and from the profile rules. Returns how many emails were relabeled.
+    pub async fn merge<P: EmailProvider + ?Sized>(
+        &mut self,
+        provider: &P,
+        profile: &mut Profile,
+        from: &str,
+        into: &str,
+    ) -> Result<usize> {
+        let mut moved = HashSet::new();
+        loop {
+            let emails = provider
+                .list_messages(MERGE_PAGE_SIZE, from, None, SortOrder::default())
+                .await?;
+            // Stop once the listing has nothing new, in case the provider is
+            // slow to reflect removed labels
+            let pending = emails
+                .into_iter()
+                .filter(|email| !moved.contains(&email.id))
+                .collect::<Vec<_>>();
+            if pending.is_empty() {
+                break;
+            }
+
+            for email in pending {
+                provider.add_label(&email.id, into).await?;
+                provider.remove_label(&email.id, from).await?;
+                moved.insert(email.id);
+            }
+        }
+
+        let count = self
+            .labels
+            .remove(from)
+            .map(|info| info.email_count)
+            .unwrap_or(0);
+        self.labels
+            .entry(into.to_string())
+            .or_insert_with(|| LabelInfo {
+                name: into.to_string(),
+                source: LabelSource::Llm,
+                email_count: 0,
+                color: Some(LabelColor::for_label(into).name.to_string()),
+            })
+            .email_count += count;
+        profile.remove_label_rules(from);
+
+        Ok(moved.len())
+    }
+
+    /// Labels that differ only by case or a plural "s", merged into the
+    /// spelling with the most emails. `others` adds untracked provider labels.
+    pub fn suggest_merges(&self, others: &[String]) -> Vec<MergeSuggestion> {
+        let mut groups: BTreeMap<String, Vec<(&str, u32)>> = BTreeMap::new();
+        let tracked = self
+            .labels
+            .values()
+            .map(|info| (info.name.as_str(), info.email_count));
+        let untracked = others
+            .iter()
+            .filter(|name| !self.labels.contains_key(name.as_str()))
+            .map(|name| (name.as_str(), 0));
+        for (name, count) in tracked.chain(untracked) {
+            let group = groups.entry(label_stem(name)).or_default();
+            if !group.iter().any(|(existing, _)| *existing == name) {
+                group.push((name, count));
+            }
+        }
+
+        let mut suggestions = Vec::new();
+        for mut group in groups.into_values().filter(|group| group.len() > 1) {
+            group.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            let into = group[0].0;
+            suggestions.extend(group[1..].iter().map(|(from, _)| MergeSuggestion {
+                from: from.to_string(),
+                into: into.to_string(),
+            }));
+        }
+        suggestions
+    }
+}
+
+/// Email counts per label as one provider last reported them, kept in
+/// `label_counts.json` so repeated `labels --counts` runs stay quick
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LabelCountCache {
+    provider: String,
+    fetched_at: Option<DateTime<Utc>>,
+    counts: BTreeMap<String, u32>,
+}
+
+impl LabelCountCache {
+    pub fn load() -> Result<Self> {
+        let path = config::label_counts_path();
+        if path.exists() {
+            let content = fs::read_to_string(&path)?;
+            Ok(serde_json::from_str(&content)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = config::config_dir();
+        if !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(config::label_counts_path(), content)?;
+        Ok(())
+    }
+
+    pub fn new(provider: &str, counts: BTreeMap<String, u32>, now: DateTime<Utc>) -> Self {
+        Self {
+            provider: provider.to_string(),
+            fetched_at: Some(now),
+            counts,
+        }
+    }
+
+    /// The cached counts, if they're the provider's and recent enough
+    pub fn fresh(&self, provider: &str, now: DateTime<Utc>) -> Option<&BTreeMap<String, u32>> {
+        let fetched_at = self.fetched_at?;
+        let recent = now - fetched_at < Duration::minutes(COUNT_CACHE_MINUTES);
+        (self.provider == provider && recent).then_some(&self.counts)
+    }
+}
+
+/// Ask the provider how many emails carry each label, several at a time.
+/// Labels whose count fails are left out with a warning.
+pub async fn fetch_label_counts<P: EmailProvider + ?Sized>(
+    provider: &P,
+    labels: &[String],
+) -> BTreeMap<String, u32> {
+    stream::iter(labels)
+        .map(|label| async move { (label, provider.count_label(label).await) })
+        .buffer_unordered(COUNT_CONCURRENCY)
+        .filter_map(|(label, count)| async move {
+            match count {
+                Ok(count) => Some((label.clone(), count)),
+                Err(error) => {
+                    warn!(label = label.as_str(), "couldn't count emails: {:#}", error);
+                    None
                 }
-                _ => {}
             }
+        })
+        .collect()
+        .await
+}
+
+/// Case-folded singular form: "Receipts" and "receipt" share a stem
+fn label_stem(name: &str) -> String {
+    let lower = name.trim().to_lowercase();
+    if let Some(stem) = lower.strip_suffix("ies") {
+        return format!("{}y", stem);
+    }
+    match lower.strip_suffix('s') {
+        Some(stem) if !stem.ends_with('s') && !stem.is_empty() => stem.to_string(),
+        _ => lower,
+    }
+}
+
+/// The one spelling of a label: known labels by table, anything else with
+/// each lower-case hyphen- or space-separated word capitalized ("smart-home"
+/// → "Smart-Home"); words with capitals of their own, such as "iOS" or
+/// "HR", are kept as written. Each level of a nested label is spelled on
+/// its own ("finance / receipts" → "Finance/Receipts").
+pub fn canonical_label(label: &str) -> String {
+    label
+        .split(LABEL_SEPARATOR)
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+        .map(canonical_segment)
+        .collect::<Vec<_>>()
+        .join(&LABEL_SEPARATOR.to_string())
+}
+
+fn canonical_segment(label: &str) -> String {
+    if let Some(known) = CANONICAL_LABELS
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(label))
+    {
+        return known.to_string();
+    }
+
+    let mut canonical = String::with_capacity(label.len());
+    for word in label.split_inclusive(['-', ' ']) {
+        let mut chars = word.chars();
+        match chars.next() {
+            Some(first) if !word.chars().any(char::is_uppercase) => {
+                canonical.extend(first.to_uppercase());
+                canonical.extend(chars);
+            }
+            _ => canonical.push_str(word),
         }
+    }
+    canonical
+}
 
-        Ok(removed)
+/// Whether two spellings name the same label
+pub fn same_label(a: &str, b: &str) -> bool {
+    canonical_label(a) == canonical_label(b)
+}
+
+/// The label one level up: "Finance" for "Finance/Receipts"
+pub fn parent_label(label: &str) -> Option<&str> {
+    label.rsplit_once(LABEL_SEPARATOR).map(|(parent, _)| parent)
+}
+
+/// Every level of a label, outermost first: "A", "A/B", "A/B/C" for "A/B/C"
+pub fn label_with_ancestors(label: &str) -> impl Iterator<Item = &str> {
+    label
+        .match_indices(LABEL_SEPARATOR)
+        .map(|(index, _)| &label[..index])
+        .chain([label])
+}
+
+/// Whether `label` is `ancestor` or nested somewhere below it
+pub fn is_within(label: &str, ancestor: &str) -> bool {
+    let (label, ancestor) = (canonical_label(label), canonical_label(ancestor));
+    label == ancestor
+        || label
+            .strip_prefix(&ancestor)
+            .is_some_and(|rest| rest.starts_with(LABEL_SEPARATOR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::mock::MockProvider;
+    use crate::providers::Email;
+
+    #[test]
+    fn test_canonical_label_spellings() {
+        assert_eq!(canonical_label("needs-reply"), "Needs-Reply");
+        assert_eq!(canonical_label("Needs-reply"), "Needs-Reply");
+        assert_eq!(canonical_label("fyi"), "FYI");
+        assert_eq!(canonical_label("FYI"), "FYI");
+        assert_eq!(canonical_label("awaiting-reply"), "Awaiting-Reply");
+        assert_eq!(canonical_label("smart-home alerts"), "Smart-Home Alerts");
+        assert_eq!(canonical_label("IRS"), "IRS");
+        assert_eq!(canonical_label("iOS updates"), "iOS Updates");
+        assert_eq!(canonical_label("eBay-orders"), "eBay-Orders");
+        assert_eq!(canonical_label("finance/receipts"), "Finance/Receipts");
+        assert!(same_label("group-thread", "Group-Thread"));
+        assert!(!same_label("Receipt", "Receipts"));
+    }
+
+    #[test]
+    fn test_nested_labels() {
+        assert_eq!(canonical_label("finance / receipts"), "Finance/Receipts");
+        assert_eq!(canonical_label("Work//fyi/"), "Work/FYI");
+        assert_eq!(parent_label("Finance/Receipts"), Some("Finance"));
+        assert_eq!(parent_label("Finance"), None);
+        assert_eq!(
+            label_with_ancestors("Home/Sensors/Leaks").collect::<Vec<_>>(),
+            vec!["Home", "Home/Sensors", "Home/Sensors/Leaks"]
+        );
+        assert!(is_within("Finance/Receipts", "finance"));
+        assert!(is_within("Finance", "Finance"));
+        assert!(!is_within("Financial", "Finance"));
+
+        let mut manager = LabelManager::default();
+        manager.record_llm_labels(&["Finance/Receipts".to_string(), "Finance".to_string()]);
+        manager.record_llm_labels(&["Finance/Bills".to_string()]);
+        assert_eq!(
+            manager.llm_labels(),
+            vec!["Finance", "Finance/Bills", "Finance/Receipts"]
+        );
+        assert_eq!(
+            manager.children("Finance"),
+            vec!["Finance/Bills", "Finance/Receipts"]
+        );
+        assert_eq!(manager.labels["Finance"].email_count, 1);
+        assert_eq!(manager.rolled_up_count("Finance"), 3);
+    }
+
+    #[test]
+    fn test_suggest_merges_case_and_plural() {
+        let mut manager = LabelManager::default();
+        for labels in [
+            vec!["Receipts", "Receipts", "receipt"],
+            vec!["Bills", "Deliveries", "Delivery", "Delivery", "Business"],
+        ] {
+            manager.record_llm_labels(&labels.iter().map(|l| l.to_string()).collect::<Vec<_>>());
+        }
+
+        let suggestions = manager.suggest_merges(&["Travel".to_string(), "bills".to_string()]);
+
+        assert_eq!(
+            suggestions,
+            vec![
+                MergeSuggestion {
+                    from: "bills".to_string(),
+                    into: "Bills".to_string()
+                },
+                MergeSuggestion {
+                    from: "Deliveries".to_string(),
+                    into: "Delivery".to_string()
+                },
+                MergeSuggestion {
+                    from: "receipt".to_string(),
+                    into: "Receipts".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_label_colors_are_deterministic_and_overridable() {
+        let color = LabelColor::for_label("Receipts");
+        assert_eq!(LabelColor::for_label("receipts"), color);
+        assert!(LABEL_PALETTE.contains(color));
+
+        let mut manager = LabelManager::default();
+        manager.record_llm_labels(&["Receipts".to_string()]);
+        assert_eq!(manager.color_of("Receipts"), color);
+
+        let other = LABEL_PALETTE.iter().find(|c| *c != color).unwrap();
+        manager.set_color("Receipts", other);
+        assert_eq!(manager.color_of("Receipts"), other);
+        assert_eq!(
+            manager.labels["Receipts"].color.as_deref(),
+            Some(other.name)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recolor_applies_and_records_color() {
+        let provider = MockProvider::default();
+        let mut manager = LabelManager::default();
+        manager.record_llm_labels(&["Travel".to_string()]);
+        let blue = LabelColor::named("blue").unwrap();
+
+        let recolored = manager
+            .recolor(&provider, &["Travel".to_string()], Some(blue))
+            .await
+            .unwrap();
+
+        assert_eq!(recolored, vec![("Travel".to_string(), blue)]);
+        assert_eq!(provider.calls(), vec!["set_label_color Travel blue"]);
+        assert_eq!(manager.color_of("Travel"), blue);
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_removes_only_unused_labels() {
+        let provider = MockProvider::with_emails(["1", "2"].map(|id| Email {
+            id: id.to_string(),
+            labels: vec![format!("Used{}", id)],
+            ..Default::default()
+        }));
+        let mut manager = LabelManager::default();
+        manager.record_llm_labels(
+            &["Used1", "Stale", "Used2", "Gone", "Finance/Used1"].map(String::from),
+        );
+        let mut profile = Profile::from_content(
+            "## Label Rules\n### Stale\n- old rule\n## Learned Corrections\n",
+        );
+
+        let removed = manager.cleanup(&provider, &mut profile).await.unwrap();
+
+        assert_eq!(removed, vec!["Finance/Used1", "Gone", "Stale"]);
+        assert_eq!(manager.llm_labels(), vec!["Finance", "Used1", "Used2"]);
+        assert!(!profile.content().contains("### Stale"));
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_keeps_labels_when_probe_fails() {
+        let provider = MockProvider::default().failing_on("list_messages");
+        let mut manager = LabelManager::default();
+        manager.record_llm_labels(&["Stale".to_string()]);
+        let mut profile = Profile::from_content("## Label Rules\n### Stale\n- old rule\n");
+
+        let removed = manager.cleanup(&provider, &mut profile).await.unwrap();
+
+        assert!(removed.is_empty());
+        assert_eq!(manager.llm_labels(), vec!["Stale"]);
+        assert!(profile.content().contains("### Stale"));
+    }
+
+    #[tokio::test]
+    async fn test_label_counts_fetched_recorded_and_cached() {
+        let provider = MockProvider::with_emails(["1", "2", "3"].map(|id| Email {
+            id: id.to_string(),
+            labels: if id == "3" {
+                vec!["Travel".to_string()]
+            } else {
+                vec!["Receipts".to_string(), "Travel".to_string()]
+            },
+            ..Default::default()
+        }));
+        let mut manager = LabelManager::default();
+        manager.record_llm_labels(&["Receipts".to_string()]);
+
+        let labels = ["Receipts", "Travel", "Empty"].map(String::from);
+        let counts = fetch_label_counts(&provider, &labels).await;
+        assert_eq!(
+            counts,
+            BTreeMap::from([
+                ("Empty".to_string(), 0),
+                ("Receipts".to_string(), 2),
+                ("Travel".to_string(), 3),
+            ])
+        );
+        manager.set_email_counts(&counts);
+        assert_eq!(manager.labels["Receipts"].email_count, 2);
+        assert!(!manager.labels.contains_key("Travel"));
+
+        let fetched_at = Utc::now();
+        let cache = LabelCountCache::new("gmail", counts.clone(), fetched_at);
+        assert_eq!(cache.fresh("gmail", fetched_at), Some(&counts));
+        assert_eq!(cache.fresh("outlook", fetched_at), None);
+        assert_eq!(cache.fresh("gmail", fetched_at + Duration::hours(1)), None);
+        assert_eq!(LabelCountCache::default().fresh("gmail", fetched_at), None);
+    }
+
+    #[tokio::test]
+    async fn test_merge_relabels_and_drops_source() {
+        let provider = MockProvider::with_emails(["1", "2"].map(|id| Email {
+            id: id.to_string(),
+            labels: vec!["Receipt".to_string()],
+            ..Default::default()
+        }));
+        let mut manager = LabelManager::default();
+        manager.record_llm_labels(&["Receipt".to_string(), "Receipts".to_string()]);
+        let mut profile = Profile::from_content(
+            "## Label Rules\n### Receipt\n- old rule\n## Learned Corrections\n",
+        );
+
+        let moved = manager
+            .merge(&provider, &mut profile, "Receipt", "Receipts")
+            .await
+            .unwrap();
+
+        assert_eq!(moved, 2);
+        let mut calls = provider.calls();
+        calls.sort();
+        assert_eq!(
+            calls,
+            vec![
+                "add_label 1 Receipts",
+                "add_label 2 Receipts",
+                "remove_label 1 Receipt",
+                "remove_label 2 Receipt"
+            ]
+        );
+        assert_eq!(manager.llm_labels(), vec!["Receipts"]);
+        assert_eq!(manager.labels["Receipts"].email_count, 2);
+        assert!(!profile.content().contains("### Receipt"));
     }
 }
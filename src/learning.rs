@@ -1,17 +1,31 @@
+use crate::config::{self, BodyPreview, LlmConfig};
+use crate::corrections::LoggedCorrection;
+use crate::labels::{canonical_label, same_label};
+use crate::llm;
 use crate::predictions::{Prediction, PredictionStore};
-use crate::profile::Profile;
-use crate::providers::{Email, EmailProvider};
+use crate::profile::{Profile, ProfilePatch};
+use crate::providers::{sender_address, Email, EmailProvider, ProviderError};
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use chrono::Utc;
-use std::process::Stdio;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
 use std::time::Duration;
-use tokio::process::Command;
-use tokio::time::timeout;
+use tracing::warn;
 
-const CLAUDE_MODEL: &str = "haiku";
-const CLAUDE_BROWSER_TOOLS: &str = "mcp__browsermcp__browser_navigate,mcp__browsermcp__browser_click,mcp__browsermcp__browser_snapshot,mcp__browsermcp__browser_screenshot,mcp__browsermcp__browser_wait,mcp__browsermcp__browser_hover,mcp__browsermcp__browser_type,mcp__browsermcp__browser_select_option,mcp__browsermcp__browser_press_key,mcp__browsermcp__browser_go_back,mcp__browsermcp__browser_go_forward,mcp__browsermcp__browser_get_console_logs";
+/// Default timeouts when `llm.timeout_secs` isn't set
+const ACTION_LEARNING_TIMEOUT: Duration = Duration::from_secs(60);
+const PROFILE_UPDATE_TIMEOUT: Duration = Duration::from_secs(90);
+/// Corrections sent to the LLM per profile update
+pub const CORRECTION_BATCH_SIZE: usize = 25;
+/// Maximum number of get_message calls in flight while detecting corrections
+const DETECTION_CONCURRENCY: usize = 8;
+/// Subjects quoted in a consolidated per-sender correction
+const GROUP_SUBJECTS_SHOWN: usize = 3;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Correction {
     pub email_id: String,
     pub from: String,
@@ -22,16 +36,166 @@ pub struct Correction {
     pub actual_spam: bool,
 }
 
+/// How a stored prediction disagrees with the provider's current state
+#[derive(Debug, Default)]
+pub struct Drift {
+    pub email_id: String,
+    pub subject: String,
+    pub email_gone: bool,
+    /// Predicted labels (or the Classified stamp) no longer on the email
+    pub missing_labels: Vec<String>,
+    /// Labels the user added on top of the prediction
+    pub extra_labels: Vec<String>,
+}
+
+/// How predicted labels held up on one label's emails
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LabelAccuracy {
+    /// Predicted and still on the email
+    pub kept: usize,
+    /// Predicted, then removed by the user
+    pub removed: usize,
+    /// Not predicted, but added by the user
+    pub added: usize,
+}
+
+/// How well stored predictions match the emails as they are now
+#[derive(Debug, Default, PartialEq)]
+pub struct Accuracy {
+    /// Predictions whose email still exists
+    pub checked: usize,
+    /// Checked predictions the user didn't relabel
+    pub unchanged: usize,
+    /// Predictions whose email was deleted
+    pub gone: usize,
+    pub by_label: BTreeMap<String, LabelAccuracy>,
+}
+
+impl Accuracy {
+    fn record(&mut self, prediction: &Prediction, drift: &Drift) {
+        if drift.email_gone {
+            self.gone += 1;
+            return;
+        }
+        self.checked += 1;
+
+        // A missing Classified stamp says nothing about the labels
+        let removed = drift
+            .missing_labels
+            .iter()
+            .filter(|label| !label.eq_ignore_ascii_case("Classified"))
+            .collect::<Vec<_>>();
+        for label in prediction.all_labels() {
+            let entry = self.by_label.entry(label.clone()).or_default();
+            if removed.iter().any(|removed| same_label(removed, &label)) {
+                entry.removed += 1;
+            } else {
+                entry.kept += 1;
+            }
+        }
+        for label in &drift.extra_labels {
+            self.by_label.entry(label.clone()).or_default().added += 1;
+        }
+        if removed.is_empty() && drift.extra_labels.is_empty() {
+            self.unchanged += 1;
+        }
+    }
+
+    /// Share of checked predictions left as they were, as a percentage
+    pub fn unchanged_percent(&self) -> Option<f64> {
+        (self.checked > 0).then(|| self.unchanged as f64 * 100.0 / self.checked as f64)
+    }
+}
+
+/// Result of checking every stored prediction against the provider
+#[derive(Debug, Default)]
+pub struct Verification {
+    /// Predictions that disagree with the provider, by email ID
+    pub drifts: Vec<Drift>,
+    pub accuracy: Accuracy,
+    /// Email IDs that couldn't be fetched for a reason other than being gone
+    /// (a timeout, a rate limit, ...), with the error; neither checked nor gone
+    pub failed: Vec<(String, String)>,
+}
+
 #[derive(Debug, Default)]
 pub struct LearningResult {
     pub corrections: Vec<Correction>,
     pub deleted_ids: Vec<String>,
 }
 
+/// Where profile updates come from: the LLM, or recorded responses when replaying
+#[async_trait]
+pub trait LearningBackend: Send + Sync {
+    /// Raw response to a profile-update prompt
+    async fn respond(&self, prompt: &str) -> Result<String>;
+}
+
+/// Profile updates from the claude CLI, using the configured learning model
+pub struct ClaudeBackend {
+    model: String,
+    timeout: Duration,
+}
+
+impl ClaudeBackend {
+    pub fn new(llm: &LlmConfig) -> Self {
+        Self {
+            model: llm.learn_model().to_string(),
+            timeout: llm.timeout(PROFILE_UPDATE_TIMEOUT),
+        }
+    }
+}
+
+#[async_trait]
+impl LearningBackend for ClaudeBackend {
+    async fn respond(&self, prompt: &str) -> Result<String> {
+        let output = llm::output(prompt, &self.model, self.timeout)
+            .await
+            .context("Profile update failed")?;
+        profile_update_response(output)
+    }
+}
+
+/// The CLI's answer to a profile-update prompt. A failed run is an error, so
+/// the batch is retried instead of counting as learned.
+fn profile_update_response(output: std::process::Output) -> Result<String> {
+    Ok(parse_claude_output(output, true)
+        .context("Profile update failed")?
+        .trim()
+        .to_string())
+}
+
+/// Answers with recorded responses in order, then declines to update.
+/// With no responses, replaying only appends the corrections themselves.
+#[derive(Default)]
+pub struct ReplayBackend {
+    responses: Mutex<VecDeque<String>>,
+}
+
+#[cfg(test)]
+impl ReplayBackend {
+    pub fn new(responses: Vec<String>) -> Self {
+        Self {
+            responses: Mutex::new(responses.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl LearningBackend for ReplayBackend {
+    async fn respond(&self, _prompt: &str) -> Result<String> {
+        let mut responses = self.responses.lock().unwrap();
+        Ok(responses
+            .pop_front()
+            .unwrap_or_else(|| "NO_UPDATE_NEEDED".to_string()))
+    }
+}
+
 pub struct LearningEngine<'a, P: EmailProvider + ?Sized> {
     provider: &'a P,
     profile: &'a mut Profile,
     predictions: &'a PredictionStore,
+    llm: LlmConfig,
 }
 
 impl<'a, P: EmailProvider + ?Sized> LearningEngine<'a, P> {
@@ -44,18 +208,33 @@ impl<'a, P: EmailProvider + ?Sized> LearningEngine<'a, P> {
             provider,
             profile,
             predictions,
+            llm: LlmConfig::default(),
         }
     }
 
+    /// Models and timeouts for the learning prompts (defaults otherwise)
+    pub fn with_llm(mut self, llm: &LlmConfig) -> Self {
+        self.llm = llm.clone();
+        self
+    }
+
     pub async fn detect_corrections(&self) -> Result<LearningResult> {
-        let mut result = LearningResult::default();
+        let current_emails = fetch_current_emails(self.provider, self.predictions).await;
 
-        for prediction in self.predictions.all_predictions() {
-            let Some(email) = self
-                .load_current_email(prediction, &mut result.deleted_ids)
-                .await
-            else {
-                continue;
+        let mut result = LearningResult::default();
+        for (prediction, email) in current_emails {
+            // A failed fetch (timeout, rate limit, ...) says nothing about the
+            // email; only a gone one takes its prediction with it
+            let email = match email {
+                Ok(email) => email,
+                Err(error) if ProviderError::is_not_found(&error) => {
+                    result.deleted_ids.push(prediction.email_id.clone());
+                    continue;
+                }
+                Err(error) => {
+                    warn!(id = prediction.email_id, "couldn't fetch: {:#}", error);
+                    continue;
+                }
             };
 
             let actual_spam = email.labels.iter().any(|label| label == "SPAM");
@@ -73,22 +252,9 @@ impl<'a, P: EmailProvider + ?Sized> LearningEngine<'a, P> {
     }
 
     pub async fn apply_corrections(&mut self, corrections: &[Correction]) -> Result<()> {
-        if corrections.is_empty() {
-            return Ok(());
-        }
-
         let date = Utc::now().format("%Y-%m-%d").to_string();
-        for correction in corrections {
-            let description = describe_correction(&date, correction);
-            self.profile.append_correction(&description);
-        }
-
-        let update = self.get_batched_profile_update(corrections).await?;
-        if let Some(new_profile) = update {
-            self.profile.update(new_profile);
-        }
-
-        Ok(())
+        let backend = ClaudeBackend::new(&self.llm);
+        learn_corrections(self.profile, &date, corrections, &backend).await
     }
 
     pub async fn learn_from_action(
@@ -96,46 +262,22 @@ impl<'a, P: EmailProvider + ?Sized> LearningEngine<'a, P> {
         email_id: &str,
         action: &str,
         email: &Email,
-    ) -> Result<Option<String>> {
+    ) -> Result<Option<ProfilePatch>> {
         let prediction = self.predictions.get(email_id);
         let prompt = self.build_action_learning_prompt(action, prediction, email);
-        let response = run_claude_prompt(&prompt, Duration::from_secs(60), false)
-            .await
-            .context("Claude CLI timed out after 60s for action learning")?;
-        if response.contains("NO_UPDATE_NEEDED") {
-            return Ok(None);
-        }
-
-        Ok(extract_profile_update(&response))
-    }
-
-    async fn get_batched_profile_update(
-        &self,
-        corrections: &[Correction],
-    ) -> Result<Option<String>> {
-        let prompt = self.build_batched_profile_prompt(corrections);
-        let response = run_claude_prompt(&prompt, Duration::from_secs(90), true)
-            .await
-            .context("Claude CLI timed out after 90s for profile update")?;
+        let response = run_claude_prompt(
+            &prompt,
+            self.llm.learn_model(),
+            self.llm.timeout(ACTION_LEARNING_TIMEOUT),
+            true,
+        )
+        .await
+        .context("Action learning failed")?;
         if response.contains("NO_UPDATE_NEEDED") {
             return Ok(None);
         }
 
-        Ok(extract_profile_update(&response))
-    }
-
-    async fn load_current_email(
-        &self,
-        prediction: &Prediction,
-        deleted_ids: &mut Vec<String>,
-    ) -> Option<Email> {
-        match self.provider.get_message(&prediction.email_id).await {
-            Ok(email) => Some(email),
-            Err(_) => {
-                deleted_ids.push(prediction.email_id.clone());
-                None
-            }
-        }
+        Ok(extract_profile_patch(&response))
     }
 
     fn labels_changed(&self, prediction: &Prediction, email: &Email) -> bool {
@@ -159,7 +301,7 @@ impl<'a, P: EmailProvider + ?Sized> LearningEngine<'a, P> {
         prediction: Option<&Prediction>,
         email: &Email,
     ) -> String {
-        let body_preview: String = email.body.chars().take(500).collect();
+        let body_preview = config::body_preview(&email.body, BodyPreview::Learn);
         let prediction_summary = prediction
             .map(format_prediction_summary)
             .unwrap_or_else(|| "No previous prediction".to_string());
@@ -178,26 +320,132 @@ Body preview: {}
 Current profile:
 {}
 
-If this action reveals a new pattern that should be added to the profile, output the COMPLETE updated profile.md.
+If this action reveals a new pattern that should be added to the profile, {}
 If no update is needed (the profile already covers this case), respond with just: NO_UPDATE_NEEDED"#,
             action,
             prediction_summary,
             email.from,
             email.subject,
             body_preview,
-            self.profile.content()
+            self.profile.content(),
+            PATCH_INSTRUCTIONS
         )
     }
+}
+
+/// Record `corrections` in the profile and add the rules the backend proposes.
+/// The profile is left untouched if the backend fails, so a retry starts clean.
+pub async fn learn_corrections(
+    profile: &mut Profile,
+    date: &str,
+    corrections: &[Correction],
+    backend: &dyn LearningBackend,
+) -> Result<()> {
+    if corrections.is_empty() {
+        return Ok(());
+    }
 
-    fn build_batched_profile_prompt(&self, corrections: &[Correction]) -> String {
-        let corrections_text = corrections
+    let original = profile.content().to_string();
+    for group in group_by_sender(corrections) {
+        let description = describe_correction_group(date, &group);
+        profile.append_correction(&description);
+    }
+
+    let prompt = batched_profile_prompt(profile, corrections);
+    let response = match backend.respond(&prompt).await {
+        Ok(response) => response,
+        Err(error) => {
+            profile.update(original);
+            return Err(error);
+        }
+    };
+    if response.contains("NO_UPDATE_NEEDED") {
+        return Ok(());
+    }
+    if let Some(patch) = extract_profile_patch(&response) {
+        profile.apply_patch(&patch);
+    }
+
+    Ok(())
+}
+
+/// Rebuild a profile from scratch by replaying logged corrections in order,
+/// a day's corrections at a time, as `learn`/`scan` would have applied them
+pub async fn replay_corrections(
+    entries: &[LoggedCorrection],
+    backend: &dyn LearningBackend,
+) -> Result<Profile> {
+    let mut profile = Profile::default();
+    for day in entries.chunk_by(|a, b| a.date == b.date) {
+        let corrections = day
             .iter()
-            .map(format_correction_block)
-            .collect::<Vec<_>>()
-            .join("\n\n");
+            .map(|entry| entry.correction.clone())
+            .collect::<Vec<_>>();
+        for batch in corrections.chunks(CORRECTION_BATCH_SIZE) {
+            learn_corrections(&mut profile, &day[0].date, batch, backend).await?;
+        }
+    }
+    Ok(profile)
+}
 
-        format!(
-            r#"The user corrected these email classifications. Update the profile rules to prevent these mistakes.
+/// Ask the model to merge duplicate and contradictory lines of `profile`,
+/// returning the rewritten text, or None when it finds nothing to merge.
+/// Fails when the rewrite drops a section, so the caller never saves it.
+pub async fn compact_profile(
+    profile: &Profile,
+    backend: &dyn LearningBackend,
+) -> Result<Option<String>> {
+    let response = backend.respond(&compact_prompt(profile)).await?;
+    if response.contains("NO_UPDATE_NEEDED") {
+        return Ok(None);
+    }
+
+    let compacted = code_block(&response).unwrap_or(&response).trim();
+    if compacted.is_empty() {
+        anyhow::bail!("The model returned no profile");
+    }
+    let dropped = profile.dropped_sections(compacted);
+    if !dropped.is_empty() {
+        anyhow::bail!(
+            "The compacted profile drops {}; keeping the current one",
+            dropped.join(", ")
+        );
+    }
+    Ok(Some(format!("{}\n", compacted)))
+}
+
+fn compact_prompt(profile: &Profile) -> String {
+    format!(
+        r#"This email classification profile has grown one correction at a time. Rewrite it to remove duplicate lines and merge overlapping or contradictory rules, keeping every distinct rule and its meaning. When two rules contradict each other, keep the later one.
+
+Current profile:
+{}
+
+Keep every section header (the lines starting with ##) in the same order, and keep each section's lines under it. In ## Learned Corrections, merge entries about the same sender into one line that keeps the most recent date.
+
+Output the complete rewritten profile in a single code block, and nothing else.
+If nothing can be merged, respond with just: NO_UPDATE_NEEDED"#,
+        profile.content()
+    )
+}
+
+/// How the learning prompts ask for additions instead of a full rewrite
+const PATCH_INSTRUCTIONS: &str = r#"output ONLY the new lines, each group under the existing section header it belongs to, e.g.:
+
+## Label Rules
+- Emails from billing@example.com are Finance
+
+You may add to: ## Spam Patterns, ## Important Signals, ## Label Rules. Do not repeat existing lines or output the rest of the profile."#;
+
+fn batched_profile_prompt(profile: &Profile, corrections: &[Correction]) -> String {
+    let corrections_text = group_by_sender(corrections)
+        .iter()
+        .map(|group| format_correction_group(group))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    format!(
+        r#"The user corrected these email classifications. Update the profile rules to prevent these mistakes.
 
 Corrections:
 {}
@@ -205,12 +453,91 @@ Corrections:
 Current profile:
 {}
 
-Output the COMPLETE updated profile.md with new rules/patterns added.
+For new rules/patterns, {}
 If no meaningful patterns can be extracted, respond with just: NO_UPDATE_NEEDED"#,
-            corrections_text,
-            self.profile.content()
-        )
+        corrections_text,
+        profile.content(),
+        PATCH_INSTRUCTIONS
+    )
+}
+
+/// Cross-check every stored prediction against the provider without learning
+/// from the differences. Consistent predictions are left out of the result.
+pub async fn verify_predictions<P: EmailProvider + ?Sized>(
+    provider: &P,
+    predictions: &PredictionStore,
+) -> Verification {
+    let mut verification = Verification::default();
+    for (prediction, email) in fetch_current_emails(provider, predictions).await {
+        let email = match email {
+            Ok(email) => Some(email),
+            Err(error) if ProviderError::is_not_found(&error) => None,
+            Err(error) => {
+                let failure = (prediction.email_id.clone(), format!("{:#}", error));
+                verification.failed.push(failure);
+                continue;
+            }
+        };
+        let drift = prediction_drift(prediction, email.as_ref());
+        verification.accuracy.record(prediction, &drift);
+        if drift.email_gone || !drift.missing_labels.is_empty() || !drift.extra_labels.is_empty() {
+            verification.drifts.push(drift);
+        }
+    }
+    verification
+        .drifts
+        .sort_by(|a, b| a.email_id.cmp(&b.email_id));
+    verification.failed.sort();
+    verification
+}
+
+async fn fetch_current_emails<'p, P: EmailProvider + ?Sized>(
+    provider: &P,
+    predictions: &'p PredictionStore,
+) -> Vec<(&'p Prediction, Result<Email>)> {
+    stream::iter(predictions.all_predictions())
+        .map(|prediction| async move {
+            let email = provider.get_message(&prediction.email_id).await;
+            (prediction, email)
+        })
+        .buffer_unordered(DETECTION_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await
+}
+
+fn prediction_drift(prediction: &Prediction, email: Option<&Email>) -> Drift {
+    let mut drift = Drift {
+        email_id: prediction.email_id.clone(),
+        subject: prediction.subject.clone(),
+        ..Default::default()
+    };
+    let Some(email) = email else {
+        drift.email_gone = true;
+        return drift;
+    };
+
+    let predicted_labels = prediction.all_labels();
+    drift.missing_labels = predicted_labels
+        .iter()
+        .filter(|label| label_was_removed(label, email))
+        .cloned()
+        .collect();
+    let is_stamped = email
+        .labels
+        .iter()
+        .any(|label| label.eq_ignore_ascii_case("Classified"));
+    if !is_stamped {
+        drift.missing_labels.push("Classified".to_string());
     }
+    drift.extra_labels = email
+        .labels
+        .iter()
+        .filter(|label| {
+            was_user_added_label(label, &predicted_labels, &prediction.pre_existing_labels)
+        })
+        .cloned()
+        .collect();
+    drift
 }
 
 pub fn is_system_label(label: &str) -> bool {
@@ -241,8 +568,8 @@ pub fn is_system_label(label: &str) -> bool {
 /// Users remove these after taking action - this is expected behavior.
 fn is_active_label(label: &str) -> bool {
     matches!(
-        label.to_lowercase().as_str(),
-        "needs-reply" | "important" | "urgent" | "awaiting-reply"
+        canonical_label(label).as_str(),
+        "Needs-Reply" | "Important" | "Urgent" | "Awaiting-Reply"
     )
 }
 
@@ -251,7 +578,7 @@ fn label_was_removed(predicted_label: &str, email: &Email) -> bool {
         && !email
             .labels
             .iter()
-            .any(|email_label| email_label.eq_ignore_ascii_case(predicted_label))
+            .any(|email_label| same_label(email_label, predicted_label))
 }
 
 fn was_user_added_label(
@@ -265,14 +592,14 @@ fn was_user_added_label(
 
     let predicted_label_present = predicted_labels
         .iter()
-        .any(|predicted_label| predicted_label.eq_ignore_ascii_case(email_label));
+        .any(|predicted_label| same_label(predicted_label, email_label));
     if predicted_label_present {
         return false;
     }
 
     !pre_existing_labels
         .iter()
-        .any(|pre_existing_label| pre_existing_label.eq_ignore_ascii_case(email_label))
+        .any(|pre_existing_label| same_label(pre_existing_label, email_label))
 }
 
 fn build_correction(prediction: &Prediction, email: &Email, actual_spam: bool) -> Correction {
@@ -287,8 +614,11 @@ fn build_correction(prediction: &Prediction, email: &Email, actual_spam: bool) -
     }
 }
 
-fn sorted_labels(mut labels: Vec<String>) -> Vec<String> {
+/// Canonical spellings, sorted, so casing drift never reads as a relabel
+fn sorted_labels(labels: Vec<String>) -> Vec<String> {
+    let mut labels: Vec<String> = labels.iter().map(|label| canonical_label(label)).collect();
     labels.sort();
+    labels.dedup();
     labels
 }
 
@@ -301,6 +631,101 @@ fn non_system_labels(email: &Email) -> Vec<String> {
         .collect()
 }
 
+/// What the user did, so one sender's spam and relabel fixes stay separate
+#[derive(Debug, PartialEq, Eq)]
+enum CorrectionKind {
+    MarkedSpam,
+    UnmarkedSpam,
+    Relabeled,
+}
+
+fn correction_kind(correction: &Correction) -> CorrectionKind {
+    match (correction.predicted_spam, correction.actual_spam) {
+        (false, true) => CorrectionKind::MarkedSpam,
+        (true, false) => CorrectionKind::UnmarkedSpam,
+        _ => CorrectionKind::Relabeled,
+    }
+}
+
+/// Corrections of the same kind from the same sender address, in order of
+/// first appearance
+fn group_by_sender(corrections: &[Correction]) -> Vec<Vec<&Correction>> {
+    let mut groups: Vec<(String, CorrectionKind, Vec<&Correction>)> = Vec::new();
+    for correction in corrections {
+        let sender = sender_address(&correction.from);
+        let kind = correction_kind(correction);
+        match groups
+            .iter_mut()
+            .find(|(existing, existing_kind, _)| *existing == sender && *existing_kind == kind)
+        {
+            Some((_, _, group)) => group.push(correction),
+            None => groups.push((sender, kind, vec![correction])),
+        }
+    }
+    groups.into_iter().map(|(_, _, group)| group).collect()
+}
+
+/// One profile line for a sender's corrections; a lone correction keeps
+/// the per-email wording
+fn describe_correction_group(date: &str, group: &[&Correction]) -> String {
+    let [first, ..] = group else {
+        return String::new();
+    };
+    if group.len() == 1 {
+        return describe_correction(date, first);
+    }
+
+    let sender = sender_address(&first.from);
+    let count = group.len();
+    match correction_kind(first) {
+        CorrectionKind::MarkedSpam => format!(
+            "{}: User marked {} emails from {} as spam (subjects: {})",
+            date,
+            count,
+            sender,
+            group_subjects(group)
+        ),
+        CorrectionKind::UnmarkedSpam => format!(
+            "{}: User unmarked {} emails from {} as spam (false positives, subjects: {})",
+            date,
+            count,
+            sender,
+            group_subjects(group)
+        ),
+        CorrectionKind::Relabeled => format!(
+            "{}: User relabeled {} emails from {} (predicted: {:?}, actual: {:?})",
+            date,
+            count,
+            sender,
+            label_union(group, |correction| &correction.predicted_labels),
+            label_union(group, |correction| &correction.actual_labels)
+        ),
+    }
+}
+
+fn group_subjects(group: &[&Correction]) -> String {
+    let mut subjects = group
+        .iter()
+        .take(GROUP_SUBJECTS_SHOWN)
+        .map(|correction| correction.subject.as_str())
+        .collect::<Vec<_>>()
+        .join("; ");
+    if group.len() > GROUP_SUBJECTS_SHOWN {
+        subjects.push_str("; ...");
+    }
+    subjects
+}
+
+fn label_union(group: &[&Correction], labels: fn(&Correction) -> &Vec<String>) -> Vec<String> {
+    let mut union = group
+        .iter()
+        .flat_map(|correction| labels(correction).iter().cloned())
+        .collect::<Vec<_>>();
+    union.sort();
+    union.dedup();
+    union
+}
+
 fn describe_correction(date: &str, correction: &Correction) -> String {
     if correction.predicted_spam != correction.actual_spam {
         return describe_spam_correction(date, correction);
@@ -334,6 +759,24 @@ fn format_prediction_summary(prediction: &Prediction) -> String {
     )
 }
 
+fn format_correction_group(group: &[&Correction]) -> String {
+    let [first, ..] = group else {
+        return String::new();
+    };
+    if group.len() == 1 {
+        return format_correction_block(first);
+    }
+
+    format!(
+        "- From: {} ({} emails)\n  Subjects: {}\n  Predicted: {:?}\n  Actual: {:?}",
+        sender_address(&first.from),
+        group.len(),
+        group_subjects(group),
+        label_union(group, |correction| &correction.predicted_labels),
+        label_union(group, |correction| &correction.actual_labels)
+    )
+}
+
 fn format_correction_block(correction: &Correction) -> String {
     format!(
         "- From: {}\n  Subject: {}\n  Predicted: {:?}\n  Actual: {:?}",
@@ -341,73 +784,18 @@ fn format_correction_block(correction: &Correction) -> String {
     )
 }
 
+/// Run a prompt and return the trimmed response. Without `require_success`,
+/// a failing CLI yields an empty response instead of an error.
 async fn run_claude_prompt(
     prompt: &str,
+    model: &str,
     timeout_duration: Duration,
-    use_stdin: bool,
+    require_success: bool,
 ) -> Result<String> {
-    let response = if use_stdin {
-        run_claude_prompt_via_stdin(prompt, timeout_duration).await?
-    } else {
-        run_claude_prompt_via_args(prompt, timeout_duration).await?
-    };
-
-    Ok(response.trim().to_string())
-}
-
-async fn run_claude_prompt_via_args(prompt: &str, timeout_duration: Duration) -> Result<String> {
-    let output = timeout(
-        timeout_duration,
-        Command::new("claude")
-            .args([
-                "-p",
-                prompt,
-                "--model",
-                CLAUDE_MODEL,
-                "--disallowedTools",
-                CLAUDE_BROWSER_TOOLS,
-                "--no-session-persistence",
-            ])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output(),
-    )
-    .await
-    .context("Failed to run claude CLI")??;
-
-    parse_claude_output(output, true)
-}
-
-async fn run_claude_prompt_via_stdin(prompt: &str, timeout_duration: Duration) -> Result<String> {
-    let prompt_file = std::env::temp_dir().join("email-assistant-profile-prompt.txt");
-    let _ = std::fs::write(&prompt_file, prompt);
-
-    let mut child = Command::new("claude")
-        .args([
-            "-p",
-            "-",
-            "--model",
-            CLAUDE_MODEL,
-            "--disallowedTools",
-            CLAUDE_BROWSER_TOOLS,
-            "--no-session-persistence",
-        ])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .context("Failed to spawn claude CLI")?;
-
-    if let Some(mut stdin) = child.stdin.take() {
-        use tokio::io::AsyncWriteExt;
-        stdin.write_all(prompt.as_bytes()).await?;
-    }
-
-    let output = timeout(timeout_duration, child.wait_with_output())
-        .await
-        .context("Failed to run claude CLI")??;
-
-    parse_claude_output(output, false)
+    let output = llm::output(prompt, model, timeout_duration).await?;
+    Ok(parse_claude_output(output, require_success)?
+        .trim()
+        .to_string())
 }
 
 fn parse_claude_output(output: std::process::Output, require_success: bool) -> Result<String> {
@@ -422,15 +810,13 @@ fn parse_claude_output(output: std::process::Output, require_success: bool) -> R
     Ok(String::from_utf8_lossy(&output.stdout).into_owned())
 }
 
-fn extract_profile_update(response: &str) -> Option<String> {
-    // Look for markdown code block
-    if let Some(start) = response.find("```markdown") {
-        if let Some(end) = response[start + 11..].find("```") {
-            return Some(response[start + 11..start + 11 + end].trim().to_string());
-        }
-    }
+/// The section additions in a response, from its code block if it has one
+fn extract_profile_patch(response: &str) -> Option<ProfilePatch> {
+    let patch = ProfilePatch::parse(code_block(response).unwrap_or(response));
+    (!patch.is_empty()).then_some(patch)
+}
 
-    // Look for generic code block
+fn code_block(response: &str) -> Option<&str> {
     if let Some(start) = response.find("```") {
         let content_start = start + 3;
         // Skip language identifier if present
@@ -439,19 +825,373 @@ fn extract_profile_update(response: &str) -> Option<String> {
             .map(|i| content_start + i + 1)
             .unwrap_or(content_start);
         if let Some(end) = response[actual_start..].find("```") {
-            return Some(
-                response[actual_start..actual_start + end]
-                    .trim()
-                    .to_string(),
-            );
+            return Some(response[actual_start..actual_start + end].trim());
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::Classification;
+    use crate::corrections::LoggedCorrection;
+    use crate::providers::mock::MockProvider;
+
+    fn make_email(id: &str, labels: &[&str]) -> Email {
+        Email {
+            id: id.to_string(),
+            from: "sender@example.com".to_string(),
+            subject: format!("Subject {}", id),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            ..Default::default()
         }
     }
 
-    // If response looks like a profile (starts with #), use it directly
-    let trimmed = response.trim();
-    if trimmed.starts_with("# Email Classification Profile") {
-        return Some(trimmed.to_string());
+    fn store_prediction(predictions: &mut PredictionStore, id: &str, theme: &str) {
+        let classification = Classification {
+            is_spam: false,
+            archive: false,
+            delete: false,
+            theme: vec![theme.to_string()],
+            action: vec![],
+            confidence: 0.9,
+            reason: None,
+            heuristic: false,
+        };
+        let email = Email {
+            id: id.to_string(),
+            from: "sender@example.com".to_string(),
+            subject: "Subject".to_string(),
+            ..Default::default()
+        };
+        predictions.store(&email, &classification, vec![]).unwrap();
     }
 
-    None
+    fn logged(date: &str, id: &str, actual: &str) -> LoggedCorrection {
+        LoggedCorrection {
+            date: date.to_string(),
+            correction: Correction {
+                email_id: id.to_string(),
+                from: format!("{}@example.com", id),
+                subject: format!("Subject {}", id),
+                predicted_labels: vec!["Work".to_string()],
+                actual_labels: vec![actual.to_string()],
+                predicted_spam: false,
+                actual_spam: false,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_corrections_from_one_sender_become_one_line() {
+        let correction = |from: &str, subject: &str, actual: &str| Correction {
+            email_id: subject.to_string(),
+            from: from.to_string(),
+            subject: subject.to_string(),
+            predicted_labels: vec!["Work".to_string()],
+            actual_labels: vec![actual.to_string()],
+            predicted_spam: false,
+            actual_spam: false,
+        };
+        let corrections = vec![
+            correction("Weekly Digest <digest@news.com>", "Issue 1", "Newsletters"),
+            correction("flights@air.com", "Boarding pass", "Travel"),
+            correction("digest@NEWS.com", "Issue 2", "Reading"),
+        ];
+        let mut profile = Profile::default();
+
+        learn_corrections(
+            &mut profile,
+            "2025-01-01",
+            &corrections,
+            &ReplayBackend::default(),
+        )
+        .await
+        .unwrap();
+
+        // Newest entries come first in the section
+        assert_eq!(
+            profile.learned_corrections(),
+            vec![
+                "2025-01-01: User relabeled email (from: flights@air.com, predicted: [\"Work\"], actual: [\"Travel\"])",
+                "2025-01-01: User relabeled 2 emails from digest@news.com (predicted: [\"Work\"], actual: [\"Newsletters\", \"Reading\"])",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_failed_profile_update_is_retried() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::{ExitStatus, Output};
+
+        /// Hands out recorded CLI runs the way `ClaudeBackend` reads them
+        struct CliRuns(Mutex<VecDeque<Output>>);
+
+        #[async_trait]
+        impl LearningBackend for CliRuns {
+            async fn respond(&self, _prompt: &str) -> Result<String> {
+                profile_update_response(self.0.lock().unwrap().pop_front().unwrap())
+            }
+        }
+
+        let run = |code: i32, stdout: &str| Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: b"overloaded".to_vec(),
+        };
+        let backend = CliRuns(Mutex::new(VecDeque::from([
+            run(1, ""),
+            run(
+                0,
+                "```markdown\n## Label Rules\n- flight@ mail is Travel\n```",
+            ),
+        ])));
+        let corrections = [logged("2025-01-02", "flight", "Travel").correction];
+        let mut profile = Profile::default();
+
+        let failed = learn_corrections(&mut profile, "2025-01-02", &corrections, &backend).await;
+        assert!(failed
+            .unwrap_err()
+            .to_string()
+            .contains("Profile update failed"));
+        assert_eq!(profile.content(), Profile::default().content());
+
+        learn_corrections(&mut profile, "2025-01-02", &corrections, &backend)
+            .await
+            .unwrap();
+        assert!(profile.content().contains("- flight@ mail is Travel"));
+    }
+
+    #[tokio::test]
+    async fn test_compaction_keeps_every_section() {
+        let profile = Profile::from_content(
+            "# Profile\n\n## Spam Patterns\n- casino\n- casino offers\n\n## Label Rules\n- x is Work\n",
+        );
+        let compacted = "```markdown\n# Profile\n\n## Spam Patterns\n- casino offers\n\n\
+                         ## Label Rules\n- x is Work\n```";
+        let backend = ReplayBackend::new(vec![compacted.to_string()]);
+        let result = compact_profile(&profile, &backend).await.unwrap().unwrap();
+        assert_eq!(
+            result,
+            "# Profile\n\n## Spam Patterns\n- casino offers\n\n## Label Rules\n- x is Work\n"
+        );
+
+        let dropping = "```\n# Profile\n\n## Spam Patterns\n- casino offers\n```";
+        let backend = ReplayBackend::new(vec![dropping.to_string()]);
+        let error = compact_profile(&profile, &backend).await.unwrap_err();
+        assert!(error.to_string().contains("drops ## Label Rules"));
+
+        let unchanged = compact_profile(&profile, &ReplayBackend::default()).await;
+        assert_eq!(unchanged.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_replay_is_deterministic() {
+        let log = vec![
+            logged("2025-01-01", "billing", "Finance"),
+            logged("2025-01-01", "invoice", "Finance"),
+            logged("2025-01-02", "flight", "Travel"),
+        ];
+        let patch = "```markdown\n## Label Rules\n- billing@ and invoice@ mail is Finance\n```";
+        let replay = || async {
+            let backend = ReplayBackend::new(vec![patch.to_string()]);
+            replay_corrections(&log, &backend).await.unwrap()
+        };
+
+        let first = replay().await;
+        let second = replay().await;
+
+        assert_eq!(first.content(), second.content());
+        assert!(first
+            .content()
+            .contains("- billing@ and invoice@ mail is Finance"));
+        assert!(first.content().contains(
+            "- 2025-01-02: User relabeled email (from: flight@example.com, predicted: [\"Work\"], actual: [\"Travel\"])"
+        ));
+        // Patches only add: the first day's corrections are still recorded
+        assert!(first
+            .content()
+            .contains("- 2025-01-01: User relabeled email"));
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_keeps_default_skeleton() {
+        let log = vec![
+            logged("2025-01-01", "billing", "Finance"),
+            logged("2025-01-02", "flight", "Travel"),
+            logged("2025-01-02", "hotel", "Travel"),
+        ];
+
+        let rebuilt = replay_corrections(&log, &ReplayBackend::default())
+            .await
+            .unwrap();
+
+        // Everything above the corrections is the default profile, untouched
+        let default = Profile::default();
+        let (skeleton, corrections) = rebuilt
+            .content()
+            .split_once("## Learned Corrections\n")
+            .unwrap();
+        assert!(default.content().starts_with(skeleton));
+        let recorded = corrections
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<_>>();
+        assert_eq!(recorded.len(), 3);
+        // Newest first, as learning would have left them
+        assert!(recorded[0].starts_with("- 2025-01-02: User relabeled email (from: hotel@"));
+        assert!(recorded[2].starts_with("- 2025-01-01: User relabeled email (from: billing@"));
+        assert!(default.dropped_sections(rebuilt.content()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_detect_corrections_with_matches_mismatches_and_deleted() {
+        let mut predictions = PredictionStore::default();
+        for index in 0..20 {
+            store_prediction(&mut predictions, &format!("match{}", index), "Work");
+        }
+        store_prediction(&mut predictions, "relabeled", "Work");
+        store_prediction(&mut predictions, "spammed", "Work");
+        store_prediction(&mut predictions, "deleted1", "Work");
+        store_prediction(&mut predictions, "deleted2", "Work");
+
+        let mut emails = (0..20)
+            .map(|index| make_email(&format!("match{}", index), &["INBOX", "Work", "Classified"]))
+            .collect::<Vec<_>>();
+        emails.push(make_email("relabeled", &["INBOX", "Finance", "Classified"]));
+        emails.push(make_email("spammed", &["SPAM", "Work", "Classified"]));
+        let provider = MockProvider::with_emails(emails);
+        let mut profile = Profile::from_content("# Email Classification Profile");
+        let engine = LearningEngine::new(&provider, &mut profile, &predictions);
+
+        let result = engine.detect_corrections().await.unwrap();
+
+        let mut corrected = result
+            .corrections
+            .iter()
+            .map(|correction| correction.email_id.as_str())
+            .collect::<Vec<_>>();
+        corrected.sort();
+        assert_eq!(corrected, vec!["relabeled", "spammed"]);
+
+        let mut deleted = result.deleted_ids.clone();
+        deleted.sort();
+        assert_eq!(deleted, vec!["deleted1", "deleted2"]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_fetches_are_not_deletions() {
+        let mut predictions = PredictionStore::default();
+        store_prediction(&mut predictions, "relabeled", "Work");
+        let provider = MockProvider::with_emails([make_email(
+            "relabeled",
+            &["INBOX", "Finance", "Classified"],
+        )])
+        .failing_on("get_message");
+        let mut profile = Profile::from_content("# Email Classification Profile");
+        let engine = LearningEngine::new(&provider, &mut profile, &predictions);
+
+        let result = engine.detect_corrections().await.unwrap();
+
+        assert!(result.corrections.is_empty());
+        assert!(result.deleted_ids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_casing_drift_is_not_a_correction() {
+        let mut predictions = PredictionStore::default();
+        store_prediction(&mut predictions, "a", "needs-reply");
+        store_prediction(&mut predictions, "b", "fyi");
+        let provider = MockProvider::with_emails([
+            make_email("a", &["INBOX", "Needs-Reply", "Classified"]),
+            make_email("b", &["INBOX", "FYI", "Classified"]),
+        ]);
+        let mut profile = Profile::from_content("# Email Classification Profile");
+        let engine = LearningEngine::new(&provider, &mut profile, &predictions);
+
+        let result = engine.detect_corrections().await.unwrap();
+
+        assert!(result.corrections.is_empty());
+        assert_eq!(
+            sorted_labels(vec![
+                "awaiting-reply".to_string(),
+                "Awaiting-Reply".to_string()
+            ]),
+            vec!["Awaiting-Reply"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_reports_induced_drift() {
+        let mut predictions = PredictionStore::default();
+        for id in ["consistent", "unstamped", "relabeled", "gone"] {
+            store_prediction(&mut predictions, id, "Work");
+        }
+        let provider = MockProvider::with_emails([
+            make_email("consistent", &["INBOX", "Work", "Classified"]),
+            make_email("unstamped", &["INBOX", "Work"]),
+            make_email("relabeled", &["INBOX", "Finance", "Classified"]),
+        ]);
+
+        let verification = verify_predictions(&provider, &predictions).await;
+
+        let summary = verification
+            .drifts
+            .iter()
+            .map(|drift| {
+                (
+                    drift.email_id.as_str(),
+                    drift.email_gone,
+                    drift.missing_labels.clone(),
+                    drift.extra_labels.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("gone", true, vec![], vec![]),
+                (
+                    "relabeled",
+                    false,
+                    vec!["Work".to_string()],
+                    vec!["Finance".to_string()]
+                ),
+                ("unstamped", false, vec!["Classified".to_string()], vec![]),
+            ]
+        );
+
+        let accuracy = &verification.accuracy;
+        assert_eq!(
+            (accuracy.checked, accuracy.unchanged, accuracy.gone),
+            (3, 2, 1)
+        );
+        let work = accuracy.by_label["Work"];
+        assert_eq!((work.kept, work.removed, work.added), (2, 1, 0));
+        assert_eq!(accuracy.by_label["Finance"].added, 1);
+        assert!(verification.failed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_verify_fetch_failure_is_not_a_deleted_email() {
+        let mut predictions = PredictionStore::default();
+        store_prediction(&mut predictions, "flaky", "Work");
+        let provider = MockProvider::with_emails([make_email("flaky", &["INBOX", "Work"])])
+            .failing_on("get_message");
+
+        let verification = verify_predictions(&provider, &predictions).await;
+
+        assert!(verification.drifts.is_empty());
+        assert_eq!(verification.accuracy.gone, 0);
+        assert_eq!(
+            verification.failed,
+            vec![(
+                "flaky".to_string(),
+                "simulated get_message failure".to_string()
+            )]
+        );
+    }
 }
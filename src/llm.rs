@@ -0,0 +1,336 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::process::{Output, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::timeout;
+use tracing::debug;
+
+const CLAUDE_BROWSER_TOOLS: &str = "mcp__browsermcp__browser_navigate,mcp__browsermcp__browser_click,mcp__browsermcp__browser_snapshot,mcp__browsermcp__browser_screenshot,mcp__browsermcp__browser_wait,mcp__browsermcp__browser_hover,mcp__browsermcp__browser_type,mcp__browsermcp__browser_select_option,mcp__browsermcp__browser_press_key,mcp__browsermcp__browser_go_back,mcp__browsermcp__browser_go_forward,mcp__browsermcp__browser_get_console_logs";
+
+/// LLM calls made by this process since the last `take_run_stats`
+static RUN_STATS: Mutex<LlmStats> = Mutex::new(LlmStats::ZERO);
+
+/// Call count and size of LLM traffic; tokens and cost when claude reports them
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LlmStats {
+    pub calls: u64,
+    pub input_chars: u64,
+    pub output_chars: u64,
+    pub elapsed_ms: u64,
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cost_usd: f64,
+    /// Classifications re-requested because the first answer wasn't valid JSON
+    #[serde(default)]
+    pub repairs: u64,
+}
+
+impl LlmStats {
+    const ZERO: Self = Self {
+        calls: 0,
+        input_chars: 0,
+        output_chars: 0,
+        elapsed_ms: 0,
+        input_tokens: 0,
+        output_tokens: 0,
+        cost_usd: 0.0,
+        repairs: 0,
+    };
+
+    pub fn add(&mut self, other: &LlmStats) {
+        self.calls += other.calls;
+        self.input_chars += other.input_chars;
+        self.output_chars += other.output_chars;
+        self.elapsed_ms += other.elapsed_ms;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cost_usd += other.cost_usd;
+        self.repairs += other.repairs;
+    }
+}
+
+/// The result event of a `--output-format json` response
+#[derive(Debug, Default, Deserialize)]
+struct ResultEvent {
+    #[serde(rename = "type", default)]
+    event_type: String,
+    result: Option<String>,
+    #[serde(default)]
+    usage: TokenUsage,
+    #[serde(default)]
+    total_cost_usd: f64,
+    /// Present when the call was made with `--json-schema`
+    structured_output: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct TokenUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
+
+/// Find the result event whether claude printed one object or an event array
+fn result_event(response: &str) -> Option<ResultEvent> {
+    if let Ok(event) = serde_json::from_str::<ResultEvent>(response) {
+        return Some(event);
+    }
+    serde_json::from_str::<Vec<ResultEvent>>(response)
+        .ok()?
+        .into_iter()
+        .rev()
+        .find(|event| event.event_type == "result")
+}
+
+/// Count one call towards this run's stats. `response` is claude's JSON
+/// output; usage is recorded when it has any, sizes are counted regardless.
+pub fn record_call(prompt: &str, response: &str, elapsed: Duration) {
+    let call = call_stats(prompt, response, elapsed);
+    RUN_STATS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .add(&call);
+}
+
+/// Count one repair re-prompt; the call itself goes through `record_call`
+pub fn record_repair() {
+    RUN_STATS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .repairs += 1;
+}
+
+fn call_stats(prompt: &str, response: &str, elapsed: Duration) -> LlmStats {
+    let event = result_event(response).unwrap_or_default();
+    let output_chars = match &event.result {
+        Some(result) => result.chars().count(),
+        None => response.chars().count(),
+    };
+    LlmStats {
+        calls: 1,
+        input_chars: prompt.chars().count() as u64,
+        output_chars: output_chars as u64,
+        elapsed_ms: elapsed.as_millis() as u64,
+        input_tokens: event.usage.input_tokens,
+        output_tokens: event.usage.output_tokens,
+        cost_usd: event.total_cost_usd,
+        repairs: 0,
+    }
+}
+
+/// LLM calls this run has made so far, without resetting the count
+pub fn run_calls() -> u64 {
+    RUN_STATS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .calls
+}
+
+/// This run's stats so far, resetting them for the next run (e.g. under `watch`)
+pub fn take_run_stats() -> LlmStats {
+    std::mem::take(
+        &mut *RUN_STATS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()),
+    )
+}
+
+/// `claude -p -` with browser tools disabled, reading the prompt from stdin
+fn claude_command(model: &str) -> Command {
+    let mut command = Command::new("claude");
+    command
+        .args([
+            "-p",
+            "-",
+            "--model",
+            model,
+            "--disallowedTools",
+            CLAUDE_BROWSER_TOOLS,
+            "--no-session-persistence",
+            "--output-format",
+            "json",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
+
+/// Run a prompt through the claude CLI and return its process output, with
+/// stdout reduced to the response text
+#[tracing::instrument(name = "llm", skip_all, fields(model = model, prompt_chars = prompt.len()))]
+pub async fn output(prompt: &str, model: &str, timeout_duration: Duration) -> Result<Output> {
+    let (mut output, response) =
+        run_command(claude_command(model), prompt, timeout_duration).await?;
+    if let Some(result) = result_event(&response).and_then(|event| event.result) {
+        output.stdout = result.into_bytes();
+    }
+    Ok(output)
+}
+
+/// The claude CLI can't constrain output to a schema: it rejected
+/// `--json-schema`, or answered without a structured result
+#[derive(Debug)]
+pub struct SchemaUnsupported(String);
+
+impl fmt::Display for SchemaUnsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "structured output unsupported: {}", self.0)
+    }
+}
+
+impl std::error::Error for SchemaUnsupported {}
+
+impl SchemaUnsupported {
+    /// Whether `error` is (or wraps) a `SchemaUnsupported`. Timeouts and
+    /// other CLI failures say nothing about schema support.
+    pub fn is(error: &anyhow::Error) -> bool {
+        error.downcast_ref::<SchemaUnsupported>().is_some()
+    }
+}
+
+/// Run a prompt with claude's output constrained to `schema`, returning the
+/// JSON value it produced. Fails with `SchemaUnsupported` when the CLI
+/// doesn't honor the schema.
+#[tracing::instrument(name = "llm", skip_all, fields(model = model, prompt_chars = prompt.len()))]
+pub async fn structured(
+    prompt: &str,
+    model: &str,
+    schema: &serde_json::Value,
+    timeout_duration: Duration,
+) -> Result<serde_json::Value> {
+    let mut command = claude_command(model);
+    command.args(["--json-schema", &schema.to_string()]);
+    let (output, response) = run_command(command, prompt, timeout_duration).await?;
+    if !output.status.success() {
+        return Err(structured_failure(&String::from_utf8_lossy(&output.stderr)));
+    }
+    structured_result(&response)
+}
+
+/// A CLI too old for `--json-schema` names the option it rejected
+fn structured_failure(stderr: &str) -> anyhow::Error {
+    let stderr = stderr.trim();
+    if stderr.contains("--json-schema") {
+        SchemaUnsupported(stderr.to_string()).into()
+    } else {
+        anyhow::anyhow!("claude CLI failed: {}", stderr)
+    }
+}
+
+fn structured_result(response: &str) -> Result<serde_json::Value> {
+    let event = result_event(response).context("claude response has no result event")?;
+    event.structured_output.ok_or_else(|| {
+        SchemaUnsupported("claude response has no structured output".to_string()).into()
+    })
+}
+
+/// Send the prompt on stdin and wait, returning the output and raw stdout
+async fn run_command(
+    mut command: Command,
+    prompt: &str,
+    timeout_duration: Duration,
+) -> Result<(Output, String)> {
+    // Keep the last prompt around for debugging
+    let prompt_file = std::env::temp_dir().join("email-assistant-prompt.txt");
+    let _ = std::fs::write(&prompt_file, prompt);
+
+    let mut child = command.spawn().context("Failed to spawn claude CLI")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(prompt.as_bytes()).await?;
+    }
+
+    let started = Instant::now();
+    let output = timeout(timeout_duration, child.wait_with_output())
+        .await
+        .with_context(|| format!("Claude CLI timed out after {}s", timeout_duration.as_secs()))?
+        .context("Failed to run claude CLI")?;
+
+    let response = String::from_utf8_lossy(&output.stdout).into_owned();
+    debug!(
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        response_chars = response.len(),
+        success = output.status.success(),
+        "claude CLI returned"
+    );
+    record_call(prompt, &response, started.elapsed());
+    Ok((output, response))
+}
+
+/// Run a prompt and return the trimmed response, failing if claude does
+pub async fn run(prompt: &str, model: &str, timeout_duration: Duration) -> Result<String> {
+    let output = output(prompt, model, timeout_duration).await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("claude CLI failed: {}", stderr);
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_stats_reads_usage_from_result_event() {
+        let response = r#"[{"type":"system"},{"type":"result","result":"Done.","usage":{"input_tokens":120,"output_tokens":8},"total_cost_usd":0.0025}]"#;
+        let stats = call_stats("Summarize", response, Duration::from_millis(1500));
+
+        assert_eq!(
+            stats,
+            LlmStats {
+                calls: 1,
+                input_chars: 9,
+                output_chars: 5,
+                elapsed_ms: 1500,
+                input_tokens: 120,
+                output_tokens: 8,
+                cost_usd: 0.0025,
+                repairs: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_structured_result_requires_structured_output() {
+        let response = r#"{"type":"result","result":"","structured_output":{"is_spam":true}}"#;
+        assert_eq!(
+            structured_result(response).unwrap(),
+            serde_json::json!({"is_spam": true})
+        );
+        let error = structured_result(r#"{"type":"result","result":"{}"}"#).unwrap_err();
+        assert!(SchemaUnsupported::is(&error));
+        assert!(!SchemaUnsupported::is(
+            &structured_result("not json").unwrap_err()
+        ));
+    }
+
+    #[test]
+    fn test_only_a_rejected_schema_option_is_unsupported() {
+        let rejected = structured_failure("error: unknown option '--json-schema'\n");
+        assert!(SchemaUnsupported::is(&rejected));
+
+        let overloaded = structured_failure("API Error: 529 Overloaded");
+        assert!(!SchemaUnsupported::is(&overloaded));
+        assert_eq!(
+            overloaded.to_string(),
+            "claude CLI failed: API Error: 529 Overloaded"
+        );
+    }
+
+    #[test]
+    fn test_call_stats_without_usage_counts_raw_output() {
+        let stats = call_stats("Hi", "plain text", Duration::ZERO);
+        assert_eq!(stats.output_chars, 10);
+        assert_eq!(stats.input_tokens, 0);
+    }
+}
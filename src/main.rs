@@ -1,15 +1,31 @@
+mod calendar;
 mod classifier;
 mod commands;
 mod config;
+mod contacts;
+mod corrections;
+mod filters;
+mod headers;
+mod heuristic;
 mod labels;
 mod learning;
+mod llm;
+mod notify;
+mod output;
+mod policy;
 mod predictions;
 mod profile;
+mod prompt;
 mod providers;
+mod quarantine;
 mod rules;
+mod sender_stats;
+mod spam_feedback;
+mod usage;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(name = "email-assistant")]
@@ -19,24 +35,57 @@ struct Cli {
     #[arg(long, global = true)]
     dry_run: bool,
 
-    /// Email provider to use (gmail, outlook, or outlook-web)
+    /// Log more to stderr: -v for progress, -vv for provider and LLM calls
+    /// with timings, -vvv for everything (RUST_LOG overrides)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Email provider to use (gmail, outlook, outlook-web, imap, jmap, or mock)
     #[arg(long, global = true)]
     provider: Option<String>,
 
+    /// Run scan, learn, or needs-reply once for every logged-in provider
+    #[arg(long, global = true, conflicts_with = "provider")]
+    all_providers: bool,
+
+    /// Truncate email bodies to this many characters in LLM prompts
+    #[arg(long, global = true)]
+    max_body_chars: Option<usize>,
+
+    /// How scan, labels, needs-reply, and stats print their results
+    #[arg(long, global = true, value_enum, default_value_t)]
+    output: output::Formatter,
+
+    /// Also load rules from this directory, after the ones in rules/
+    #[arg(long, global = true, value_name = "DIR")]
+    rules_dir: Option<std::path::PathBuf>,
+
+    /// Keep config, profile, and predictions here instead of the default
+    /// directory (or EMAIL_ASSISTANT_CONFIG_DIR)
+    #[arg(long, global = true, value_name = "DIR")]
+    config_dir: Option<std::path::PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
-#[derive(Subcommand)]
+#[derive(Clone, Subcommand)]
 enum Commands {
     /// Configure settings
     Config {
-        /// Set default provider (gmail, outlook, or outlook-web)
+        /// Set default provider (gmail, outlook, outlook-web, imap, or jmap)
         #[arg(long)]
         provider: Option<String>,
+        /// Prune predictions older than this many days (default 30)
+        #[arg(long)]
+        prediction_max_age_days: Option<u32>,
     },
     /// Authenticate with email provider (opens browser)
-    Login,
+    Login {
+        /// Sign in by entering a code on another device instead (for headless machines)
+        #[arg(long)]
+        device: bool,
+    },
     /// Scan and classify emails (learns from corrections first)
     Scan {
         /// Maximum number of emails to scan
@@ -45,9 +94,54 @@ enum Commands {
         /// Scan archived emails instead of inbox
         #[arg(long)]
         archived: bool,
+        /// Stop on the first per-email error instead of warning and continuing
+        #[arg(long)]
+        fail_fast: bool,
+        /// Process oldest emails first (useful when backfilling an archive)
+        #[arg(long)]
+        oldest_first: bool,
+        /// Print this fraction of emails in full (e.g. 0.1) to audit quality
+        #[arg(long, value_name = "R", value_parser = parse_fraction)]
+        spot_check: Option<f64>,
+        /// Classify from sender and subject only (cheap bulk triage; deletes need high confidence)
+        #[arg(long)]
+        subject_only: bool,
+        /// Show and classify every message of a thread instead of only the latest
+        #[arg(long)]
+        no_collapse: bool,
+        /// Only scan emails matching this search (Gmail syntax; Outlook supports
+        /// from:, subject:, is:unread, and is:read)
+        #[arg(long)]
+        query: Option<String>,
+        /// Print why the model classified each email, and keep the reason in predictions
+        #[arg(long)]
+        explain: bool,
+        /// Stop classifying after this many LLM calls (learning included); the
+        /// rest are left for the next run
+        #[arg(long, value_name = "N")]
+        max_llm_calls: Option<u64>,
+        /// Only classify unread emails, leaving read ones alone
+        #[arg(long)]
+        unread_only: bool,
+        /// Hold deletes back and confirm them all at the end of the scan
+        #[arg(long)]
+        confirm_deletes: bool,
+    },
+    /// Learn and scan repeatedly on an interval until Ctrl-C
+    Watch {
+        /// Seconds between scans
+        #[arg(long, default_value = "300", value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
+        /// Maximum number of emails per scan
+        #[arg(short = 'n', long, default_value = "50")]
+        max: u32,
     },
     /// List all known labels
+    #[command(args_conflicts_with_subcommands = true)]
     Labels {
+        /// Show how many emails carry each label, as counted by the provider
+        #[arg(long)]
+        counts: bool,
         #[command(subcommand)]
         action: Option<LabelsAction>,
     },
@@ -61,6 +155,32 @@ enum Commands {
         /// Email ID
         id: String,
     },
+    /// List recent spam/unspam decisions and senders the classifier keeps getting wrong
+    SpamReview {
+        /// Number of recent decisions to show
+        #[arg(short = 'n', long, default_value = "20")]
+        limit: usize,
+    },
+    /// Never auto-archive or delete mail from this address or domain
+    Protect {
+        /// Address (boss@example.com) or domain (example.com)
+        sender: String,
+    },
+    /// Remove an address or domain added with `protect`
+    Unprotect {
+        /// Address or domain
+        sender: String,
+    },
+    /// Skip mail from this sender during scan, without classifying it
+    Ignore {
+        /// Address (builds@ci.example.com), domain (github.com), or /regex/
+        sender: String,
+    },
+    /// Remove a sender added with `ignore`
+    Unignore {
+        /// Address, domain, or /regex/ as given to `ignore`
+        sender: String,
+    },
     /// Archive email (remove from inbox, keep in All Mail)
     Archive {
         /// Email ID
@@ -78,42 +198,438 @@ enum Commands {
         /// Label to add
         label: String,
     },
+    /// Remove label from email (triggers learning)
+    Unlabel {
+        /// Email ID
+        id: String,
+        /// Label to remove
+        label: String,
+    },
+    /// Classify one email and show the result without changing anything
+    Classify {
+        /// Email ID
+        id: String,
+        /// Also print the model's reason
+        #[arg(long)]
+        explain: bool,
+    },
+    /// List emails matching a search, optionally with the classification
+    /// each would get; nothing is labeled or stored
+    Search {
+        /// Search selecting the emails (Gmail syntax; Outlook supports from:,
+        /// subject:, is:unread, and is:read)
+        #[arg(long)]
+        query: String,
+        /// Maximum number of emails to list
+        #[arg(short = 'n', long, default_value = "20")]
+        max: u32,
+        /// Search archived emails instead of the inbox
+        #[arg(long)]
+        archived: bool,
+        /// Also show the classification the model suggests for each email
+        #[arg(long)]
+        classify: bool,
+    },
+    /// Classify one email again, replacing its labels and stored prediction
+    Reclassify {
+        /// Email ID
+        id: String,
+    },
+    /// Archive, label, or delete every inbox email matching a search
+    Bulk {
+        #[command(subcommand)]
+        action: BulkAction,
+    },
+    /// Archive duplicate deliveries in the inbox, keeping the newest copy
+    Dedupe {
+        /// Maximum number of inbox emails to check
+        #[arg(short = 'n', long, default_value = "200")]
+        max: u32,
+    },
     /// Detect and learn from user corrections
     Learn,
-    /// Show current classification profile
-    Profile,
+    /// Check stored predictions against the provider: drift and per-label accuracy
+    Verify {
+        /// Re-stamp missing labels and prune predictions for missing emails
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Show, edit, or reset the classification profile
+    Profile {
+        #[command(subcommand)]
+        action: Option<ProfileAction>,
+    },
+    /// Show, edit, or reset the classification prompt template
+    Prompt {
+        #[command(subcommand)]
+        action: Option<PromptAction>,
+    },
     /// Show emails that need a reply
-    NeedsReply,
+    NeedsReply {
+        #[command(subcommand)]
+        action: Option<NeedsReplyAction>,
+        /// Order of the listing
+        #[arg(long, value_enum, default_value_t)]
+        sort: predictions::NeedsReplySort,
+        /// List stored predictions without checking the provider (no unread
+        /// markers, and deleted emails may still show)
+        #[arg(long)]
+        fast: bool,
+        /// Only emails with this theme or one nested below it (e.g. "Finance")
+        #[arg(long)]
+        label: Option<String>,
+    },
     /// AI-generated inbox summary
-    Summary,
+    Summary {
+        /// Maximum number of inbox emails to analyze
+        #[arg(short = 'n', long, default_value = "100")]
+        max: u32,
+        #[arg(long, value_enum, default_value_t)]
+        format: commands::SummaryFormat,
+        /// Write to this file instead of stdout
+        #[arg(long, value_name = "FILE")]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Send a plain-text email
+    Send {
+        /// Recipient address
+        #[arg(long)]
+        to: String,
+        #[arg(long)]
+        subject: String,
+        #[arg(long)]
+        body: String,
+    },
+    /// Draft a reply with the LLM and save it to the provider's drafts
+    DraftReply {
+        /// Email ID
+        id: String,
+    },
+    /// List the rules in rules/ (and --rules-dir), or check them for mistakes
+    Rules {
+        #[command(subcommand)]
+        action: Option<RulesAction>,
+    },
+    /// Create/remove native Gmail filters for deterministic rules
+    SyncFilters,
+    /// Show which operations the active provider supports
+    Capabilities,
+    /// List known contacts, whose emails the classifier treats as from real people
+    Contacts {
+        #[command(subcommand)]
+        action: Option<ContactsAction>,
+    },
+    /// Review emails scan quarantined instead of deleting (delete_mode "quarantine")
+    Quarantine {
+        #[command(subcommand)]
+        action: Option<QuarantineAction>,
+    },
+    /// Summarize classification activity from stored predictions
+    Stats {
+        /// Output as JSON (same as --output json)
+        #[arg(long)]
+        json: bool,
+        /// Count nested themes ("Finance/Receipts") toward their parents too
+        #[arg(long)]
+        rollup: bool,
+    },
+    /// Check how often predictions at each confidence were corrected
+    Calibrate,
+    /// Show daily LLM call counts, sizes, tokens, and cost
+    Usage {
+        /// Number of days to show, including today
+        #[arg(long, default_value = "30")]
+        days: u32,
+    },
+    /// Export stored predictions (or learned corrections) for spreadsheets and scripts
+    Export {
+        #[arg(long, value_enum, default_value_t)]
+        format: commands::ExportFormat,
+        /// Write to this file instead of stdout
+        #[arg(long, value_name = "FILE")]
+        out: Option<std::path::PathBuf>,
+        /// Export the profile's Learned Corrections instead of predictions
+        #[arg(long)]
+        corrections: bool,
+    },
+    /// Replay the corrections log against a fresh profile and show the result
+    /// (the real profile and provider are not touched)
+    Simulate {
+        /// Replay this JSON Lines file instead of the corrections log
+        #[arg(long, value_name = "FILE")]
+        dataset: Option<std::path::PathBuf>,
+        /// Don't call the LLM; only record the corrections
+        #[arg(long)]
+        offline: bool,
+    },
+}
+
+/// Which emails `bulk` acts on
+#[derive(Clone, clap::Args)]
+struct BulkTarget {
+    /// Search selecting the emails (Gmail syntax; Outlook supports from:,
+    /// subject:, is:unread, and is:read)
+    #[arg(long)]
+    query: String,
+    /// Maximum number of emails to act on
+    #[arg(short = 'n', long, default_value = "100")]
+    max: u32,
+    /// Skip the confirmation prompt
+    #[arg(long)]
+    yes: bool,
+}
+
+#[derive(Clone, Subcommand)]
+enum BulkAction {
+    /// Archive matching emails
+    Archive {
+        #[command(flatten)]
+        target: BulkTarget,
+    },
+    /// Add a label to matching emails
+    Label {
+        label: String,
+        #[command(flatten)]
+        target: BulkTarget,
+    },
+    /// Move matching emails to trash
+    Delete {
+        #[command(flatten)]
+        target: BulkTarget,
+    },
 }
 
-#[derive(Subcommand)]
+#[derive(Clone, Subcommand)]
 enum LabelsAction {
     /// Remove labels with no emails
     Cleanup,
+    /// Move every email from one label to another and retire the first
+    Merge {
+        /// Label to retire
+        from: String,
+        /// Label to keep
+        into: String,
+    },
+    /// Suggest merges for labels that differ only by case or plural
+    Normalize,
+    /// Reapply label colors on the provider, or assign a new one
+    Recolor {
+        /// Labels to recolor (default: every tracked label)
+        labels: Vec<String>,
+        /// Palette color to use instead of each label's current one
+        /// (red, orange, yellow, green, teal, blue, purple, pink)
+        #[arg(long)]
+        color: Option<String>,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+enum ContactsAction {
+    /// Show the addresses in contacts.json
+    List,
+    /// Fetch the provider's contacts and add them to contacts.json
+    Sync,
+}
+
+#[derive(Clone, Subcommand)]
+enum QuarantineAction {
+    /// List quarantined emails and when each will be deleted
+    Review,
+    /// Move an email back to the inbox so it's never deleted
+    Rescue {
+        /// Email ID
+        id: String,
+    },
+    /// Delete a quarantined email now
+    Confirm {
+        /// Email ID
+        id: String,
+    },
+    /// Delete every quarantined email past its grace period
+    Purge,
+}
+
+#[derive(Clone, Subcommand)]
+enum NeedsReplyAction {
+    /// Drop a handled email from the needs-reply list
+    Done {
+        /// Email ID
+        #[arg(required_unless_present = "all")]
+        id: Option<String>,
+        /// Clear every email from the list
+        #[arg(long, conflicts_with = "id")]
+        all: bool,
+        /// Also remove the Needs-Reply label from the provider
+        #[arg(long)]
+        remove_label: bool,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+enum RulesAction {
+    /// Show each rule with its description and whether it's enabled
+    List,
+    /// Check that every rule file parses and uses known fields and actions
+    Validate,
+    /// Translate existing Gmail filters into rules/gmail-import.json
+    ImportGmail,
+}
+
+#[derive(Clone, Subcommand)]
+enum PromptAction {
+    /// Print the prompt template in use
+    Show,
+    /// Open prompt.tmpl in $EDITOR, creating it from the built-in prompt
+    Edit,
+    /// Remove prompt.tmpl and go back to the built-in prompt
+    Reset,
+}
+
+#[derive(Clone, Subcommand)]
+enum ProfileAction {
+    /// Show current classification profile
+    Show,
+    /// Open the profile in $EDITOR
+    Edit,
+    /// Restore the default profile
+    Reset {
+        /// Skip the confirmation prompt
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Have the model merge duplicate and contradictory rules, then save
+    /// the result after showing the diff
+    Compact {
+        /// Skip the confirmation prompt
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Rebuild the profile from the default by replaying the corrections log,
+    /// then save it after showing the diff
+    Rebuild {
+        /// Replay without the model, recording only the corrections
+        #[arg(long)]
+        offline: bool,
+        /// Skip the confirmation prompt
+        #[arg(long)]
+        yes: bool,
+    },
+    /// List profile backups, or restore one
+    Restore {
+        /// Backup number from the listing, or its file name
+        backup: Option<String>,
+    },
+    /// Show how confident stored classifications are, to help pick thresholds
+    Stats {
+        /// Also count predictions below this confidence
+        #[arg(long, default_value = "0.5", value_parser = parse_fraction)]
+        threshold: f64,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    init_logging(cli.verbose);
+    if let Some(dir) = cli.config_dir.clone() {
+        config::set_config_dir(dir);
+    }
     let cfg = config::Config::load()?;
     let dry_run = cli.dry_run;
     let provider = selected_provider(&cli, &cfg).to_string();
+    config::set_body_preview_limits(cli.max_body_chars, &cfg);
+    output::set_formatter(cli.output);
+    if let Some(dir) = cli.rules_dir.clone() {
+        config::set_extra_rules_dir(dir)?;
+    }
+    config::migrate_unscoped_stores(cfg.default_provider())?;
+    let all_providers = cli.all_providers;
     let command = cli.command;
     print_dry_run_notice(dry_run);
+    if all_providers {
+        return run_for_all_providers(command, dry_run, &cfg).await;
+    }
+    config::set_prediction_scope(&provider);
     run_command(command, dry_run, &provider).await
 }
 
+/// Logs go to stderr so they never mix with command output on stdout
+fn init_logging(verbose: u8) {
+    // Dependencies stay at warn until -vvv
+    let level = match verbose {
+        0 => "warn",
+        1 => "warn,email_assistant=info",
+        2 => "warn,email_assistant=debug",
+        _ => "trace",
+    };
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(level));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .init();
+}
+
+/// Run `command` per provider; one provider failing doesn't stop the rest
+async fn run_for_all_providers(
+    command: Commands,
+    dry_run: bool,
+    cfg: &config::Config,
+) -> Result<()> {
+    if !matches!(
+        command,
+        Commands::Scan { .. } | Commands::Learn | Commands::NeedsReply { .. }
+    ) {
+        anyhow::bail!("--all-providers only works with scan, learn, and needs-reply");
+    }
+
+    let providers = commands::configured_providers(cfg);
+    if providers.is_empty() {
+        anyhow::bail!("No provider is logged in. Run 'login' first");
+    }
+
+    let mut failed = Vec::new();
+    for provider in providers {
+        println!("\n=== {} ===", provider);
+        config::set_prediction_scope(provider);
+        if let Err(error) = run_command(command.clone(), dry_run, provider).await {
+            tracing::error!(provider, "{:#}", error);
+            failed.push(provider);
+        }
+    }
+
+    if !failed.is_empty() {
+        anyhow::bail!("failed for: {}", failed.join(", "));
+    }
+    Ok(())
+}
+
 fn selected_provider<'a>(cli: &'a Cli, cfg: &'a config::Config) -> &'a str {
     cli.provider
         .as_deref()
         .unwrap_or_else(|| cfg.default_provider())
 }
 
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    let fraction: f64 = value
+        .parse()
+        .map_err(|_| format!("not a number: {}", value))?;
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err("must be between 0 and 1".to_string());
+    }
+    Ok(fraction)
+}
+
+/// On stderr when --output is table or json, so the records stay parseable
 fn print_dry_run_notice(dry_run: bool) {
-    if dry_run {
+    if !dry_run {
+        return;
+    }
+    if output::formatter().is_plain() {
         println!("🔍 DRY RUN MODE - no changes will be made\n");
+    } else {
+        eprintln!("🔍 DRY RUN MODE - no changes will be made");
     }
 }
 
@@ -121,29 +637,204 @@ async fn run_command(command: Commands, dry_run: bool, provider: &str) -> Result
     match command {
         Commands::Config {
             provider: new_provider,
-        } => commands::config(new_provider).await,
-        Commands::Login => commands::login(provider).await,
-        Commands::Scan { max, archived } => commands::scan(max, dry_run, provider, archived).await,
-        Commands::Labels { action } => run_labels_command(action, dry_run, provider).await,
+            prediction_max_age_days,
+        } => commands::config(new_provider, prediction_max_age_days).await,
+        Commands::Login { device } => commands::login(provider, device).await,
+        Commands::Scan {
+            max,
+            archived,
+            fail_fast,
+            oldest_first,
+            spot_check,
+            subject_only,
+            no_collapse,
+            query,
+            explain,
+            max_llm_calls,
+            unread_only,
+            confirm_deletes,
+        } => {
+            let options = commands::ScanOptions {
+                max,
+                archived,
+                dry_run,
+                fail_fast,
+                oldest_first,
+                spot_check,
+                subject_only,
+                no_collapse,
+                query,
+                explain,
+                max_llm_calls,
+                unread_only,
+                confirm_deletes,
+            };
+            commands::scan(options, provider).await
+        }
+        Commands::Watch { interval, max } => {
+            let options = commands::ScanOptions {
+                max,
+                archived: false,
+                dry_run,
+                fail_fast: false,
+                oldest_first: false,
+                spot_check: None,
+                subject_only: false,
+                no_collapse: false,
+                query: None,
+                explain: false,
+                max_llm_calls: None,
+                unread_only: false,
+                confirm_deletes: false,
+            };
+            commands::watch(Duration::from_secs(interval), options, provider).await
+        }
+        Commands::Labels { counts, action } => {
+            run_labels_command(action, counts, dry_run, provider).await
+        }
         Commands::Spam { id } => commands::spam(&id, dry_run, provider).await,
         Commands::Unspam { id } => commands::unspam(&id, dry_run, provider).await,
+        Commands::SpamReview { limit } => commands::spam_review(limit).await,
+        Commands::Protect { sender } => commands::protect(&sender).await,
+        Commands::Unprotect { sender } => commands::unprotect(&sender).await,
+        Commands::Ignore { sender } => commands::ignore(&sender).await,
+        Commands::Unignore { sender } => commands::unignore(&sender).await,
         Commands::Archive { id } => commands::archive(&id, dry_run, provider).await,
         Commands::Delete { id } => commands::delete(&id, dry_run, provider).await,
         Commands::Label { id, label } => commands::label(&id, &label, dry_run, provider).await,
+        Commands::Unlabel { id, label } => commands::unlabel(&id, &label, dry_run, provider).await,
+        Commands::Classify { id, explain } => commands::classify(&id, explain, provider).await,
+        Commands::Search {
+            query,
+            max,
+            archived,
+            classify,
+        } => commands::search(&query, max, archived, classify, provider).await,
+        Commands::Reclassify { id } => commands::reclassify(&id, dry_run, provider).await,
+        Commands::Bulk { action } => run_bulk_command(action, dry_run, provider).await,
+        Commands::Dedupe { max } => commands::dedupe(max, dry_run, provider).await,
         Commands::Learn => commands::learn(dry_run, provider).await,
-        Commands::Profile => commands::profile().await,
-        Commands::NeedsReply => commands::needs_reply(provider).await,
-        Commands::Summary => commands::summary(provider).await,
+        Commands::Verify { fix } => commands::verify(fix, dry_run, provider).await,
+        Commands::Profile { action } => run_profile_command(action, dry_run).await,
+        Commands::Prompt { action } => match action {
+            Some(PromptAction::Show) | None => commands::prompt_show().await,
+            Some(PromptAction::Edit) => commands::prompt_edit().await,
+            Some(PromptAction::Reset) => commands::prompt_reset(dry_run).await,
+        },
+        Commands::NeedsReply {
+            action,
+            sort,
+            fast,
+            label,
+        } => run_needs_reply_command(action, sort, fast, label, dry_run, provider).await,
+        Commands::Summary { max, format, out } => {
+            commands::summary(max, format, out.as_deref(), provider).await
+        }
+        Commands::Send { to, subject, body } => {
+            commands::send(&to, &subject, &body, dry_run, provider).await
+        }
+        Commands::DraftReply { id } => commands::draft_reply(&id, dry_run, provider).await,
+        Commands::Rules { action } => match action {
+            Some(RulesAction::List) | None => commands::rules_list().await,
+            Some(RulesAction::Validate) => commands::rules_validate().await,
+            Some(RulesAction::ImportGmail) => commands::rules_import_gmail(dry_run, provider).await,
+        },
+        Commands::SyncFilters => commands::sync_filters(dry_run, provider).await,
+        Commands::Capabilities => commands::capabilities(provider).await,
+        Commands::Contacts { action } => match action {
+            Some(ContactsAction::Sync) => commands::contacts_sync(dry_run, provider).await,
+            Some(ContactsAction::List) | None => commands::contacts_list().await,
+        },
+        Commands::Quarantine { action } => match action {
+            Some(QuarantineAction::Rescue { id }) => {
+                commands::quarantine_rescue(&id, dry_run, provider).await
+            }
+            Some(QuarantineAction::Confirm { id }) => {
+                commands::quarantine_confirm(&id, dry_run, provider).await
+            }
+            Some(QuarantineAction::Purge) => commands::quarantine_purge(dry_run, provider).await,
+            Some(QuarantineAction::Review) | None => commands::quarantine_review().await,
+        },
+        Commands::Stats { json, rollup } => commands::stats(json, rollup).await,
+        Commands::Calibrate => commands::calibrate(provider).await,
+        Commands::Usage { days } => commands::usage(days).await,
+        Commands::Export {
+            format,
+            out,
+            corrections,
+        } => commands::export(format, out.as_deref(), corrections).await,
+        Commands::Simulate { dataset, offline } => {
+            commands::simulate(dataset.as_deref(), offline).await
+        }
+    }
+}
+
+async fn run_needs_reply_command(
+    action: Option<NeedsReplyAction>,
+    sort: predictions::NeedsReplySort,
+    fast: bool,
+    label: Option<String>,
+    dry_run: bool,
+    provider: &str,
+) -> Result<()> {
+    match action {
+        Some(NeedsReplyAction::Done {
+            id,
+            all: _,
+            remove_label,
+        }) => commands::needs_reply_done(id.as_deref(), remove_label, dry_run, provider).await,
+        None => commands::needs_reply(sort, fast, label.as_deref(), provider).await,
     }
 }
 
+async fn run_bulk_command(action: BulkAction, dry_run: bool, provider: &str) -> Result<()> {
+    let (operation, target) = match action {
+        BulkAction::Archive { target } => (commands::BulkOperation::Archive, target),
+        BulkAction::Label { label, target } => (commands::BulkOperation::Label(label), target),
+        BulkAction::Delete { target } => (commands::BulkOperation::Delete, target),
+    };
+    commands::bulk(
+        operation,
+        &target.query,
+        target.max,
+        target.yes,
+        dry_run,
+        provider,
+    )
+    .await
+}
+
 async fn run_labels_command(
     action: Option<LabelsAction>,
+    counts: bool,
     dry_run: bool,
     provider: &str,
 ) -> Result<()> {
     match action {
         Some(LabelsAction::Cleanup) => commands::labels_cleanup(dry_run, provider).await,
-        None => commands::labels_list(provider).await,
+        Some(LabelsAction::Merge { from, into }) => {
+            commands::labels_merge(&from, &into, dry_run, provider).await
+        }
+        Some(LabelsAction::Normalize) => commands::labels_normalize(provider).await,
+        Some(LabelsAction::Recolor { labels, color }) => {
+            commands::labels_recolor(&labels, color.as_deref(), dry_run, provider).await
+        }
+        None => commands::labels_list(counts, dry_run, provider).await,
+    }
+}
+
+async fn run_profile_command(action: Option<ProfileAction>, dry_run: bool) -> Result<()> {
+    match action {
+        Some(ProfileAction::Show) | None => commands::profile().await,
+        Some(ProfileAction::Edit) => commands::profile_edit().await,
+        Some(ProfileAction::Reset { yes }) => commands::profile_reset(yes, dry_run).await,
+        Some(ProfileAction::Compact { yes }) => commands::profile_compact(yes, dry_run).await,
+        Some(ProfileAction::Rebuild { offline, yes }) => {
+            commands::profile_rebuild(offline, yes, dry_run).await
+        }
+        Some(ProfileAction::Restore { backup }) => {
+            commands::profile_restore(backup.as_deref(), dry_run).await
+        }
+        Some(ProfileAction::Stats { threshold }) => commands::profile_stats(threshold as f32).await,
     }
 }
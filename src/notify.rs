@@ -0,0 +1,221 @@
+use crate::config::NotificationConfig;
+use crate::providers::Email;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::io::Write;
+use tokio::process::Command;
+
+/// Emails with this label go to the default target when no route matches
+const DEFAULT_NOTIFY_LABEL: &str = "Urgent";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub id: String,
+    pub from: String,
+    pub subject: String,
+    pub labels: Vec<String>,
+}
+
+impl Notification {
+    fn text(&self) -> String {
+        format!(
+            "[{}] {} - {}",
+            self.labels.join(", "),
+            self.subject,
+            self.from
+        )
+    }
+}
+
+#[async_trait]
+pub trait NotificationSink: Send + Sync {
+    async fn send(&self, target: &str, notification: &Notification) -> Result<()>;
+}
+
+/// Delivers to webhook URLs (JSON POST via curl) and `file:` log targets
+pub struct TargetSink;
+
+#[async_trait]
+impl NotificationSink for TargetSink {
+    async fn send(&self, target: &str, notification: &Notification) -> Result<()> {
+        if let Some(path) = target.strip_prefix("file:") {
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open {}", path))?;
+            writeln!(file, "{}", notification.text())?;
+            return Ok(());
+        }
+
+        if !target.starts_with("http://") && !target.starts_with("https://") {
+            anyhow::bail!("Unknown notification target: {}", target);
+        }
+
+        let payload = serde_json::json!({
+            "text": notification.text(),
+            "email": notification,
+        });
+        let output = Command::new("curl")
+            .args(["-sS", "--fail", "-X", "POST"])
+            .args(["-H", "Content-Type: application/json"])
+            .args(["-d", &payload.to_string(), target])
+            .output()
+            .await
+            .context("Failed to run curl")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Webhook {} failed: {}", target, stderr.trim());
+        }
+        Ok(())
+    }
+}
+
+/// Routes classified emails to notification targets by label
+pub struct Notifier<S = TargetSink> {
+    config: NotificationConfig,
+    sink: S,
+}
+
+impl Notifier {
+    pub fn new(config: NotificationConfig) -> Self {
+        Self {
+            config,
+            sink: TargetSink,
+        }
+    }
+}
+
+impl<S: NotificationSink> Notifier<S> {
+    #[cfg(test)]
+    pub fn with_sink(config: NotificationConfig, sink: S) -> Self {
+        Self { config, sink }
+    }
+
+    /// Distinct targets for these labels, falling back to the default target
+    /// for unrouted urgent emails
+    pub fn targets_for(&self, labels: &[String]) -> Vec<&str> {
+        let mut targets: Vec<&str> = Vec::new();
+        for label in labels {
+            let routed = self
+                .config
+                .routes
+                .iter()
+                .find(|(route, _)| route.eq_ignore_ascii_case(label));
+            if let Some((_, target)) = routed {
+                if !targets.contains(&target.as_str()) {
+                    targets.push(target);
+                }
+            }
+        }
+
+        let is_urgent = labels
+            .iter()
+            .any(|label| label.eq_ignore_ascii_case(DEFAULT_NOTIFY_LABEL));
+        if targets.is_empty() && is_urgent {
+            targets.extend(self.config.default.as_deref());
+        }
+        targets
+    }
+
+    /// Send the email to every matching target. Delivery failures are
+    /// collected so one broken target doesn't block the others.
+    pub async fn notify(&self, email: &Email, labels: &[String]) -> Result<()> {
+        let notification = Notification {
+            id: email.id.clone(),
+            from: email.from.clone(),
+            subject: email.subject.clone(),
+            labels: labels.to_vec(),
+        };
+
+        let mut errors = Vec::new();
+        for target in self.targets_for(labels) {
+            if let Err(error) = self.sink.send(target, &notification).await {
+                errors.push(error.to_string());
+            }
+        }
+
+        if !errors.is_empty() {
+            anyhow::bail!("notification failed: {}", errors.join("; "));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        sent: Mutex<Vec<(String, String)>>,
+    }
+
+    #[async_trait]
+    impl NotificationSink for RecordingSink {
+        async fn send(&self, target: &str, notification: &Notification) -> Result<()> {
+            self.sent
+                .lock()
+                .unwrap()
+                .push((target.to_string(), notification.id.clone()));
+            Ok(())
+        }
+    }
+
+    fn make_config() -> NotificationConfig {
+        NotificationConfig {
+            default: Some("https://hooks.example.com/default".to_string()),
+            routes: [
+                ("Urgent", "https://push.example.com/phone"),
+                ("Finance", "file:/tmp/finance.log"),
+            ]
+            .iter()
+            .map(|(label, target)| (label.to_string(), target.to_string()))
+            .collect(),
+        }
+    }
+
+    fn labels(labels: &[&str]) -> Vec<String> {
+        labels.iter().map(|label| label.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_label_hits_mapped_target() {
+        let notifier = Notifier::with_sink(make_config(), RecordingSink::default());
+        let email = Email {
+            id: "1".to_string(),
+            ..Default::default()
+        };
+
+        notifier
+            .notify(&email, &labels(&["Finance", "Bills"]))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *notifier.sink.sent.lock().unwrap(),
+            vec![("file:/tmp/finance.log".to_string(), "1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_default_target_only_for_unrouted_urgent() {
+        let mut config = make_config();
+        let notifier = Notifier::with_sink(config.clone(), RecordingSink::default());
+        assert_eq!(
+            notifier.targets_for(&labels(&["urgent", "Finance"])),
+            vec!["https://push.example.com/phone", "file:/tmp/finance.log"]
+        );
+        assert!(notifier.targets_for(&labels(&["Travel"])).is_empty());
+
+        config.routes.remove("Urgent");
+        let notifier = Notifier::with_sink(config, RecordingSink::default());
+        assert_eq!(
+            notifier.targets_for(&labels(&["Urgent"])),
+            vec!["https://hooks.example.com/default"]
+        );
+    }
+}
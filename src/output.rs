@@ -0,0 +1,193 @@
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// Format picked with `--output`, set once at startup
+static FORMATTER: OnceLock<Formatter> = OnceLock::new();
+
+/// How list commands print their records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Formatter {
+    /// The human-readable lines each command has always printed
+    #[default]
+    Plain,
+    /// Aligned columns under a header row
+    Table,
+    /// One JSON object per line
+    Json,
+}
+
+pub fn set_formatter(formatter: Formatter) {
+    let _ = FORMATTER.set(formatter);
+}
+
+pub fn formatter() -> Formatter {
+    FORMATTER.get().copied().unwrap_or_default()
+}
+
+/// `println!` for progress and summary text around a command's records:
+/// stdout under plain output, stderr under table or JSON output so that
+/// stdout holds only the records
+macro_rules! note {
+    ($($arg:tt)*) => {
+        if $crate::output::formatter().is_plain() {
+            println!($($arg)*);
+        } else {
+            eprintln!($($arg)*);
+        }
+    };
+}
+pub(crate) use note;
+
+/// A record a command prints, one per email, label, or thread
+pub trait Renderable: Serialize {
+    /// Table header, one name per cell
+    const COLUMNS: &'static [&'static str];
+
+    /// Table cells, in `COLUMNS` order
+    fn cells(&self) -> Vec<String>;
+
+    /// The line plain output prints
+    fn plain(&self) -> String;
+}
+
+impl Formatter {
+    pub fn is_plain(self) -> bool {
+        self == Self::Plain
+    }
+
+    /// Print every record at once
+    pub fn print<R: Renderable>(self, records: &[R]) {
+        if let Some(text) = self.render(records) {
+            print!("{}", text);
+        }
+    }
+
+    /// The text for `records`, or None when there's nothing to print
+    pub fn render<R: Renderable>(self, records: &[R]) -> Option<String> {
+        if records.is_empty() {
+            return None;
+        }
+        let lines = match self {
+            Self::Plain => records.iter().map(Renderable::plain).collect(),
+            Self::Json => records.iter().map(json_line).collect(),
+            Self::Table => table_lines(R::COLUMNS, records),
+        };
+        Some(lines.into_iter().map(|line| line + "\n").collect())
+    }
+}
+
+/// Records printed as a command produces them. Plain and JSON records print
+/// right away; table records wait for `flush` so the columns line up.
+pub struct Rows<R> {
+    formatter: Formatter,
+    pending: Vec<R>,
+}
+
+impl<R: Renderable> Rows<R> {
+    pub fn new(formatter: Formatter) -> Self {
+        Self {
+            formatter,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, record: R) {
+        match self.formatter {
+            Formatter::Plain => println!("{}", record.plain()),
+            Formatter::Json => println!("{}", json_line(&record)),
+            Formatter::Table => self.pending.push(record),
+        }
+    }
+
+    pub fn flush(&mut self) {
+        self.formatter.print(&self.pending);
+        self.pending.clear();
+    }
+}
+
+fn json_line<R: Serialize>(record: &R) -> String {
+    serde_json::to_string(record).unwrap_or_else(|error| format!("{{\"error\":\"{}\"}}", error))
+}
+
+fn table_lines<R: Renderable>(columns: &[&str], records: &[R]) -> Vec<String> {
+    let header = columns.iter().map(|column| column.to_string()).collect();
+    let rows = std::iter::once(header)
+        .chain(records.iter().map(Renderable::cells))
+        .collect::<Vec<Vec<String>>>();
+    let mut widths = vec![0; columns.len()];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            let last = row.len().saturating_sub(1);
+            row.iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(i, (cell, &width))| {
+                    // The last column isn't padded, so lines have no trailing spaces
+                    if i == last {
+                        cell.clone()
+                    } else {
+                        format!("{:<width$}", cell, width = width)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("  ")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Row {
+        name: &'static str,
+        count: usize,
+    }
+
+    impl Renderable for Row {
+        const COLUMNS: &'static [&'static str] = &["NAME", "COUNT"];
+
+        fn cells(&self) -> Vec<String> {
+            vec![self.name.to_string(), self.count.to_string()]
+        }
+
+        fn plain(&self) -> String {
+            format!("  {} ({})", self.name, self.count)
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let rows = [
+            Row {
+                name: "Work",
+                count: 12,
+            },
+            Row {
+                name: "Newsletters",
+                count: 3,
+            },
+        ];
+
+        assert_eq!(
+            Formatter::Plain.render(&rows).unwrap(),
+            "  Work (12)\n  Newsletters (3)\n"
+        );
+        assert_eq!(
+            Formatter::Table.render(&rows).unwrap(),
+            "NAME         COUNT\nWork         12\nNewsletters  3\n"
+        );
+        assert_eq!(
+            Formatter::Json.render(&rows).unwrap(),
+            "{\"name\":\"Work\",\"count\":12}\n{\"name\":\"Newsletters\",\"count\":3}\n"
+        );
+        assert_eq!(Formatter::Table.render::<Row>(&[]), None);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::classifier::Classification;
+use crate::config::{Config, LabelPolicy};
+use crate::providers::sender_address;
+use anyhow::{Context, Result};
+use regex::Regex;
+
+/// Deterministic archive/delete decisions per label, applied over the model's
+/// booleans. When labels disagree, keeping wins: any matching `false` beats a
+/// `true` from another label.
+#[derive(Debug, Default)]
+pub struct PolicyTable {
+    policies: Vec<(String, LabelPolicy)>,
+}
+
+impl PolicyTable {
+    pub fn from_config(config: &Config) -> Self {
+        let policies = config
+            .label_policies
+            .iter()
+            .flatten()
+            .map(|(label, policy)| (label.clone(), policy.clone()))
+            .collect();
+        Self { policies }
+    }
+
+    pub fn apply(&self, classification: &mut Classification) {
+        let labels = classification.labels();
+        let matching = self
+            .policies
+            .iter()
+            .filter(|(label, _)| labels.iter().any(|l| l.eq_ignore_ascii_case(label)))
+            .map(|(_, policy)| policy)
+            .collect::<Vec<_>>();
+
+        if let Some(delete) = combine(matching.iter().map(|policy| policy.delete)) {
+            classification.delete = delete;
+        }
+        if let Some(archive) = combine(matching.iter().map(|policy| policy.archive)) {
+            classification.archive = archive;
+        }
+        if classification.delete {
+            classification.archive = false;
+        }
+    }
+}
+
+/// Senders whose mail is never auto-archived or deleted: exact addresses, or
+/// domains (which also cover their subdomains)
+#[derive(Debug, Default)]
+pub struct ProtectedSenders {
+    entries: Vec<String>,
+}
+
+impl ProtectedSenders {
+    pub fn from_config(config: &Config) -> Self {
+        let entries = config
+            .protected_senders
+            .iter()
+            .flatten()
+            .map(|entry| normalize_sender(entry))
+            .collect();
+        Self { entries }
+    }
+
+    pub fn matches(&self, from: &str) -> bool {
+        let address = sender_address(from);
+        self.entries
+            .iter()
+            .any(|entry| entry_matches(entry, &address))
+    }
+
+    pub fn apply(&self, from: &str, classification: &mut Classification) {
+        if self.matches(from) {
+            classification.archive = false;
+            classification.delete = false;
+        }
+    }
+}
+
+/// Senders whose mail scan skips entirely, before any LLM call. Entries are
+/// addresses or domains as for `ProtectedSenders`, or `/regex/` patterns
+/// matched case-insensitively against the address.
+#[derive(Debug, Default)]
+pub struct IgnoredSenders {
+    entries: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl IgnoredSenders {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut ignored = Self::default();
+        for entry in config.ignored_senders.iter().flatten() {
+            match regex_source(entry) {
+                Some(source) => ignored.patterns.push(
+                    Regex::new(&format!("(?i){}", source))
+                        .with_context(|| format!("Invalid ignored_senders pattern {}", entry))?,
+                ),
+                None => ignored.entries.push(normalize_sender(entry)),
+            }
+        }
+        Ok(ignored)
+    }
+
+    pub fn matches(&self, from: &str) -> bool {
+        let address = sender_address(from);
+        self.entries
+            .iter()
+            .any(|entry| entry_matches(entry, &address))
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| pattern.is_match(&address))
+    }
+}
+
+/// An exact address, or a domain that also covers its subdomains
+fn entry_matches(entry: &str, address: &str) -> bool {
+    if entry.contains('@') {
+        return entry == address;
+    }
+    let domain = address.rsplit_once('@').map_or("", |(_, domain)| domain);
+    domain == entry
+        || domain
+            .strip_suffix(entry)
+            .is_some_and(|sub| sub.ends_with('.'))
+}
+
+/// The pattern inside a `/.../` entry
+fn regex_source(entry: &str) -> Option<&str> {
+    entry
+        .trim()
+        .strip_prefix('/')?
+        .strip_suffix('/')
+        .filter(|source| !source.is_empty())
+}
+
+/// Lowercase, and turn "@example.com" into the domain "example.com".
+/// `/regex/` entries are only trimmed.
+pub fn normalize_sender(entry: &str) -> String {
+    if regex_source(entry).is_some() {
+        return entry.trim().to_string();
+    }
+    let entry = entry.trim().to_lowercase();
+    entry.strip_prefix('@').map(str::to_string).unwrap_or(entry)
+}
+
+/// The table's verdict for one flag, or None if no matching policy sets it
+fn combine(values: impl Iterator<Item = Option<bool>>) -> Option<bool> {
+    values.flatten().reduce(|a, b| a && b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn table(entries: &[(&str, Option<bool>, Option<bool>)]) -> PolicyTable {
+        let policies = entries
+            .iter()
+            .map(|(label, archive, delete)| {
+                (
+                    label.to_string(),
+                    LabelPolicy {
+                        archive: *archive,
+                        delete: *delete,
+                    },
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+        PolicyTable::from_config(&Config {
+            label_policies: Some(policies),
+            ..Default::default()
+        })
+    }
+
+    fn classified(labels: &[&str], archive: bool, delete: bool) -> Classification {
+        Classification {
+            is_spam: false,
+            archive,
+            delete,
+            theme: labels.iter().map(|label| label.to_string()).collect(),
+            action: vec![],
+            confidence: 0.9,
+            reason: None,
+            heuristic: false,
+        }
+    }
+
+    #[test]
+    fn test_policy_overrides_model() {
+        let policies = table(&[
+            ("Promotional", Some(true), None),
+            ("Social", None, Some(true)),
+        ]);
+
+        let mut promo = classified(&["promotional"], false, false);
+        policies.apply(&mut promo);
+        assert!(promo.archive && !promo.delete);
+
+        let mut social = classified(&["Social"], true, false);
+        policies.apply(&mut social);
+        assert!(social.delete && !social.archive);
+
+        let mut other = classified(&["Work"], true, false);
+        policies.apply(&mut other);
+        assert!(other.archive && !other.delete);
+    }
+
+    #[test]
+    fn test_protected_senders_address_vs_domain() {
+        let protected = ProtectedSenders::from_config(&Config {
+            protected_senders: Some(vec![
+                "Boss@Work.com".to_string(),
+                "@family.org".to_string(),
+                "bank.com".to_string(),
+            ]),
+            ..Default::default()
+        });
+
+        assert!(protected.matches("The Boss <boss@work.com>"));
+        assert!(!protected.matches("colleague@work.com"));
+        assert!(protected.matches("mom@family.org"));
+        assert!(protected.matches("alerts@secure.bank.com"));
+        assert!(!protected.matches("phish@notbank.com"));
+        assert!(!protected.matches("boss@work.com.evil.net"));
+
+        let mut classification = classified(&["Work"], true, true);
+        protected.apply("boss@work.com", &mut classification);
+        assert!(!classification.archive && !classification.delete);
+    }
+
+    #[test]
+    fn test_ignored_senders_addresses_domains_and_patterns() {
+        let ignored = IgnoredSenders::from_config(&Config {
+            ignored_senders: Some(vec![
+                "builds@ci.example.com".to_string(),
+                "github.com".to_string(),
+                "/^(no-?reply|bot)[+-]/".to_string(),
+            ]),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(ignored.matches("CI <Builds@ci.example.com>"));
+        assert!(!ignored.matches("people@ci.example.com"));
+        assert!(ignored.matches("notifications@github.com"));
+        assert!(ignored.matches("NoReply-alerts@shop.com"));
+        assert!(ignored.matches("bot+deploys@corp.net"));
+        assert!(!ignored.matches("robot@corp.net"));
+
+        let invalid = IgnoredSenders::from_config(&Config {
+            ignored_senders: Some(vec!["/(unclosed/".to_string()]),
+            ..Default::default()
+        });
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_keep_wins_when_labels_disagree() {
+        let policies = table(&[
+            ("Promotional", Some(true), Some(true)),
+            ("Security", Some(false), Some(false)),
+        ]);
+
+        let mut classification = classified(&["Promotional", "Security"], true, true);
+        policies.apply(&mut classification);
+
+        assert!(!classification.archive && !classification.delete);
+    }
+}
@@ -1,11 +1,16 @@
 use crate::classifier::Classification;
 use crate::config;
+use crate::labels::{is_within, label_with_ancestors};
+use crate::providers::Email;
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 
+const NEEDS_REPLY: &str = "Needs-Reply";
+pub const CONFIDENCE_BUCKETS: usize = 10;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Prediction {
     pub email_id: String,
@@ -28,6 +33,16 @@ pub struct Prediction {
     pub pre_existing_labels: Vec<String>,
     pub confidence: f32,
     pub timestamp: DateTime<Utc>,
+    /// When the email was received; missing for predictions stored before
+    /// this was tracked and for providers that don't report it
+    #[serde(default)]
+    pub received: Option<DateTime<Utc>>,
+    /// Conversation the email belongs to, for providers that report one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+    /// The model's explanation, kept only for `--explain` runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
 
 impl Prediction {
@@ -46,13 +61,130 @@ impl Prediction {
     }
 
     pub fn needs_reply(&self) -> bool {
-        self.action.iter().any(|a| a == "Needs-Reply")
+        self.action.iter().any(|a| a == NEEDS_REPLY)
+    }
+
+    /// Whether a theme is `label` or nested below it
+    pub fn has_theme_within(&self, label: &str) -> bool {
+        self.theme.iter().any(|theme| is_within(theme, label))
+    }
+
+    /// Theme labels plus every parent of a nested one, each once
+    fn rolled_up_themes(&self) -> BTreeSet<&str> {
+        self.theme
+            .iter()
+            .flat_map(|theme| label_with_ancestors(theme))
+            .collect()
+    }
+
+    /// Received date, falling back to when the email was classified
+    pub fn date(&self) -> DateTime<Utc> {
+        self.received.unwrap_or(self.timestamp)
     }
 }
 
+/// Ordering for the needs-reply listing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum NeedsReplySort {
+    /// Most recently received first
+    #[default]
+    Date,
+    /// Most confident first
+    Confidence,
+}
+
+/// Needs-reply emails of one conversation
+pub struct ReplyThread<'a> {
+    /// Newest first
+    pub messages: Vec<&'a Prediction>,
+}
+
+impl<'a> ReplyThread<'a> {
+    pub fn latest(&self) -> &'a Prediction {
+        self.messages[0]
+    }
+}
+
+/// Group predictions by thread ID, keeping threads in the order of their
+/// first listed message. A prediction without a thread ID is its own thread.
+pub fn group_reply_threads(predictions: Vec<&Prediction>) -> Vec<ReplyThread<'_>> {
+    let mut threads: Vec<ReplyThread> = Vec::new();
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    for prediction in predictions {
+        let position = prediction
+            .thread_id
+            .as_deref()
+            .and_then(|id| positions.get(id).copied());
+        match position {
+            Some(index) => threads[index].messages.push(prediction),
+            None => {
+                if let Some(id) = prediction.thread_id.as_deref() {
+                    positions.insert(id, threads.len());
+                }
+                threads.push(ReplyThread {
+                    messages: vec![prediction],
+                });
+            }
+        }
+    }
+    for thread in &mut threads {
+        thread
+            .messages
+            .sort_by_key(|prediction| std::cmp::Reverse(prediction.date()));
+    }
+    threads
+}
+
+fn confidence_bucket(confidence: f32) -> usize {
+    let bucket = (confidence * CONFIDENCE_BUCKETS as f32) as isize;
+    bucket.clamp(0, CONFIDENCE_BUCKETS as isize - 1) as usize
+}
+
+/// One confidence bucket of the `calibrate` report
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CalibrationBucket {
+    pub predictions: usize,
+    /// Predictions the user has since corrected
+    pub corrected: usize,
+    confidence_sum: f32,
+}
+
+impl CalibrationBucket {
+    pub fn average_confidence(&self) -> Option<f32> {
+        (self.predictions > 0).then(|| self.confidence_sum / self.predictions as f32)
+    }
+
+    /// Share of predictions left uncorrected, the accuracy the confidence claims
+    pub fn accuracy(&self) -> Option<f32> {
+        (self.predictions > 0).then(|| 1.0 - self.corrected as f32 / self.predictions as f32)
+    }
+}
+
+/// Aggregate view over the prediction store, for the `stats` command
+#[derive(Debug, Serialize, Default)]
+pub struct PredictionStats {
+    pub total: usize,
+    pub spam: usize,
+    pub needs_reply: usize,
+    pub average_confidence: f32,
+    pub oldest: Option<DateTime<Utc>>,
+    pub newest: Option<DateTime<Utc>>,
+    /// Received-date range of the classified emails
+    pub oldest_received: Option<DateTime<Utc>>,
+    pub newest_received: Option<DateTime<Utc>>,
+    /// Theme label counts, most frequent first. Rolled up, a nested label
+    /// also counts toward each of its parents.
+    pub themes: Vec<(String, usize)>,
+    /// Action label counts, most frequent first
+    pub actions: Vec<(String, usize)>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct PredictionStore {
     predictions: HashMap<String, Prediction>,
+    /// Store each classification's reason; off by default to keep the file small
+    #[serde(skip)]
+    keep_reasons: bool,
 }
 
 impl PredictionStore {
@@ -76,24 +208,26 @@ impl PredictionStore {
         Ok(())
     }
 
+    pub fn keep_reasons(&mut self, keep: bool) {
+        self.keep_reasons = keep;
+    }
+
     pub fn get(&self, email_id: &str) -> Option<&Prediction> {
         self.predictions.get(email_id)
     }
 
     pub fn store(
         &mut self,
-        email_id: &str,
-        from: &str,
-        subject: &str,
+        email: &Email,
         classification: &Classification,
         pre_existing_labels: Vec<String>,
     ) -> Result<()> {
         self.predictions.insert(
-            email_id.to_string(),
+            email.id.clone(),
             Prediction {
-                email_id: email_id.to_string(),
-                from: from.to_string(),
-                subject: subject.to_string(),
+                email_id: email.id.clone(),
+                from: email.from.clone(),
+                subject: email.subject.clone(),
                 is_spam: classification.is_spam,
                 theme: classification.theme.clone(),
                 action: classification.action.clone(),
@@ -101,6 +235,9 @@ impl PredictionStore {
                 pre_existing_labels,
                 confidence: classification.confidence,
                 timestamp: Utc::now(),
+                received: email.date,
+                thread_id: email.thread_id.clone(),
+                reason: classification.reason.clone().filter(|_| self.keep_reasons),
             },
         );
         Ok(())
@@ -110,7 +247,445 @@ impl PredictionStore {
         self.predictions.remove(email_id);
     }
 
+    /// Drop predictions older than `max_age`. Returns how many were removed.
+    pub fn prune(&mut self, max_age: Duration) -> usize {
+        let cutoff = Utc::now() - max_age;
+        let before = self.predictions.len();
+        self.predictions
+            .retain(|_, prediction| prediction.timestamp >= cutoff);
+        before - self.predictions.len()
+    }
+
     pub fn all_predictions(&self) -> impl Iterator<Item = &Prediction> {
         self.predictions.values()
     }
+
+    pub fn needs_reply(&self, sort: NeedsReplySort) -> Vec<&Prediction> {
+        let mut pending = self
+            .predictions
+            .values()
+            .filter(|prediction| prediction.needs_reply())
+            .collect::<Vec<_>>();
+        match sort {
+            NeedsReplySort::Date => {
+                pending.sort_by_key(|prediction| std::cmp::Reverse(prediction.date()))
+            }
+            NeedsReplySort::Confidence => {
+                pending.sort_by(|a, b| b.confidence.total_cmp(&a.confidence))
+            }
+        }
+        pending
+    }
+
+    /// The needs-reply emails in `email_id`'s thread, `email_id` first.
+    /// Just `email_id` when it has no thread ID or isn't stored.
+    pub fn needs_reply_thread(&self, email_id: &str) -> Vec<String> {
+        let mut ids = vec![email_id.to_string()];
+        let Some(thread_id) = self
+            .get(email_id)
+            .and_then(|prediction| prediction.thread_id.as_deref())
+        else {
+            return ids;
+        };
+        ids.extend(
+            self.predictions
+                .values()
+                .filter(|prediction| prediction.email_id != email_id && prediction.needs_reply())
+                .filter(|prediction| prediction.thread_id.as_deref() == Some(thread_id))
+                .map(|prediction| prediction.email_id.clone()),
+        );
+        ids
+    }
+
+    /// Drop the Needs-Reply action so the email leaves the needs-reply list.
+    /// When the provider label stays on the email, it's recorded as
+    /// pre-existing so learning doesn't mistake it for a user correction.
+    /// Returns false if there was nothing to dismiss.
+    pub fn dismiss_needs_reply(&mut self, email_id: &str, label_kept: bool) -> bool {
+        let Some(prediction) = self.predictions.get_mut(email_id) else {
+            return false;
+        };
+        if !prediction.needs_reply() {
+            return false;
+        }
+
+        prediction.action.retain(|action| action != NEEDS_REPLY);
+        if label_kept {
+            prediction.pre_existing_labels.push(NEEDS_REPLY.to_string());
+        }
+        true
+    }
+
+    /// Rewrite `from` to `into` in stored labels so a merge isn't later
+    /// mistaken for the user removing the prediction's label. Returns how
+    /// many predictions changed.
+    pub fn rename_label(&mut self, from: &str, into: &str) -> usize {
+        let mut renamed = 0;
+        for prediction in self.predictions.values_mut() {
+            let mut changed = false;
+            for labels in [
+                &mut prediction.theme,
+                &mut prediction.action,
+                &mut prediction.pre_existing_labels,
+            ] {
+                if labels.iter().any(|label| label == from) {
+                    labels.retain(|label| label != from && label != into);
+                    labels.push(into.to_string());
+                    changed = true;
+                }
+            }
+            renamed += usize::from(changed);
+        }
+        renamed
+    }
+
+    /// Predictions per confidence bucket of width 0.1, from [0.0, 0.1) up to
+    /// [0.9, 1.0]; out-of-range confidences land in the nearest end bucket
+    pub fn confidence_histogram(&self) -> [usize; CONFIDENCE_BUCKETS] {
+        let mut buckets = [0; CONFIDENCE_BUCKETS];
+        for prediction in self.predictions.values() {
+            buckets[confidence_bucket(prediction.confidence)] += 1;
+        }
+        buckets
+    }
+
+    /// Predictions and their corrections per confidence bucket (as in
+    /// `confidence_histogram`), leaving out emails that are gone
+    pub fn calibration(
+        &self,
+        corrected_ids: &[String],
+        gone_ids: &[String],
+    ) -> [CalibrationBucket; CONFIDENCE_BUCKETS] {
+        let mut buckets = [CalibrationBucket::default(); CONFIDENCE_BUCKETS];
+        for prediction in self.predictions.values() {
+            if gone_ids.contains(&prediction.email_id) {
+                continue;
+            }
+            let bucket = &mut buckets[confidence_bucket(prediction.confidence)];
+            bucket.predictions += 1;
+            bucket.confidence_sum += prediction.confidence;
+            if corrected_ids.contains(&prediction.email_id) {
+                bucket.corrected += 1;
+            }
+        }
+        buckets
+    }
+
+    /// How many predictions have a confidence under `threshold`
+    pub fn count_below(&self, threshold: f32) -> usize {
+        self.predictions
+            .values()
+            .filter(|prediction| prediction.confidence < threshold)
+            .count()
+    }
+
+    pub fn stats(&self, rollup: bool) -> PredictionStats {
+        let total = self.predictions.len();
+        if total == 0 {
+            return PredictionStats::default();
+        }
+
+        let predictions = || self.predictions.values();
+        let confidence_sum: f32 = predictions().map(|p| p.confidence).sum();
+
+        PredictionStats {
+            total,
+            spam: predictions().filter(|p| p.is_spam).count(),
+            needs_reply: predictions().filter(|p| p.needs_reply()).count(),
+            average_confidence: confidence_sum / total as f32,
+            oldest: predictions().map(|p| p.timestamp).min(),
+            newest: predictions().map(|p| p.timestamp).max(),
+            oldest_received: predictions().filter_map(|p| p.received).min(),
+            newest_received: predictions().filter_map(|p| p.received).max(),
+            themes: if rollup {
+                count_by_frequency(predictions().flat_map(|p| p.rolled_up_themes()))
+            } else {
+                count_by_frequency(predictions().flat_map(|p| p.theme.iter().map(String::as_str)))
+            },
+            actions: count_by_frequency(
+                predictions().flat_map(|p| p.action.iter().map(String::as_str)),
+            ),
+        }
+    }
+}
+
+fn count_by_frequency<'a>(labels: impl Iterator<Item = &'a str>) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for label in labels {
+        *counts.entry(label.to_string()).or_default() += 1;
+    }
+
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_prediction(email_id: &str, age: Duration) -> Prediction {
+        Prediction {
+            email_id: email_id.to_string(),
+            from: "sender@example.com".to_string(),
+            subject: "Test Subject".to_string(),
+            is_spam: false,
+            theme: vec!["Work".to_string()],
+            action: vec!["Needs-Reply".to_string()],
+            labels: vec![],
+            pre_existing_labels: vec![],
+            confidence: 0.9,
+            timestamp: Utc::now() - age,
+            received: None,
+            thread_id: None,
+            reason: None,
+        }
+    }
+
+    fn make_store(predictions: Vec<Prediction>) -> PredictionStore {
+        PredictionStore {
+            predictions: predictions
+                .into_iter()
+                .map(|prediction| (prediction.email_id.clone(), prediction))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_reason_stored_only_when_kept() {
+        let email = Email {
+            id: "1".to_string(),
+            ..Default::default()
+        };
+        let classification = Classification {
+            is_spam: false,
+            archive: true,
+            delete: false,
+            theme: vec!["Updates".to_string()],
+            action: vec![],
+            confidence: 0.8,
+            reason: Some("Routine account notice".to_string()),
+            heuristic: false,
+        };
+        let mut store = PredictionStore::default();
+
+        store.store(&email, &classification, vec![]).unwrap();
+        assert_eq!(store.get("1").unwrap().reason, None);
+        assert!(!serde_json::to_string(&store).unwrap().contains("reason"));
+
+        store.keep_reasons(true);
+        store.store(&email, &classification, vec![]).unwrap();
+        assert_eq!(
+            store.get("1").unwrap().reason.as_deref(),
+            Some("Routine account notice")
+        );
+    }
+
+    #[test]
+    fn test_prune_removes_only_stale_predictions() {
+        let mut store = make_store(vec![
+            make_prediction("old", Duration::days(45)),
+            make_prediction("recent", Duration::days(2)),
+        ]);
+
+        let pruned = store.prune(Duration::days(30));
+
+        assert_eq!(pruned, 1);
+        assert!(store.get("old").is_none());
+        assert!(store.get("recent").is_some());
+    }
+
+    #[test]
+    fn test_stats_counts_sorted_by_frequency() {
+        let mut spam = make_prediction("spam", Duration::days(1));
+        spam.is_spam = true;
+        spam.theme = vec!["Shopping".to_string()];
+        spam.action = vec![];
+        spam.confidence = 0.5;
+        let store = make_store(vec![
+            make_prediction("a", Duration::days(3)),
+            make_prediction("b", Duration::days(2)),
+            spam,
+        ]);
+
+        let stats = store.stats(false);
+
+        assert_eq!(stats.total, 3);
+        assert_eq!(stats.spam, 1);
+        assert_eq!(stats.needs_reply, 2);
+        assert!((stats.average_confidence - 0.766).abs() < 0.01);
+        assert_eq!(
+            stats.themes,
+            vec![("Work".to_string(), 2), ("Shopping".to_string(), 1)]
+        );
+        assert_eq!(stats.actions, vec![("Needs-Reply".to_string(), 2)]);
+        assert!(stats.oldest < stats.newest);
+    }
+
+    #[test]
+    fn test_stats_roll_up_nested_themes() {
+        let mut receipts = make_prediction("receipts", Duration::days(1));
+        receipts.theme = vec!["Finance/Receipts".to_string(), "Finance".to_string()];
+        let mut bills = make_prediction("bills", Duration::days(1));
+        bills.theme = vec!["Finance/Bills".to_string()];
+        let store = make_store(vec![receipts, bills]);
+
+        let flat = store.stats(false);
+        assert_eq!(flat.themes.len(), 3);
+        assert!(flat.themes.contains(&("Finance".to_string(), 1)));
+
+        let rolled_up = store.stats(true);
+        assert_eq!(rolled_up.themes[0], ("Finance".to_string(), 2));
+        assert!(rolled_up.themes.contains(&("Finance/Bills".to_string(), 1)));
+        assert!(store.get("bills").unwrap().has_theme_within("finance"));
+        assert!(!store
+            .get("bills")
+            .unwrap()
+            .has_theme_within("Finance/Receipts"));
+    }
+
+    #[test]
+    fn test_confidence_histogram_buckets() {
+        let predictions = [0.05, 0.1, 0.45, 0.95, 1.0, 1.2]
+            .into_iter()
+            .enumerate()
+            .map(|(i, confidence)| {
+                let mut prediction = make_prediction(&i.to_string(), Duration::days(1));
+                prediction.confidence = confidence;
+                prediction
+            })
+            .collect();
+        let store = make_store(predictions);
+
+        assert_eq!(store.confidence_histogram(), [1, 1, 0, 0, 1, 0, 0, 0, 0, 3]);
+        assert_eq!(store.count_below(0.5), 3);
+    }
+
+    #[test]
+    fn test_calibration_counts_corrections_per_bucket() {
+        let predictions = [
+            ("a", 0.92),
+            ("b", 0.97),
+            ("c", 0.95),
+            ("d", 0.35),
+            ("e", 0.9),
+        ]
+        .into_iter()
+        .map(|(id, confidence)| {
+            let mut prediction = make_prediction(id, Duration::days(1));
+            prediction.confidence = confidence;
+            prediction
+        })
+        .collect();
+        let store = make_store(predictions);
+
+        let buckets = store.calibration(&["b".to_string(), "d".to_string()], &["e".to_string()]);
+        let high = buckets[CONFIDENCE_BUCKETS - 1];
+        assert_eq!((high.predictions, high.corrected), (3, 1));
+        assert!((high.accuracy().unwrap() - 2.0 / 3.0).abs() < 1e-6);
+        assert!((high.average_confidence().unwrap() - 0.9466667).abs() < 1e-4);
+        assert_eq!(buckets[3].accuracy(), Some(0.0));
+        assert_eq!(buckets[0].accuracy(), None);
+    }
+
+    #[test]
+    fn test_needs_reply_grouped_by_thread() {
+        let in_thread = |id: &str, days: i64, thread: Option<&str>| {
+            let mut prediction = make_prediction(id, Duration::days(days));
+            prediction.subject = format!("Re: {}", id);
+            prediction.thread_id = thread.map(str::to_string);
+            prediction
+        };
+        let store = make_store(vec![
+            in_thread("first", 5, Some("t1")),
+            in_thread("alone", 3, None),
+            in_thread("reply", 1, Some("t1")),
+            in_thread("other", 4, Some("t2")),
+        ]);
+
+        let threads = group_reply_threads(store.needs_reply(NeedsReplySort::Date));
+
+        let latest = threads
+            .iter()
+            .map(|thread| (thread.latest().email_id.as_str(), thread.messages.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(latest, vec![("reply", 2), ("alone", 1), ("other", 1)]);
+        assert_eq!(threads[0].latest().subject, "Re: reply");
+        assert_eq!(store.needs_reply_thread("reply"), vec!["reply", "first"]);
+        assert_eq!(store.needs_reply_thread("alone"), vec!["alone"]);
+    }
+
+    #[test]
+    fn test_prune_keeps_needs_reply_within_window() {
+        let mut store = make_store(vec![make_prediction("reply", Duration::days(29))]);
+
+        store.prune(Duration::days(30));
+
+        assert_eq!(
+            store
+                .all_predictions()
+                .filter(|prediction| prediction.needs_reply())
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_dismiss_needs_reply() {
+        let mut store = make_store(vec![
+            make_prediction("kept", Duration::days(1)),
+            make_prediction("removed", Duration::days(2)),
+        ]);
+
+        assert!(store.dismiss_needs_reply("kept", true));
+        assert!(store.dismiss_needs_reply("removed", false));
+        assert!(!store.dismiss_needs_reply("kept", true));
+        assert!(!store.dismiss_needs_reply("missing", true));
+
+        assert!(store.needs_reply(NeedsReplySort::Date).is_empty());
+        assert_eq!(
+            store.get("kept").unwrap().pre_existing_labels,
+            vec![NEEDS_REPLY]
+        );
+        assert!(store.get("removed").unwrap().pre_existing_labels.is_empty());
+    }
+
+    #[test]
+    fn test_needs_reply_sort() {
+        let mut confident = make_prediction("confident", Duration::days(5));
+        confident.confidence = 0.99;
+        let store = make_store(vec![
+            make_prediction("recent", Duration::days(1)),
+            confident,
+        ]);
+
+        let ids = |sort| {
+            store
+                .needs_reply(sort)
+                .iter()
+                .map(|prediction| prediction.email_id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(NeedsReplySort::Date), vec!["recent", "confident"]);
+        assert_eq!(ids(NeedsReplySort::Confidence), vec!["confident", "recent"]);
+    }
+
+    #[test]
+    fn test_needs_reply_date_sort_uses_received_date() {
+        let mut backfilled = make_prediction("backfilled", Duration::zero());
+        backfilled.received = Some(Utc::now() - Duration::days(90));
+        let mut fresh = make_prediction("fresh", Duration::days(3));
+        fresh.received = Some(Utc::now() - Duration::days(3));
+        let store = make_store(vec![backfilled, fresh]);
+
+        let ids = store
+            .needs_reply(NeedsReplySort::Date)
+            .iter()
+            .map(|prediction| prediction.email_id.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(ids, vec!["fresh", "backfilled"]);
+        assert!(store.stats(false).oldest_received < store.stats(false).newest_received);
+    }
 }
@@ -1,6 +1,8 @@
-use crate::config;
-use anyhow::Result;
+use crate::config::{self, Config};
+use anyhow::{Context, Result};
+use std::fmt;
 use std::fs;
+use std::path::{Path, PathBuf};
 
 const DEFAULT_PROFILE: &str = r#"# Email Classification Profile
 
@@ -16,10 +18,70 @@ const DEFAULT_PROFILE: &str = r#"# Email Classification Profile
 ## Learned Corrections
 "#;
 
+/// Section headers the learning prompts and append_correction rely on
+const REQUIRED_SECTIONS: &[&str] = &[
+    "## Spam Patterns",
+    "## Important Signals",
+    "## Label Rules",
+    "## Learned Corrections",
+];
+
+/// Sections the LLM may add to; Learned Corrections is written only by the assistant
+const PATCHABLE_SECTIONS: &[&str] = &["## Spam Patterns", "## Important Signals", "## Label Rules"];
+
+/// Lines proposed for the profile, each under the section it belongs to
+#[derive(Debug, Default, PartialEq)]
+pub struct ProfilePatch {
+    additions: Vec<(String, String)>,
+}
+
+impl ProfilePatch {
+    /// Parse "## Section" headers, each followed by the lines to add under it.
+    /// Blank lines and anything before the first header are ignored.
+    pub fn parse(text: &str) -> Self {
+        let mut additions = Vec::new();
+        let mut section = None;
+        for line in text.lines().map(str::trim_end) {
+            if line.starts_with("## ") {
+                section = Some(line.trim().to_string());
+            } else if let Some(section) = section.as_ref().filter(|_| !line.trim().is_empty()) {
+                additions.push((section.clone(), line.to_string()));
+            }
+        }
+        Self { additions }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.additions.is_empty()
+    }
+}
+
+impl fmt::Display for ProfilePatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut current = None;
+        for (section, line) in &self.additions {
+            if current != Some(section) {
+                writeln!(f, "{}", section)?;
+                current = Some(section);
+            }
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
 pub struct Profile {
     content: String,
 }
 
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            content: DEFAULT_PROFILE.to_string(),
+        }
+    }
+}
+
 impl Profile {
     pub fn load() -> Result<Self> {
         let path = config::profile_path();
@@ -31,12 +93,23 @@ impl Profile {
         Ok(Self { content })
     }
 
+    #[cfg(test)]
+    pub fn from_content(content: &str) -> Self {
+        Self {
+            content: content.to_string(),
+        }
+    }
+
+    /// Write the profile, first copying the current file to profile-backups/
     pub fn save(&self) -> Result<()> {
         let dir = config::config_dir();
         if !dir.exists() {
             fs::create_dir_all(&dir)?;
         }
-        fs::write(config::profile_path(), &self.content)?;
+        let path = config::profile_path();
+        let keep = Config::load().unwrap_or_default().profile_backups();
+        back_up(&path, &self.content, keep).context("Failed to back up profile.md")?;
+        fs::write(path, &self.content)?;
         Ok(())
     }
 
@@ -48,6 +121,63 @@ impl Profile {
         self.content = new_content;
     }
 
+    pub fn reset(&mut self) {
+        self.content = DEFAULT_PROFILE.to_string();
+    }
+
+    /// Required section headers missing from the profile
+    pub fn missing_sections(&self) -> Vec<&'static str> {
+        missing_sections_in(&self.content)
+    }
+
+    /// Add `line` at the end of the `header` section (created if missing).
+    /// Returns false if the section already has that line.
+    pub fn append_to_section(&mut self, header: &str, line: &str) -> bool {
+        let mut lines = self.content.lines().collect::<Vec<_>>();
+        let Some(start) = lines.iter().position(|l| l.trim() == header) else {
+            if !self.content.is_empty() && !self.content.ends_with('\n') {
+                self.content.push('\n');
+            }
+            self.content.push_str(&format!("\n{}\n{}\n", header, line));
+            return true;
+        };
+
+        let end = lines[start + 1..]
+            .iter()
+            .position(|l| l.starts_with("## "))
+            .map_or(lines.len(), |i| start + 1 + i);
+        if lines[start + 1..end]
+            .iter()
+            .any(|l| l.trim() == line.trim())
+        {
+            return false;
+        }
+
+        let mut insert_at = end;
+        while insert_at > start + 1 && lines[insert_at - 1].trim().is_empty() {
+            insert_at -= 1;
+        }
+        lines.insert(insert_at, line);
+        self.content = lines.join("\n") + "\n";
+        true
+    }
+
+    /// Apply an LLM patch, skipping lines for sections it may not touch and
+    /// lines already present. Returns what was actually added.
+    pub fn apply_patch(&mut self, patch: &ProfilePatch) -> ProfilePatch {
+        let mut applied = ProfilePatch::default();
+        for (section, line) in &patch.additions {
+            if !PATCHABLE_SECTIONS.contains(&section.as_str()) {
+                tracing::warn!("ignoring profile addition under {}", section);
+                continue;
+            }
+            if self.append_to_section(section, line) {
+                applied.additions.push((section.clone(), line.clone()));
+            }
+        }
+        applied
+    }
+
     pub fn append_correction(&mut self, correction: &str) {
         // Find the "## Learned Corrections" section and append
         if let Some(idx) = self.content.find("## Learned Corrections") {
@@ -64,6 +194,25 @@ impl Profile {
         }
     }
 
+    /// Entries of the Learned Corrections section, without the "- " bullet
+    pub fn learned_corrections(&self) -> Vec<&str> {
+        self.section_lines("## Learned Corrections")
+            .filter_map(|line| line.strip_prefix("- "))
+            .collect()
+    }
+
+    /// Trimmed, non-empty lines under a `## ` section, including any
+    /// `### ` subsection headers
+    pub fn section_lines<'s>(&'s self, header: &'s str) -> impl Iterator<Item = &'s str> {
+        self.content
+            .lines()
+            .skip_while(move |line| line.trim() != header)
+            .skip(1)
+            .take_while(|line| !line.starts_with("## "))
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+    }
+
     pub fn remove_label_rules(&mut self, label: &str) {
         // Remove a label section from profile
         let section_header = format!("### {}", label);
@@ -79,4 +228,248 @@ impl Profile {
             self.content = format!("{}{}", &self.content[..start], &self.content[end..]);
         }
     }
+
+    /// `## ` sections of this profile that `rewritten` leaves out or empties,
+    /// so a model's rewrite can't silently drop rules wholesale
+    pub fn dropped_sections(&self, rewritten: &str) -> Vec<String> {
+        let before = section_line_counts(&self.content);
+        let after = section_line_counts(rewritten);
+        before
+            .into_iter()
+            .filter(
+                |(header, lines)| match after.iter().find(|(kept, _)| kept == header) {
+                    Some((_, kept_lines)) => *lines > 0 && *kept_lines == 0,
+                    None => true,
+                },
+            )
+            .map(|(header, _)| header)
+            .collect()
+    }
+}
+
+/// Each `## ` header with its number of non-empty lines
+fn section_line_counts(content: &str) -> Vec<(String, usize)> {
+    let mut sections: Vec<(String, usize)> = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.starts_with("## ") {
+            sections.push((line.to_string(), 0));
+        } else if let Some((_, lines)) = sections.last_mut().filter(|_| !line.is_empty()) {
+            *lines += 1;
+        }
+    }
+    sections
+}
+
+fn missing_sections_in(content: &str) -> Vec<&'static str> {
+    REQUIRED_SECTIONS
+        .iter()
+        .filter(|section| !content.lines().any(|line| line.trim() == **section))
+        .copied()
+        .collect()
+}
+
+/// Lines removed from `old` ("- ") and added in `new` ("+ "), in order.
+/// A plain LCS diff; profiles are small enough for the quadratic table.
+pub fn diff_lines(old: &str, new: &str) -> Vec<String> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            diff.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            diff.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+/// Copy the profile at `path` into the backup directory unless it already
+/// holds `new_content`, then drop all but the newest `keep` backups
+fn back_up(path: &Path, new_content: &str, keep: usize) -> Result<()> {
+    if keep == 0 || !path.exists() {
+        return Ok(());
+    }
+    let current = fs::read_to_string(path)?;
+    if current == new_content {
+        return Ok(());
+    }
+
+    let dir = config::profile_backups_dir();
+    fs::create_dir_all(&dir)?;
+    let name = format!(
+        "profile-{}.md",
+        chrono::Local::now().format("%Y%m%d-%H%M%S%.3f")
+    );
+    fs::write(dir.join(name), current)?;
+
+    let names = list_backups()?
+        .iter()
+        .filter_map(|path| path.file_name()?.to_str().map(str::to_string))
+        .collect();
+    for name in backups_to_prune(names, keep) {
+        fs::remove_file(dir.join(name))?;
+    }
+    Ok(())
+}
+
+/// Profile backups, newest first
+pub fn list_backups() -> Result<Vec<PathBuf>> {
+    let dir = config::profile_backups_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(is_backup_name)
+        })
+        .collect::<Vec<_>>();
+    backups.sort_by(|a, b| b.cmp(a));
+    Ok(backups)
+}
+
+fn is_backup_name(name: &str) -> bool {
+    name.starts_with("profile-") && name.ends_with(".md")
+}
+
+/// Backup names beyond the newest `keep`. Timestamped names sort by age.
+fn backups_to_prune(mut names: Vec<String>, keep: usize) -> Vec<String> {
+    names.retain(|name| is_backup_name(name));
+    names.sort_by(|a, b| b.cmp(a));
+    names.split_off(keep.min(names.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backups_to_prune_keeps_newest() {
+        let names = [
+            "profile-20260102-090000.000.md",
+            "notes.txt",
+            "profile-20260103-090000.000.md",
+            "profile-20260101-090000.000.md",
+        ]
+        .map(String::from)
+        .to_vec();
+
+        assert_eq!(
+            backups_to_prune(names.clone(), 2),
+            vec!["profile-20260101-090000.000.md"]
+        );
+        assert!(backups_to_prune(names.clone(), 10).is_empty());
+        assert_eq!(backups_to_prune(names, 0).len(), 3);
+    }
+
+    #[test]
+    fn test_default_profile_has_all_sections() {
+        let mut profile = Profile::from_content("");
+        profile.reset();
+        assert!(profile.missing_sections().is_empty());
+    }
+
+    #[test]
+    fn test_learned_corrections() {
+        let mut profile = Profile::default();
+        profile.append_correction("2025-01-02: User marked email as spam (from: a, subject: b)");
+        profile.append_correction("2025-01-01: User relabeled email");
+        assert_eq!(
+            profile.learned_corrections(),
+            vec![
+                "2025-01-01: User relabeled email",
+                "2025-01-02: User marked email as spam (from: a, subject: b)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let old = "# Profile\n- keep\n- drop\n- tail";
+        let new = "# Profile\n- keep\n- added\n- tail";
+        assert_eq!(diff_lines(old, new), vec!["- - drop", "+ - added"]);
+        assert!(diff_lines(old, old).is_empty());
+    }
+
+    #[test]
+    fn test_dropped_sections_detected() {
+        let profile = Profile::from_content(
+            "# Profile\n\n## Spam Patterns\n- a\n- a again\n\n## Important Signals\n\n\
+             ## Label Rules\n- b\n\n## Travel\n- c\n",
+        );
+        let compacted = "# Profile\n\n## Spam Patterns\n- a\n\n## Important Signals\n\n\
+             ## Label Rules\n";
+        assert_eq!(
+            profile.dropped_sections(compacted),
+            vec!["## Label Rules", "## Travel"]
+        );
+        assert!(profile.dropped_sections(profile.content()).is_empty());
+    }
+
+    #[test]
+    fn test_missing_sections_detected() {
+        let profile = Profile::from_content(
+            "# Email Classification Profile\n\n## Spam Patterns\n- foo\n\n## Label Rules\n",
+        );
+        assert_eq!(
+            profile.missing_sections(),
+            vec!["## Important Signals", "## Learned Corrections"]
+        );
+    }
+
+    #[test]
+    fn test_append_to_section_keeps_hand_written_rules() {
+        let mut profile = Profile::from_content(
+            "# Profile\n\n## Label Rules\n- my own rule\n\n## Learned Corrections\n- 2025-01-01: x\n",
+        );
+
+        assert!(profile.append_to_section("## Label Rules", "- billing@ is Finance"));
+        assert!(!profile.append_to_section("## Label Rules", "- my own rule"));
+        assert!(profile.append_to_section("## Spam Patterns", "- crypto giveaways"));
+
+        assert_eq!(
+            profile.content(),
+            "# Profile\n\n## Label Rules\n- my own rule\n- billing@ is Finance\n\n## Learned Corrections\n- 2025-01-01: x\n\n## Spam Patterns\n- crypto giveaways\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_patch_only_touches_patchable_sections() {
+        let patch = ProfilePatch::parse(
+            "Here is the patch:\n## Label Rules\n- flights are Travel\n\n## Learned Corrections\n- forged entry\n## Spam Patterns\n- lottery wins\n",
+        );
+        let mut profile = Profile::default();
+
+        let applied = profile.apply_patch(&patch);
+
+        assert_eq!(
+            applied.to_string(),
+            "## Label Rules\n- flights are Travel\n## Spam Patterns\n- lottery wins\n"
+        );
+        assert!(profile
+            .content()
+            .contains("## Label Rules\n- flights are Travel\n"));
+        assert!(profile.learned_corrections().is_empty());
+        assert!(profile.apply_patch(&patch).is_empty());
+    }
 }
@@ -0,0 +1,213 @@
+use crate::config;
+use anyhow::Result;
+use std::fs;
+
+/// Placeholders a classification template can use, filled per email
+pub const PLACEHOLDERS: &[&str] = &[
+    "profile",
+    "from",
+    "to",
+    "subject",
+    "list_id",
+    "known_contact",
+    "sender_history",
+    "header_signals",
+    "folder",
+    "language",
+    "label_language",
+    "body",
+];
+/// Placeholders without which the model can't classify anything useful
+const REQUIRED_PLACEHOLDERS: &[&str] = &["profile", "from", "subject", "body"];
+
+/// Built-in classification prompt, used when there is no prompt.tmpl
+pub const DEFAULT_TEMPLATE: &str = r#"You are an email classifier. Analyze this email and assign appropriate labels.
+
+<profile>
+{profile}
+</profile>
+
+<email>
+From: {from}
+To: {to}
+Subject: {subject}
+Mailing list: {list_id}
+Known contact: {known_contact}
+Sender history: {sender_history}
+Header signals: {header_signals}
+Folder: {folder}
+Language: {language}
+Body: {body}
+</email>
+
+A mailing list (List-Id) is a strong sign of a newsletter or bulk mail.
+
+The sender history says how this sender's earlier emails were labeled, after the user's corrections. Stay consistent with it unless this email is clearly different.
+
+Header signals note Reply-To or Return-Path domains that differ from the sender's and failed SPF/DKIM/DMARC checks. Bulk senders often use another Return-Path domain, but a mismatched Reply-To together with failed checks points to phishing.
+
+A known contact is a real person from the user's address book: lean toward Important or Needs-Reply when they ask or tell the user something, and never mark their email Promotional or delete it.
+
+An email whose folder isn't the inbox has already been filed away: set archive to false for it.
+
+The email may not be in English - read it in its detected language. Write theme labels in {label_language}; keep action labels exactly as listed below.
+
+Classify this email:
+- is_spam: true if malicious/scam/phishing/horoscope/astrology/psychic spam, false for legitimate newsletters
+- theme: 1-5 labels describing what email is about. Examples: "Receipts" (payment confirmations AFTER charge), "Bills" (upcoming payments, auto-renewal notices, subscription charges - archive if auto-pay), "Finance", "Health", "Shopping", "Travel", "Work", "Personal", "Social", "Security", "Gaming", "Shipping", "Updates", "Account", "Home" (smart home alerts, leak sensors, thermostat, security cameras). A theme may be a "Parent/Child" path such as "Finance/Receipts" when it narrows a broader theme
+- action: 0+ labels for what to do. Options:
+  - "Newsletters" - regular subscription content you signed up for
+  - "Promotional" - ads, sales, marketing, webinar invites from companies (auto-delete)
+  - "Survey" - feedback requests, satisfaction surveys, NPS scores (auto-archive)
+  - "Needs-Reply" - expects a response from you (questions, requests, invitations). Archive unless reply needed today/tomorrow
+  - "Important" - requires your attention today
+  - "Urgent" - time-sensitive, needs immediate attention (security alerts are always Urgent)
+  - "Awaiting-Reply" - you sent something and are waiting for response, no action needed now (auto-archive)
+  - "Group-Thread" - group thread/discussion where you're CC'd (auto-archive)
+  - "Other" - doesn't fit other categories (auto-archive)
+- archive: true if email doesn't need to stay in inbox (Newsletters, Survey, Awaiting-Reply, Group-Thread, Other, Updates, Needs-Reply without urgency, Bills without Needs-Reply, receipts under $500, account notifications without action required). NEVER archive Security emails
+- delete: true if is_spam OR Promotional OR expired calendar invites (date in the past) OR matches auto-delete rules in profile (including language rules). CHECK THE TO FIELD - if email is TO a work address listed in Auto-Delete Rules, set delete=true. NEVER delete Personal emails, Needs-Reply emails, or emails from personal contacts. "Personal" means from someone you know, NOT spam with your name in it
+
+- reason: one short sentence explaining the classification
+
+Respond with JSON only:
+{{"is_spam": false, "theme": ["Finance"], "action": ["Important"], "archive": false, "delete": false, "confidence": 0.8, "reason": "Invoice due Friday from a known vendor"}}"#;
+
+/// Classification prompt with `{name}` placeholders; `{{` and `}}` stand for
+/// literal braces, as in the JSON example the default template ends with
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    text: String,
+}
+
+impl Default for PromptTemplate {
+    fn default() -> Self {
+        Self {
+            text: DEFAULT_TEMPLATE.to_string(),
+        }
+    }
+}
+
+impl PromptTemplate {
+    /// prompt.tmpl from the config directory, or the built-in default
+    pub fn load() -> Result<Self> {
+        let path = config::prompt_template_path();
+        if path.exists() {
+            Ok(Self::new(fs::read_to_string(&path)?))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = config::config_dir();
+        if !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
+        fs::write(config::prompt_template_path(), &self.text)?;
+        Ok(())
+    }
+
+    pub fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into() }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Fill in the placeholders in one pass, so braces inside the values
+    /// (an email body quoting `{subject}`, say) are left alone. Unknown
+    /// placeholders are kept as written.
+    pub fn render(&self, values: &[(&str, &str)]) -> String {
+        let mut rendered = String::with_capacity(self.text.len());
+        let mut rest = self.text.as_str();
+        while let Some(brace) = rest.find(['{', '}']) {
+            rendered.push_str(&rest[..brace]);
+            rest = &rest[brace..];
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                rendered.push_str(&rest[..1]);
+                rest = &rest[2..];
+                continue;
+            }
+            let placeholder = rest
+                .strip_prefix('{')
+                .and_then(|after| Some(&after[..after.find('}')?]))
+                .and_then(|name| {
+                    let (_, value) = values.iter().find(|(key, _)| *key == name)?;
+                    Some((name.len() + 2, *value))
+                });
+            match placeholder {
+                Some((len, value)) => {
+                    rendered.push_str(value);
+                    rest = &rest[len..];
+                }
+                None => {
+                    rendered.push_str(&rest[..1]);
+                    rest = &rest[1..];
+                }
+            }
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+
+    /// Required placeholders the template never uses
+    pub fn missing_placeholders(&self) -> Vec<&'static str> {
+        REQUIRED_PLACEHOLDERS
+            .iter()
+            .copied()
+            .filter(|name| !self.text.contains(&format!("{{{}}}", name)))
+            .collect()
+    }
+
+    /// `{name}` placeholders that aren't in `PLACEHOLDERS`, likely typos
+    pub fn unknown_placeholders(&self) -> Vec<String> {
+        let escaped = self.text.replace("{{", "").replace("}}", "");
+        let mut unknown = Vec::new();
+        for (start, _) in escaped.match_indices('{') {
+            let Some(len) = escaped[start + 1..].find('}') else {
+                break;
+            };
+            let name = &escaped[start + 1..start + 1 + len];
+            let looks_like_placeholder =
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if looks_like_placeholder
+                && !PLACEHOLDERS.contains(&name)
+                && !unknown.iter().any(|known| known == name)
+            {
+                unknown.push(name.to_string());
+            }
+        }
+        unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_fills_placeholders_once() {
+        let template = PromptTemplate::new("From: {from}\nBody: {body}\n{{\"x\": {unknown}}}");
+        let rendered = template.render(&[("from", "a@example.com"), ("body", "see {from}")]);
+        assert_eq!(
+            rendered,
+            "From: a@example.com\nBody: see {from}\n{\"x\": {unknown}}"
+        );
+    }
+
+    #[test]
+    fn test_default_template_is_complete() {
+        let template = PromptTemplate::default();
+        assert!(template.missing_placeholders().is_empty());
+        assert!(template.unknown_placeholders().is_empty());
+
+        let custom = PromptTemplate::new("Classify {subject} from {sender}");
+        assert_eq!(
+            custom.missing_placeholders(),
+            vec!["profile", "from", "body"]
+        );
+        assert_eq!(custom.unknown_placeholders(), vec!["sender"]);
+    }
+}
@@ -0,0 +1,229 @@
+use super::{Email, EmailProvider, Label, ProviderCapabilities, SortOrder};
+use crate::labels::LabelColor;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+
+/// Canonical label names ↔ one provider's names for them (`label_aliases` in
+/// config.json). Matching is case-insensitive; unmapped labels pass through.
+#[derive(Debug, Default)]
+pub struct LabelAliasMap {
+    to_provider: HashMap<String, String>,
+    to_canonical: HashMap<String, String>,
+}
+
+impl LabelAliasMap {
+    pub fn new(aliases: &BTreeMap<String, String>) -> Self {
+        let mut map = Self::default();
+        for (canonical, provider) in aliases {
+            map.to_provider
+                .insert(canonical.to_lowercase(), provider.clone());
+            map.to_canonical
+                .insert(provider.to_lowercase(), canonical.clone());
+        }
+        map
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to_provider.is_empty()
+    }
+
+    /// The name to write to the provider
+    pub fn to_provider(&self, label: &str) -> String {
+        self.to_provider
+            .get(&label.to_lowercase())
+            .cloned()
+            .unwrap_or_else(|| label.to_string())
+    }
+
+    /// The name the profile and predictions use
+    pub fn to_canonical(&self, label: &str) -> String {
+        self.to_canonical
+            .get(&label.to_lowercase())
+            .cloned()
+            .unwrap_or_else(|| label.to_string())
+    }
+
+    /// Rewrite `label:X` and `-label:X` terms of a search
+    fn query_to_provider(&self, query: &str) -> String {
+        query
+            .split_whitespace()
+            .map(|term| {
+                let (prefix, label) = match term.strip_prefix("-label:") {
+                    Some(label) => ("-label:", label),
+                    None => match term.strip_prefix("label:") {
+                        Some(label) => ("label:", label),
+                        None => return term.to_string(),
+                    },
+                };
+                format!("{}{}", prefix, self.to_provider(label))
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn email_to_canonical(&self, mut email: Email) -> Email {
+        for label in &mut email.labels {
+            *label = self.to_canonical(label);
+        }
+        email
+    }
+}
+
+/// Wraps a provider so the rest of the tool only ever sees canonical label
+/// names: labels read from the provider are renamed to canonical ones, and
+/// canonical names are translated back when writing.
+pub struct Aliased<P> {
+    inner: P,
+    aliases: LabelAliasMap,
+}
+
+impl<P: EmailProvider> Aliased<P> {
+    pub fn new(inner: P, aliases: LabelAliasMap) -> Self {
+        Self { inner, aliases }
+    }
+}
+
+#[async_trait]
+impl<P: EmailProvider> EmailProvider for Aliased<P> {
+    async fn list_messages(
+        &self,
+        max: u32,
+        label: &str,
+        query: Option<&str>,
+        order: SortOrder,
+    ) -> Result<Vec<Email>> {
+        let label = self.aliases.to_provider(label);
+        let query = query.map(|query| self.aliases.query_to_provider(query));
+        let emails = self
+            .inner
+            .list_messages(max, &label, query.as_deref(), order)
+            .await?;
+        Ok(emails
+            .into_iter()
+            .map(|email| self.aliases.email_to_canonical(email))
+            .collect())
+    }
+    async fn get_message(&self, id: &str) -> Result<Email> {
+        let email = self.inner.get_message(id).await?;
+        Ok(self.aliases.email_to_canonical(email))
+    }
+    async fn list_labels(&self) -> Result<Vec<Label>> {
+        let labels = self.inner.list_labels().await?;
+        Ok(labels
+            .into_iter()
+            .map(|label| Label {
+                name: self.aliases.to_canonical(&label.name),
+                ..label
+            })
+            .collect())
+    }
+    async fn add_label(&self, id: &str, label: &str) -> Result<()> {
+        self.inner
+            .add_label(id, &self.aliases.to_provider(label))
+            .await
+    }
+    async fn remove_label(&self, id: &str, label: &str) -> Result<()> {
+        self.inner
+            .remove_label(id, &self.aliases.to_provider(label))
+            .await
+    }
+    async fn mark_spam(&self, id: &str) -> Result<()> {
+        self.inner.mark_spam(id).await
+    }
+    async fn unspam(&self, id: &str) -> Result<()> {
+        self.inner.unspam(id).await
+    }
+    async fn archive(&self, id: &str) -> Result<()> {
+        self.inner.archive(id).await
+    }
+    async fn trash(&self, id: &str) -> Result<()> {
+        self.inner.trash(id).await
+    }
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<()> {
+        self.inner.send(to, subject, body).await
+    }
+    async fn create_draft(&self, reply_to_id: &str, body: &str) -> Result<String> {
+        self.inner.create_draft(reply_to_id, body).await
+    }
+    async fn set_label_color(&self, label: &str, color: &LabelColor) -> Result<()> {
+        self.inner
+            .set_label_color(&self.aliases.to_provider(label), color)
+            .await
+    }
+    async fn list_contacts(&self) -> Result<Vec<String>> {
+        self.inner.list_contacts().await
+    }
+    async fn set_important(&self, id: &str) -> Result<()> {
+        self.inner.set_important(id).await
+    }
+    async fn clear_important(&self, id: &str) -> Result<()> {
+        self.inner.clear_important(id).await
+    }
+    async fn count_label(&self, label: &str) -> Result<u32> {
+        self.inner
+            .count_label(&self.aliases.to_provider(label))
+            .await
+    }
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.inner.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::mock::MockProvider;
+
+    fn receipts_aliases() -> LabelAliasMap {
+        LabelAliasMap::new(&BTreeMap::from([(
+            "Receipts".to_string(),
+            "Finance/Receipts".to_string(),
+        )]))
+    }
+
+    #[test]
+    fn test_alias_round_trip() {
+        let aliases = receipts_aliases();
+        for label in ["Receipts", "receipts", "Work", "INBOX"] {
+            let provider = aliases.to_provider(label);
+            assert!(aliases.to_canonical(&provider).eq_ignore_ascii_case(label));
+        }
+        assert_eq!(aliases.to_provider("receipts"), "Finance/Receipts");
+        assert_eq!(aliases.to_canonical("finance/receipts"), "Receipts");
+        assert_eq!(
+            aliases.query_to_provider("is:unread -label:Receipts -label:Classified"),
+            "is:unread -label:Finance/Receipts -label:Classified"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_provider_sees_its_names_and_callers_see_canonical_ones() {
+        let email = Email {
+            id: "1".to_string(),
+            labels: vec!["INBOX".to_string(), "Finance/Receipts".to_string()],
+            ..Default::default()
+        };
+        let provider = Aliased::new(MockProvider::with_emails([email]), receipts_aliases());
+
+        let read = provider.get_message("1").await.unwrap();
+        assert_eq!(read.labels, vec!["INBOX", "Receipts"]);
+        let listed = provider
+            .list_messages(10, "Receipts", None, SortOrder::default())
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+
+        provider.remove_label("1", "Receipts").await.unwrap();
+        provider.add_label("1", "Receipts").await.unwrap();
+        assert_eq!(
+            provider.inner.calls(),
+            vec![
+                "remove_label 1 Finance/Receipts",
+                "add_label 1 Finance/Receipts"
+            ]
+        );
+        let labels = provider.list_labels().await.unwrap();
+        assert!(labels.iter().any(|label| label.name == "Receipts"));
+    }
+}
@@ -0,0 +1,302 @@
+/// A node of a parsed MIME tree, as a provider's API returns it
+pub trait MimePart: Sized {
+    /// Lowercase type, e.g. "text/plain" or "multipart/alternative"
+    fn mime_type(&self) -> String;
+    /// Decoded content of a leaf part
+    fn content(&self) -> Option<String>;
+    fn children(&self) -> &[Self];
+    /// Parts with a filename are attachments, never the body
+    fn is_attachment(&self) -> bool;
+}
+
+/// Readable text of a message: the first text/plain part anywhere in the
+/// tree, else the first text/html part with its markup stripped
+pub fn body_text<P: MimePart>(root: &P) -> Option<String> {
+    find_part(root, "text/plain")
+        .or_else(|| find_part(root, "text/html").map(|html| strip_html(&html)))
+        .filter(|text| !text.trim().is_empty())
+}
+
+/// The text/calendar part of an invite, wherever it sits in the tree
+pub fn calendar_text<P: MimePart>(root: &P) -> Option<String> {
+    find_part(root, "text/calendar")
+}
+
+/// Depth-first, so a nested multipart/alternative is searched in place
+fn find_part<P: MimePart>(part: &P, mime_type: &str) -> Option<String> {
+    if part.is_attachment() {
+        return None;
+    }
+    if part.mime_type() == mime_type {
+        if let Some(content) = part.content().filter(|content| !content.trim().is_empty()) {
+            return Some(content);
+        }
+    }
+    part.children()
+        .iter()
+        .find_map(|child| find_part(child, mime_type))
+}
+
+/// Elements whose content is never readable text
+const HIDDEN_ELEMENTS: &[&str] = &["head", "script", "style", "title"];
+
+/// Visible text of an HTML body: tags, comments, and hidden elements dropped,
+/// entities decoded, and whitespace collapsed
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let Some(end) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let name = tag_name(&rest[1..end]);
+        rest = &rest[end + 1..];
+        // Tags separate words even when the markup has no whitespace
+        text.push(' ');
+
+        if HIDDEN_ELEMENTS.contains(&name.as_str()) && !rest.is_empty() {
+            let close = format!("</{}", name);
+            rest = find_ignore_case(rest, &close)
+                .and_then(|at| rest[at..].find('>').map(|end| &rest[at + end + 1..]))
+                .unwrap_or("");
+        }
+    }
+    text.push_str(rest);
+
+    decode_entities(&text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Lowercased element name of an opening tag; empty for closing tags
+fn tag_name(tag: &str) -> String {
+    if tag.starts_with('/') {
+        return String::new();
+    }
+    tag.chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack.to_ascii_lowercase().find(needle)
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end > 0 && end <= 10)
+            .and_then(|end| decode_entity(&rest[1..=end]).map(|c| (c, end + 2)));
+        match entity {
+            Some((c, len)) => {
+                if !is_invisible(c) {
+                    decoded.push(c);
+                }
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded.chars().filter(|c| !is_invisible(*c)).collect()
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+
+    let c = match entity {
+        "nbsp" => ' ',
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "bull" => '•',
+        "middot" => '·',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "euro" => '€',
+        "pound" => '£',
+        "shy" => '\u{ad}',
+        "zwnj" => '\u{200c}',
+        "zwj" => '\u{200d}',
+        _ => return None,
+    };
+    Some(c)
+}
+
+/// Zero-width padding marketing mail uses to pad preview text
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{ad}' | '\u{34f}' | '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Part {
+        mime_type: &'static str,
+        content: Option<&'static str>,
+        filename: Option<&'static str>,
+        parts: Vec<Part>,
+    }
+
+    impl MimePart for Part {
+        fn mime_type(&self) -> String {
+            self.mime_type.to_string()
+        }
+        fn content(&self) -> Option<String> {
+            self.content.map(str::to_string)
+        }
+        fn children(&self) -> &[Self] {
+            &self.parts
+        }
+        fn is_attachment(&self) -> bool {
+            self.filename.is_some()
+        }
+    }
+
+    fn leaf(mime_type: &'static str, content: &'static str) -> Part {
+        Part {
+            mime_type,
+            content: Some(content),
+            filename: None,
+            parts: vec![],
+        }
+    }
+
+    fn multipart(mime_type: &'static str, parts: Vec<Part>) -> Part {
+        Part {
+            mime_type,
+            content: None,
+            filename: None,
+            parts,
+        }
+    }
+
+    #[test]
+    fn test_body_text_prefers_nested_plain_text() {
+        // multipart/mixed → [multipart/related → [multipart/alternative → [html, plain]], attachment]
+        let message = multipart(
+            "multipart/mixed",
+            vec![
+                multipart(
+                    "multipart/related",
+                    vec![multipart(
+                        "multipart/alternative",
+                        vec![
+                            leaf("text/html", "<p>Your <b>invoice</b></p>"),
+                            leaf("text/plain", "Your invoice is attached."),
+                        ],
+                    )],
+                ),
+                Part {
+                    filename: Some("notes.txt"),
+                    ..leaf("text/plain", "attachment text")
+                },
+            ],
+        );
+        assert_eq!(
+            body_text(&message).as_deref(),
+            Some("Your invoice is attached.")
+        );
+    }
+
+    #[test]
+    fn test_body_text_strips_html_only_messages() {
+        let message = multipart(
+            "multipart/mixed",
+            vec![
+                multipart(
+                    "multipart/alternative",
+                    vec![
+                        leaf("text/plain", "  "),
+                        leaf(
+                            "text/html",
+                            "<html><style>p{}</style><p>Sale&nbsp;ends</p></html>",
+                        ),
+                    ],
+                ),
+                Part {
+                    filename: Some("logo.png"),
+                    ..leaf("image/png", "")
+                },
+            ],
+        );
+        assert_eq!(body_text(&message).as_deref(), Some("Sale ends"));
+        assert_eq!(body_text(&multipart("multipart/mixed", vec![])), None);
+    }
+
+    #[test]
+    fn test_strip_html_marketing_email() {
+        let html = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>Spring Sale</title>
+<style type="text/css">
+  body { margin: 0; font-family: Arial, sans-serif; }
+  .cta a { color: #ffffff !important; }
+  @media only screen and (max-width: 600px) { .col { width: 100% !important; } }
+</style></head>
+<body>
+<div style="display:none">Don&rsquo;t miss out&#8204;&nbsp;&#847;&zwnj;&nbsp;</div>
+<!-- preheader end -->
+<table><tr><td class="col"><h1>Spring Sale&mdash;40% off</h1>
+<p>Shop now &amp; save&hellip; offer ends Sunday.<br>Questions? Reply to &lt;help@shop.example&gt;</p>
+<SCRIPT>window.track({"id": 42});</SCRIPT>
+<p class="cta"><a href="https://shop.example/?a=1&amp;b=2">Shop&nbsp;now</a></p>
+<p>&#169; 2025 Shop Inc. It&#x2019;s free&#39;s to unsubscribe.</p></td></tr></table>
+</body></html>"#;
+
+        assert_eq!(
+            strip_html(html),
+            "Don’t miss out Spring Sale—40% off Shop now & save… offer ends Sunday. \
+             Questions? Reply to <help@shop.example> Shop now © 2025 Shop Inc. \
+             It’s free's to unsubscribe."
+        );
+    }
+
+    #[test]
+    fn test_strip_html_leaves_unknown_entities_and_stray_ampersands() {
+        assert_eq!(
+            strip_html("Fish &chips; R&D &bogus; a<b"),
+            "Fish &chips; R&D &bogus; a"
+        );
+    }
+}
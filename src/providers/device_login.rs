@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time::Instant;
+
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+/// RFC 8628 default when the server doesn't say how often to poll
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 5;
+/// RFC 8628: a `slow_down` response adds five seconds to the interval
+const SLOW_DOWN_SECS: u64 = 5;
+
+/// Endpoints and client of one provider's OAuth device-code grant
+pub struct DeviceFlow<'a> {
+    pub device_code_url: &'a str,
+    pub token_url: &'a str,
+    pub client_id: &'a str,
+    pub scope: &'a str,
+}
+
+/// Response to the device authorization request
+#[derive(Debug, Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    expires_in: u64,
+    #[serde(default)]
+    interval: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+/// What one poll of the token endpoint said
+#[derive(Debug)]
+enum PollOutcome {
+    Authorized(DeviceTokens),
+    Pending,
+    SlowDown,
+    Failed(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenError {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+impl PollOutcome {
+    fn parse(body: &str) -> Result<Self> {
+        if let Ok(tokens) = serde_json::from_str::<DeviceTokens>(body) {
+            return Ok(Self::Authorized(tokens));
+        }
+        let error: TokenError =
+            serde_json::from_str(body).context("Unexpected response from the token endpoint")?;
+        Ok(match error.error.as_str() {
+            "authorization_pending" => Self::Pending,
+            "slow_down" => Self::SlowDown,
+            "expired_token" => Self::Failed("the code expired before it was entered".to_string()),
+            "access_denied" | "authorization_declined" => {
+                Self::Failed("access was denied".to_string())
+            }
+            _ => Self::Failed(error.error_description.unwrap_or(error.error)),
+        })
+    }
+}
+
+impl DeviceFlow<'_> {
+    /// Print the code for the user to enter elsewhere, then poll until they
+    /// approve it, it expires, or the server refuses
+    pub async fn login(&self) -> Result<DeviceTokens> {
+        let response = post_form(
+            self.device_code_url,
+            &[("client_id", self.client_id), ("scope", self.scope)],
+        )
+        .await?;
+        let code: DeviceCode = serde_json::from_str(&response)
+            .with_context(|| format!("Device code request failed: {}", response.trim()))?;
+
+        println!("On any device, open {}", code.verification_uri);
+        println!("and enter the code: {}", code.user_code);
+        println!("Waiting for approval...");
+
+        let deadline = Instant::now() + Duration::from_secs(code.expires_in);
+        let mut interval = code.interval.unwrap_or(DEFAULT_POLL_INTERVAL_SECS);
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            if Instant::now() >= deadline {
+                anyhow::bail!("Login failed: the code expired before it was entered");
+            }
+
+            let form = [
+                ("client_id", self.client_id),
+                ("device_code", code.device_code.as_str()),
+                ("grant_type", DEVICE_CODE_GRANT),
+            ];
+            match PollOutcome::parse(&post_form(self.token_url, &form).await?)? {
+                PollOutcome::Authorized(tokens) => return Ok(tokens),
+                PollOutcome::Pending => {}
+                PollOutcome::SlowDown => interval += SLOW_DOWN_SECS,
+                PollOutcome::Failed(reason) => anyhow::bail!("Login failed: {}", reason),
+            }
+        }
+    }
+}
+
+/// POST a urlencoded form and return the body, whatever the status: token
+/// endpoints report "still pending" as a 400 with a JSON error
+async fn post_form(url: &str, form: &[(&str, &str)]) -> Result<String> {
+    let mut command = Command::new("curl");
+    command.args(["-sS", "-X", "POST"]);
+    for (key, value) in form {
+        command
+            .arg("--data-urlencode")
+            .arg(format!("{}={}", key, value));
+    }
+    let output = command
+        .arg(url)
+        .output()
+        .await
+        .context("Failed to run curl")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Request to {} failed: {}", url, stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poll_outcomes() {
+        let authorized = PollOutcome::parse(
+            r#"{"access_token": "at", "refresh_token": "rt", "expires_in": 3599}"#,
+        )
+        .unwrap();
+        assert!(
+            matches!(authorized, PollOutcome::Authorized(tokens) if tokens.refresh_token == "rt")
+        );
+
+        let pending = PollOutcome::parse(r#"{"error": "authorization_pending"}"#).unwrap();
+        assert!(matches!(pending, PollOutcome::Pending));
+        let slow_down = PollOutcome::parse(r#"{"error": "slow_down"}"#).unwrap();
+        assert!(matches!(slow_down, PollOutcome::SlowDown));
+
+        let expired = PollOutcome::parse(r#"{"error": "expired_token"}"#).unwrap();
+        assert!(matches!(expired, PollOutcome::Failed(reason) if reason.contains("expired")));
+        let other = PollOutcome::parse(
+            r#"{"error": "invalid_client", "error_description": "Unknown client"}"#,
+        )
+        .unwrap();
+        assert!(matches!(other, PollOutcome::Failed(reason) if reason == "Unknown client"));
+        assert!(PollOutcome::parse("<html>").is_err());
+    }
+
+    #[test]
+    fn test_device_code_interval_is_optional() {
+        let code: DeviceCode = serde_json::from_str(
+            r#"{"device_code": "d", "user_code": "XYZ", "verification_uri": "https://microsoft.com/devicelogin", "expires_in": 900, "message": "To sign in..."}"#,
+        )
+        .unwrap();
+        assert_eq!(code.user_code, "XYZ");
+        assert_eq!(code.interval, None);
+    }
+}
@@ -1,40 +1,92 @@
-use super::{Email, EmailProvider, Label};
+use super::body::{body_text, calendar_text, MimePart};
+use super::refresh::RefreshingClient;
+use super::{
+    bearer_http, body_with_retry, credential_from_env_or, mime, not_found_on_404, sort_by_date,
+    text_part_missing, tokens_from_env, BodyCache, Email, EmailProvider, Label, ProviderError,
+    RateLimiter, SortOrder,
+};
+use crate::config::Config;
+use crate::filters::{FilterSpec, GmailFilter};
+use crate::headers;
+use crate::labels::{label_with_ancestors, LabelColor};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+const FILTERS_URL: &str = "https://gmail.googleapis.com/gmail/v1/users/me/settings/filters";
+const LABELS_URL: &str = "https://gmail.googleapis.com/gmail/v1/users/me/labels";
 
 pub struct GmailProvider {
-    client: gmail::Client,
+    client: RefreshingClient<gmail::Client>,
+    /// Current access token, for endpoints the gmail client doesn't cover
+    access_token: Arc<Mutex<String>>,
     label_id_to_name: HashMap<String, String>,
+    /// Label names known to exist, so new ones get created with a color
+    label_names: Mutex<HashSet<String>>,
+    body_cache: BodyCache,
 }
 
 impl GmailProvider {
     pub async fn new() -> Result<Self> {
-        let cfg = gmail::config::load_config()?;
-        let client_id = cfg.client_id();
-        let client_secret = cfg.client_secret();
+        let (client_id, client_secret) = Self::client_credentials()?;
 
-        let tokens =
-            gmail::config::load_tokens().context("Not logged in. Run 'gmail login' first")?;
+        // Tokens from the environment replace the token file, which is then
+        // left alone
+        let env_tokens = tokens_from_env("GMAIL");
+        let persist_tokens = env_tokens.is_none();
+        let tokens = match env_tokens {
+            Some((access_token, refresh_token)) => gmail::config::Tokens {
+                access_token,
+                refresh_token,
+            },
+            None => {
+                gmail::config::load_tokens().context("Not logged in. Run 'gmail login' first")?
+            }
+        };
 
-        // Try to use existing token, refresh if needed
-        let client = gmail::Client::new(&tokens.access_token);
+        let requests_per_second = Config::load().unwrap_or_default().requests_per_second();
 
-        // Test if token works
-        let client = match client.list_messages(None, "INBOX", 1).await {
-            Ok(_) => client,
-            Err(_) => {
-                // Token expired, try refresh
+        // Refreshes run again whenever a call hits 401 mid-session
+        let refresh_token = tokens.refresh_token.clone();
+        let access_token = Arc::new(Mutex::new(tokens.access_token.clone()));
+        let refreshed_token = access_token.clone();
+        let client = RefreshingClient::new(gmail::Client::new(&tokens.access_token), move || {
+            let (client_id, client_secret, refresh_token, refreshed_token) = (
+                client_id.clone(),
+                client_secret.clone(),
+                refresh_token.clone(),
+                refreshed_token.clone(),
+            );
+            async move {
                 let new_tokens =
-                    gmail::auth::refresh_token(client_id, client_secret, &tokens.refresh_token)
-                        .await?;
-                gmail::Client::new(&new_tokens.access_token)
+                    gmail::auth::refresh_token(&client_id, &client_secret, &refresh_token).await?;
+                if persist_tokens {
+                    gmail::config::save_tokens(&new_tokens)?;
+                }
+                if let Ok(mut token) = refreshed_token.lock() {
+                    token.clone_from(&new_tokens.access_token);
+                }
+                Ok(gmail::Client::new(&new_tokens.access_token))
             }
-        };
+            .boxed()
+        })
+        .with_rate_limit(RateLimiter::per_second(requests_per_second));
+
+        // Test if token works
+        let probe = client
+            .call(|c| async move { c.list_messages(None, "INBOX", 1).await })
+            .await;
+        if probe.is_err() {
+            // Token expired, try refresh
+            client.refresh().await?;
+        }
 
         // Build label ID to name mapping
         let mut label_id_to_name = HashMap::new();
-        if let Ok(labels) = client.list_labels().await {
+        if let Ok(labels) = client.call(|c| async move { c.list_labels().await }).await {
             if let Some(label_list) = labels.labels {
                 for label in label_list {
                     label_id_to_name.insert(label.id, label.name);
@@ -42,12 +94,43 @@ impl GmailProvider {
             }
         }
 
+        let label_names = Mutex::new(label_id_to_name.values().cloned().collect());
         Ok(Self {
             client,
+            access_token,
             label_id_to_name,
+            label_names,
+            body_cache: BodyCache::default(),
         })
     }
 
+    fn is_new_label(&self, label: &str) -> bool {
+        self.label_names
+            .lock()
+            .map(|names| !names.contains(label))
+            .unwrap_or(false)
+    }
+
+    fn remember_label(&self, label: &str) {
+        if let Ok(mut names) = self.label_names.lock() {
+            names.insert(label.to_string());
+        }
+    }
+
+    /// Client ID and secret, from EMAIL_ASSISTANT_GMAIL_CLIENT_ID and
+    /// EMAIL_ASSISTANT_GMAIL_CLIENT_SECRET when set
+    pub fn client_credentials() -> Result<(String, String)> {
+        // The config file is optional when both credentials come from the environment
+        let cfg = gmail::config::load_config().ok();
+        let client_id =
+            credential_from_env_or("GMAIL_CLIENT_ID", cfg.as_ref().map(|cfg| cfg.client_id()))?;
+        let client_secret = credential_from_env_or(
+            "GMAIL_CLIENT_SECRET",
+            cfg.as_ref().map(|cfg| cfg.client_secret()),
+        )?;
+        Ok((client_id, client_secret))
+    }
+
     fn resolve_label_ids(&self, label_ids: Vec<String>) -> Vec<String> {
         label_ids
             .into_iter()
@@ -55,12 +138,13 @@ impl GmailProvider {
             .collect()
     }
 
-    fn message_to_email(&self, msg: gmail::Message) -> Email {
+    async fn message_to_email(&self, msg: gmail::Message) -> Email {
         let label_ids = msg.label_ids.clone().unwrap_or_default();
-        // Use body text if available, fall back to snippet
-        let body = msg
-            .get_body_text()
-            .or_else(|| msg.snippet.clone())
+        let body = self.resolve_body(&msg).await;
+        let attachment_names = msg
+            .payload
+            .as_ref()
+            .map(part_attachment_names)
             .unwrap_or_default();
         Email {
             id: msg.id.clone(),
@@ -72,8 +156,139 @@ impl GmailProvider {
                 .to_string(),
             body,
             labels: self.resolve_label_ids(label_ids),
+            date: parse_internal_date(msg.internal_date.as_deref()),
+            list_id: msg.get_header("List-Id").map(str::to_string),
+            message_id: msg.get_header("Message-ID").map(str::to_string),
+            thread_id: msg.thread_id.clone(),
+            has_attachments: !attachment_names.is_empty(),
+            attachment_names,
+            content_type: msg
+                .payload
+                .as_ref()
+                .and_then(|payload| payload.mime_type.clone()),
+            calendar: msg.payload.as_ref().and_then(calendar_text),
+            headers: headers::collect(|name| msg.get_header(name).map(str::to_string)),
         }
     }
+
+    /// Create a native Gmail filter, returning its ID
+    pub async fn create_filter(&self, spec: &FilterSpec) -> Result<String> {
+        let criteria = gmail::FilterCriteria {
+            from: spec.from.clone(),
+            to: spec.to.clone(),
+            subject: spec.subject.clone(),
+            ..Default::default()
+        };
+        let action = gmail::FilterAction {
+            add_label_ids: spec.add_label_ids.clone(),
+            remove_label_ids: spec.remove_label_ids.clone(),
+        };
+        let (criteria, action) = (&criteria, &action);
+        self.client
+            .call(|c| async move { c.create_filter(criteria, action).await })
+            .await
+    }
+
+    pub async fn delete_filter(&self, filter_id: &str) -> Result<()> {
+        self.client
+            .call(|c| async move { c.delete_filter(filter_id).await })
+            .await
+    }
+
+    /// The account's filters (settings.filters.list), label IDs resolved to names
+    pub async fn list_filters(&self) -> Result<Vec<GmailFilter>> {
+        #[derive(serde::Deserialize)]
+        struct FilterList {
+            #[serde(default)]
+            filter: Vec<GmailFilter>,
+        }
+
+        let response = self
+            .client
+            .call(|_| {
+                let token = self
+                    .access_token
+                    .lock()
+                    .map(|token| token.clone())
+                    .unwrap_or_default();
+                async move { bearer_http(&token, FILTERS_URL, None).await }
+            })
+            .await?;
+        let list: FilterList =
+            serde_json::from_str(&response).context("Unexpected filters response")?;
+        Ok(list
+            .filter
+            .into_iter()
+            .map(|mut filter| {
+                let action = &mut filter.action;
+                action.add_label_ids =
+                    self.resolve_label_ids(std::mem::take(&mut action.add_label_ids));
+                action.remove_label_ids =
+                    self.resolve_label_ids(std::mem::take(&mut action.remove_label_ids));
+                filter
+            })
+            .collect())
+    }
+
+    async fn resolve_body(&self, msg: &gmail::Message) -> String {
+        if let Some(body) = self.body_cache.get(&msg.id) {
+            return body;
+        }
+
+        // Plain text from anywhere in the MIME tree, else stripped HTML, else the snippet
+        let text = msg.payload.as_ref().and_then(body_text);
+        let retried = text_part_missing(text.as_deref());
+        let id = msg.id.as_str();
+        let body = body_with_retry(text, msg.snippet.clone(), || {
+            self.client
+                .call(|c| async move { c.get_raw_message(id).await })
+        })
+        .await;
+        if retried {
+            self.body_cache.insert(&msg.id, &body);
+        }
+        body
+    }
+}
+
+impl MimePart for gmail::MessagePart {
+    fn mime_type(&self) -> String {
+        self.mime_type.as_deref().unwrap_or("").to_ascii_lowercase()
+    }
+
+    fn content(&self) -> Option<String> {
+        // Part data comes base64url-encoded
+        let data = self.body.as_ref()?.data.as_deref()?;
+        mime::decode_base64(data).map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn children(&self) -> &[Self] {
+        self.parts.as_deref().unwrap_or_default()
+    }
+
+    fn is_attachment(&self) -> bool {
+        self.filename
+            .as_deref()
+            .is_some_and(|name| !name.is_empty())
+    }
+}
+
+/// Filenames of attachment parts, found anywhere in the MIME tree
+fn part_attachment_names(part: &gmail::MessagePart) -> Vec<String> {
+    let mut names = Vec::new();
+    if let Some(filename) = part.filename.as_deref().filter(|name| !name.is_empty()) {
+        names.push(filename.to_string());
+    }
+    for child in part.parts.iter().flatten() {
+        names.extend(part_attachment_names(child));
+    }
+    names
+}
+
+/// Gmail's internalDate is milliseconds since the epoch, as a string
+fn parse_internal_date(internal_date: Option<&str>) -> Option<DateTime<Utc>> {
+    let millis = internal_date?.parse().ok()?;
+    DateTime::from_timestamp_millis(millis)
 }
 
 #[async_trait]
@@ -83,27 +298,48 @@ impl EmailProvider for GmailProvider {
         max: u32,
         label: &str,
         query: Option<&str>,
+        order: SortOrder,
     ) -> Result<Vec<Email>> {
-        let list = self.client.list_messages(query, label, max).await?;
+        // System labels are listed too, so an empty map means listing failed
+        if !label.is_empty() && !self.label_id_to_name.is_empty() && self.is_new_label(label) {
+            return Err(ProviderError::NotFound(format!("Label {}", label)).into());
+        }
+        let list = self
+            .client
+            .call(|c| async move { c.list_messages(query, label, max).await })
+            .await?;
 
         let mut emails = Vec::new();
         if let Some(messages) = list.messages {
             for msg_ref in messages {
-                let msg = self.client.get_message(&msg_ref.id).await?;
-                emails.push(self.message_to_email(msg));
+                let id = msg_ref.id.as_str();
+                let msg = self
+                    .client
+                    .call(|c| async move { c.get_message(id).await })
+                    .await?;
+                emails.push(self.message_to_email(msg).await);
             }
         }
 
+        // Gmail has no sort option; it always returns newest first
+        sort_by_date(&mut emails, order);
         Ok(emails)
     }
 
     async fn get_message(&self, id: &str) -> Result<Email> {
-        let msg = self.client.get_message(id).await?;
-        Ok(self.message_to_email(msg))
+        let msg = self
+            .client
+            .call(|c| async move { c.get_message(id).await })
+            .await
+            .map_err(|error| not_found_on_404(error, format!("Message {}", id)))?;
+        Ok(self.message_to_email(msg).await)
     }
 
     async fn list_labels(&self) -> Result<Vec<Label>> {
-        let list = self.client.list_labels().await?;
+        let list = self
+            .client
+            .call(|c| async move { c.list_labels().await })
+            .await?;
         let mut labels = Vec::new();
 
         if let Some(gmail_labels) = list.labels {
@@ -119,22 +355,144 @@ impl EmailProvider for GmailProvider {
     }
 
     async fn add_label(&self, id: &str, label: &str) -> Result<()> {
-        self.client.add_label(id, label).await
+        // add_label would create a missing label itself, but without a color.
+        // Gmail only nests "Finance/Receipts" under an existing "Finance", so
+        // missing parents are created first.
+        for path in label_with_ancestors(label) {
+            if !self.is_new_label(path) {
+                continue;
+            }
+            let color = LabelColor::for_label(path);
+            let (background, text) = (color.background, color.text);
+            // If it exists after all (e.g. the startup listing failed),
+            // add_label below still applies it
+            let _ = self
+                .client
+                .call(|c| async move { c.create_label(path, background, text).await })
+                .await;
+            self.remember_label(path);
+        }
+        self.client
+            .call(|c| async move { c.add_label(id, label).await })
+            .await
+    }
+
+    async fn remove_label(&self, id: &str, label: &str) -> Result<()> {
+        self.client
+            .call(|c| async move { c.remove_label(id, label).await })
+            .await
     }
 
     async fn mark_spam(&self, id: &str) -> Result<()> {
-        self.client.mark_spam(id).await
+        self.client
+            .call(|c| async move { c.mark_spam(id).await })
+            .await
     }
 
     async fn unspam(&self, id: &str) -> Result<()> {
-        self.client.unspam(id).await
+        self.client
+            .call(|c| async move { c.unspam(id).await })
+            .await
     }
 
     async fn archive(&self, id: &str) -> Result<()> {
-        self.client.archive(id).await
+        self.client
+            .call(|c| async move { c.archive(id).await })
+            .await
     }
 
     async fn trash(&self, id: &str) -> Result<()> {
-        self.client.trash(id).await
+        self.client.call(|c| async move { c.trash(id).await }).await
+    }
+
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<()> {
+        // messages.send takes the whole RFC 822 message, base64url-encoded
+        let message = mime::Message {
+            to,
+            subject,
+            body,
+            ..Default::default()
+        };
+        let raw = mime::base64url(message.to_rfc822().as_bytes());
+        let raw = raw.as_str();
+        self.client
+            .call(|c| async move { c.send_raw(raw).await })
+            .await
+    }
+
+    async fn create_draft(&self, reply_to_id: &str, body: &str) -> Result<String> {
+        // Threaded onto the original via drafts.create
+        self.client
+            .call(|c| async move { c.create_reply_draft(reply_to_id, body).await })
+            .await
+    }
+
+    async fn list_contacts(&self) -> Result<Vec<String>> {
+        // People API connections, email addresses only
+        self.client
+            .call(|c| async move { c.list_contact_emails().await })
+            .await
+    }
+
+    /// IMPORTANT is a system label, so it's applied directly rather than
+    /// through `add_label`, which would try to create it
+    async fn set_important(&self, id: &str) -> Result<()> {
+        self.client
+            .call(|c| async move { c.add_label(id, "IMPORTANT").await })
+            .await
+    }
+
+    async fn clear_important(&self, id: &str) -> Result<()> {
+        self.client
+            .call(|c| async move { c.remove_label(id, "IMPORTANT").await })
+            .await
+    }
+
+    /// labels.get's messagesTotal; system labels like INBOX are their own IDs
+    async fn count_label(&self, label: &str) -> Result<u32> {
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct LabelCounts {
+            #[serde(default)]
+            messages_total: u32,
+        }
+
+        let id = self
+            .label_id_to_name
+            .iter()
+            .find(|(_, name)| name.as_str() == label)
+            .map(|(id, _)| id.as_str())
+            .unwrap_or(label);
+        let url = format!("{}/{}", LABELS_URL, id);
+        let url = url.as_str();
+        let response = self
+            .client
+            .call(|_| {
+                let token = self
+                    .access_token
+                    .lock()
+                    .map(|token| token.clone())
+                    .unwrap_or_default();
+                async move { bearer_http(&token, url, None).await }
+            })
+            .await?;
+        let counts: LabelCounts =
+            serde_json::from_str(&response).context("Unexpected label response")?;
+        Ok(counts.messages_total)
+    }
+
+    async fn set_label_color(&self, label: &str, color: &LabelColor) -> Result<()> {
+        let (background, text) = (color.background, color.text);
+        if self.is_new_label(label) {
+            self.client
+                .call(|c| async move { c.create_label(label, background, text).await })
+                .await?;
+        } else {
+            self.client
+                .call(|c| async move { c.set_label_color(label, background, text).await })
+                .await?;
+        }
+        self.remember_label(label);
+        Ok(())
     }
 }
@@ -0,0 +1,667 @@
+use super::{
+    excluded_labels, extract_text_from_mime, keyword_to_label, label_to_keyword, mime,
+    sender_address, sort_by_date, unread_filter, Email, EmailProvider, Label, ProviderCapabilities,
+    ProviderError, ProviderOp, SortOrder,
+};
+use crate::calendar;
+use crate::config::{Config, ImapConfig};
+use crate::headers;
+use anyhow::{Context, Result};
+use async_imap::types::{Fetch, Flag};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+type Session = async_imap::Session<async_native_tls::TlsStream<TcpStream>>;
+
+const INBOX: &str = "INBOX";
+
+pub struct ImapProvider {
+    session: Mutex<Session>,
+    config: ImapConfig,
+}
+
+impl ImapProvider {
+    pub async fn new() -> Result<Self> {
+        let config = Config::load()?
+            .imap
+            .context("IMAP not configured. Add an \"imap\" section to config.json")?;
+
+        let tcp = TcpStream::connect((config.host.as_str(), config.port()))
+            .await
+            .with_context(|| format!("Failed to connect to {}", config.host))?;
+        let tls = async_native_tls::TlsConnector::new()
+            .connect(config.host.as_str(), tcp)
+            .await?;
+        let session = async_imap::Client::new(tls)
+            .login(&config.user, &config.password)
+            .await
+            .map_err(|(error, _)| error)
+            .context("IMAP login failed")?;
+
+        Ok(Self {
+            session: Mutex::new(session),
+            config,
+        })
+    }
+
+    /// Map a Gmail-style label to the IMAP mailbox that holds it
+    fn mailbox_for_label<'a>(&'a self, label: &'a str) -> Option<&'a str> {
+        match label {
+            "INBOX" => Some(INBOX),
+            "" => Some(self.config.archive_folder()),
+            "SPAM" => Some(self.config.junk_folder()),
+            "TRASH" => Some(self.config.trash_folder()),
+            _ => None,
+        }
+    }
+
+    fn fetch_to_email(&self, mailbox: &str, fetch: &Fetch) -> Option<Email> {
+        let uid = fetch.uid?;
+        let raw = String::from_utf8_lossy(fetch.body()?).into_owned();
+        let mut labels = Vec::new();
+
+        if mailbox == INBOX {
+            labels.push("INBOX".to_string());
+        } else if mailbox == self.config.junk_folder() {
+            labels.push("SPAM".to_string());
+        } else if mailbox == self.config.trash_folder() {
+            labels.push("TRASH".to_string());
+        }
+
+        let mut seen = false;
+        for flag in fetch.flags() {
+            match flag {
+                Flag::Seen => seen = true,
+                Flag::Flagged => labels.push("STARRED".to_string()),
+                Flag::Custom(keyword) if !keyword.starts_with('\\') => {
+                    labels.push(keyword_to_label(&keyword));
+                }
+                _ => {}
+            }
+        }
+        if !seen {
+            labels.push("UNREAD".to_string());
+        }
+
+        let attachment_names = attachment_filenames(&raw);
+        Some(Email {
+            id: message_key(&raw, mailbox, uid),
+            from: header_value(&raw, "From").unwrap_or_default(),
+            to: header_value(&raw, "To").unwrap_or_default(),
+            subject: header_value(&raw, "Subject").unwrap_or_else(|| "(no subject)".to_string()),
+            body: message_body(&raw),
+            labels,
+            date: header_value(&raw, "Date")
+                .and_then(|date| DateTime::parse_from_rfc2822(&date).ok())
+                .map(|date| date.with_timezone(&Utc)),
+            list_id: header_value(&raw, "List-Id"),
+            message_id: header_value(&raw, "Message-ID"),
+            thread_id: thread_root(&raw),
+            has_attachments: !attachment_names.is_empty(),
+            attachment_names,
+            content_type: header_value(&raw, "Content-Type")
+                .and_then(|value| value.split(';').next().map(|t| t.trim().to_lowercase())),
+            calendar: calendar::extract_calendar(&raw),
+            headers: headers::collect(|name| header_value(&raw, name)),
+        })
+    }
+
+    async fn fetch_emails(
+        &self,
+        session: &mut Session,
+        mailbox: &str,
+        uids: &[u32],
+        order: SortOrder,
+    ) -> Result<Vec<Email>> {
+        if uids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let uid_set = uids
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut fetches: Vec<Fetch> = session
+            .uid_fetch(&uid_set, "(UID FLAGS BODY.PEEK[])")
+            .await?
+            .try_collect()
+            .await?;
+
+        // UIDs increase as messages arrive, so they order by received date
+        fetches.sort_by_key(|fetch| fetch.uid);
+        if order == SortOrder::NewestFirst {
+            fetches.reverse();
+        }
+        Ok(fetches
+            .iter()
+            .filter_map(|fetch| self.fetch_to_email(mailbox, fetch))
+            .collect())
+    }
+
+    /// Up to `max` messages in the selected mailbox matching `criteria`
+    async fn search_mailbox(
+        &self,
+        session: &mut Session,
+        mailbox: &str,
+        criteria: &str,
+        max: u32,
+        order: SortOrder,
+    ) -> Result<Vec<Email>> {
+        let mut uids = session
+            .uid_search(criteria)
+            .await?
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        uids.sort_unstable();
+        if order == SortOrder::NewestFirst {
+            uids.reverse();
+        }
+        uids.truncate(max as usize);
+
+        self.fetch_emails(session, mailbox, &uids, order).await
+    }
+
+    /// Mailboxes a message can be moved between, searched in this order
+    fn searched_mailboxes(&self) -> [&str; 4] {
+        [
+            INBOX,
+            self.config.archive_folder(),
+            self.config.junk_folder(),
+            self.config.trash_folder(),
+        ]
+    }
+
+    /// Select the mailbox holding the message and return its current UID.
+    /// Message-ID keys are looked up with `UID SEARCH HEADER`, since moving
+    /// a message gives it a new UID in another mailbox.
+    async fn locate(&self, session: &mut Session, id: &str) -> Result<(String, u32)> {
+        if !is_message_id(id) {
+            let (mailbox, uid) = parse_id(id)?;
+            session.select(mailbox).await?;
+            return Ok((mailbox.to_string(), uid));
+        }
+
+        let criteria = format!("HEADER Message-ID {}", quoted(id));
+        for mailbox in self.searched_mailboxes() {
+            // A configured folder the server doesn't have can't hold it
+            if session.select(mailbox).await.is_err() {
+                continue;
+            }
+            if let Some(uid) = session.uid_search(&criteria).await?.into_iter().max() {
+                return Ok((mailbox.to_string(), uid));
+            }
+        }
+        Err(ProviderError::NotFound(format!("Message {}", id)).into())
+    }
+
+    async fn store_flags(&self, id: &str, change: &str) -> Result<()> {
+        let mut session = self.session.lock().await;
+        let (_, uid) = self.locate(&mut session, id).await?;
+        let _: Vec<Fetch> = session
+            .uid_store(uid.to_string(), change)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(())
+    }
+
+    async fn move_to(&self, id: &str, target: &str) -> Result<()> {
+        let mut session = self.session.lock().await;
+        let (mailbox, uid) = self.locate(&mut session, id).await?;
+        if mailbox != target {
+            session.uid_mv(uid.to_string(), target).await?;
+        }
+        Ok(())
+    }
+
+    async fn fetch_raw(&self, session: &mut Session, id: &str) -> Result<String> {
+        let (_, uid) = self.locate(session, id).await?;
+        let fetches: Vec<Fetch> = session
+            .uid_fetch(uid.to_string(), "(UID BODY.PEEK[HEADER])")
+            .await?
+            .try_collect()
+            .await?;
+        fetches
+            .first()
+            .and_then(|fetch| fetch.header())
+            .map(|raw| String::from_utf8_lossy(raw).into_owned())
+            .ok_or_else(|| ProviderError::NotFound(format!("Message {}", id)).into())
+    }
+}
+
+#[async_trait]
+impl EmailProvider for ImapProvider {
+    async fn list_messages(
+        &self,
+        max: u32,
+        label: &str,
+        query: Option<&str>,
+        order: SortOrder,
+    ) -> Result<Vec<Email>> {
+        let query = query.map(search_criteria).unwrap_or_default();
+        let mut session = self.session.lock().await;
+
+        if let Some(mailbox) = self.mailbox_for_label(label) {
+            let criteria = [vec!["ALL".to_string()], query].concat().join(" ");
+            session.select(mailbox).await?;
+            return self
+                .search_mailbox(&mut session, mailbox, &criteria, max, order)
+                .await;
+        }
+
+        // Labels that aren't folders are IMAP keywords, and labeled mail is
+        // usually archived, so every mailbox is searched
+        let criteria = [vec![format!("KEYWORD {}", label_to_keyword(label))], query]
+            .concat()
+            .join(" ");
+        let mut emails = Vec::new();
+        for mailbox in self.searched_mailboxes() {
+            // A configured folder the server doesn't have holds nothing
+            if session.select(mailbox).await.is_err() {
+                continue;
+            }
+            emails.extend(
+                self.search_mailbox(&mut session, mailbox, &criteria, max, order)
+                    .await?,
+            );
+        }
+        sort_by_date(&mut emails, order);
+        emails.truncate(max as usize);
+        Ok(emails)
+    }
+
+    async fn get_message(&self, id: &str) -> Result<Email> {
+        let mut session = self.session.lock().await;
+        let (mailbox, uid) = self.locate(&mut session, id).await?;
+        self.fetch_emails(&mut session, &mailbox, &[uid], SortOrder::default())
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ProviderError::NotFound(format!("Message {}", id)).into())
+    }
+
+    async fn list_labels(&self) -> Result<Vec<Label>> {
+        let mut session = self.session.lock().await;
+        let names: Vec<_> = session.list(None, Some("*")).await?.try_collect().await?;
+
+        Ok(names
+            .iter()
+            .map(|name| Label {
+                id: name.name().to_string(),
+                name: name.name().to_string(),
+            })
+            .collect())
+    }
+
+    async fn add_label(&self, id: &str, label: &str) -> Result<()> {
+        let change = format!("+FLAGS ({})", label_to_keyword(label));
+        self.store_flags(id, &change).await
+    }
+
+    async fn remove_label(&self, id: &str, label: &str) -> Result<()> {
+        let change = format!("-FLAGS ({})", label_to_keyword(label));
+        self.store_flags(id, &change).await
+    }
+
+    async fn mark_spam(&self, id: &str) -> Result<()> {
+        self.move_to(id, self.config.junk_folder()).await
+    }
+
+    async fn unspam(&self, id: &str) -> Result<()> {
+        self.move_to(id, INBOX).await
+    }
+
+    async fn archive(&self, id: &str) -> Result<()> {
+        self.move_to(id, self.config.archive_folder()).await
+    }
+
+    async fn trash(&self, id: &str) -> Result<()> {
+        self.move_to(id, self.config.trash_folder()).await
+    }
+
+    async fn send(&self, _to: &str, _subject: &str, _body: &str) -> Result<()> {
+        Err(ProviderError::Unsupported(ProviderOp::Send).into())
+    }
+
+    async fn create_draft(&self, reply_to_id: &str, body: &str) -> Result<String> {
+        let mut session = self.session.lock().await;
+        let original = self.fetch_raw(&mut session, reply_to_id).await?;
+        let message_id = new_message_id(&self.config.user, Utc::now());
+        let draft = reply_message(&original, &self.config.user, &message_id, body);
+
+        let drafts = self.config.drafts_folder();
+        session
+            .append(drafts, Some("(\\Draft \\Seen)"), None, draft.as_bytes())
+            .await?;
+        // The client doesn't surface UIDPLUS's APPENDUID, so look the draft
+        // up by the Message-ID it was given
+        session.select(drafts).await?;
+        let criteria = format!("HEADER Message-ID {}", quoted(&message_id));
+        let uid = session
+            .uid_search(&criteria)
+            .await?
+            .into_iter()
+            .max()
+            .with_context(|| format!("Saved the draft to {}, but couldn't find its UID", drafts))?;
+        Ok(format!("{}:{}", drafts, uid))
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        // Sending needs SMTP. Labels are keywords, which carry no color, and
+        // IMAP has no address book or importance flag. Counting a keyword
+        // means a search per mailbox, so it's left out.
+        ProviderCapabilities {
+            send: false,
+            label_color: false,
+            contacts: false,
+            importance: false,
+            label_count: false,
+            ..ProviderCapabilities::FULL
+        }
+    }
+}
+
+/// An email's ID: its Message-ID header, which stays the same when the
+/// message moves between mailboxes. Messages without one fall back to
+/// "<mailbox>:<uid>", which only holds until they move.
+fn message_key(raw: &str, mailbox: &str, uid: u32) -> String {
+    header_value(raw, "Message-ID")
+        .and_then(|value| value.split_whitespace().next().map(str::to_string))
+        .filter(|value| is_message_id(value))
+        .unwrap_or_else(|| format!("{}:{}", mailbox, uid))
+}
+
+fn is_message_id(id: &str) -> bool {
+    id.len() > 2 && id.starts_with('<') && id.ends_with('>')
+}
+
+/// An IMAP quoted string
+fn quoted(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// IMAP UIDs are only unique per mailbox, so fallback IDs are "<mailbox>:<uid>"
+fn parse_id(id: &str) -> Result<(&str, u32)> {
+    let (mailbox, uid) = id
+        .rsplit_once(':')
+        .with_context(|| format!("Invalid IMAP message ID: {}", id))?;
+    let uid = uid
+        .parse()
+        .with_context(|| format!("Invalid IMAP message ID: {}", id))?;
+    Ok((mailbox, uid))
+}
+
+/// Translate the `-label:X` and `is:unread`/`is:read` terms of a Gmail-style
+/// query into IMAP SEARCH criteria
+fn search_criteria(query: &str) -> Vec<String> {
+    let mut criteria = excluded_labels(query)
+        .into_iter()
+        .map(|label| format!("UNKEYWORD {}", label_to_keyword(label)))
+        .collect::<Vec<_>>();
+    match unread_filter(query) {
+        Some(true) => criteria.push("UNSEEN".to_string()),
+        Some(false) => criteria.push("SEEN".to_string()),
+        None => {}
+    }
+    criteria
+}
+
+/// Read a (possibly folded) header from a raw RFC 822 message
+fn header_value(raw: &str, name: &str) -> Option<String> {
+    let raw = raw.replace("\r\n", "\n");
+    let headers = raw.split("\n\n").next()?;
+    let prefix = format!("{}:", name.to_ascii_lowercase());
+    let mut lines = headers.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        if !line.to_ascii_lowercase().starts_with(&prefix) {
+            continue;
+        }
+
+        let mut value = line[prefix.len()..].trim().to_string();
+        while let Some(next) = lines.next_if(|next| next.starts_with([' ', '\t'])) {
+            value.push(' ');
+            value.push_str(next.trim());
+        }
+        return Some(value);
+    }
+
+    None
+}
+
+/// Filenames from `filename=` / `name=` parameters of the message's MIME parts
+fn attachment_filenames(raw: &str) -> Vec<String> {
+    let raw = raw.replace("\r\n", "\n");
+    let mut names = Vec::new();
+    for line in raw.lines() {
+        // Parameters live on Content-* header lines or their folded continuations
+        let lower = line.to_ascii_lowercase();
+        if !lower.starts_with("content-") && !line.starts_with([' ', '\t']) {
+            continue;
+        }
+        let Some(start) = lower
+            .find("filename=")
+            .map(|at| at + "filename=".len())
+            .or_else(|| lower.find(" name=").map(|at| at + " name=".len()))
+        else {
+            continue;
+        };
+        let value = line[start..].trim_start();
+        let name = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split('"').next().unwrap_or_default(),
+            None => value.split(';').next().unwrap_or_default().trim(),
+        };
+        if !name.is_empty() && !names.iter().any(|seen| seen == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// IMAP has no portable thread ID, so use the Message-ID that started the
+/// conversation: the first References entry, else In-Reply-To, else our own
+fn thread_root(raw: &str) -> Option<String> {
+    ["References", "In-Reply-To", "Message-ID"]
+        .iter()
+        .find_map(|name| header_value(raw, name))
+        .and_then(|value| value.split_whitespace().next().map(str::to_string))
+}
+
+/// A Message-ID for a message we create, unique per call and on the
+/// sender's domain
+fn new_message_id(from: &str, now: DateTime<Utc>) -> String {
+    let domain = sender_address(from)
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.to_string())
+        .filter(|domain| !domain.is_empty())
+        .unwrap_or_else(|| "localhost".to_string());
+    format!(
+        "<{:x}.{}@{}>",
+        now.timestamp_nanos_opt().unwrap_or_default(),
+        std::process::id(),
+        domain
+    )
+}
+
+/// Build an RFC 822 reply threaded onto `original` via In-Reply-To/References
+fn reply_message(original: &str, from: &str, message_id: &str, body: &str) -> String {
+    let subject = header_value(original, "Subject").unwrap_or_default();
+    let subject = if subject.to_ascii_lowercase().starts_with("re:") {
+        subject
+    } else {
+        format!("Re: {}", subject)
+    };
+    let to = header_value(original, "Reply-To")
+        .or_else(|| header_value(original, "From"))
+        .unwrap_or_default();
+
+    let mut headers = vec![
+        ("From", from.to_string()),
+        ("Message-ID", message_id.to_string()),
+    ];
+    if let Some(original_id) = header_value(original, "Message-ID") {
+        let references = header_value(original, "References")
+            .map(|refs| format!("{} {}", refs, original_id))
+            .unwrap_or_else(|| original_id.clone());
+        headers.push(("In-Reply-To", original_id));
+        headers.push(("References", references));
+    }
+
+    mime::Message {
+        to: &to,
+        subject: &subject,
+        body,
+        headers,
+    }
+    .to_rfc822()
+}
+
+fn message_body(raw: &str) -> String {
+    if let Some(text) = extract_text_from_mime(raw) {
+        return text;
+    }
+
+    let raw = raw.replace("\r\n", "\n");
+    raw.split_once("\n\n")
+        .map(|(_, body)| body.trim().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::Classification;
+    use crate::predictions::PredictionStore;
+    use chrono::TimeZone;
+
+    const RAW: &str = "From: Alice <alice@example.com>\r\n\
+Subject: Quarterly\r\n report\r\n\
+To: me@example.com\r\n\
+\r\n\
+Plain body text.\r\n";
+
+    #[test]
+    fn test_header_value_unfolds_continuation_lines() {
+        assert_eq!(
+            header_value(RAW, "from").as_deref(),
+            Some("Alice <alice@example.com>")
+        );
+        assert_eq!(
+            header_value(RAW, "Subject").as_deref(),
+            Some("Quarterly report")
+        );
+        assert_eq!(header_value(RAW, "Cc"), None);
+    }
+
+    #[test]
+    fn test_message_body_without_content_type() {
+        assert_eq!(message_body(RAW), "Plain body text.");
+    }
+
+    #[test]
+    fn test_attachment_filenames_from_mime_parts() {
+        let raw = "Subject: Receipt\r\nContent-Type: multipart/mixed; boundary=b\r\n\r\n--b\r\nContent-Type: text/plain\r\n\r\nThanks\r\n--b\r\nContent-Type: application/pdf; name=\"receipt 42.pdf\"\r\nContent-Disposition: attachment; filename=\"receipt 42.pdf\"\r\n\r\nJVBERi0=\r\n--b\r\nContent-Disposition: attachment; filename=notes.txt\r\n\r\nhi\r\n--b--\r\n";
+        assert_eq!(
+            attachment_filenames(raw),
+            vec!["receipt 42.pdf", "notes.txt"]
+        );
+        assert!(attachment_filenames("Subject: Hi\r\n\r\nNo files here").is_empty());
+    }
+
+    #[test]
+    fn test_search_criteria_from_query() {
+        assert_eq!(
+            search_criteria("-label:Classified -in:spam"),
+            vec!["UNKEYWORD Classified"]
+        );
+        assert_eq!(
+            search_criteria("-label:Classified is:unread"),
+            vec!["UNKEYWORD Classified", "UNSEEN"]
+        );
+        assert_eq!(label_to_keyword("Needs Reply"), "Needs_Reply");
+        assert_eq!(label_to_keyword("Finance/Receipts"), "Finance/Receipts");
+    }
+
+    #[test]
+    fn test_reply_message_threads_onto_original() {
+        let original = "From: Alice <alice@example.com>\r\n\
+Subject: Lunch\r\n\
+Message-ID: <abc@example.com>\r\n\r\n";
+        let reply = reply_message(original, "me@example.com", "<draft@example.com>", "Sure.");
+
+        assert_eq!(
+            header_value(&reply, "Subject").as_deref(),
+            Some("Re: Lunch")
+        );
+        assert_eq!(
+            header_value(&reply, "To").as_deref(),
+            Some("Alice <alice@example.com>")
+        );
+        assert_eq!(
+            header_value(&reply, "In-Reply-To").as_deref(),
+            Some("<abc@example.com>")
+        );
+        assert_eq!(
+            header_value(&reply, "Message-ID").as_deref(),
+            Some("<draft@example.com>")
+        );
+
+        let now = Utc.with_ymd_and_hms(2025, 3, 1, 9, 0, 0).unwrap();
+        let draft_id = new_message_id("Me <me@example.com>", now);
+        assert!(is_message_id(&draft_id));
+        assert!(draft_id.ends_with("@example.com>"));
+        assert_ne!(
+            draft_id,
+            new_message_id(
+                "Me <me@example.com>",
+                now + chrono::Duration::nanoseconds(1)
+            )
+        );
+        assert!(reply.ends_with("\r\n\r\nSure.\r\n"));
+    }
+
+    #[test]
+    fn test_moved_email_keeps_its_id_and_prediction() {
+        let raw = "From: Shop <news@shop.com>\r\n\
+Message-ID:\r\n <sale-42@shop.com>\r\n\
+Subject: Sale\r\n\r\nToday only.\r\n";
+        let in_inbox = message_key(raw, INBOX, 17);
+        let archived = message_key(raw, "Archive", 3);
+        assert_eq!(in_inbox, "<sale-42@shop.com>");
+        assert_eq!(archived, in_inbox);
+
+        let mut predictions = PredictionStore::default();
+        let email = Email {
+            id: in_inbox,
+            ..Default::default()
+        };
+        let classification = Classification {
+            is_spam: false,
+            archive: true,
+            delete: false,
+            theme: vec!["Shopping".to_string()],
+            action: vec![],
+            confidence: 0.9,
+            reason: None,
+            heuristic: false,
+        };
+        predictions.store(&email, &classification, vec![]).unwrap();
+        assert!(predictions.get(&archived).is_some());
+
+        // Without a Message-ID the UID is all there is
+        let anonymous = "Subject: Hi\r\n\r\nHello\r\n";
+        assert_eq!(message_key(anonymous, INBOX, 17), "INBOX:17");
+        assert_eq!(quoted("<a\"b@x>"), "\"<a\\\"b@x>\"");
+    }
+
+    #[test]
+    fn test_parse_id_with_colon_in_mailbox() {
+        assert_eq!(parse_id("Archive:2024:42").unwrap(), ("Archive:2024", 42));
+        assert!(parse_id("INBOX").is_err());
+    }
+}
@@ -0,0 +1,724 @@
+use super::body::strip_html;
+use super::{
+    bearer_http as http, env_credential, excluded_labels, keyword_to_label, label_to_keyword,
+    unread_filter, Email, EmailProvider, Label, ProviderCapabilities, ProviderError, ProviderOp,
+    SortOrder,
+};
+use crate::config::Config;
+use crate::headers;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+const CORE_CAPABILITY: &str = "urn:ietf:params:jmap:core";
+const MAIL_CAPABILITY: &str = "urn:ietf:params:jmap:mail";
+/// Body bytes fetched per message, well past what the classifier reads
+const MAX_BODY_BYTES: u64 = 256 * 1024;
+const EMAIL_PROPERTIES: &[&str] = &[
+    "id",
+    "threadId",
+    "mailboxIds",
+    "keywords",
+    "from",
+    "to",
+    "subject",
+    "receivedAt",
+    "hasAttachment",
+    "attachments",
+    "textBody",
+    "htmlBody",
+    "bodyValues",
+    "header:List-Id:asText",
+    "messageId",
+    // headers::SECURITY_HEADERS
+    "header:Reply-To:asText",
+    "header:Return-Path:asText",
+    "header:List-Unsubscribe:asText",
+    "header:Authentication-Results:asText",
+    "header:Received-SPF:asText",
+];
+
+/// JMAP (RFC 8621) provider, e.g. Fastmail. Labels are keywords, as with
+/// IMAP; INBOX, SPAM, TRASH, SENT, and DRAFT come from mailbox roles, and
+/// other mailboxes show up as labels under their own names.
+pub struct JmapProvider {
+    token: String,
+    api_url: String,
+    account_id: String,
+    mailboxes: Mailboxes,
+}
+
+/// The part of the session resource the provider needs
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Session {
+    api_url: String,
+    primary_accounts: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Mailbox {
+    id: String,
+    name: String,
+    #[serde(default)]
+    role: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Mailboxes {
+    all: Vec<Mailbox>,
+}
+
+impl Mailboxes {
+    fn with_role(&self, role: &str) -> Option<&str> {
+        self.all
+            .iter()
+            .find(|mailbox| mailbox.role.as_deref() == Some(role))
+            .map(|mailbox| mailbox.id.as_str())
+    }
+
+    fn require_role(&self, role: &str) -> Result<&str> {
+        self.with_role(role)
+            .with_context(|| format!("The account has no mailbox with the {} role", role))
+    }
+
+    /// A mailbox without a special role, by name
+    fn named(&self, name: &str) -> Option<&str> {
+        self.all
+            .iter()
+            .find(|mailbox| mailbox.role.is_none() && mailbox.name == name)
+            .map(|mailbox| mailbox.id.as_str())
+    }
+
+    /// The pseudo-label an email in this mailbox carries, if any: the Gmail
+    /// system label for role mailboxes, the name for plain ones
+    fn label(&self, id: &str) -> Option<String> {
+        let mailbox = self.all.iter().find(|mailbox| mailbox.id == id)?;
+        match mailbox.role.as_deref() {
+            None => Some(mailbox.name.clone()),
+            Some("inbox") => Some("INBOX".to_string()),
+            Some("junk") => Some("SPAM".to_string()),
+            Some("trash") => Some("TRASH".to_string()),
+            Some("sent") => Some("SENT".to_string()),
+            Some("drafts") => Some("DRAFT".to_string()),
+            Some(_) => None,
+        }
+    }
+
+    /// Mailbox for a system label that names one
+    fn for_label(&self, label: &str) -> Result<Option<&str>> {
+        let role = match label {
+            "INBOX" => "inbox",
+            "SPAM" => "junk",
+            "TRASH" => "trash",
+            "SENT" => "sent",
+            "DRAFT" => "drafts",
+            _ => return Ok(self.named(label)),
+        };
+        self.require_role(role).map(Some)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct JmapEmail {
+    id: String,
+    thread_id: Option<String>,
+    mailbox_ids: HashMap<String, bool>,
+    keywords: HashMap<String, bool>,
+    from: Option<Vec<Address>>,
+    to: Option<Vec<Address>>,
+    subject: Option<String>,
+    received_at: Option<DateTime<Utc>>,
+    has_attachment: bool,
+    attachments: Vec<BodyPart>,
+    text_body: Vec<BodyPart>,
+    html_body: Vec<BodyPart>,
+    body_values: HashMap<String, BodyValue>,
+    #[serde(rename = "header:List-Id:asText")]
+    list_id: Option<String>,
+    message_id: Option<Vec<String>>,
+    references: Option<Vec<String>>,
+    /// The other `header:*:asText` properties
+    #[serde(flatten)]
+    headers: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Address {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct BodyPart {
+    part_id: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BodyValue {
+    value: String,
+}
+
+impl JmapProvider {
+    pub async fn new() -> Result<Self> {
+        let config = Config::load()?.jmap.unwrap_or_default();
+        let token = env_credential("JMAP_TOKEN")
+            .or(config.token.clone())
+            .filter(|token| !token.is_empty())
+            .context(
+                "JMAP not configured. Add a \"jmap\" section with a token to config.json \
+                 or set EMAIL_ASSISTANT_JMAP_TOKEN",
+            )?;
+
+        let response = http(&token, config.session_url(), None).await?;
+        let session: Session =
+            serde_json::from_str(&response).context("Unexpected JMAP session resource")?;
+        let account_id = session
+            .primary_accounts
+            .get(MAIL_CAPABILITY)
+            .cloned()
+            .context("The JMAP account has no mail access")?;
+
+        let mut provider = Self {
+            token,
+            api_url: session.api_url,
+            account_id,
+            mailboxes: Mailboxes::default(),
+        };
+        let [mailboxes] = provider
+            .call([("Mailbox/get", json!({ "ids": null }))])
+            .await?;
+        provider.mailboxes.all = serde_json::from_value(mailboxes["list"].clone())
+            .context("Unexpected Mailbox/get response")?;
+        Ok(provider)
+    }
+
+    /// Send method calls as one request, returning each call's arguments.
+    /// Every call gets the account ID; a later call can refer to an earlier
+    /// one's result as call "0", "1", ...
+    async fn call<const N: usize>(&self, calls: [(&str, Value); N]) -> Result<[Value; N]> {
+        let method_calls = calls
+            .into_iter()
+            .enumerate()
+            .map(|(index, (method, mut args))| {
+                args["accountId"] = json!(self.account_id);
+                json!([method, args, index.to_string()])
+            })
+            .collect::<Vec<_>>();
+        let request = json!({
+            "using": [CORE_CAPABILITY, MAIL_CAPABILITY],
+            "methodCalls": method_calls,
+        });
+
+        let response = http(&self.token, &self.api_url, Some(&request.to_string())).await?;
+        let results = method_results(&response)?;
+        results.try_into().map_err(|results: Vec<Value>| {
+            anyhow::anyhow!("Expected {} JMAP responses, got {}", N, results.len())
+        })
+    }
+
+    /// Apply an Email/set patch to one email
+    async fn update(&self, id: &str, patch: Value) -> Result<()> {
+        let [result] = self
+            .call([("Email/set", json!({ "update": { id: patch } }))])
+            .await?;
+        if let Some(error) = result["notUpdated"].get(id) {
+            anyhow::bail!("Couldn't update email {}: {}", id, set_error(error));
+        }
+        Ok(())
+    }
+
+    async fn get_emails(&self, ids: Value, extra_properties: &[&str]) -> Result<Vec<JmapEmail>> {
+        let mut properties = EMAIL_PROPERTIES.to_vec();
+        properties.extend_from_slice(extra_properties);
+        let [result] = self
+            .call([("Email/get", email_get_args(ids, &properties))])
+            .await?;
+        emails_from_get(&result)
+    }
+
+    fn to_email(&self, email: JmapEmail) -> Email {
+        let mut labels = email
+            .mailbox_ids
+            .iter()
+            .filter(|(_, &member)| member)
+            .filter_map(|(id, _)| self.mailboxes.label(id))
+            .collect::<Vec<_>>();
+        labels.sort();
+        labels.extend(keyword_labels(&email.keywords));
+
+        let body = body_text(&email);
+        let attachment_names = email
+            .attachments
+            .iter()
+            .filter_map(|part| part.name.clone())
+            .collect::<Vec<_>>();
+        Email {
+            id: email.id,
+            from: format_addresses(email.from.as_deref()),
+            to: format_addresses(email.to.as_deref()),
+            subject: email.subject.unwrap_or_else(|| "(no subject)".to_string()),
+            body,
+            labels,
+            has_attachments: email.has_attachment || !attachment_names.is_empty(),
+            attachment_names,
+            date: email.received_at,
+            list_id: email.list_id.map(|list_id| list_id.trim().to_string()),
+            message_id: email.message_id.and_then(|ids| ids.into_iter().next()),
+            thread_id: email.thread_id,
+            content_type: None,
+            calendar: None,
+            headers: headers::collect(|name| {
+                email
+                    .headers
+                    .get(&format!("header:{}:asText", name))
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+            }),
+        }
+    }
+}
+
+/// Email/query conditions for a label and the `-label:X` and
+/// `is:unread`/`is:read` terms of a query.
+/// An empty label means all mail outside spam and trash.
+fn email_filter(label: &str, query: Option<&str>, mailboxes: &Mailboxes) -> Result<Option<Value>> {
+    let mut conditions = Vec::new();
+    if label.is_empty() {
+        let skipped = ["junk", "trash"]
+            .iter()
+            .filter_map(|role| mailboxes.with_role(role))
+            .collect::<Vec<_>>();
+        if !skipped.is_empty() {
+            conditions.push(json!({ "inMailboxOtherThan": skipped }));
+        }
+    } else {
+        conditions.push(match mailboxes.for_label(label)? {
+            Some(mailbox) => json!({ "inMailbox": mailbox }),
+            None => json!({ "hasKeyword": label_to_keyword(label) }),
+        });
+    }
+    for excluded in query.map(excluded_labels).unwrap_or_default() {
+        conditions.push(json!({ "notKeyword": label_to_keyword(excluded) }));
+    }
+    match query.and_then(unread_filter) {
+        Some(true) => conditions.push(json!({ "notKeyword": "$seen" })),
+        Some(false) => conditions.push(json!({ "hasKeyword": "$seen" })),
+        None => {}
+    }
+
+    Ok(match conditions.len() {
+        0 => None,
+        1 => conditions.pop(),
+        _ => Some(json!({ "operator": "AND", "conditions": conditions })),
+    })
+}
+
+fn email_get_args(ids: Value, properties: &[&str]) -> Value {
+    let (key, ids) = match ids {
+        // A back-reference to an earlier call's result
+        Value::Object(reference) => ("#ids", Value::Object(reference)),
+        ids => ("ids", ids),
+    };
+    let mut args = Map::new();
+    args.insert(key.to_string(), ids);
+    args.insert("properties".to_string(), json!(properties));
+    args.insert("fetchTextBodyValues".to_string(), json!(true));
+    args.insert("fetchHTMLBodyValues".to_string(), json!(true));
+    args.insert("maxBodyValueBytes".to_string(), json!(MAX_BODY_BYTES));
+    Value::Object(args)
+}
+
+fn emails_from_get(result: &Value) -> Result<Vec<JmapEmail>> {
+    serde_json::from_value(result["list"].clone()).context("Unexpected Email/get response")
+}
+
+/// Arguments of each method response, failing on the first error response
+fn method_results(response: &str) -> Result<Vec<Value>> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Response {
+        method_responses: Vec<(String, Value, String)>,
+    }
+
+    let response: Response = serde_json::from_str(response)
+        .with_context(|| format!("Unexpected JMAP response: {}", response.trim()))?;
+    response
+        .method_responses
+        .into_iter()
+        .map(|(name, args, _)| {
+            if name == "error" {
+                anyhow::bail!("JMAP request failed: {}", set_error(&args));
+            }
+            Ok(args)
+        })
+        .collect()
+}
+
+/// "type: description" of a JMAP method or SetError
+fn set_error(error: &Value) -> String {
+    let kind = error["type"].as_str().unwrap_or("unknown error");
+    match error["description"].as_str() {
+        Some(description) => format!("{}: {}", kind, description),
+        None => kind.to_string(),
+    }
+}
+
+/// UNREAD and STARRED from the system keywords, labels from the rest
+fn keyword_labels(keywords: &HashMap<String, bool>) -> Vec<String> {
+    let has = |keyword: &str| keywords.get(keyword).copied().unwrap_or(false);
+    let mut labels = keywords
+        .iter()
+        .filter(|(keyword, &set)| set && !keyword.starts_with('$'))
+        .map(|(keyword, _)| keyword_to_label(keyword))
+        .collect::<Vec<_>>();
+    labels.sort();
+    if has("$flagged") {
+        labels.push("STARRED".to_string());
+    }
+    if !has("$seen") {
+        labels.push("UNREAD".to_string());
+    }
+    labels
+}
+
+/// Email/set patch that sets or clears a label's keyword
+fn keyword_patch(label: &str, set: bool) -> Value {
+    let keyword = format!("keywords/{}", label_to_keyword(label));
+    json!({ keyword: if set { Value::Bool(true) } else { Value::Null } })
+}
+
+fn format_addresses(addresses: Option<&[Address]>) -> String {
+    addresses
+        .unwrap_or_default()
+        .iter()
+        .map(|address| {
+            let email = address.email.as_deref().unwrap_or_default();
+            match address.name.as_deref().filter(|name| !name.is_empty()) {
+                Some(name) => format!("{} <{}>", name, email),
+                None => email.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The text/plain parts, or the HTML parts as text when there are none
+fn body_text(email: &JmapEmail) -> String {
+    let values = |parts: &[BodyPart]| {
+        parts
+            .iter()
+            .filter_map(|part| email.body_values.get(part.part_id.as_deref()?))
+            .map(|value| value.value.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let text = values(&email.text_body);
+    if !text.trim().is_empty() {
+        return text.trim().to_string();
+    }
+    strip_html(&values(&email.html_body))
+}
+
+#[async_trait]
+impl EmailProvider for JmapProvider {
+    /// One request: Email/query, with Email/get fetching its results
+    async fn list_messages(
+        &self,
+        max: u32,
+        label: &str,
+        query: Option<&str>,
+        order: SortOrder,
+    ) -> Result<Vec<Email>> {
+        let filter = email_filter(label, query, &self.mailboxes)?;
+        let sort = json!([{
+            "property": "receivedAt",
+            "isAscending": order == SortOrder::OldestFirst,
+        }]);
+        let ids = json!({ "resultOf": "0", "name": "Email/query", "path": "/ids" });
+        let [_, result] = self
+            .call([
+                (
+                    "Email/query",
+                    json!({ "filter": filter, "sort": sort, "limit": max }),
+                ),
+                ("Email/get", email_get_args(ids, EMAIL_PROPERTIES)),
+            ])
+            .await?;
+        Ok(emails_from_get(&result)?
+            .into_iter()
+            .map(|email| self.to_email(email))
+            .collect())
+    }
+
+    async fn get_message(&self, id: &str) -> Result<Email> {
+        let email = self
+            .get_emails(json!([id]), &[])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ProviderError::NotFound(format!("Message {}", id)))?;
+        Ok(self.to_email(email))
+    }
+
+    /// Mailboxes only: JMAP can't list the keywords in use without reading
+    /// every email
+    async fn list_labels(&self) -> Result<Vec<Label>> {
+        Ok(self
+            .mailboxes
+            .all
+            .iter()
+            .filter_map(|mailbox| {
+                Some(Label {
+                    id: mailbox.id.clone(),
+                    name: self.mailboxes.label(&mailbox.id)?,
+                })
+            })
+            .collect())
+    }
+
+    async fn add_label(&self, id: &str, label: &str) -> Result<()> {
+        self.update(id, keyword_patch(label, true)).await
+    }
+
+    async fn remove_label(&self, id: &str, label: &str) -> Result<()> {
+        self.update(id, keyword_patch(label, false)).await
+    }
+
+    async fn mark_spam(&self, id: &str) -> Result<()> {
+        let junk = self.mailboxes.require_role("junk")?;
+        let patch = json!({
+            "mailboxIds": { junk: true },
+            "keywords/$junk": true,
+            "keywords/$notjunk": null,
+        });
+        self.update(id, patch).await
+    }
+
+    async fn unspam(&self, id: &str) -> Result<()> {
+        let inbox = self.mailboxes.require_role("inbox")?;
+        let patch = json!({
+            "mailboxIds": { inbox: true },
+            "keywords/$junk": null,
+            "keywords/$notjunk": true,
+        });
+        self.update(id, patch).await
+    }
+
+    /// Out of the inbox into the archive mailbox; other mailboxes it's in stay
+    async fn archive(&self, id: &str) -> Result<()> {
+        let inbox = self.mailboxes.require_role("inbox")?;
+        let mut patch = Map::new();
+        patch.insert(format!("mailboxIds/{}", inbox), Value::Null);
+        if let Some(archive) = self.mailboxes.with_role("archive") {
+            patch.insert(format!("mailboxIds/{}", archive), json!(true));
+        }
+        self.update(id, Value::Object(patch)).await
+    }
+
+    async fn trash(&self, id: &str) -> Result<()> {
+        let trash = self.mailboxes.require_role("trash")?;
+        self.update(id, json!({ "mailboxIds": { trash: true } }))
+            .await
+    }
+
+    async fn send(&self, _to: &str, _subject: &str, _body: &str) -> Result<()> {
+        Err(ProviderError::Unsupported(ProviderOp::Send).into())
+    }
+
+    async fn create_draft(&self, reply_to_id: &str, body: &str) -> Result<String> {
+        let drafts = self.mailboxes.require_role("drafts")?;
+        let original = self
+            .get_emails(json!([reply_to_id]), &["messageId", "references"])
+            .await?
+            .into_iter()
+            .next()
+            .with_context(|| format!("Message not found: {}", reply_to_id))?;
+
+        let subject = original.subject.as_deref().unwrap_or_default();
+        let subject = if subject.to_lowercase().starts_with("re:") {
+            subject.to_string()
+        } else {
+            format!("Re: {}", subject)
+        };
+        let mut references = original.references.clone().unwrap_or_default();
+        references.extend(original.message_id.clone().unwrap_or_default());
+        let to = original
+            .from
+            .unwrap_or_default()
+            .into_iter()
+            .map(|address| json!({ "name": address.name, "email": address.email }))
+            .collect::<Vec<_>>();
+        let draft = json!({
+            "mailboxIds": { drafts: true },
+            "keywords": { "$draft": true, "$seen": true },
+            "to": to,
+            "subject": subject,
+            "inReplyTo": original.message_id,
+            "references": references,
+            "bodyValues": { "body": { "value": body } },
+            "textBody": [{ "partId": "body", "type": "text/plain" }],
+        });
+
+        let [result] = self
+            .call([("Email/set", json!({ "create": { "draft": draft } }))])
+            .await?;
+        if let Some(error) = result["notCreated"].get("draft") {
+            anyhow::bail!("Couldn't save draft: {}", set_error(error));
+        }
+        result["created"]["draft"]["id"]
+            .as_str()
+            .map(str::to_string)
+            .context("Email/set didn't return the draft's ID")
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        // Sending needs an identity and EmailSubmission, keywords carry no
+        // color, contacts live in a separate JMAP extension, and there's no
+        // standard importance flag. Keywords have no per-label totals.
+        ProviderCapabilities {
+            send: false,
+            label_color: false,
+            contacts: false,
+            importance: false,
+            label_count: false,
+            ..ProviderCapabilities::FULL
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mailboxes() -> Mailboxes {
+        let mailbox = |id: &str, name: &str, role: Option<&str>| Mailbox {
+            id: id.to_string(),
+            name: name.to_string(),
+            role: role.map(str::to_string),
+        };
+        Mailboxes {
+            all: vec![
+                mailbox("m1", "Inbox", Some("inbox")),
+                mailbox("m2", "Spam", Some("junk")),
+                mailbox("m3", "Trash", Some("trash")),
+                mailbox("m4", "Archive", Some("archive")),
+                mailbox("m5", "Receipts", None),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_email_filter_maps_labels_to_mailboxes_and_keywords() {
+        let mailboxes = mailboxes();
+        let inbox = email_filter("INBOX", Some("-label:Classified"), &mailboxes).unwrap();
+        assert_eq!(
+            inbox,
+            Some(json!({
+                "operator": "AND",
+                "conditions": [{ "inMailbox": "m1" }, { "notKeyword": "Classified" }],
+            }))
+        );
+        assert_eq!(
+            email_filter("Receipts", None, &mailboxes).unwrap(),
+            Some(json!({ "inMailbox": "m5" }))
+        );
+        assert_eq!(
+            email_filter("Needs Reply", None, &mailboxes).unwrap(),
+            Some(json!({ "hasKeyword": "Needs_Reply" }))
+        );
+        assert_eq!(
+            email_filter("", None, &mailboxes).unwrap(),
+            Some(json!({ "inMailboxOtherThan": ["m2", "m3"] }))
+        );
+        assert!(email_filter("SENT", None, &mailboxes).is_err());
+    }
+
+    #[test]
+    fn test_jmap_email_to_pseudo_labels() {
+        let response = r#"{"methodResponses": [["Email/get", {"list": [{
+            "id": "e1", "threadId": "t1",
+            "mailboxIds": {"m1": true, "m5": true},
+            "keywords": {"$flagged": true, "finance/receipts": true, "needs-reply": true},
+            "from": [{"name": "Shop", "email": "orders@shop.example"}],
+            "to": [{"name": null, "email": "me@example.com"}],
+            "subject": "Your order",
+            "receivedAt": "2024-05-01T10:00:00Z",
+            "hasAttachment": true,
+            "attachments": [{"partId": "3", "name": "invoice.pdf"}],
+            "textBody": [],
+            "htmlBody": [{"partId": "2"}],
+            "bodyValues": {"2": {"value": "<p>Thanks &amp; enjoy</p>"}},
+            "header:List-Id:asText": " <news.shop.example>",
+            "header:Reply-To:asText": "help@shop.example",
+            "header:Return-Path:asText": null
+        }]}, "1"]]}"#;
+        let [result] = <[Value; 1]>::try_from(method_results(response).unwrap()).unwrap();
+        let email = emails_from_get(&result).unwrap().pop().unwrap();
+        let provider = JmapProvider {
+            token: String::new(),
+            api_url: String::new(),
+            account_id: String::new(),
+            mailboxes: mailboxes(),
+        };
+        let email = provider.to_email(email);
+
+        assert_eq!(
+            email.labels,
+            vec![
+                "INBOX",
+                "Receipts",
+                "Finance/Receipts",
+                "Needs-Reply",
+                "STARRED",
+                "UNREAD"
+            ]
+        );
+        assert_eq!(email.from, "Shop <orders@shop.example>");
+        assert_eq!(email.to, "me@example.com");
+        assert_eq!(email.body, "Thanks & enjoy");
+        assert_eq!(email.attachment_names, vec!["invoice.pdf"]);
+        assert_eq!(email.list_id.as_deref(), Some("<news.shop.example>"));
+        assert_eq!(
+            email.headers,
+            HashMap::from([("Reply-To".to_string(), "help@shop.example".to_string())])
+        );
+        assert_eq!(email.thread_id.as_deref(), Some("t1"));
+    }
+
+    #[test]
+    fn test_keyword_patches_round_trip_labels() {
+        assert_eq!(
+            keyword_patch("Needs Reply", true),
+            json!({ "keywords/Needs_Reply": true })
+        );
+        assert_eq!(
+            keyword_patch("FYI", false),
+            json!({ "keywords/F~Y~I": null })
+        );
+
+        // The label written by a patch reads back unchanged, even lowercased
+        for label in ["FYI", "snake_case", "Finance/Receipts"] {
+            let patch = keyword_patch(label, true);
+            let (path, _) = patch.as_object().unwrap().iter().next().unwrap();
+            let keyword = path.strip_prefix("keywords/").unwrap().to_lowercase();
+            let keywords = HashMap::from([(keyword, true), ("$seen".to_string(), true)]);
+            assert_eq!(keyword_labels(&keywords), vec![label]);
+        }
+    }
+
+    #[test]
+    fn test_method_error_fails_the_request() {
+        let response = r#"{"methodResponses": [["error", {"type": "accountNotFound"}, "0"]]}"#;
+        let error = method_results(response).unwrap_err();
+        assert!(error.to_string().contains("accountNotFound"));
+    }
+}
@@ -0,0 +1,124 @@
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A plain-text RFC 822 message, for providers that send or store raw MIME.
+/// Extra headers (In-Reply-To, From, ...) are written as given.
+#[derive(Debug, Default)]
+pub struct Message<'a> {
+    pub to: &'a str,
+    pub subject: &'a str,
+    pub body: &'a str,
+    pub headers: Vec<(&'static str, String)>,
+}
+
+impl Message<'_> {
+    /// Render with CRLF line endings, as RFC 822 requires
+    pub fn to_rfc822(&self) -> String {
+        let mut lines = self
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect::<Vec<_>>();
+        lines.push(format!("To: {}", self.to));
+        lines.push(format!("Subject: {}", encode_header(self.subject)));
+        lines.push("MIME-Version: 1.0".to_string());
+        lines.push("Content-Type: text/plain; charset=\"UTF-8\"".to_string());
+        lines.push("Content-Transfer-Encoding: 8bit".to_string());
+
+        let body = self.body.replace("\r\n", "\n").replace('\n', "\r\n");
+        format!("{}\r\n\r\n{}\r\n", lines.join("\r\n"), body)
+    }
+}
+
+/// RFC 2047 encoded-word for non-ASCII header values
+pub fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+    format!("=?UTF-8?B?{}?=", base64(value.as_bytes()))
+}
+
+pub fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | u32::from(*byte) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// URL-safe base64 without padding, as Gmail's `raw` field expects
+pub fn base64url(bytes: &[u8]) -> String {
+    base64(bytes)
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_")
+}
+
+/// Decode standard or URL-safe base64, padded or not, ignoring line breaks.
+/// None on any other character.
+pub fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(text.len() / 4 * 3);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in text.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' | b'\r' | b'\n' | b' ' | b'\t' => continue,
+            _ => return None,
+        };
+        buffer = buffer << 6 | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_variants() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(base64url(&[0xfb, 0xff]), "-_8");
+        assert_eq!(decode_base64("TWE=").unwrap(), b"Ma");
+        assert_eq!(decode_base64("-_8").unwrap(), [0xfb, 0xff]);
+        assert_eq!(decode_base64("TW\r\nFu").unwrap(), b"Man");
+        assert!(decode_base64("T*").is_none());
+    }
+
+    #[test]
+    fn test_message_headers_and_crlf_body() {
+        let message = Message {
+            to: "alice@example.com",
+            subject: "Café plans",
+            body: "Line one\nLine two",
+            headers: vec![("In-Reply-To", "<abc@example.com>".to_string())],
+        };
+
+        let raw = message.to_rfc822();
+
+        assert!(raw.starts_with("In-Reply-To: <abc@example.com>\r\nTo: alice@example.com\r\n"));
+        assert!(raw.contains("Subject: =?UTF-8?B?Q2Fmw6kgcGxhbnM=?=\r\n"));
+        assert!(raw.ends_with("\r\n\r\nLine one\r\nLine two\r\n"));
+    }
+}
@@ -0,0 +1,284 @@
+use super::{
+    excluded_labels, sort_by_date, unread_filter, Email, EmailProvider, Label, ProviderError,
+    SortOrder,
+};
+use crate::config;
+use crate::labels::LabelColor;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Emails `--provider mock` starts from when no fixture file exists
+const SAMPLE_FIXTURE: &str = include_str!("../../fixtures/mock-emails.json");
+
+/// In-memory provider for tests and `--provider mock`. Every action changes
+/// the in-memory mailbox the way the real providers would (archive drops
+/// INBOX, trash moves to TRASH, ...), and nothing is written back. Tests can
+/// also inspect the calls made and have specific operations fail.
+#[derive(Default)]
+pub struct MockProvider {
+    emails: Mutex<HashMap<String, Email>>,
+    /// Addresses returned by `list_contacts`
+    pub contacts: Vec<String>,
+    /// Operations ("add_label", "archive", "trash", ...) that should fail
+    failing: HashSet<&'static str>,
+    calls: Mutex<Vec<String>>,
+}
+
+impl MockProvider {
+    pub fn with_emails(emails: impl IntoIterator<Item = Email>) -> Self {
+        Self {
+            emails: Mutex::new(
+                emails
+                    .into_iter()
+                    .map(|email| (email.id.clone(), email))
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    /// The mailbox in EMAIL_ASSISTANT_MOCK_FIXTURE, else `mock-emails.json`
+    /// in the config directory, else a built-in sample: a JSON list of emails
+    pub fn from_fixture() -> Result<Self> {
+        let path = std::env::var("EMAIL_ASSISTANT_MOCK_FIXTURE")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| config::config_dir().join("mock-emails.json"));
+        let content = if path.exists() {
+            std::fs::read_to_string(&path)?
+        } else {
+            SAMPLE_FIXTURE.to_string()
+        };
+        let emails: Vec<Email> = serde_json::from_str(&content)
+            .with_context(|| format!("{} must be a list of emails", path.display()))?;
+        Ok(Self::with_emails(emails))
+    }
+
+    #[cfg(test)]
+    pub fn failing_on(mut self, operation: &'static str) -> Self {
+        self.failing.insert(operation);
+        self
+    }
+
+    #[cfg(test)]
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Record the call, then apply `change` to the email unless the
+    /// operation was set to fail. Unknown IDs are recorded and ignored.
+    fn record(
+        &self,
+        operation: &'static str,
+        call: String,
+        id: &str,
+        change: impl FnOnce(&mut Vec<String>),
+    ) -> Result<()> {
+        self.calls.lock().unwrap().push(call);
+        if self.failing.contains(operation) {
+            anyhow::bail!("simulated {} failure", operation);
+        }
+        if let Some(email) = self.emails.lock().unwrap().get_mut(id) {
+            change(&mut email.labels);
+        }
+        Ok(())
+    }
+}
+
+fn add(labels: &mut Vec<String>, label: &str) {
+    if !labels.iter().any(|existing| existing == label) {
+        labels.push(label.to_string());
+    }
+}
+
+fn remove(labels: &mut Vec<String>, label: &str) {
+    labels.retain(|existing| existing != label);
+}
+
+#[async_trait]
+impl EmailProvider for MockProvider {
+    /// An empty label lists every email; `-label:X` and `is:unread`/`is:read`
+    /// query terms are honored
+    async fn list_messages(
+        &self,
+        max: u32,
+        label: &str,
+        query: Option<&str>,
+        order: SortOrder,
+    ) -> Result<Vec<Email>> {
+        if self.failing.contains("list_messages") {
+            anyhow::bail!("simulated list_messages failure");
+        }
+        let excluded = query.map(excluded_labels).unwrap_or_default();
+        let unread = query.and_then(unread_filter);
+        let has = |email: &Email, label: &str| email.labels.iter().any(|l| l == label);
+        let mut emails = self
+            .emails
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|email| label.is_empty() || has(email, label))
+            .filter(|email| !excluded.iter().any(|label| has(email, label)))
+            .filter(|email| unread.is_none_or(|unread| has(email, "UNREAD") == unread))
+            .cloned()
+            .collect::<Vec<_>>();
+        sort_by_date(&mut emails, order);
+        emails.truncate(max as usize);
+        Ok(emails)
+    }
+
+    async fn get_message(&self, id: &str) -> Result<Email> {
+        if self.failing.contains("get_message") {
+            anyhow::bail!("simulated get_message failure");
+        }
+        self.emails
+            .lock()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| ProviderError::NotFound(format!("Message {}", id)).into())
+    }
+
+    async fn list_labels(&self) -> Result<Vec<Label>> {
+        let names = self
+            .emails
+            .lock()
+            .unwrap()
+            .values()
+            .flat_map(|email| email.labels.clone())
+            .collect::<BTreeSet<_>>();
+        Ok(names
+            .into_iter()
+            .map(|name| Label {
+                id: name.clone(),
+                name,
+            })
+            .collect())
+    }
+
+    async fn add_label(&self, id: &str, label: &str) -> Result<()> {
+        let call = format!("add_label {} {}", id, label);
+        self.record("add_label", call, id, |labels| add(labels, label))
+    }
+
+    async fn remove_label(&self, id: &str, label: &str) -> Result<()> {
+        let call = format!("remove_label {} {}", id, label);
+        self.record("remove_label", call, id, |labels| remove(labels, label))
+    }
+
+    async fn mark_spam(&self, id: &str) -> Result<()> {
+        self.record("mark_spam", format!("mark_spam {}", id), id, |labels| {
+            remove(labels, "INBOX");
+            add(labels, "SPAM");
+        })
+    }
+
+    async fn unspam(&self, id: &str) -> Result<()> {
+        self.record("unspam", format!("unspam {}", id), id, |labels| {
+            remove(labels, "SPAM");
+            add(labels, "INBOX");
+        })
+    }
+
+    async fn archive(&self, id: &str) -> Result<()> {
+        self.record("archive", format!("archive {}", id), id, |labels| {
+            remove(labels, "INBOX")
+        })
+    }
+
+    async fn trash(&self, id: &str) -> Result<()> {
+        self.record("trash", format!("trash {}", id), id, |labels| {
+            remove(labels, "INBOX");
+            add(labels, "TRASH");
+        })
+    }
+
+    async fn send(&self, to: &str, subject: &str, _body: &str) -> Result<()> {
+        self.record("send", format!("send {} {}", to, subject), "", |_| {})
+    }
+
+    async fn create_draft(&self, reply_to_id: &str, body: &str) -> Result<String> {
+        let call = format!("create_draft {} {}", reply_to_id, body);
+        self.record("create_draft", call, "", |_| {})?;
+        Ok(format!("draft-{}", reply_to_id))
+    }
+
+    async fn set_label_color(&self, label: &str, color: &LabelColor) -> Result<()> {
+        let call = format!("set_label_color {} {}", label, color.name);
+        self.record("set_label_color", call, "", |_| {})
+    }
+
+    async fn list_contacts(&self) -> Result<Vec<String>> {
+        Ok(self.contacts.clone())
+    }
+
+    async fn count_label(&self, label: &str) -> Result<u32> {
+        let emails = self.emails.lock().unwrap();
+        let count = emails
+            .values()
+            .filter(|email| email.labels.iter().any(|existing| existing == label))
+            .count();
+        Ok(count as u32)
+    }
+
+    /// Importance is Gmail's IMPORTANT system label here
+    async fn set_important(&self, id: &str) -> Result<()> {
+        self.record(
+            "set_important",
+            format!("set_important {}", id),
+            id,
+            |labels| add(labels, "IMPORTANT"),
+        )
+    }
+
+    async fn clear_important(&self, id: &str) -> Result<()> {
+        let call = format!("clear_important {}", id);
+        self.record("clear_important", call, id, |labels| {
+            remove(labels, "IMPORTANT")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_actions_change_the_mailbox() {
+        let emails: Vec<Email> = serde_json::from_str(SAMPLE_FIXTURE).unwrap();
+        let provider = MockProvider::with_emails(emails);
+        let inbox = provider
+            .list_messages(
+                100,
+                "INBOX",
+                Some("-label:Classified"),
+                SortOrder::default(),
+            )
+            .await
+            .unwrap();
+        assert!(inbox.len() > 1);
+        let (first, second) = (&inbox[0].id, &inbox[1].id);
+
+        provider.add_label(first, "Classified").await.unwrap();
+        provider.archive(first).await.unwrap();
+        provider.trash(second).await.unwrap();
+
+        let remaining = provider
+            .list_messages(
+                100,
+                "INBOX",
+                Some("-label:Classified"),
+                SortOrder::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(remaining.len(), inbox.len() - 2);
+        let archived = provider.get_message(first).await.unwrap();
+        assert!(archived.labels.contains(&"Classified".to_string()));
+        assert!(!archived.labels.contains(&"INBOX".to_string()));
+        let trashed = provider.list_messages(100, "TRASH", None, SortOrder::default());
+        assert_eq!(trashed.await.unwrap()[0].id, *second);
+    }
+}
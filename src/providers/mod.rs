@@ -1,11 +1,38 @@
+pub mod aliased;
+pub mod body;
+pub mod device_login;
 pub mod gmail;
+pub mod imap;
+pub mod jmap;
+pub mod mime;
+#[cfg(any(test, debug_assertions, feature = "mock"))]
+pub mod mock;
 pub mod outlook;
 pub mod outlook_web;
+pub mod refresh;
+pub mod traced;
 
-use anyhow::Result;
+use crate::labels::LabelColor;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::Instant;
 
-#[derive(Debug, Clone)]
+/// Prefix of the environment variables credentials and tokens can come from
+const ENV_PREFIX: &str = "EMAIL_ASSISTANT_";
+
+/// Deserializable so `--provider mock` can load a mailbox from a JSON fixture
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Email {
     pub id: String,
     pub from: String,
@@ -13,6 +40,355 @@ pub struct Email {
     pub subject: String,
     pub body: String,
     pub labels: Vec<String>,
+    pub has_attachments: bool,
+    pub attachment_names: Vec<String>,
+    /// When the provider received the email, if known
+    pub date: Option<DateTime<Utc>>,
+    /// The List-Id header, present on mailing-list and newsletter mail
+    pub list_id: Option<String>,
+    /// The Message-ID header, which copies of one delivery share
+    pub message_id: Option<String>,
+    /// Conversation this email belongs to, in the provider's own ID scheme
+    pub thread_id: Option<String>,
+    /// Top-level MIME type (e.g. "text/calendar"), when the provider exposes it
+    pub content_type: Option<String>,
+    /// iCalendar text of an invite's text/calendar part, when the provider exposes it
+    pub calendar: Option<String>,
+    /// Those of `headers::SECURITY_HEADERS` the message has
+    pub headers: HashMap<String, String>,
+}
+
+/// Order of messages returned by `list_messages`, by received date
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    #[default]
+    NewestFirst,
+    OldestFirst,
+}
+
+/// Client-side ordering for providers without native sort.
+/// Emails without a date keep their relative order at the end.
+pub fn sort_by_date(emails: &mut [Email], order: SortOrder) {
+    emails.sort_by(|a, b| match (a.date, b.date) {
+        (Some(a), Some(b)) => match order {
+            SortOrder::NewestFirst => b.cmp(&a),
+            SortOrder::OldestFirst => a.cmp(&b),
+        },
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+}
+
+/// "Name <addr@example.com>" → "addr@example.com", lowercased. Also handles
+/// the older "addr@example.com (Name)" form and stray quotes.
+pub fn sender_address(from: &str) -> String {
+    let address = match (from.rfind('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &from[start + 1..end],
+        _ => from
+            .split_whitespace()
+            .find(|word| word.contains('@'))
+            .unwrap_or(from),
+    };
+    address
+        .trim_matches(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '(' | ')'))
+        .to_lowercase()
+}
+
+/// Labels named by the `-label:X` terms of a Gmail-style query; other terms
+/// (e.g. `-in:spam`) are left to providers that understand them
+pub fn excluded_labels(query: &str) -> Vec<&str> {
+    query
+        .split_whitespace()
+        .filter_map(|term| term.strip_prefix("-label:"))
+        .filter(|label| !label.is_empty())
+        .collect()
+}
+
+/// Read state asked for by an `is:unread` (true) or `is:read` (false) term of
+/// a Gmail-style query, for providers that filter it themselves
+pub fn unread_filter(query: &str) -> Option<bool> {
+    query.split_whitespace().rev().find_map(|term| match term {
+        "is:unread" => Some(true),
+        "is:read" => Some(false),
+        _ => None,
+    })
+}
+
+/// Marks a letter whose case differs from the label-style default
+const CASE_TOGGLE: char = '~';
+/// Starts a two-digit hex escape of one UTF-8 byte
+const BYTE_ESCAPE: char = '+';
+
+/// The IMAP/JMAP keyword for a label. Keywords are atoms and servers may
+/// lowercase them, so letters are written in the default label style (a
+/// capital starting each word, after a space, '-', or '/') with `~` before
+/// any that differ, spaces become '_', and every other character outside
+/// [A-Za-z0-9-/] is a `+XX` byte escape: "Needs Reply" → "Needs_Reply",
+/// "FYI" → "F~Y~I", "snake_case" → "~snake+5Fcase".
+pub fn label_to_keyword(label: &str) -> String {
+    let mut keyword = String::with_capacity(label.len());
+    let mut word_start = true;
+    for c in label.chars() {
+        match c {
+            ' ' => keyword.push('_'),
+            '-' | '/' => keyword.push(c),
+            _ if c.is_ascii_alphabetic() => {
+                if c.is_ascii_uppercase() != word_start {
+                    keyword.push(CASE_TOGGLE);
+                }
+                keyword.push(c);
+            }
+            _ if c.is_ascii_digit() => keyword.push(c),
+            _ => {
+                let mut bytes = [0; 4];
+                for byte in c.encode_utf8(&mut bytes).bytes() {
+                    keyword.push_str(&format!("{}{:02X}", BYTE_ESCAPE, byte));
+                }
+            }
+        }
+        word_start = matches!(c, ' ' | '-' | '/');
+    }
+    keyword
+}
+
+/// The label `label_to_keyword` made `keyword` from, whatever case the
+/// server returns it in
+pub fn keyword_to_label(keyword: &str) -> String {
+    let mut bytes = Vec::with_capacity(keyword.len());
+    let mut word_start = true;
+    let mut toggled = false;
+    let mut chars = keyword.chars();
+    while let Some(c) = chars.next() {
+        let escaped = (c == BYTE_ESCAPE)
+            .then(|| chars.as_str().get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let Some(byte) = escaped {
+            chars.nth(1);
+            bytes.push(byte);
+            word_start = false;
+            continue;
+        }
+        match c {
+            CASE_TOGGLE => {
+                toggled = true;
+                continue;
+            }
+            '_' => bytes.push(b' '),
+            _ if c.is_ascii_alphabetic() => {
+                let upper = word_start != toggled;
+                let c = if upper {
+                    c.to_ascii_uppercase()
+                } else {
+                    c.to_ascii_lowercase()
+                };
+                bytes.push(c as u8);
+            }
+            _ => bytes.extend_from_slice(c.to_string().as_bytes()),
+        }
+        word_start = matches!(c, '_' | '-' | '/');
+        toggled = false;
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// A provider operation that a backend may or may not support
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderOp {
+    List,
+    ListByLabel,
+    Label,
+    Spam,
+    Archive,
+    Trash,
+    Send,
+    Draft,
+    LabelColor,
+    Contacts,
+    Importance,
+    LabelCount,
+}
+
+impl fmt::Display for ProviderOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::List => "listing the inbox",
+            Self::ListByLabel => "listing by label",
+            Self::Label => "labeling",
+            Self::Spam => "marking spam",
+            Self::Archive => "archiving",
+            Self::Trash => "trashing",
+            Self::Send => "sending",
+            Self::Draft => "saving drafts",
+            Self::LabelColor => "coloring labels",
+            Self::Contacts => "listing contacts",
+            Self::Importance => "setting importance",
+            Self::LabelCount => "counting emails per label",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Typed provider failures callers may want to tell apart from transport errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderError {
+    /// The backend can't perform this operation at all
+    Unsupported(ProviderOp),
+    /// The named message or label doesn't exist (any more)
+    NotFound(String),
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported(op) => write!(f, "{} is not supported by this provider", op),
+            Self::NotFound(what) => write!(f, "{} not found", what),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+impl ProviderError {
+    /// Whether `error` is (or wraps) an `Unsupported` provider error
+    pub fn is_unsupported(error: &anyhow::Error) -> bool {
+        matches!(
+            error.downcast_ref::<ProviderError>(),
+            Some(ProviderError::Unsupported(_))
+        )
+    }
+
+    /// Whether `error` is (or wraps) a `NotFound` provider error. Anything
+    /// else, such as a timeout or rate limit, says nothing about existence.
+    pub fn is_not_found(error: &anyhow::Error) -> bool {
+        matches!(
+            error.downcast_ref::<ProviderError>(),
+            Some(ProviderError::NotFound(_))
+        )
+    }
+}
+
+/// HTTP status of a failed API call. The provider crates surface HTTP errors
+/// as text, such as curl's "The requested URL returned error: 404" or
+/// "HTTP 404 Not Found", so only a number in one of those positions counts;
+/// digits elsewhere in the message (IDs, subjects) are ignored.
+pub(crate) fn http_status(error: &anyhow::Error) -> Option<u16> {
+    error.chain().find_map(|cause| {
+        let message = cause.to_string();
+        ["returned error: ", "HTTP "].iter().find_map(|marker| {
+            message.match_indices(marker).find_map(|(at, _)| {
+                let rest = &message[at + marker.len()..];
+                let digits = rest.get(..3)?;
+                let followed_by_digit = rest[3..].starts_with(|c: char| c.is_ascii_digit());
+                if followed_by_digit || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                digits.parse().ok()
+            })
+        })
+    })
+}
+
+/// `error` as `ProviderError::NotFound` when the API answered 404, else unchanged
+pub(crate) fn not_found_on_404(error: anyhow::Error, what: String) -> anyhow::Error {
+    if http_status(&error) == Some(404) {
+        ProviderError::NotFound(what).into()
+    } else {
+        error
+    }
+}
+
+/// Operations a provider supports; anything false fails with
+/// `ProviderError::Unsupported`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProviderCapabilities {
+    pub list: bool,
+    pub list_by_label: bool,
+    pub label: bool,
+    pub spam: bool,
+    pub archive: bool,
+    pub trash: bool,
+    pub send: bool,
+    pub draft: bool,
+    pub label_color: bool,
+    /// Listing the account's contacts
+    pub contacts: bool,
+    /// Setting the provider's own important flag
+    pub importance: bool,
+    /// Counting the emails that carry a label
+    pub label_count: bool,
+    /// Moving an email to an arbitrary folder
+    pub move_to_folder: bool,
+    /// Marking an email read or unread
+    pub read_toggle: bool,
+}
+
+impl ProviderCapabilities {
+    /// Everything the `EmailProvider` trait can do. Moving and read toggling
+    /// have no trait method yet, so no provider offers them.
+    pub const FULL: Self = Self {
+        list: true,
+        list_by_label: true,
+        label: true,
+        spam: true,
+        archive: true,
+        trash: true,
+        send: true,
+        draft: true,
+        label_color: true,
+        contacts: true,
+        importance: true,
+        label_count: true,
+        move_to_folder: false,
+        read_toggle: false,
+    };
+
+    /// Each capability with its user-facing name, in display order
+    pub fn entries(&self) -> [(&'static str, bool); 14] {
+        [
+            ("list", self.list),
+            ("list-by-label", self.list_by_label),
+            ("label", self.label),
+            ("spam", self.spam),
+            ("archive", self.archive),
+            ("trash", self.trash),
+            ("send", self.send),
+            ("draft", self.draft),
+            ("label-color", self.label_color),
+            ("contacts", self.contacts),
+            ("importance", self.importance),
+            ("label-count", self.label_count),
+            ("move", self.move_to_folder),
+            ("read-toggle", self.read_toggle),
+        ]
+    }
+
+    pub fn supports(&self, op: ProviderOp) -> bool {
+        match op {
+            ProviderOp::List => self.list,
+            ProviderOp::ListByLabel => self.list_by_label,
+            ProviderOp::Label => self.label,
+            ProviderOp::Spam => self.spam,
+            ProviderOp::Archive => self.archive,
+            ProviderOp::Trash => self.trash,
+            ProviderOp::Send => self.send,
+            ProviderOp::Draft => self.draft,
+            ProviderOp::LabelColor => self.label_color,
+            ProviderOp::Contacts => self.contacts,
+            ProviderOp::Importance => self.importance,
+            ProviderOp::LabelCount => self.label_count,
+        }
+    }
+
+    /// Fail up front, before doing any work the operation would waste
+    pub fn require(&self, op: ProviderOp) -> Result<()> {
+        if self.supports(op) {
+            Ok(())
+        } else {
+            Err(ProviderError::Unsupported(op).into())
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -21,17 +397,274 @@ pub struct Label {
     pub name: String,
 }
 
+/// Bodies recovered by a raw refetch, keyed by message ID.
+#[derive(Default)]
+pub struct BodyCache {
+    bodies: Mutex<HashMap<String, String>>,
+}
+
+impl BodyCache {
+    pub fn get(&self, id: &str) -> Option<String> {
+        self.bodies.lock().ok()?.get(id).cloned()
+    }
+
+    pub fn insert(&self, id: &str, body: &str) {
+        if let Ok(mut bodies) = self.bodies.lock() {
+            bodies.insert(id.to_string(), body.to_string());
+        }
+    }
+}
+
+/// Token bucket shared by every request a provider client makes, so large
+/// scans stay under the API's per-user quota instead of drawing 429s.
+/// Up to `burst` requests go out at once; after that, one per interval.
+pub struct RateLimiter {
+    interval: Duration,
+    burst_window: Duration,
+    /// When the bucket is next completely full (GCRA theoretical arrival time)
+    next_free: tokio::sync::Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        let interval = Duration::from_secs_f64(1.0 / requests_per_second);
+        Self {
+            interval,
+            burst_window: interval * burst.saturating_sub(1),
+            next_free: tokio::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    /// A limiter allowing about one second's worth of requests as a burst
+    pub fn per_second(requests_per_second: f64) -> Self {
+        Self::new(
+            requests_per_second,
+            requests_per_second.ceil().max(1.0) as u32,
+        )
+    }
+
+    /// Wait until a request may be sent
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut next_free = self.next_free.lock().await;
+            let now = Instant::now();
+            let start = (*next_free).max(now);
+            *next_free = start + self.interval;
+            start.saturating_duration_since(now + self.burst_window)
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// An OAuth credential from the environment when set (see `env_credential`),
+/// else from the provider's config file. Lets servers and CI keep secrets out
+/// of config files.
+pub fn credential_from_env_or(name: &str, file_value: Option<&str>) -> Result<String> {
+    credential_from(&process_env, name, file_value)
+}
+
+/// Looks up an environment variable; tests pass a fixed set instead of
+/// changing the process environment under other threads
+type Env<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+fn process_env(var: &str) -> Option<String> {
+    std::env::var(var).ok()
+}
+
+fn credential_from(env: Env, name: &str, file_value: Option<&str>) -> Result<String> {
+    credential_in(env, name)
+        .or_else(|| file_value.map(str::to_string))
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Missing OAuth credential: set {}{} or add it to the provider config",
+                ENV_PREFIX,
+                name
+            )
+        })
+}
+
+/// `EMAIL_ASSISTANT_<name>`, or the unprefixed `<name>` accepted before the
+/// prefix was introduced; blank values count as unset
+pub fn env_credential(name: &str) -> Option<String> {
+    credential_in(&process_env, name)
+}
+
+fn credential_in(env: Env, name: &str) -> Option<String> {
+    [format!("{}{}", ENV_PREFIX, name), name.to_string()]
+        .iter()
+        .filter_map(|var| env(var))
+        .find(|value| !value.trim().is_empty())
+}
+
+/// Access and refresh token from `<provider>_ACCESS_TOKEN` and
+/// `<provider>_REFRESH_TOKEN`, for containers with no token file. Only the
+/// refresh token is required: a missing access token is refreshed at startup.
+pub fn tokens_from_env(provider: &str) -> Option<(String, String)> {
+    tokens_in(&process_env, provider)
+}
+
+fn tokens_in(env: Env, provider: &str) -> Option<(String, String)> {
+    let refresh_token = credential_in(env, &format!("{}_REFRESH_TOKEN", provider))?;
+    let access_token =
+        credential_in(env, &format!("{}_ACCESS_TOKEN", provider)).unwrap_or_default();
+    Some((access_token, refresh_token))
+}
+
+/// GET (no body) or POST JSON with the bearer token, returning the response
+/// body. The token goes through stdin so it never shows up in `ps`.
+pub async fn bearer_http(token: &str, url: &str, body: Option<&str>) -> Result<String> {
+    let method = if body.is_some() { "POST" } else { "GET" };
+    bearer_request(token, method, url, body).await
+}
+
+/// `bearer_http` with an explicit method, for PATCH and DELETE
+pub async fn bearer_request(
+    token: &str,
+    method: &str,
+    url: &str,
+    body: Option<&str>,
+) -> Result<String> {
+    let mut command = Command::new("curl");
+    command.args(["-sS", "--fail-with-body", "-X", method, "-H", "@-"]);
+    if let Some(body) = body {
+        command
+            .args(["-H", "Content-Type: application/json", "--data-binary"])
+            .arg(body);
+    }
+    let mut child = command
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run curl")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(format!("Authorization: Bearer {}\n", token).as_bytes())
+            .await?;
+    }
+
+    let output = child.wait_with_output().await?;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!(
+            "Request to {} failed: {} {}",
+            url,
+            stderr.trim(),
+            stdout.trim()
+        );
+    }
+    Ok(stdout)
+}
+
+/// Whether the message's structure gave no usable text part, leaving only a
+/// snippet or preview to classify
+pub fn text_part_missing(text: Option<&str>) -> bool {
+    text.is_none_or(|text| text.trim().is_empty())
+}
+
+/// The body from the message's text part. When its structure had none, the
+/// raw message is refetched and re-extracted, keeping it or `fallback` (the
+/// snippet or preview), whichever is longer. A short text part is just a
+/// short email and costs no second call.
+pub async fn body_with_retry<F, Fut>(
+    text: Option<String>,
+    fallback: Option<String>,
+    fetch_raw: F,
+) -> String
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    if let Some(text) = text.filter(|text| !text_part_missing(Some(text))) {
+        return text;
+    }
+
+    let body = fallback.unwrap_or_default();
+    let refetched = match fetch_raw().await {
+        Ok(raw) => extract_text_from_mime(&raw),
+        Err(_) => None,
+    };
+
+    match refetched {
+        Some(text) if text.chars().count() > body.trim().chars().count() => text,
+        _ => body,
+    }
+}
+
+/// Extract the first non-base64 text/plain part from a raw RFC 822 message.
+pub fn extract_text_from_mime(raw: &str) -> Option<String> {
+    let raw = raw.replace("\r\n", "\n");
+    let lower = raw.to_ascii_lowercase();
+    let mut offset = 0;
+
+    while let Some(found) = lower[offset..].find("content-type: text/plain") {
+        let header_start = offset + found;
+        let body_start = header_start + raw[header_start..].find("\n\n")? + 2;
+        offset = body_start;
+
+        if lower[header_start..body_start].contains("content-transfer-encoding: base64") {
+            continue;
+        }
+
+        let part = &raw[body_start..];
+        let end = part.find("\n--").unwrap_or(part.len());
+        let text = part[..end].replace("=\n", "").trim().to_string();
+        if !text.is_empty() {
+            return Some(text);
+        }
+    }
+
+    None
+}
+
 #[async_trait]
 pub trait EmailProvider: Send + Sync {
-    async fn list_messages(&self, max: u32, label: &str, query: Option<&str>)
-        -> Result<Vec<Email>>;
+    async fn list_messages(
+        &self,
+        max: u32,
+        label: &str,
+        query: Option<&str>,
+        order: SortOrder,
+    ) -> Result<Vec<Email>>;
     async fn get_message(&self, id: &str) -> Result<Email>;
     async fn list_labels(&self) -> Result<Vec<Label>>;
     async fn add_label(&self, id: &str, label: &str) -> Result<()>;
+    async fn remove_label(&self, id: &str, label: &str) -> Result<()>;
     async fn mark_spam(&self, id: &str) -> Result<()>;
     async fn unspam(&self, id: &str) -> Result<()>;
     async fn archive(&self, id: &str) -> Result<()>;
     async fn trash(&self, id: &str) -> Result<()>;
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<()>;
+    /// Save a reply to `reply_to_id` as a draft, returning the draft's ID
+    async fn create_draft(&self, reply_to_id: &str, body: &str) -> Result<String>;
+    /// Show `label` in `color`, creating the label if needed
+    async fn set_label_color(&self, _label: &str, _color: &LabelColor) -> Result<()> {
+        Err(ProviderError::Unsupported(ProviderOp::LabelColor).into())
+    }
+    /// Email addresses of the account's contacts
+    async fn list_contacts(&self) -> Result<Vec<String>> {
+        Err(ProviderError::Unsupported(ProviderOp::Contacts).into())
+    }
+    /// Flag the email important in the provider's own UI
+    async fn set_important(&self, _id: &str) -> Result<()> {
+        Err(ProviderError::Unsupported(ProviderOp::Importance).into())
+    }
+    async fn clear_important(&self, _id: &str) -> Result<()> {
+        Err(ProviderError::Unsupported(ProviderOp::Importance).into())
+    }
+    /// How many emails carry `label`, from the provider's own count
+    async fn count_label(&self, _label: &str) -> Result<u32> {
+        Err(ProviderError::Unsupported(ProviderOp::LabelCount).into())
+    }
+    /// Operations this backend can perform
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities::FULL
+    }
 }
 
 #[async_trait]
@@ -41,8 +674,9 @@ impl EmailProvider for Box<dyn EmailProvider> {
         max: u32,
         label: &str,
         query: Option<&str>,
+        order: SortOrder,
     ) -> Result<Vec<Email>> {
-        (**self).list_messages(max, label, query).await
+        (**self).list_messages(max, label, query, order).await
     }
     async fn get_message(&self, id: &str) -> Result<Email> {
         (**self).get_message(id).await
@@ -53,6 +687,9 @@ impl EmailProvider for Box<dyn EmailProvider> {
     async fn add_label(&self, id: &str, label: &str) -> Result<()> {
         (**self).add_label(id, label).await
     }
+    async fn remove_label(&self, id: &str, label: &str) -> Result<()> {
+        (**self).remove_label(id, label).await
+    }
     async fn mark_spam(&self, id: &str) -> Result<()> {
         (**self).mark_spam(id).await
     }
@@ -65,4 +702,293 @@ impl EmailProvider for Box<dyn EmailProvider> {
     async fn trash(&self, id: &str) -> Result<()> {
         (**self).trash(id).await
     }
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<()> {
+        (**self).send(to, subject, body).await
+    }
+    async fn create_draft(&self, reply_to_id: &str, body: &str) -> Result<String> {
+        (**self).create_draft(reply_to_id, body).await
+    }
+    async fn set_label_color(&self, label: &str, color: &LabelColor) -> Result<()> {
+        (**self).set_label_color(label, color).await
+    }
+    async fn list_contacts(&self) -> Result<Vec<String>> {
+        (**self).list_contacts().await
+    }
+    async fn set_important(&self, id: &str) -> Result<()> {
+        (**self).set_important(id).await
+    }
+    async fn clear_important(&self, id: &str) -> Result<()> {
+        (**self).clear_important(id).await
+    }
+    async fn count_label(&self, label: &str) -> Result<u32> {
+        (**self).count_label(label).await
+    }
+    fn capabilities(&self) -> ProviderCapabilities {
+        (**self).capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAW_MESSAGE: &str = "From: sender@example.com\r\n\
+Content-Type: multipart/alternative; boundary=\"b1\"\r\n\
+\r\n\
+--b1\r\n\
+Content-Type: text/plain; charset=\"UTF-8\"\r\n\
+\r\n\
+Hi, the quarterly invoice is attached. Please review it before Friday.\r\n\
+--b1\r\n\
+Content-Type: text/html; charset=\"UTF-8\"\r\n\
+\r\n\
+<p>Hi, the quarterly invoice is attached.</p>\r\n\
+--b1--\r\n";
+
+    /// A fixed environment holding `vars`
+    fn env_with(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |var| vars.get(var).cloned()
+    }
+
+    #[test]
+    fn test_env_credentials_take_precedence() {
+        let (name, var) = ("TEST_CLIENT_ID", "EMAIL_ASSISTANT_TEST_CLIENT_ID");
+        let empty = env_with(&[]);
+        assert_eq!(
+            credential_from(&empty, name, Some("from-file")).unwrap(),
+            "from-file"
+        );
+        assert!(credential_from(&empty, name, None).is_err());
+
+        let unprefixed = env_with(&[(name, "unprefixed")]);
+        assert_eq!(
+            credential_from(&unprefixed, name, Some("from-file")).unwrap(),
+            "unprefixed"
+        );
+        let both = env_with(&[(name, "unprefixed"), (var, "from-env")]);
+        assert_eq!(
+            credential_from(&both, name, Some("from-file")).unwrap(),
+            "from-env"
+        );
+        let blank = env_with(&[(var, "  ")]);
+        assert_eq!(
+            credential_from(&blank, name, Some("from-file")).unwrap(),
+            "from-file"
+        );
+    }
+
+    #[test]
+    fn test_tokens_from_env_need_a_refresh_token() {
+        let (access, refresh) = (
+            "EMAIL_ASSISTANT_TEST_ACCESS_TOKEN",
+            "EMAIL_ASSISTANT_TEST_REFRESH_TOKEN",
+        );
+        assert_eq!(tokens_in(&env_with(&[(access, "at")]), "TEST"), None);
+        assert_eq!(
+            tokens_in(&env_with(&[(refresh, "rt")]), "TEST"),
+            Some((String::new(), "rt".to_string()))
+        );
+    }
+
+    fn make_dated_email(id: &str, day: Option<u32>) -> Email {
+        Email {
+            id: id.to_string(),
+            date: day.map(|day| {
+                chrono::NaiveDate::from_ymd_opt(2024, 1, day)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap()
+                    .and_utc()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sort_by_date_applies_order() {
+        let mut emails = vec![
+            make_dated_email("mid", Some(15)),
+            make_dated_email("undated", None),
+            make_dated_email("old", Some(1)),
+            make_dated_email("new", Some(30)),
+        ];
+        let ids = |emails: &[Email]| emails.iter().map(|e| e.id.clone()).collect::<Vec<_>>();
+
+        sort_by_date(&mut emails, SortOrder::OldestFirst);
+        assert_eq!(ids(&emails), vec!["old", "mid", "new", "undated"]);
+
+        sort_by_date(&mut emails, SortOrder::NewestFirst);
+        assert_eq!(ids(&emails), vec!["new", "mid", "old", "undated"]);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_paces_calls() {
+        // Paused, the clock only moves when every task is waiting on a timer
+        tokio::time::pause();
+        let limiter = RateLimiter::new(100.0, 1);
+        let started = Instant::now();
+
+        for _ in 0..11 {
+            limiter.acquire().await;
+        }
+
+        // 10 intervals of 10ms after the first call
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(99), "{:?}", elapsed);
+        assert!(elapsed <= Duration::from_millis(101), "{:?}", elapsed);
+
+        let bursty = RateLimiter::per_second(100.0);
+        let started = Instant::now();
+        for _ in 0..50 {
+            bursty.acquire().await;
+        }
+        assert_eq!(started.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_body_retry_when_first_extraction_empty() {
+        let body = body_with_retry(None, None, || async { Ok(RAW_MESSAGE.to_string()) }).await;
+        assert_eq!(
+            body,
+            "Hi, the quarterly invoice is attached. Please review it before Friday."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_body_retry_keeps_original_when_refetch_fails() {
+        let body = body_with_retry(None, Some("Short snippet".to_string()), || async {
+            anyhow::bail!("not found")
+        })
+        .await;
+        assert_eq!(body, "Short snippet");
+    }
+
+    #[tokio::test]
+    async fn test_body_retry_skipped_for_complete_body() {
+        let long_body = "word ".repeat(100);
+        let body = body_with_retry(Some(long_body.clone()), None, || async {
+            panic!("should not refetch a complete body")
+        })
+        .await;
+        assert_eq!(body, long_body);
+    }
+
+    #[tokio::test]
+    async fn test_body_retry_skipped_for_short_text_part() {
+        let body = body_with_retry(
+            Some("Thanks, see you at 3.".to_string()),
+            Some("Thanks, see you".to_string()),
+            || async { panic!("a short email's text part is its whole body") },
+        )
+        .await;
+        assert_eq!(body, "Thanks, see you at 3.");
+        assert!(text_part_missing(Some("  \n")));
+        assert!(!text_part_missing(Some("ok")));
+    }
+
+    #[test]
+    fn test_sender_address_from_header_formats() {
+        for (from, expected) in [
+            ("Jane Doe <Jane@Example.com>", "jane@example.com"),
+            ("\"Doe, Jane\" <jane@example.com>", "jane@example.com"),
+            ("<jane@example.com>", "jane@example.com"),
+            ("jane@example.com", "jane@example.com"),
+            ("  JANE@example.com  ", "jane@example.com"),
+            ("jane@example.com (Jane Doe)", "jane@example.com"),
+            ("\"jane@example.com\"", "jane@example.com"),
+            ("Newsletter", "newsletter"),
+        ] {
+            assert_eq!(sender_address(from), expected, "{}", from);
+        }
+    }
+
+    #[test]
+    fn test_require_unsupported_op_is_detectable() {
+        let capabilities = ProviderCapabilities {
+            send: false,
+            ..ProviderCapabilities::FULL
+        };
+        assert!(capabilities.require(ProviderOp::Archive).is_ok());
+
+        let error = capabilities
+            .require(ProviderOp::Send)
+            .unwrap_err()
+            .context("Failed to send");
+        assert!(ProviderError::is_unsupported(&error));
+        assert!(!ProviderError::is_unsupported(&anyhow::anyhow!("timeout")));
+
+        let missing = anyhow::Error::from(ProviderError::NotFound("Label Old".to_string()))
+            .context("Failed to list");
+        assert!(ProviderError::is_not_found(&missing));
+        assert!(!ProviderError::is_not_found(&error));
+        assert!(!ProviderError::is_not_found(&anyhow::anyhow!(
+            "429 Too Many Requests"
+        )));
+    }
+
+    #[test]
+    fn test_label_keywords_round_trip_even_lowercased() {
+        for (label, keyword) in [
+            ("Needs Reply", "Needs_Reply"),
+            ("Needs-Reply", "Needs-Reply"),
+            ("Finance/Receipts", "Finance/Receipts"),
+            ("FYI", "F~Y~I"),
+            ("iPhone 15", "~i~Phone_15"),
+            ("snake_case", "~snake+5Fcase"),
+            ("Café & Bar", "Caf+C3+A9_+26_Bar"),
+            ("a~b+c", "~a+7Eb+2Bc"),
+        ] {
+            assert_eq!(label_to_keyword(label), keyword);
+            assert_eq!(keyword_to_label(keyword), label);
+            assert_eq!(keyword_to_label(&keyword.to_lowercase()), label);
+        }
+        // Keywords written before escaping read back as before
+        assert_eq!(keyword_to_label("needs_reply"), "Needs Reply");
+    }
+
+    #[test]
+    fn test_http_status_only_from_status_positions() {
+        let curl = anyhow::anyhow!("The requested URL returned error: 404").context("get_message");
+        assert_eq!(http_status(&curl), Some(404));
+        assert_eq!(
+            http_status(&anyhow::anyhow!("HTTP 401 Unauthorized")),
+            Some(401)
+        );
+        for message in [
+            "Message 404 has no body",
+            "Subject: Invoice 4010 unauthorized charge",
+            "HTTP 40123",
+            "connection reset",
+        ] {
+            assert_eq!(http_status(&anyhow::anyhow!(message)), None, "{}", message);
+        }
+
+        let missing = not_found_on_404(curl, "Message abc".to_string());
+        assert!(ProviderError::is_not_found(&missing));
+        let limited = not_found_on_404(
+            anyhow::anyhow!("The requested URL returned error: 429"),
+            "Message abc".to_string(),
+        );
+        assert!(!ProviderError::is_not_found(&limited));
+    }
+
+    #[test]
+    fn test_capability_entries_follow_fields() {
+        let capabilities = ProviderCapabilities {
+            draft: false,
+            ..ProviderCapabilities::FULL
+        };
+        let unsupported = capabilities
+            .entries()
+            .into_iter()
+            .filter(|(_, supported)| !supported)
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(unsupported, vec!["draft", "move", "read-toggle"]);
+        assert_eq!(capabilities.entries().len(), 14);
+    }
 }
@@ -1,34 +1,142 @@
-use super::{Email, EmailProvider, Label};
+use super::body::strip_html;
+use super::device_login::DeviceFlow;
+use super::refresh::RefreshingClient;
+use super::{
+    bearer_http, bearer_request, body_with_retry, credential_from_env_or, not_found_on_404,
+    text_part_missing, tokens_from_env, BodyCache, Email, EmailProvider, Label,
+    ProviderCapabilities, RateLimiter, SortOrder,
+};
+use crate::config::Config;
+use crate::headers;
+use crate::labels::LabelColor;
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use std::sync::{Arc, Mutex};
+
+const DEVICE_CODE_URL: &str = "https://login.microsoftonline.com/common/oauth2/v2.0/devicecode";
+const TOKEN_URL: &str = "https://login.microsoftonline.com/common/oauth2/v2.0/token";
+/// Mail access plus categories (mailbox settings) and a refresh token
+const DEVICE_LOGIN_SCOPE: &str = "offline_access https://graph.microsoft.com/Mail.ReadWrite \
+     https://graph.microsoft.com/Mail.Send https://graph.microsoft.com/MailboxSettings.ReadWrite";
+const MESSAGES_URL: &str = "https://graph.microsoft.com/v1.0/me/messages";
 
 pub struct OutlookProvider {
-    client: outlook::api::Client,
+    client: RefreshingClient<outlook::api::Client>,
+    /// Current access token, for Graph calls the outlook crate doesn't wrap
+    access_token: Arc<Mutex<String>>,
+    /// ID of the inbox folder, to tell inbox messages from archived ones
+    inbox_folder_id: Option<String>,
+    body_cache: BodyCache,
 }
 
 impl OutlookProvider {
     pub async fn new() -> Result<Self> {
-        let cfg = outlook::config::load_config()?;
-        let client_id = cfg.client_id();
+        let client_id = Self::client_id()?;
+
+        // Tokens from the environment replace the token file, which is then
+        // left alone
+        let env_tokens = tokens_from_env("OUTLOOK");
+        let persist_tokens = env_tokens.is_none();
+        let tokens = match env_tokens {
+            Some((access_token, refresh_token)) => outlook::config::Tokens {
+                access_token,
+                refresh_token,
+            },
+            None => outlook::config::load_tokens()
+                .context("Not logged in. Run 'outlook login' first")?,
+        };
 
-        let tokens =
-            outlook::config::load_tokens().context("Not logged in. Run 'outlook login' first")?;
+        let requests_per_second = Config::load().unwrap_or_default().requests_per_second();
 
-        // Try to use existing token, refresh if needed
-        let client = outlook::api::Client::new(&tokens.access_token);
+        // Refreshes run again whenever a call hits 401 mid-session
+        let refresh_token = tokens.refresh_token.clone();
+        let access_token = Arc::new(Mutex::new(tokens.access_token.clone()));
+        let refreshed_token = access_token.clone();
+        let client =
+            RefreshingClient::new(outlook::api::Client::new(&tokens.access_token), move || {
+                let (client_id, refresh_token, refreshed_token) = (
+                    client_id.clone(),
+                    refresh_token.clone(),
+                    refreshed_token.clone(),
+                );
+                async move {
+                    let new_tokens =
+                        outlook::auth::refresh_token(&client_id, &refresh_token).await?;
+                    if persist_tokens {
+                        outlook::config::save_tokens(&new_tokens)?;
+                    }
+                    if let Ok(mut token) = refreshed_token.lock() {
+                        token.clone_from(&new_tokens.access_token);
+                    }
+                    Ok(outlook::api::Client::new(&new_tokens.access_token))
+                }
+                .boxed()
+            })
+            .with_rate_limit(RateLimiter::per_second(requests_per_second));
 
         // Test if token works by listing one message
-        let client = match client.list_messages("inbox", None, 1).await {
-            Ok(_) => client,
-            Err(_) => {
-                // Token expired, try refresh
-                let new_tokens =
-                    outlook::auth::refresh_token(client_id, &tokens.refresh_token).await?;
-                outlook::api::Client::new(&new_tokens.access_token)
-            }
-        };
+        let probe = client
+            .call(|c| async move { c.list_messages("inbox", None, 1).await })
+            .await;
+        if probe.is_err() {
+            // Token expired, try refresh
+            client.refresh().await?;
+        }
+
+        let inbox_folder_id = client
+            .call(|c| async move { c.get_folder_id("inbox").await })
+            .await
+            .ok();
 
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            access_token,
+            inbox_folder_id,
+            body_cache: BodyCache::default(),
+        })
+    }
+
+    /// Sign in without a local browser, via the device-code grant
+    pub async fn device_login() -> Result<()> {
+        let client_id = Self::client_id()?;
+        let tokens = DeviceFlow {
+            device_code_url: DEVICE_CODE_URL,
+            token_url: TOKEN_URL,
+            client_id: &client_id,
+            scope: DEVICE_LOGIN_SCOPE,
+        }
+        .login()
+        .await?;
+        outlook::config::save_tokens(&outlook::config::Tokens {
+            access_token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
+        })
+    }
+
+    /// Client ID, from EMAIL_ASSISTANT_OUTLOOK_CLIENT_ID when set
+    pub fn client_id() -> Result<String> {
+        let cfg = outlook::config::load_config().ok();
+        credential_from_env_or("OUTLOOK_CLIENT_ID", cfg.as_ref().map(|cfg| cfg.client_id()))
+    }
+
+    /// PATCH the message's importance ("high", "normal" or "low")
+    async fn set_importance(&self, id: &str, importance: &str) -> Result<()> {
+        let url = format!("{}/{}", MESSAGES_URL, id);
+        let body = serde_json::json!({ "importance": importance }).to_string();
+        let (url, body) = (url.as_str(), body.as_str());
+        self.client
+            .call(|_| {
+                let token = self
+                    .access_token
+                    .lock()
+                    .map(|token| token.clone())
+                    .unwrap_or_default();
+                async move { bearer_request(&token, "PATCH", url, Some(body)).await }
+            })
+            .await?;
+        Ok(())
     }
 
     fn resolve_category_ids(&self, category_names: Vec<String>) -> Vec<String> {
@@ -37,26 +145,28 @@ impl OutlookProvider {
         category_names
     }
 
-    fn message_to_email(&self, msg: outlook::api::Message) -> Email {
+    /// `listed_in_inbox` is used when the message's folder can't be compared
+    /// with the inbox's ID
+    async fn message_to_email(&self, msg: outlook::api::Message, listed_in_inbox: bool) -> Email {
         let categories = msg.categories.clone().unwrap_or_default();
 
         // Build pseudo-labels from Outlook state
         let mut labels = self.resolve_category_ids(categories);
 
-        // Add INBOX pseudo-label (outlook list defaults to inbox)
-        labels.push("INBOX".to_string());
+        let in_inbox = match (&msg.parent_folder_id, &self.inbox_folder_id) {
+            (Some(folder), Some(inbox)) => folder == inbox,
+            _ => listed_in_inbox,
+        };
+        if in_inbox {
+            labels.push("INBOX".to_string());
+        }
 
         // Add UNREAD pseudo-label if not read
         if msg.is_read == Some(false) {
             labels.push("UNREAD".to_string());
         }
 
-        // Use body text if available, fall back to body preview
-        let body = msg
-            .get_body_text()
-            .or_else(|| msg.body_preview.clone())
-            .map(|b| strip_html(&b))
-            .unwrap_or_default();
+        let body = self.resolve_body(&msg).await;
 
         Email {
             id: msg.id.clone(),
@@ -68,36 +178,121 @@ impl OutlookProvider {
                 .unwrap_or_else(|| "(no subject)".to_string()),
             body,
             labels,
+            date: msg
+                .received_date_time
+                .as_deref()
+                .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+                .map(|date| date.with_timezone(&Utc)),
+            list_id: msg.get_internet_header("List-Id"),
+            message_id: msg.get_internet_header("Message-ID"),
+            thread_id: msg.conversation_id.clone(),
+            // hasAttachments is false for inline-only images, matching Outlook's paperclip
+            has_attachments: msg.has_attachments.unwrap_or(false),
+            attachment_names: msg
+                .attachments
+                .iter()
+                .flatten()
+                .filter_map(|attachment| attachment.name.clone())
+                .collect(),
+            // Graph reports only the body's html/text type, not the MIME structure
+            content_type: None,
+            calendar: None,
+            headers: headers::collect(|name| msg.get_internet_header(name)),
         }
     }
+
+    async fn resolve_body(&self, msg: &outlook::api::Message) -> String {
+        if let Some(body) = self.body_cache.get(&msg.id) {
+            return body;
+        }
+
+        // Use body text if available, fall back to body preview
+        let text = msg.get_body_text().map(|b| strip_html(&b));
+        let retried = text_part_missing(text.as_deref());
+        let preview = msg.body_preview.as_deref().map(strip_html);
+        let id = msg.id.as_str();
+        let body = body_with_retry(text, preview, || {
+            self.client
+                .call(|c| async move { c.get_mime_content(id).await })
+        })
+        .await;
+        if retried {
+            self.body_cache.insert(&msg.id, &body);
+        }
+        body
+    }
 }
 
-fn strip_html(html: &str) -> String {
-    // Simple HTML stripping - remove tags and decode common entities
-    let mut result = String::new();
-    let mut in_tag = false;
+/// Translate a Gmail-style query into an OData filter. `-label:X` excludes a
+/// category and `-in:` terms are covered by the folder; anything else that
+/// Outlook can't express is an error rather than silently ignored.
+fn odata_filter(query: &str) -> Result<Option<String>> {
+    let mut clauses = Vec::new();
+    for term in query_terms(query) {
+        let clause = match term.split_once(':') {
+            Some(("-label", category)) if !category.is_empty() => {
+                format!("NOT (categories/any(c:c eq '{}'))", odata_string(category))
+            }
+            Some(("-in", _)) => continue,
+            Some(("from", sender)) if sender.contains('@') => {
+                format!("from/emailAddress/address eq '{}'", odata_string(sender))
+            }
+            Some(("from", sender)) if !sender.is_empty() => format!(
+                "contains(from/emailAddress/address, '{}')",
+                odata_string(sender)
+            ),
+            Some(("subject", subject)) if !subject.is_empty() => {
+                format!("contains(subject, '{}')", odata_string(subject))
+            }
+            Some(("is", "unread")) => "isRead eq false".to_string(),
+            Some(("is", "read")) => "isRead eq true".to_string(),
+            _ => anyhow::bail!(
+                "Outlook can't filter on '{}' (supported: from:, subject:, is:unread, is:read)",
+                term
+            ),
+        };
+        clauses.push(clause);
+    }
+    Ok((!clauses.is_empty()).then(|| clauses.join(" and ")))
+}
 
-    for c in html.chars() {
+/// Whitespace-separated terms; double quotes group a value, as in subject:"two words"
+fn query_terms(query: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
         match c {
-            '<' => in_tag = true,
-            '>' => in_tag = false,
-            _ if !in_tag => result.push(c),
-            _ => {}
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    terms.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
         }
     }
+    if !current.is_empty() {
+        terms.push(current);
+    }
+    terms
+}
+
+fn odata_string(value: &str) -> String {
+    value.replace('\'', "''")
+}
 
-    // Decode common HTML entities
-    result
-        .replace("&nbsp;", " ")
-        .replace("&amp;", "&")
-        .replace("&lt;", "<")
-        .replace("&gt;", ">")
-        .replace("&quot;", "\"")
-        .replace("&#39;", "'")
-        .replace("\r\n", "\n")
-        .split_whitespace()
-        .collect::<Vec<_>>()
-        .join(" ")
+/// Percent-encode a query parameter value; curl sends the URL as given
+fn encode_query_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
 }
 
 #[async_trait]
@@ -107,38 +302,40 @@ impl EmailProvider for OutlookProvider {
         max: u32,
         label: &str,
         query: Option<&str>,
+        order: SortOrder,
     ) -> Result<Vec<Email>> {
-        // Map Gmail-style label to Outlook folder
+        // Map Gmail-style label to Outlook folder; "" is archived mail
         let folder = match label {
             "INBOX" => "inbox",
+            "" => "archive",
             "SENT" => "sentitems",
             "TRASH" => "deleteditems",
             "SPAM" => "junkemail",
             _ => "inbox",
         };
 
-        // Handle query - convert -label:X to category filter
-        let filter = query.and_then(|q| {
-            if q.starts_with("-label:") {
-                let cat = q.trim_start_matches("-label:");
-                // OData filter to exclude category
-                Some(format!("NOT (categories/any(c:c eq '{}'))", cat))
-            } else {
-                None
-            }
-        });
+        let filter = query.map(odata_filter).transpose()?.flatten();
 
+        let order_by = match order {
+            SortOrder::NewestFirst => "receivedDateTime desc",
+            SortOrder::OldestFirst => "receivedDateTime asc",
+        };
+        let filter = filter.as_deref();
         let list = self
             .client
-            .list_messages(folder, filter.as_deref(), max)
+            .call(|c| async move { c.list_messages_ordered(folder, filter, order_by, max).await })
             .await?;
 
         let mut emails = Vec::new();
         if let Some(messages) = list.value {
             for msg_ref in messages {
                 // Get full message with body
-                let msg = self.client.get_message(&msg_ref.id).await?;
-                emails.push(self.message_to_email(msg));
+                let id = msg_ref.id.as_str();
+                let msg = self
+                    .client
+                    .call(|c| async move { c.get_message(id).await })
+                    .await?;
+                emails.push(self.message_to_email(msg, folder == "inbox").await);
             }
         }
 
@@ -146,12 +343,19 @@ impl EmailProvider for OutlookProvider {
     }
 
     async fn get_message(&self, id: &str) -> Result<Email> {
-        let msg = self.client.get_message(id).await?;
-        Ok(self.message_to_email(msg))
+        let msg = self
+            .client
+            .call(|c| async move { c.get_message(id).await })
+            .await
+            .map_err(|error| not_found_on_404(error, format!("Message {}", id)))?;
+        Ok(self.message_to_email(msg, true).await)
     }
 
     async fn list_labels(&self) -> Result<Vec<Label>> {
-        let list = self.client.list_categories().await?;
+        let list = self
+            .client
+            .call(|c| async move { c.list_categories().await })
+            .await?;
         let mut labels = Vec::new();
 
         if let Some(categories) = list.value {
@@ -167,24 +371,145 @@ impl EmailProvider for OutlookProvider {
     }
 
     async fn add_label(&self, id: &str, label: &str) -> Result<()> {
-        // Ensure category exists in master list first
-        self.client.ensure_category(label).await?;
-        self.client.add_category(id, label).await
+        // Ensure category exists in master list first, colored when new
+        let preset = LabelColor::for_label(label).outlook_preset;
+        self.client
+            .call(|c| async move {
+                c.ensure_category_with_color(label, preset).await?;
+                c.add_category(id, label).await
+            })
+            .await
+    }
+
+    async fn remove_label(&self, id: &str, label: &str) -> Result<()> {
+        self.client
+            .call(|c| async move { c.remove_category(id, label).await })
+            .await
     }
 
     async fn mark_spam(&self, id: &str) -> Result<()> {
-        self.client.mark_spam(id).await
+        self.client
+            .call(|c| async move { c.mark_spam(id).await })
+            .await
     }
 
     async fn unspam(&self, id: &str) -> Result<()> {
-        self.client.unspam(id).await
+        self.client
+            .call(|c| async move { c.unspam(id).await })
+            .await
     }
 
     async fn archive(&self, id: &str) -> Result<()> {
-        self.client.archive(id).await
+        self.client
+            .call(|c| async move { c.archive(id).await })
+            .await
     }
 
     async fn trash(&self, id: &str) -> Result<()> {
-        self.client.trash(id).await
+        self.client.call(|c| async move { c.trash(id).await }).await
+    }
+
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<()> {
+        // Graph's sendMail action takes JSON, so no MIME encoding here
+        self.client
+            .call(|c| async move { c.send_mail(to, subject, body).await })
+            .await
+    }
+
+    async fn create_draft(&self, reply_to_id: &str, body: &str) -> Result<String> {
+        // createReply, then fill in the body of the new draft
+        self.client
+            .call(|c| async move { c.create_reply_draft(reply_to_id, body).await })
+            .await
+    }
+
+    async fn set_label_color(&self, label: &str, color: &LabelColor) -> Result<()> {
+        let preset = color.outlook_preset;
+        self.client
+            .call(|c| async move {
+                c.ensure_category_with_color(label, preset).await?;
+                c.set_category_color(label, preset).await
+            })
+            .await
+    }
+
+    async fn set_important(&self, id: &str) -> Result<()> {
+        self.set_importance(id, "high").await
+    }
+
+    async fn clear_important(&self, id: &str) -> Result<()> {
+        self.set_importance(id, "normal").await
+    }
+
+    /// Messages carrying the category, from Graph's `$count` segment
+    async fn count_label(&self, label: &str) -> Result<u32> {
+        let filter = format!("categories/any(c:c eq '{}')", odata_string(label));
+        let url = format!(
+            "{}/$count?$filter={}",
+            MESSAGES_URL,
+            encode_query_value(&filter)
+        );
+        let url = url.as_str();
+        let response = self
+            .client
+            .call(|_| {
+                let token = self
+                    .access_token
+                    .lock()
+                    .map(|token| token.clone())
+                    .unwrap_or_default();
+                async move { bearer_http(&token, url, None).await }
+            })
+            .await?;
+        response
+            .trim()
+            .trim_start_matches('\u{feff}')
+            .parse()
+            .with_context(|| format!("Unexpected count response: {}", response.trim()))
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        // Contacts sync only knows the Gmail People API so far
+        ProviderCapabilities {
+            contacts: false,
+            ..ProviderCapabilities::FULL
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_filter_is_percent_encoded() {
+        let filter = format!("categories/any(c:c eq '{}')", odata_string("Kid's School"));
+        assert_eq!(
+            encode_query_value(&filter),
+            "categories%2Fany%28c%3Ac%20eq%20%27Kid%27%27s%20School%27%29"
+        );
+    }
+
+    #[test]
+    fn test_odata_filter_translates_supported_terms() {
+        let filter =
+            odata_filter("-label:Classified -in:spam from:amazon subject:\"Your order\" is:unread")
+                .unwrap();
+        assert_eq!(
+            filter.as_deref(),
+            Some(
+                "NOT (categories/any(c:c eq 'Classified')) and \
+                 contains(from/emailAddress/address, 'amazon') and \
+                 contains(subject, 'Your order') and isRead eq false"
+            )
+        );
+        assert_eq!(
+            odata_filter("from:o'brien@example.com").unwrap().as_deref(),
+            Some("from/emailAddress/address eq 'o''brien@example.com'")
+        );
+        assert_eq!(odata_filter("").unwrap(), None);
+
+        let error = odata_filter("-label:Classified larger:5M").unwrap_err();
+        assert!(error.to_string().contains("larger:5M"));
     }
 }
@@ -1,6 +1,10 @@
-use super::{Email, EmailProvider, Label};
+use super::{
+    excluded_labels, sort_by_date, unread_filter, Email, EmailProvider, Label,
+    ProviderCapabilities, ProviderError, ProviderOp, SortOrder,
+};
 use anyhow::Result;
 use async_trait::async_trait;
+use chrono::Utc;
 
 pub struct OutlookWebProvider {
     client: outlook_web::api::Client,
@@ -31,6 +35,10 @@ impl OutlookWebProvider {
             subject: msg.subject.unwrap_or_else(|| "(no subject)".to_string()),
             body: msg.body.or(msg.preview).unwrap_or_default(),
             labels,
+            // outlook-web doesn't expose the received time; a just-listed
+            // inbox message is recent, so "now" keeps sorting and aging sane
+            date: Some(Utc::now()),
+            ..Default::default()
         }
     }
 }
@@ -42,10 +50,12 @@ impl EmailProvider for OutlookWebProvider {
         max: u32,
         label: &str,
         query: Option<&str>,
+        order: SortOrder,
     ) -> Result<Vec<Email>> {
-        // outlook-web only supports inbox for now
-        if label != "INBOX" && !label.is_empty() {
-            return Ok(Vec::new());
+        // outlook-web only supports inbox for now; an empty or inbox-only
+        // list here would be mistaken for the requested label's contents
+        if label != "INBOX" {
+            return Err(ProviderError::Unsupported(ProviderOp::ListByLabel).into());
         }
 
         let messages = self.client.list_messages(max).await?;
@@ -55,14 +65,12 @@ impl EmailProvider for OutlookWebProvider {
             .map(|msg| self.message_to_email(msg))
             .collect();
 
-        // Apply query filter if present (e.g., "-label:Classified")
-        if let Some(q) = query {
-            if q.starts_with("-label:") {
-                let excluded_label = q.trim_start_matches("-label:");
-                emails.retain(|e| !e.labels.iter().any(|l| l == excluded_label));
-            }
+        if let Some(query) = query {
+            retain_unexcluded(&mut emails, query);
+            retain_read_state(&mut emails, query);
         }
 
+        sort_by_date(&mut emails, order);
         Ok(emails)
     }
 
@@ -86,6 +94,10 @@ impl EmailProvider for OutlookWebProvider {
         self.client.add_label(id, label).await
     }
 
+    async fn remove_label(&self, id: &str, label: &str) -> Result<()> {
+        self.client.remove_label(id, label).await
+    }
+
     async fn mark_spam(&self, id: &str) -> Result<()> {
         self.client.mark_spam(id).await
     }
@@ -101,4 +113,97 @@ impl EmailProvider for OutlookWebProvider {
     async fn trash(&self, id: &str) -> Result<()> {
         self.client.trash(id).await
     }
+
+    async fn send(&self, _to: &str, _subject: &str, _body: &str) -> Result<()> {
+        Err(ProviderError::Unsupported(ProviderOp::Send).into())
+    }
+
+    async fn create_draft(&self, _reply_to_id: &str, _body: &str) -> Result<String> {
+        Err(ProviderError::Unsupported(ProviderOp::Draft).into())
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            list_by_label: false,
+            send: false,
+            draft: false,
+            label_color: false,
+            contacts: false,
+            importance: false,
+            label_count: false,
+            ..ProviderCapabilities::FULL
+        }
+    }
+}
+
+/// Drop emails carrying any `-label:X` label of the query (e.g. "-label:Classified -label:Keep")
+fn retain_unexcluded(emails: &mut Vec<Email>, query: &str) {
+    let excluded = excluded_labels(query);
+    emails.retain(|email| {
+        !email
+            .labels
+            .iter()
+            .any(|label| excluded.contains(&label.as_str()))
+    });
+}
+
+/// Keep only unread (`is:unread`) or read (`is:read`) emails, by the UNREAD pseudo-label
+fn retain_read_state(emails: &mut Vec<Email>, query: &str) {
+    if let Some(unread) = unread_filter(query) {
+        emails.retain(|email| email.labels.iter().any(|label| label == "UNREAD") == unread);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retain_unexcluded_with_two_exclusions() {
+        let email = |id: &str, labels: &[&str]| Email {
+            id: id.to_string(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            ..Default::default()
+        };
+        let mut emails = vec![
+            email("new", &["INBOX"]),
+            email("classified", &["INBOX", "Classified"]),
+            email("kept", &["INBOX", "Keep"]),
+            email("both", &["Classified", "Keep"]),
+        ];
+
+        retain_unexcluded(&mut emails, "-label:Classified -label:Keep");
+
+        let ids = emails
+            .iter()
+            .map(|email| email.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["new"]);
+    }
+
+    #[test]
+    fn test_retain_read_state_from_query() {
+        let email = |id: &str, labels: &[&str]| Email {
+            id: id.to_string(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            ..Default::default()
+        };
+        let listed = || {
+            vec![
+                email("seen", &["INBOX"]),
+                email("new", &["INBOX", "UNREAD"]),
+            ]
+        };
+        let ids = |emails: Vec<Email>| emails.into_iter().map(|email| email.id).collect::<Vec<_>>();
+
+        let mut unread = listed();
+        retain_read_state(&mut unread, "-label:Classified is:unread");
+        assert_eq!(ids(unread), vec!["new"]);
+        let mut read = listed();
+        retain_read_state(&mut read, "is:read");
+        assert_eq!(ids(read), vec!["seen"]);
+        let mut all = listed();
+        retain_read_state(&mut all, "-label:Classified");
+        assert_eq!(all.len(), 2);
+    }
 }
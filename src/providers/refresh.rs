@@ -0,0 +1,169 @@
+use super::{http_status, RateLimiter};
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+type Refresh<C> = Box<dyn Fn() -> BoxFuture<'static, Result<C>> + Send + Sync>;
+
+/// An API client that transparently refreshes its access token when a call
+/// fails with 401, then retries that call once.
+pub struct RefreshingClient<C> {
+    client: RwLock<Arc<C>>,
+    refresh: Refresh<C>,
+    limiter: Option<RateLimiter>,
+}
+
+impl<C: Send + Sync> RefreshingClient<C> {
+    /// `refresh` exchanges the refresh token for a new client; saving the new
+    /// tokens is up to it, and tokens read from the environment are never saved
+    pub fn new<F>(client: C, refresh: F) -> Self
+    where
+        F: Fn() -> BoxFuture<'static, Result<C>> + Send + Sync + 'static,
+    {
+        Self {
+            client: RwLock::new(Arc::new(client)),
+            refresh: Box::new(refresh),
+            limiter: None,
+        }
+    }
+
+    /// Pace every call, including the retry after a refresh
+    pub fn with_rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    async fn throttle(&self) {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+    }
+
+    pub async fn call<T, F, Fut>(&self, op: F) -> Result<T>
+    where
+        F: Fn(Arc<C>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let client = self.client.read().await.clone();
+        self.throttle().await;
+        match op(client.clone()).await {
+            Err(error) if is_unauthorized(&error) => {
+                let client = self.refresh_after(&client).await?;
+                self.throttle().await;
+                op(client).await
+            }
+            result => result,
+        }
+    }
+
+    pub async fn refresh(&self) -> Result<()> {
+        let stale = self.client.read().await.clone();
+        self.refresh_after(&stale).await.map(|_| ())
+    }
+
+    /// Replace `stale` with a fresh client. Concurrent calls that all hit the
+    /// same 401 share one refresh instead of each spending the refresh token.
+    async fn refresh_after(&self, stale: &Arc<C>) -> Result<Arc<C>> {
+        let mut client = self.client.write().await;
+        if !Arc::ptr_eq(&client, stale) {
+            return Ok(client.clone());
+        }
+
+        *client = Arc::new((self.refresh)().await?);
+        Ok(client.clone())
+    }
+}
+
+fn is_unauthorized(error: &anyhow::Error) -> bool {
+    http_status(error) == Some(401)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Stand-in for an HTTP client: rejects requests made with an expired token
+    struct FakeHttpClient {
+        token: &'static str,
+    }
+
+    impl FakeHttpClient {
+        async fn get(&self, requests: &AtomicUsize) -> Result<&'static str> {
+            requests.fetch_add(1, Ordering::SeqCst);
+            match self.token {
+                "expired" => anyhow::bail!("HTTP 401 Unauthorized"),
+                "broken" => anyhow::bail!("HTTP 500 Internal Server Error"),
+                "misleading" => {
+                    anyhow::bail!(
+                        "The requested URL returned error: 404 (message 401, unauthorized)"
+                    )
+                }
+                token => Ok(token),
+            }
+        }
+    }
+
+    fn make_client(
+        token: &'static str,
+        refreshes: Arc<AtomicUsize>,
+    ) -> RefreshingClient<FakeHttpClient> {
+        RefreshingClient::new(FakeHttpClient { token }, move || {
+            refreshes.fetch_add(1, Ordering::SeqCst);
+            async { Ok(FakeHttpClient { token: "fresh" }) }.boxed()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_401_refreshes_and_retries() {
+        let refreshes = Arc::new(AtomicUsize::new(0));
+        let requests = AtomicUsize::new(0);
+        let client = make_client("expired", refreshes.clone());
+
+        let response = client
+            .call(|client| {
+                let requests = &requests;
+                async move { client.get(requests).await }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(response, "fresh");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+
+        // Later calls reuse the refreshed client
+        client
+            .call(|client| {
+                let requests = &requests;
+                async move { client.get(requests).await }
+            })
+            .await
+            .unwrap();
+        assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_are_not_retried() {
+        // "401" and "unauthorized" outside the status don't count
+        for token in ["broken", "misleading"] {
+            let refreshes = Arc::new(AtomicUsize::new(0));
+            let requests = AtomicUsize::new(0);
+            let client = make_client(token, refreshes.clone());
+
+            let result = client
+                .call(|client| {
+                    let requests = &requests;
+                    async move { client.get(requests).await }
+                })
+                .await;
+
+            assert!(result.is_err());
+            assert_eq!(requests.load(Ordering::SeqCst), 1, "{}", token);
+            assert_eq!(refreshes.load(Ordering::SeqCst), 0, "{}", token);
+        }
+    }
+}
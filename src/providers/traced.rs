@@ -0,0 +1,132 @@
+use super::{Email, EmailProvider, Label, ProviderCapabilities, SortOrder};
+use crate::labels::LabelColor;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::future::Future;
+use tokio::time::Instant;
+use tracing::field::Empty;
+use tracing::{debug, debug_span, Instrument, Span};
+
+/// Wraps a provider so every call runs in its own span and logs its outcome
+/// and duration at debug level (`-vv`)
+pub struct Traced<P> {
+    inner: P,
+    provider: &'static str,
+}
+
+impl<P: EmailProvider> Traced<P> {
+    pub fn new(provider: &'static str, inner: P) -> Self {
+        Self { inner, provider }
+    }
+
+    /// Span for one call; `id` and `label` are recorded when the call has them
+    fn span(&self, op: &'static str, id: Option<&str>, label: Option<&str>) -> Span {
+        let span = debug_span!(
+            "provider",
+            name = self.provider,
+            op,
+            id = Empty,
+            label = Empty
+        );
+        if let Some(id) = id {
+            span.record("id", id);
+        }
+        if let Some(label) = label {
+            span.record("label", label);
+        }
+        span
+    }
+}
+
+async fn traced<T>(span: Span, call: impl Future<Output = Result<T>>) -> Result<T> {
+    async {
+        let started = Instant::now();
+        let result = call.await;
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        match &result {
+            Ok(_) => debug!(elapsed_ms, "done"),
+            Err(error) => debug!(elapsed_ms, error = %error, "failed"),
+        }
+        result
+    }
+    .instrument(span)
+    .await
+}
+
+#[async_trait]
+impl<P: EmailProvider> EmailProvider for Traced<P> {
+    async fn list_messages(
+        &self,
+        max: u32,
+        label: &str,
+        query: Option<&str>,
+        order: SortOrder,
+    ) -> Result<Vec<Email>> {
+        let span = self.span("list_messages", None, Some(label));
+        debug!(parent: &span, max, query, "listing");
+        traced(span, self.inner.list_messages(max, label, query, order)).await
+    }
+    async fn get_message(&self, id: &str) -> Result<Email> {
+        let span = self.span("get_message", Some(id), None);
+        traced(span, self.inner.get_message(id)).await
+    }
+    async fn list_labels(&self) -> Result<Vec<Label>> {
+        let span = self.span("list_labels", None, None);
+        traced(span, self.inner.list_labels()).await
+    }
+    async fn add_label(&self, id: &str, label: &str) -> Result<()> {
+        let span = self.span("add_label", Some(id), Some(label));
+        traced(span, self.inner.add_label(id, label)).await
+    }
+    async fn remove_label(&self, id: &str, label: &str) -> Result<()> {
+        let span = self.span("remove_label", Some(id), Some(label));
+        traced(span, self.inner.remove_label(id, label)).await
+    }
+    async fn mark_spam(&self, id: &str) -> Result<()> {
+        let span = self.span("mark_spam", Some(id), None);
+        traced(span, self.inner.mark_spam(id)).await
+    }
+    async fn unspam(&self, id: &str) -> Result<()> {
+        let span = self.span("unspam", Some(id), None);
+        traced(span, self.inner.unspam(id)).await
+    }
+    async fn archive(&self, id: &str) -> Result<()> {
+        let span = self.span("archive", Some(id), None);
+        traced(span, self.inner.archive(id)).await
+    }
+    async fn trash(&self, id: &str) -> Result<()> {
+        let span = self.span("trash", Some(id), None);
+        traced(span, self.inner.trash(id)).await
+    }
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<()> {
+        let span = self.span("send", None, None);
+        traced(span, self.inner.send(to, subject, body)).await
+    }
+    async fn create_draft(&self, reply_to_id: &str, body: &str) -> Result<String> {
+        let span = self.span("create_draft", Some(reply_to_id), None);
+        traced(span, self.inner.create_draft(reply_to_id, body)).await
+    }
+    async fn set_label_color(&self, label: &str, color: &LabelColor) -> Result<()> {
+        let span = self.span("set_label_color", None, Some(label));
+        traced(span, self.inner.set_label_color(label, color)).await
+    }
+    async fn list_contacts(&self) -> Result<Vec<String>> {
+        let span = self.span("list_contacts", None, None);
+        traced(span, self.inner.list_contacts()).await
+    }
+    async fn set_important(&self, id: &str) -> Result<()> {
+        let span = self.span("set_important", Some(id), None);
+        traced(span, self.inner.set_important(id)).await
+    }
+    async fn clear_important(&self, id: &str) -> Result<()> {
+        let span = self.span("clear_important", Some(id), None);
+        traced(span, self.inner.clear_important(id)).await
+    }
+    async fn count_label(&self, label: &str) -> Result<u32> {
+        let span = self.span("count_label", None, Some(label));
+        traced(span, self.inner.count_label(label)).await
+    }
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.inner.capabilities()
+    }
+}
@@ -0,0 +1,124 @@
+use crate::config;
+use crate::providers::Email;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Provider label marking emails that wait out the grace period before deletion
+pub const QUARANTINE_LABEL: &str = "Quarantine";
+
+/// An email scan would have deleted, held back for review
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedEmail {
+    pub email_id: String,
+    pub from: String,
+    pub subject: String,
+    pub quarantined_at: DateTime<Utc>,
+    /// Why the classifier wanted it deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl QuarantinedEmail {
+    /// When the email becomes due for deletion
+    pub fn delete_after(&self, grace: Duration) -> DateTime<Utc> {
+        self.quarantined_at + grace
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Quarantine {
+    emails: BTreeMap<String, QuarantinedEmail>,
+}
+
+impl Quarantine {
+    pub fn load() -> Result<Self> {
+        let path = config::quarantine_path();
+        if path.exists() {
+            let content = fs::read_to_string(&path)?;
+            Ok(serde_json::from_str(&content)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = config::config_dir();
+        if !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(config::quarantine_path(), content)?;
+        Ok(())
+    }
+
+    pub fn add(&mut self, email: &Email, reason: Option<String>, now: DateTime<Utc>) {
+        self.emails.insert(
+            email.id.clone(),
+            QuarantinedEmail {
+                email_id: email.id.clone(),
+                from: email.from.clone(),
+                subject: email.subject.clone(),
+                quarantined_at: now,
+                reason,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, email_id: &str) -> Option<QuarantinedEmail> {
+        self.emails.remove(email_id)
+    }
+
+    /// Quarantined emails, oldest first
+    pub fn entries(&self) -> Vec<&QuarantinedEmail> {
+        let mut entries = self.emails.values().collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.quarantined_at);
+        entries
+    }
+
+    /// IDs of emails whose grace period is over, oldest first
+    pub fn expired(&self, grace: Duration, now: DateTime<Utc>) -> Vec<String> {
+        self.entries()
+            .into_iter()
+            .filter(|entry| entry.delete_after(grace) <= now)
+            .map(|entry| entry.email_id.clone())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.emails.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.emails.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired_after_grace_period() {
+        let now = Utc::now();
+        let mut quarantine = Quarantine::default();
+        for (id, age) in [("new", 1), ("old", 10), ("older", 20)] {
+            let email = Email {
+                id: id.to_string(),
+                ..Default::default()
+            };
+            quarantine.add(&email, None, now - Duration::days(age));
+        }
+
+        assert_eq!(
+            quarantine.expired(Duration::days(7), now),
+            vec!["older", "old"]
+        );
+        assert!(quarantine.remove("old").is_some());
+        assert_eq!(quarantine.expired(Duration::days(7), now), vec!["older"]);
+        assert_eq!(quarantine.entries()[0].email_id, "older");
+        assert_eq!(quarantine.len(), 2);
+    }
+}
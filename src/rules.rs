@@ -1,64 +1,145 @@
 use crate::classifier::Classification;
 use crate::config;
 use crate::providers::Email;
-use anyhow::Result;
-use serde::Deserialize;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Deserialize)]
+/// Accepted `condition.field` values
+const FIELDS: &[&str] = &[
+    "to",
+    "from",
+    "subject",
+    "has_attachment",
+    "attachment",
+    "attachment_name",
+];
+/// Accepted `action` values
+const ACTIONS: &[&str] = &["delete", "archive", "label"];
+/// Accepted `condition.and` values
+const AND_CONDITIONS: &[&str] = &["archive", "delete"];
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct RuleFile {
     pub rules: Vec<Rule>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Rule {
-    #[serde(rename = "name")]
-    pub _name: String,
+    pub name: String,
     #[serde(default)]
-    #[serde(rename = "description")]
-    pub _description: String,
+    pub description: String,
+    /// Disabled rules stay in their file but are never applied
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
     pub condition: Condition,
+    /// "archive", "delete", or "label" (adds `label`)
     pub action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// ID of the Gmail filter the rule was imported from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gmail_filter: Option<String>,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl Rule {
+    /// Why the rule can't work as written; empty when it's valid
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let field = self.condition.field.as_str();
+        if !FIELDS.contains(&field) {
+            problems.push(format!(
+                "unknown field \"{}\" (expected one of: {})",
+                field,
+                FIELDS.join(", ")
+            ));
+        } else if field != "has_attachment" && self.condition.contains.trim().is_empty() {
+            problems.push(format!("field \"{}\" needs a \"contains\" value", field));
+        }
+        if !ACTIONS.contains(&self.action.as_str()) {
+            problems.push(format!(
+                "unknown action \"{}\" (expected one of: {})",
+                self.action,
+                ACTIONS.join(", ")
+            ));
+        } else if self.action == "label" && self.label.as_deref().unwrap_or("").trim().is_empty() {
+            problems.push("action \"label\" needs a \"label\" value".to_string());
+        }
+        if let Some(and) = &self.condition.and {
+            if !AND_CONDITIONS.contains(&and.as_str()) {
+                problems.push(format!(
+                    "unknown \"and\" condition \"{}\" (expected one of: {})",
+                    and,
+                    AND_CONDITIONS.join(", ")
+                ));
+            }
+        }
+        problems
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Condition {
+    /// "to", "from", "subject", "has_attachment", or "attachment"
+    /// ("attachment_name" is accepted as an alias)
     pub field: String,
+    /// Case-insensitive substring to match (ignored for "has_attachment")
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub contains: String,
     /// Additional condition: "archive" means only apply if classification.archive is true
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub and: Option<String>,
 }
 
-fn rules_dir() -> PathBuf {
-    config::config_dir().join("rules")
+/// `rules/` in the config directory, then the `--rules-dir` directory if given
+pub fn rules_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![config::config_dir().join("rules")];
+    dirs.extend(config::extra_rules_dir().map(Path::to_path_buf));
+    dirs
 }
 
-pub fn load_rules() -> Result<Vec<Rule>> {
-    let dir = rules_dir();
-    if !dir.exists() {
-        return Ok(Vec::new());
+/// Every `.json` file in the rules directories, in directory then name order
+pub fn rule_file_paths() -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for dir in rules_dirs() {
+        if !dir.exists() {
+            continue;
+        }
+        let mut in_dir = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().map(|e| e == "json").unwrap_or(false) {
+                in_dir.push(path);
+            }
+        }
+        in_dir.sort();
+        paths.extend(in_dir);
     }
+    Ok(paths)
+}
 
-    let mut all_rules = Vec::new();
+pub fn load_rule_file(path: &Path) -> Result<RuleFile> {
+    let content = fs::read_to_string(path)?;
+    serde_json::from_str(&content).with_context(|| format!("Invalid rule file {}", path.display()))
+}
 
-    for entry in fs::read_dir(&dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.extension().map(|e| e == "json").unwrap_or(false) {
-            let content = fs::read_to_string(&path)?;
-            let rule_file: RuleFile = serde_json::from_str(&content)?;
-            all_rules.extend(rule_file.rules);
-        }
+/// Every rule from every rules directory, disabled ones included
+pub fn load_rules() -> Result<Vec<Rule>> {
+    let mut all_rules = Vec::new();
+    for path in rule_file_paths()? {
+        all_rules.extend(load_rule_file(&path)?.rules);
     }
-
     Ok(all_rules)
 }
 
 /// Apply rules to override classification
 pub fn apply_rules(email: &Email, classification: &mut Classification, rules: &[Rule]) {
-    for rule in rules {
+    for rule in rules.iter().filter(|rule| rule.enabled) {
         if matches_condition(email, classification, &rule.condition) {
             match rule.action.as_str() {
                 "delete" => {
@@ -68,6 +149,18 @@ pub fn apply_rules(email: &Email, classification: &mut Classification, rules: &[
                 "archive" => {
                     classification.archive = true;
                 }
+                "label" => {
+                    let Some(label) = rule.label.as_deref() else {
+                        continue;
+                    };
+                    if !classification
+                        .theme
+                        .iter()
+                        .any(|theme| theme.eq_ignore_ascii_case(label))
+                    {
+                        classification.theme.push(label.to_string());
+                    }
+                }
                 _ => {}
             }
         }
@@ -80,19 +173,16 @@ fn matches_condition(
     condition: &Condition,
 ) -> bool {
     // Check the field condition
+    let needle = condition.contains.to_lowercase();
     let field_matches = match condition.field.as_str() {
-        "to" => email
-            .to
-            .to_lowercase()
-            .contains(&condition.contains.to_lowercase()),
-        "from" => email
-            .from
-            .to_lowercase()
-            .contains(&condition.contains.to_lowercase()),
-        "subject" => email
-            .subject
-            .to_lowercase()
-            .contains(&condition.contains.to_lowercase()),
+        "to" => email.to.to_lowercase().contains(&needle),
+        "from" => email.from.to_lowercase().contains(&needle),
+        "subject" => email.subject.to_lowercase().contains(&needle),
+        "has_attachment" => email.has_attachments,
+        "attachment" | "attachment_name" => email
+            .attachment_names
+            .iter()
+            .any(|name| name.to_lowercase().contains(&needle)),
         _ => false,
     };
 
@@ -123,7 +213,31 @@ mod tests {
             to: to.to_string(),
             subject: "Test Subject".to_string(),
             body: "Test body".to_string(),
-            labels: vec![],
+            ..Default::default()
+        }
+    }
+
+    fn make_email_with_attachments(names: &[&str]) -> Email {
+        Email {
+            has_attachments: !names.is_empty(),
+            attachment_names: names.iter().map(|name| name.to_string()).collect(),
+            ..make_email("me@example.com")
+        }
+    }
+
+    fn attachment_rule(field: &str, contains: &str) -> Rule {
+        Rule {
+            name: "Archive attachments".to_string(),
+            description: String::new(),
+            enabled: true,
+            condition: Condition {
+                field: field.to_string(),
+                contains: contains.to_string(),
+                and: None,
+            },
+            action: "archive".to_string(),
+            label: None,
+            gmail_filter: None,
         }
     }
 
@@ -135,19 +249,24 @@ mod tests {
             theme: vec![],
             action: vec![],
             confidence: 0.9,
+            reason: None,
+            heuristic: false,
         }
     }
 
     fn globalcomix_rule() -> Rule {
         Rule {
-            _name: "Delete globalcomix".to_string(),
-            _description: "Test rule".to_string(),
+            name: "Delete globalcomix".to_string(),
+            description: "Test rule".to_string(),
+            enabled: true,
             condition: Condition {
                 field: "to".to_string(),
                 contains: "globalcomix.com".to_string(),
                 and: Some("archive".to_string()),
             },
             action: "delete".to_string(),
+            label: None,
+            gmail_filter: None,
         }
     }
 
@@ -197,4 +316,131 @@ mod tests {
 
         assert!(classification.delete);
     }
+
+    #[test]
+    fn test_label_rule_adds_theme_once() {
+        let rule = Rule {
+            action: "label".to_string(),
+            label: Some("Newsletters".to_string()),
+            ..attachment_rule("to", "news@")
+        };
+        let mut classification = make_classification(false);
+        classification.theme = vec!["newsletters".to_string()];
+        let rules = vec![rule];
+
+        apply_rules(&make_email("news@example.com"), &mut classification, &rules);
+        assert_eq!(classification.theme, vec!["newsletters"]);
+
+        classification.theme.clear();
+        apply_rules(&make_email("news@example.com"), &mut classification, &rules);
+        assert_eq!(classification.theme, vec!["Newsletters"]);
+        assert!(!classification.archive);
+
+        let unlabeled = Rule {
+            label: None,
+            ..rules.into_iter().next().unwrap()
+        };
+        assert_eq!(unlabeled.problems().len(), 1);
+    }
+
+    #[test]
+    fn test_has_attachment_rule() {
+        let rules = vec![attachment_rule("has_attachment", "")];
+
+        let mut with_attachment = make_classification(false);
+        apply_rules(
+            &make_email_with_attachments(&["report.pdf"]),
+            &mut with_attachment,
+            &rules,
+        );
+        assert!(with_attachment.archive);
+
+        let mut without_attachment = make_classification(false);
+        apply_rules(
+            &make_email_with_attachments(&[]),
+            &mut without_attachment,
+            &rules,
+        );
+        assert!(!without_attachment.archive);
+    }
+
+    #[test]
+    fn test_attachment_rule_matches_extension() {
+        let rules = vec![attachment_rule("attachment", ".pdf")];
+
+        let mut receipt = make_classification(false);
+        apply_rules(
+            &make_email_with_attachments(&["Receipt-0042.PDF"]),
+            &mut receipt,
+            &rules,
+        );
+        assert!(receipt.archive);
+
+        let mut no_attachment = make_classification(false);
+        apply_rules(
+            &make_email_with_attachments(&[]),
+            &mut no_attachment,
+            &rules,
+        );
+        assert!(!no_attachment.archive);
+    }
+
+    #[test]
+    fn test_attachment_name_rule_case_insensitive() {
+        let rules = vec![attachment_rule("attachment_name", "invoice")];
+
+        let mut matching = make_classification(false);
+        apply_rules(
+            &make_email_with_attachments(&["logo.png", "INVOICE-2024.pdf"]),
+            &mut matching,
+            &rules,
+        );
+        assert!(matching.archive);
+
+        let mut other = make_classification(false);
+        apply_rules(
+            &make_email_with_attachments(&["photo.jpg"]),
+            &mut other,
+            &rules,
+        );
+        assert!(!other.archive);
+    }
+
+    #[test]
+    fn test_disabled_rule_is_skipped() {
+        let email = make_email("user@globalcomix.com");
+        let mut classification = make_classification(true);
+        let rules = vec![Rule {
+            enabled: false,
+            ..globalcomix_rule()
+        }];
+
+        apply_rules(&email, &mut classification, &rules);
+
+        assert!(!classification.delete);
+        let parsed: RuleFile = serde_json::from_str(
+            r#"{"rules": [{"name": "a", "condition": {"field": "to", "contains": "x"}, "action": "archive"}]}"#,
+        )
+        .unwrap();
+        assert!(parsed.rules[0].enabled);
+    }
+
+    #[test]
+    fn test_rule_problems() {
+        assert!(globalcomix_rule().problems().is_empty());
+        assert!(attachment_rule("has_attachment", "").problems().is_empty());
+
+        let mut rule = attachment_rule("body", "x");
+        rule.action = "star".to_string();
+        rule.condition.and = Some("spam".to_string());
+        let problems = rule.problems();
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].starts_with("unknown field \"body\""));
+        assert!(problems[1].starts_with("unknown action \"star\""));
+
+        assert_eq!(
+            attachment_rule("from", " ").problems(),
+            vec!["field \"from\" needs a \"contains\" value"]
+        );
+    }
 }
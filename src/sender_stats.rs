@@ -0,0 +1,204 @@
+use crate::config;
+use crate::labels::canonical_label;
+use crate::learning::Correction;
+use crate::providers::sender_address;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+
+/// Labels named in a sender's prompt hint, most frequent first
+const HINT_LABELS: usize = 3;
+
+/// How one sender's emails have been classified so far
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SenderRecord {
+    pub emails: u32,
+    pub spam: u32,
+    /// Times each label was given, after corrections
+    #[serde(default)]
+    pub labels: BTreeMap<String, u32>,
+}
+
+/// Per-sender label history, kept in `sender_stats.json` and shared by all
+/// providers like the profile. Scans add each stored prediction; applied
+/// corrections move the counts to the labels the user chose.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SenderStats {
+    senders: HashMap<String, SenderRecord>,
+}
+
+impl SenderStats {
+    pub fn load() -> Result<Self> {
+        let path = config::sender_stats_path();
+        if path.exists() {
+            let content = fs::read_to_string(&path)?;
+            Ok(serde_json::from_str(&content)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = config::config_dir();
+        if !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(config::sender_stats_path(), content)?;
+        Ok(())
+    }
+
+    pub fn get(&self, from: &str) -> Option<&SenderRecord> {
+        self.senders.get(&sender_address(from))
+    }
+
+    /// Count a stored prediction toward its sender
+    pub fn record(&mut self, from: &str, is_spam: bool, labels: &[String]) {
+        let record = self.senders.entry(sender_address(from)).or_default();
+        record.emails += 1;
+        record.spam += u32::from(is_spam);
+        for label in labels {
+            *record.labels.entry(canonical_label(label)).or_default() += 1;
+        }
+    }
+
+    /// Replace a prediction's counts with what the user corrected it to.
+    /// Both sides are compared and counted under their canonical names,
+    /// as `record` stores them.
+    pub fn record_correction(&mut self, correction: &Correction) {
+        let Some(record) = self.senders.get_mut(&sender_address(&correction.from)) else {
+            return;
+        };
+        let predicted = canonical_labels(&correction.predicted_labels);
+        let actual = canonical_labels(&correction.actual_labels);
+        for label in predicted.difference(&actual) {
+            if let Some(count) = record.labels.get_mut(label) {
+                *count -= 1;
+                if *count == 0 {
+                    record.labels.remove(label);
+                }
+            }
+        }
+        for label in actual.difference(&predicted) {
+            *record.labels.entry(label.clone()).or_default() += 1;
+        }
+        match (correction.predicted_spam, correction.actual_spam) {
+            (true, false) => record.spam = record.spam.saturating_sub(1),
+            (false, true) => record.spam = (record.spam + 1).min(record.emails),
+            _ => {}
+        }
+    }
+
+    /// "labeled Newsletters 12 times, Updates 2 times (of 12 emails; never spam)",
+    /// for the classify prompt; None for senders not seen before
+    pub fn hint(&self, from: &str) -> Option<String> {
+        let record = self.get(from).filter(|record| record.emails > 0)?;
+        let mut labels = record.labels.iter().collect::<Vec<_>>();
+        labels.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        let labeled = labels
+            .iter()
+            .take(HINT_LABELS)
+            .map(|(label, count)| format!("{} {}", label, times(**count)))
+            .collect::<Vec<_>>();
+        let spam = match record.spam {
+            0 => "never spam".to_string(),
+            spam => format!("spam {}", times(spam)),
+        };
+        let emails = match record.emails {
+            1 => "1 email".to_string(),
+            emails => format!("{} emails", emails),
+        };
+        Some(if labeled.is_empty() {
+            format!("no labels so far ({}; {})", emails, spam)
+        } else {
+            format!("labeled {} ({}; {})", labeled.join(", "), emails, spam)
+        })
+    }
+}
+
+fn canonical_labels(labels: &[String]) -> BTreeSet<String> {
+    labels.iter().map(|label| canonical_label(label)).collect()
+}
+
+fn times(count: u32) -> String {
+    match count {
+        1 => "once".to_string(),
+        count => format!("{} times", count),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_hint_summarizes_sender_history() {
+        let mut stats = SenderStats::default();
+        assert_eq!(stats.hint("news@example.com"), None);
+
+        for _ in 0..3 {
+            stats.record(
+                "Example News <News@Example.com>",
+                false,
+                &labels(&["Newsletters", "Shopping"]),
+            );
+        }
+        stats.record("news@example.com", false, &labels(&["newsletters"]));
+
+        assert_eq!(
+            stats.hint("news@example.com").unwrap(),
+            "labeled Newsletters 4 times, Shopping 3 times (4 emails; never spam)"
+        );
+    }
+
+    #[test]
+    fn test_correction_moves_counts() {
+        let mut stats = SenderStats::default();
+        stats.record("deals@shop.com", true, &labels(&["Promotional"]));
+        stats.record("deals@shop.com", true, &labels(&["Promotional"]));
+
+        stats.record_correction(&Correction {
+            email_id: "1".to_string(),
+            from: "deals@shop.com".to_string(),
+            subject: "Your order".to_string(),
+            predicted_labels: labels(&["Promotional"]),
+            actual_labels: labels(&["Receipts"]),
+            predicted_spam: true,
+            actual_spam: false,
+        });
+
+        let record = stats.get("deals@shop.com").unwrap();
+        assert_eq!(record.spam, 1);
+        assert_eq!(
+            stats.hint("deals@shop.com").unwrap(),
+            "labeled Promotional once, Receipts once (2 emails; spam once)"
+        );
+    }
+
+    #[test]
+    fn test_correction_matches_labels_by_canonical_name() {
+        let mut stats = SenderStats::default();
+        stats.record("deals@shop.com", false, &labels(&["Promotional"]));
+
+        stats.record_correction(&Correction {
+            email_id: "1".to_string(),
+            from: "deals@shop.com".to_string(),
+            subject: "Your order".to_string(),
+            predicted_labels: labels(&["promotional"]),
+            actual_labels: labels(&["receipts", "Receipts"]),
+            predicted_spam: false,
+            actual_spam: false,
+        });
+
+        assert_eq!(
+            stats.hint("deals@shop.com").unwrap(),
+            "labeled Receipts once (1 email; never spam)"
+        );
+    }
+}
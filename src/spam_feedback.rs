@@ -0,0 +1,133 @@
+use crate::config;
+use crate::providers::{sender_address, Email};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpamDecision {
+    Spam,
+    NotSpam,
+}
+
+/// One manual spam/unspam action, kept apart from the free-text profile so
+/// repeat offenders can be queried
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpamFeedback {
+    pub sender: String,
+    pub subject: String,
+    pub decision: SpamDecision,
+    /// What the classifier said, if it had classified the email
+    pub predicted_spam: Option<bool>,
+    pub date: DateTime<Utc>,
+}
+
+impl SpamFeedback {
+    pub fn new(email: &Email, decision: SpamDecision, predicted_spam: Option<bool>) -> Self {
+        Self {
+            sender: email.from.clone(),
+            subject: email.subject.clone(),
+            decision,
+            predicted_spam,
+            date: Utc::now(),
+        }
+    }
+
+    /// The user reversed the classifier's spam verdict
+    pub fn is_misclassification(&self) -> bool {
+        self.predicted_spam
+            .is_some_and(|predicted| predicted != (self.decision == SpamDecision::Spam))
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SpamFeedbackLog {
+    entries: Vec<SpamFeedback>,
+}
+
+impl SpamFeedbackLog {
+    pub fn load() -> Result<Self> {
+        let path = config::spam_feedback_path();
+        if path.exists() {
+            let content = fs::read_to_string(&path)?;
+            Ok(serde_json::from_str(&content)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = config::config_dir();
+        if !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(config::spam_feedback_path(), content)?;
+        Ok(())
+    }
+
+    pub fn record(&mut self, feedback: SpamFeedback) {
+        self.entries.push(feedback);
+    }
+
+    /// The latest `limit` decisions, newest first
+    pub fn recent(&self, limit: usize) -> impl Iterator<Item = &SpamFeedback> {
+        self.entries.iter().rev().take(limit)
+    }
+
+    /// Sender addresses the classifier got wrong at least `min` times, worst first
+    pub fn repeat_misclassified(&self, min: usize) -> Vec<(String, usize)> {
+        let mut counts = BTreeMap::<String, usize>::new();
+        for entry in self.entries.iter().filter(|e| e.is_misclassification()) {
+            *counts.entry(sender_address(&entry.sender)).or_default() += 1;
+        }
+
+        let mut senders = counts
+            .into_iter()
+            .filter(|(_, count)| *count >= min)
+            .collect::<Vec<_>>();
+        senders.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        senders
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feedback(from: &str, decision: SpamDecision, predicted_spam: Option<bool>) -> SpamFeedback {
+        let email = Email {
+            from: from.to_string(),
+            subject: "Hello".to_string(),
+            ..Default::default()
+        };
+        SpamFeedback::new(&email, decision, predicted_spam)
+    }
+
+    #[test]
+    fn test_repeat_misclassified_groups_by_address() {
+        let mut log = SpamFeedbackLog::default();
+        log.record(feedback(
+            "News <news@shop.com>",
+            SpamDecision::NotSpam,
+            Some(true),
+        ));
+        log.record(feedback("NEWS@shop.com", SpamDecision::NotSpam, Some(true)));
+        log.record(feedback("news@shop.com", SpamDecision::Spam, Some(true)));
+        log.record(feedback("scam@x.com", SpamDecision::Spam, Some(false)));
+        log.record(feedback("unknown@x.com", SpamDecision::Spam, None));
+
+        assert_eq!(
+            log.repeat_misclassified(1),
+            vec![
+                ("news@shop.com".to_string(), 2),
+                ("scam@x.com".to_string(), 1)
+            ]
+        );
+        assert_eq!(log.repeat_misclassified(2).len(), 1);
+        assert_eq!(log.recent(2).next().unwrap().sender, "unknown@x.com");
+    }
+}
@@ -0,0 +1,73 @@
+use crate::config;
+use crate::llm::LlmStats;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+
+/// LLM usage totals per day (YYYY-MM-DD), for spotting trends across runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UsageLog {
+    days: BTreeMap<String, LlmStats>,
+}
+
+impl UsageLog {
+    pub fn load() -> Result<Self> {
+        let path = config::usage_path();
+        if path.exists() {
+            let content = fs::read_to_string(&path)?;
+            Ok(serde_json::from_str(&content)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = config::config_dir();
+        if !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(config::usage_path(), content)?;
+        Ok(())
+    }
+
+    pub fn record(&mut self, date: &str, stats: &LlmStats) {
+        self.days.entry(date.to_string()).or_default().add(stats);
+    }
+
+    /// Days on or after `since` (YYYY-MM-DD), oldest first
+    pub fn since<'a>(&'a self, since: &'a str) -> impl Iterator<Item = (&'a str, &'a LlmStats)> {
+        self.days
+            .iter()
+            .filter(move |(date, _)| date.as_str() >= since)
+            .map(|(date, stats)| (date.as_str(), stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calls(count: u64) -> LlmStats {
+        LlmStats {
+            calls: count,
+            input_chars: count * 100,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_record_accumulates_per_day() {
+        let mut log = UsageLog::default();
+        log.record("2025-01-01", &calls(2));
+        log.record("2025-01-02", &calls(1));
+        log.record("2025-01-02", &calls(3));
+
+        let recent = log.since("2025-01-02").collect::<Vec<_>>();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].0, "2025-01-02");
+        assert_eq!(recent[0].1, &calls(4));
+        assert_eq!(log.since("2024-12-01").count(), 2);
+    }
+}